@@ -0,0 +1,64 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// The DP row kernel against the per-pixel `min_by_key` loop it
+// replaced, and the whole forward-energy DP.
+
+#[macro_use]
+extern crate criterion;
+
+use criterion::{black_box, Criterion};
+use image::{ImageBuffer, Luma};
+use pnmseam::dprow::min_parents;
+use pnmseam::{AviShaTwo, SeamFinder};
+
+const WIDTH: usize = 1920;
+
+fn row() -> Vec<u32> {
+	let mut seed = 0x2545_f491u32;
+	(0..WIDTH)
+		.map(|_| {
+			seed ^= seed << 13;
+			seed ^= seed >> 17;
+			seed ^= seed << 5;
+			seed % 10_000
+		})
+		.collect()
+}
+
+fn scalar(above: &[u32], costs: &mut [u32], parents: &mut [u32]) {
+	let last = above.len() - 1;
+	for x in 0..above.len() {
+		let start = if x == 0 { 0 } else { x - 1 };
+		let end = if x == last { last } else { x + 1 };
+		let parent = (start..=end).min_by_key(|p| above[*p]).unwrap();
+		costs[x] = above[parent];
+		parents[x] = parent as u32;
+	}
+}
+
+fn dp_rows(c: &mut Criterion) {
+	let above = row();
+	c.bench_function("scalar min_by_key row", move |b| {
+		let (mut costs, mut parents) = (vec![0; WIDTH], vec![0; WIDTH]);
+		b.iter(|| scalar(black_box(&above), &mut costs, &mut parents))
+	});
+	let above = row();
+	c.bench_function("min_parents row", move |b| {
+		let (mut costs, mut parents) = (vec![0; WIDTH], vec![0; WIDTH]);
+		b.iter(|| min_parents(black_box(&above), &mut costs, &mut parents))
+	});
+}
+
+fn forward_dp(c: &mut Criterion) {
+	let image: ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_fn(640, 480, |x, y| {
+		Luma([((x * 53 + y * 29 + x * y * 7) % 256) as u8])
+	});
+	c.bench_function("forward-energy seam 640x480", move |b| {
+		b.iter(|| AviShaTwo::new(black_box(&image)).find_vertical_seam())
+	});
+}
+
+criterion_group!(benches, dp_rows, forward_dp);
+criterion_main!(benches);
@@ -0,0 +1,54 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// Carving height with the horizontal seams followed down the image's
+// columns, against carving it in a transposed copy, over a range of
+// image sizes.  Where the second overtakes the first is where
+// `TRANSPOSE_ABOVE` belongs.
+
+#[macro_use]
+extern crate criterion;
+
+use criterion::{black_box, Criterion};
+use image::{ImageBuffer, Rgb};
+use pnmseam::{seamcarve_with_options, CarveOptions};
+
+const SEAMS: u32 = 8;
+
+fn image(side: u32) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+	ImageBuffer::from_fn(side, side, |x, y| {
+		Rgb([
+			(x * 53 % 256) as u8,
+			(y * 29 % 256) as u8,
+			((x * y * 7 + x) % 256) as u8,
+		])
+	})
+}
+
+fn carve(c: &mut Criterion, name: &str, transpose_above: u64) {
+	let options = CarveOptions {
+		transpose_above: Some(transpose_above),
+		..CarveOptions::default()
+	};
+	c.bench_function_over_inputs(
+		name,
+		move |b, &&side| {
+			let buf = image(side);
+			b.iter(|| seamcarve_with_options(black_box(&buf), side, side - SEAMS, &options))
+		},
+		&[256, 512, 1024, 2048],
+	);
+}
+
+fn heights(c: &mut Criterion) {
+	carve(c, "carve height in place", u64::MAX);
+	carve(c, "carve height transposed", 0);
+}
+
+criterion_group! {
+	name = benches;
+	config = Criterion::default().sample_size(10);
+	targets = heights
+}
+criterion_main!(benches);
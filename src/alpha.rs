@@ -0,0 +1,141 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Alpha
+//!
+//! Carving moves pixels around whole, so the alpha of an RGBA or
+//! LumaA image comes through a removal untouched.  Two things need
+//! more care.  The energy is found from the luma of the color
+//! channels, so a transparent region with leftover color in it (as
+//! many exporters leave) looks busy, and the edge of an opaque shape
+//! over a transparent background may not look like an edge at all;
+//! premultiplying the color by the alpha first makes the energy see
+//! what a viewer does.  And the pixels of an inserted seam are the
+//! average of their neighbors, where the color of a transparent
+//! neighbor shouldn't count, so the colors are averaged weighted by
+//! their alphas.
+
+use crate::cq;
+use crate::pixelpairs::{channel_max, is_float};
+use image::{GenericImageView, ImageBuffer, Pixel, Primitive};
+use num_traits::NumCast;
+
+/// How the energy takes an image's alpha into account.  Images
+/// without alpha are the same either way.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum AlphaEnergy {
+	/// Look only at the color channels.  This is the historical
+	/// behavior.
+	#[default]
+	Ignore,
+	/// Multiply the color channels by the alpha before finding the
+	/// energy, so that transparent pixels are as dark as they look.
+	Premultiply,
+}
+
+// Whether the pixel type has an alpha channel; it's always the last.
+pub(crate) fn has_alpha<P: Pixel>() -> bool {
+	P::COLOR_MODEL.ends_with('A')
+}
+
+// A pixel's alpha in the unit range: 1 for pixels without one.
+fn opacity<P, S>(pixel: &P) -> f64
+where
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	if !has_alpha::<P>() {
+		return 1.0;
+	}
+	let max = channel_max::<S>();
+	let alpha: f64 = NumCast::from(pixel.channels()[P::CHANNEL_COUNT as usize - 1]).unwrap();
+	alpha / max
+}
+
+// The image with its color premultiplied by its alpha, and the alpha
+// left as it was.
+pub(crate) fn premultiplied<I, P, S>(image: &I) -> ImageBuffer<P, Vec<S>>
+where
+	I: GenericImageView<Pixel = P>,
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	ImageBuffer::from_fn(image.width(), image.height(), |x, y| {
+		let pixel = image.get_pixel(x, y);
+		let alpha = opacity(&pixel);
+		pixel.map_with_alpha(
+			|c| {
+				let c: f64 = NumCast::from(c).unwrap();
+				let c = c * alpha;
+				NumCast::from(cq!(is_float::<S>(), c, c.round())).unwrap()
+			},
+			|a| a,
+		)
+	})
+}
+
+// The pixel between two others: the colors averaged weighted by their
+// alphas, and the alphas averaged.  Two transparent pixels average
+// plainly.
+pub(crate) fn blend<P, S>(a: &P, b: &P) -> P
+where
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	blend_toward(a, b, 0.5)
+}
+
+// As `blend`, taking the fraction `t` of the mix from `b` and the rest
+// from `a`, before the alphas weigh in.
+pub(crate) fn blend_toward<P, S>(a: &P, b: &P, t: f64) -> P
+where
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	let (wa, wb) = (opacity(a) * (1.0 - t), opacity(b) * t);
+	let (wa, wb) = if wa + wb > 0.0 {
+		(wa / (wa + wb), wb / (wa + wb))
+	} else {
+		(1.0 - t, t)
+	};
+	let alpha = P::CHANNEL_COUNT as usize - 1;
+	let mut mixed = *a;
+	let channels = a.channels().iter().zip(b.channels().iter());
+	for (i, (out, (ca, cb))) in mixed.channels_mut().iter_mut().zip(channels).enumerate() {
+		let (ca, cb): (f64, f64) = (NumCast::from(*ca).unwrap(), NumCast::from(*cb).unwrap());
+		let value = if has_alpha::<P>() && i == alpha {
+			ca * (1.0 - t) + cb * t
+		} else {
+			ca * wa + cb * wb
+		};
+		*out = NumCast::from(value).unwrap();
+	}
+	mixed
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use image::{Luma, LumaA, Rgba};
+
+	#[test]
+	fn transparent_color_neither_shows_nor_blends() {
+		let clear = Rgba([200u8, 100, 50, 0]);
+		let solid = Rgba([10u8, 20, 30, 255]);
+		assert_eq!(blend(&clear, &solid), Rgba([10, 20, 30, 127]));
+		assert_eq!(blend(&clear, &clear), clear);
+		assert_eq!(blend(&Luma([10u8]), &Luma([20])), Luma([15]));
+		assert_eq!(
+			blend(&LumaA([0u16, 65535]), &LumaA([60000, 21845])),
+			LumaA([15000, 43690])
+		);
+
+		let image: ImageBuffer<LumaA<u8>, Vec<u8>> =
+			ImageBuffer::from_fn(2, 1, |x, _| LumaA([200, (x * 255) as u8]));
+		let premultiplied = premultiplied(&image);
+		assert_eq!(premultiplied[(0, 0)], LumaA([0, 0]));
+		assert_eq!(premultiplied[(1, 0)], LumaA([200, 255]));
+		assert!(has_alpha::<Rgba<u8>>() && !has_alpha::<Luma<u8>>());
+	}
+}
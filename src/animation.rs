@@ -0,0 +1,288 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Carve animations
+//!
+//! The classic seam-carving demo: the image shrinks a seam at a time,
+//! each seam shown in a bright color just before it goes.  This
+//! carves the image once, as `seamcarve_with_report` does, and then
+//! replays the seams the report recorded over a copy of the original,
+//! handing out a frame per seam.  The frames are plain RGB images,
+//! ready for whatever GIF or video encoder is at hand.
+//!
+//! Only removals are animated.  When the carve also enlarges the
+//! image, the last frame is the finished image, without the in-betweens.
+//!
+//! `CarveSteps` replays the carve the same way, without the
+//! highlighting: the image as it is after each seam comes out, in its
+//! own pixel type, for a UI to scrub through or a caller to save as
+//! it goes.
+
+use crate::cq;
+use crate::error::CarveError;
+use crate::pixelpairs::channel_max;
+use crate::report::CarveReport;
+use crate::seamcarver::{remove_seam, seamcarve_with_report, Carve, CarveOptions};
+use crate::seamfinder::Orientation;
+use image::{GenericImageView, ImageBuffer, Pixel, Primitive, Rgb, RgbImage};
+use num_traits::NumCast;
+
+/// The frames of a carve, as an iterator: the image with the first
+/// seam highlighted, the image less that seam with the second one
+/// highlighted, and so on, and last the finished image.
+pub struct CarveAnimation {
+	image: RgbImage,
+	seams: Vec<(Orientation, Vec<u32>)>,
+	next: usize,
+	finished: Option<RgbImage>,
+	highlight: Rgb<u8>,
+	canvas: Option<(u32, u32, Rgb<u8>)>,
+}
+
+impl CarveAnimation {
+	/// Carve the image to the new size with the given options, and
+	/// get ready to hand out its frames.  The report of the carve is
+	/// returned alongside.
+	pub fn new<I, P, S>(
+		image: &I,
+		newwidth: u32,
+		newheight: u32,
+		options: &CarveOptions,
+	) -> Result<(Self, CarveReport), CarveError>
+	where
+		I: GenericImageView<Pixel = P>,
+		P: Pixel<Subpixel = S> + 'static,
+		S: Primitive + 'static,
+	{
+		let (carved, report) = seamcarve_with_report(image, newwidth, newheight, options)?;
+		let animation = CarveAnimation {
+			image: to_rgb8(image),
+			seams: report
+				.seams
+				.iter()
+				.map(|(orientation, seam)| (orientation, seam.to_vec()))
+				.collect(),
+			next: 0,
+			finished: Some(to_rgb8(&carved)),
+			highlight: Rgb([255, 0, 0]),
+			canvas: None,
+		};
+		Ok((animation, report))
+	}
+
+	/// Highlight the seams in this color, rather than red.
+	pub fn highlight(mut self, color: Rgb<u8>) -> Self {
+		self.highlight = color;
+		self
+	}
+
+	/// Draw every frame in the top left corner of a canvas the size of
+	/// the original image, filled with the background color, for
+	/// encoders that want all the frames the same size.  Enlargements
+	/// stretch the canvas to fit.
+	pub fn on_canvas(mut self, background: Rgb<u8>) -> Self {
+		let (width, height) = self.image.dimensions();
+		let (width, height) = match &self.finished {
+			Some(done) => (width.max(done.width()), height.max(done.height())),
+			None => (width, height),
+		};
+		self.canvas = Some((width, height, background));
+		self
+	}
+
+	/// How many frames are left: one for each seam, and the finished
+	/// image.
+	pub fn remaining(&self) -> usize {
+		self.seams.len() - self.next + self.finished.as_ref().map_or(0, |_| 1)
+	}
+
+	fn framed(&self, frame: RgbImage) -> RgbImage {
+		match self.canvas {
+			Some((width, height, background)) => ImageBuffer::from_fn(width, height, |x, y| {
+				if x < frame.width() && y < frame.height() {
+					frame[(x, y)]
+				} else {
+					background
+				}
+			}),
+			None => frame,
+		}
+	}
+}
+
+impl Iterator for CarveAnimation {
+	type Item = RgbImage;
+
+	fn next(&mut self) -> Option<RgbImage> {
+		if self.next < self.seams.len() {
+			let (orientation, seam) = &self.seams[self.next];
+			let mut frame = self.image.clone();
+			for (i, at) in seam.iter().enumerate() {
+				let at = match orientation {
+					Orientation::Vertical => (*at, i as u32),
+					Orientation::Horizontal => (i as u32, *at),
+				};
+				frame[at] = self.highlight;
+			}
+			let direction = match orientation {
+				Orientation::Vertical => Carve::Width,
+				Orientation::Horizontal => Carve::Height,
+			};
+			self.image = remove_seam(&self.image, seam, direction);
+			self.next += 1;
+			return Some(self.framed(frame));
+		}
+		self.finished.take().map(|done| self.framed(done))
+	}
+
+	fn size_hint(&self) -> (usize, Option<usize>) {
+		(self.remaining(), Some(self.remaining()))
+	}
+}
+
+/// The image after each seam of a carve is removed, as an iterator,
+/// ending at the carved image.  When the carve also enlarges the
+/// image, the finished image comes last, without the in-betweens; a
+/// carve to the image's own size has no steps at all.
+pub struct CarveSteps<P: Pixel> {
+	image: ImageBuffer<P, Vec<P::Subpixel>>,
+	seams: Vec<(Orientation, Vec<u32>)>,
+	next: usize,
+	finished: Option<ImageBuffer<P, Vec<P::Subpixel>>>,
+}
+
+impl<P, S> CarveSteps<P>
+where
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	/// Carve the image to the new size with the given options, and
+	/// get ready to hand out the steps.  The report of the carve is
+	/// returned alongside.
+	pub fn new<I>(
+		image: &I,
+		newwidth: u32,
+		newheight: u32,
+		options: &CarveOptions,
+	) -> Result<(Self, CarveReport), CarveError>
+	where
+		I: GenericImageView<Pixel = P>,
+	{
+		let (carved, report) = seamcarve_with_report(image, newwidth, newheight, options)?;
+		let inserted = report.width_seams_inserted + report.height_seams_inserted;
+		let steps = CarveSteps {
+			image: ImageBuffer::from_fn(image.width(), image.height(), |x, y| {
+				image.get_pixel(x, y)
+			}),
+			seams: report
+				.seams
+				.iter()
+				.map(|(orientation, seam)| (orientation, seam.to_vec()))
+				.collect(),
+			next: 0,
+			finished: cq!(inserted > 0, Some(carved), None),
+		};
+		Ok((steps, report))
+	}
+
+	/// How many steps are left.
+	pub fn remaining(&self) -> usize {
+		self.seams.len() - self.next + self.finished.as_ref().map_or(0, |_| 1)
+	}
+}
+
+impl<P, S> Iterator for CarveSteps<P>
+where
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	type Item = ImageBuffer<P, Vec<S>>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		if self.next < self.seams.len() {
+			let (orientation, seam) = &self.seams[self.next];
+			let direction = match orientation {
+				Orientation::Vertical => Carve::Width,
+				Orientation::Horizontal => Carve::Height,
+			};
+			self.image = remove_seam(&self.image, seam, direction);
+			self.next += 1;
+			return Some(self.image.clone());
+		}
+		self.finished.take()
+	}
+
+	fn size_hint(&self) -> (usize, Option<usize>) {
+		(self.remaining(), Some(self.remaining()))
+	}
+}
+
+// The image in 8-bit RGB, whatever it was.
+fn to_rgb8<I, P, S>(image: &I) -> RgbImage
+where
+	I: GenericImageView<Pixel = P>,
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	let scale = channel_max::<S>();
+	ImageBuffer::from_fn(image.width(), image.height(), |x, y| {
+		let rgb = image.get_pixel(x, y).to_rgb();
+		let channel = |i: usize| {
+			let value: f64 = NumCast::from(rgb[i]).unwrap();
+			(value * 255.0 / scale).round() as u8
+		};
+		Rgb([channel(0), channel(1), channel(2)])
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use image::Luma;
+
+	#[test]
+	fn every_seam_is_shown_before_it_goes() {
+		let buf: ImageBuffer<Luma<u8>, Vec<u8>> =
+			ImageBuffer::from_fn(9, 7, |x, y| Luma([((x * 41 + y * 23 + x * y) % 200) as u8]));
+		let (animation, report) =
+			CarveAnimation::new(&buf, 7, 6, &CarveOptions::default()).unwrap();
+		assert_eq!(animation.remaining(), 4);
+		let frames: Vec<RgbImage> = animation.highlight(Rgb([0, 255, 0])).collect();
+		assert_eq!(frames.len(), report.seams.len() + 1);
+		assert_eq!(frames[0].dimensions(), (9, 7));
+		assert_eq!(frames[3].dimensions(), (7, 6));
+		for ((_, seam), frame) in report.seams.iter().zip(frames.iter()) {
+			let lit = frame.pixels().filter(|&&p| p == Rgb([0, 255, 0])).count();
+			assert_eq!(lit, seam.len());
+		}
+
+		let framed: Vec<RgbImage> = CarveAnimation::new(&buf, 7, 6, &CarveOptions::default())
+			.unwrap()
+			.0
+			.on_canvas(Rgb([0, 0, 0]))
+			.collect();
+		assert!(framed.iter().all(|frame| frame.dimensions() == (9, 7)));
+		assert_eq!(framed[3][(8, 6)], Rgb([0, 0, 0]));
+	}
+
+	#[test]
+	fn the_steps_shrink_a_seam_at_a_time_to_the_carved_image() {
+		let buf: ImageBuffer<Luma<u16>, Vec<u16>> = ImageBuffer::from_fn(8, 6, |x, y| {
+			Luma([((x * 4099 + y * 811 + x * y * 97) % 65536) as u16])
+		});
+		let options = CarveOptions::default();
+		let (steps, _) = CarveSteps::new(&buf, 5, 6, &options).unwrap();
+		assert_eq!(steps.remaining(), 3);
+		let steps: Vec<_> = steps.collect();
+		let sizes: Vec<_> = steps.iter().map(|step| step.dimensions()).collect();
+		assert_eq!(sizes, vec![(7, 6), (6, 6), (5, 6)]);
+		let carved = crate::seamcarver::seamcarve_with_options(&buf, 5, 6, &options).unwrap();
+		assert_eq!(steps[2].clone().into_raw(), carved.into_raw());
+
+		let (grown, _) = CarveSteps::new(&buf, 7, 8, &options).unwrap();
+		let sizes: Vec<_> = grown.map(|step| step.dimensions()).collect();
+		assert_eq!(sizes, vec![(7, 6), (7, 8)]);
+		assert_eq!(CarveSteps::new(&buf, 8, 6, &options).unwrap().0.count(), 0);
+	}
+}
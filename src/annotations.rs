@@ -0,0 +1,199 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Annotations
+//!
+//! Computer vision datasets come with geometry attached: keypoints,
+//! bounding boxes, segmentation polygons.  Retargeting the image
+//! without moving the geometry along with it ruins the dataset.  This
+//! replays a carve's seams over the annotations, moving each
+//! coordinate as the pixel under it moved, and drops the annotations
+//! whose area the carve removed entirely.
+//!
+//! Coordinates are continuous: pixel (x, y) covers the square from
+//! (x, y) to (x + 1, y + 1).  A coordinate inside a removed pixel is
+//! moved to the edge where its neighbors meet.
+
+use crate::cq;
+use crate::report::SeamSequence;
+use crate::seamfinder::Orientation;
+
+/// A piece of image-space geometry.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Annotation {
+	/// A single point, such as a keypoint.
+	Point {
+		/// Horizontal position.
+		x: f32,
+		/// Vertical position.
+		y: f32,
+	},
+	/// An axis-aligned box.
+	Rect {
+		/// Left edge.
+		x: f32,
+		/// Top edge.
+		y: f32,
+		/// Width.
+		width: f32,
+		/// Height.
+		height: f32,
+	},
+	/// A closed polygon, by its vertices.
+	Polygon(Vec<(f32, f32)>),
+}
+
+/// Move every annotation as the carve described by `seams` moved the
+/// image under it, removing those with no area left.  Annotations are
+/// removed from the vector, which is why this takes one rather than a
+/// slice.  Points have no area, and are never removed.
+pub fn carve_annotations(annotations: &mut Vec<Annotation>, seams: &SeamSequence) {
+	for (orientation, seam) in seams.iter() {
+		let carved = annotations
+			.drain(..)
+			.filter_map(|mut annotation| {
+				cq!(
+					carve_one(&mut annotation, orientation, seam),
+					Some(annotation),
+					None
+				)
+			})
+			.collect();
+		*annotations = carved;
+	}
+}
+
+// Where a coordinate across the seam ends up, given the row (or
+// column) it's in.
+fn shift(across: f32, along: f32, seam: &[u32]) -> f32 {
+	let index = std::cmp::min(along.max(0.0) as usize, seam.len() - 1);
+	let removed = seam[index] as f32;
+	if across >= removed + 1.0 {
+		across - 1.0
+	} else if across >= removed {
+		removed
+	} else {
+		across
+	}
+}
+
+// Move one annotation past one seam, returning whether it survived.
+fn carve_one(annotation: &mut Annotation, orientation: Orientation, seam: &[u32]) -> bool {
+	// Everything is worked as if the seam were vertical, with the
+	// coordinates swapped for horizontal seams.
+	let swap = |(x, y): (f32, f32)| match orientation {
+		Orientation::Vertical => (x, y),
+		Orientation::Horizontal => (y, x),
+	};
+	let carve_point = |point: (f32, f32)| {
+		let (across, along) = swap(point);
+		swap((shift(across, along, seam), along))
+	};
+
+	match annotation {
+		Annotation::Point { x, y } => {
+			let (nx, ny) = carve_point((*x, *y));
+			*x = nx;
+			*y = ny;
+			true
+		}
+		Annotation::Rect {
+			x,
+			y,
+			width,
+			height,
+		} => {
+			// A box stays a box, so both of its edges are moved as the
+			// middle of the box moved.
+			let (left, top) = swap((*x, *y));
+			let (breadth, length) = swap((*width, *height));
+			let middle = top + length / 2.0;
+			let (left, right) = (
+				shift(left, middle, seam),
+				shift(left + breadth, middle, seam),
+			);
+			let (nx, ny) = swap((left, top));
+			let (nw, nh) = swap((right - left, length));
+			*x = nx;
+			*y = ny;
+			*width = nw;
+			*height = nh;
+			right > left
+		}
+		Annotation::Polygon(vertices) => {
+			for vertex in vertices.iter_mut() {
+				*vertex = carve_point(*vertex);
+			}
+			area(vertices) > 0.0
+		}
+	}
+}
+
+// The shoelace formula.
+fn area(vertices: &[(f32, f32)]) -> f32 {
+	let n = vertices.len();
+	let twice: f32 = (0..n)
+		.map(|i| {
+			let ((x1, y1), (x2, y2)) = (vertices[i], vertices[(i + 1) % n]);
+			x1 * y2 - x2 * y1
+		})
+		.sum();
+	twice.abs() / 2.0
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn annotations_follow_the_seams() {
+		let mut seams = SeamSequence::new();
+		// Down column 2 of a 6-wide image, then column 2 again.
+		seams.push(Orientation::Vertical, vec![2; 4]);
+		seams.push(Orientation::Vertical, vec![2; 4]);
+		let mut annotations = vec![
+			Annotation::Point { x: 4.5, y: 1.0 },
+			Annotation::Point { x: 0.5, y: 1.0 },
+			Annotation::Rect {
+				x: 1.0,
+				y: 0.0,
+				width: 4.0,
+				height: 2.0,
+			},
+			// Exactly the two columns removed.
+			Annotation::Rect {
+				x: 2.0,
+				y: 0.0,
+				width: 2.0,
+				height: 4.0,
+			},
+			Annotation::Polygon(vec![(2.0, 0.0), (4.0, 0.0), (4.0, 4.0), (2.0, 4.0)]),
+			Annotation::Polygon(vec![(0.0, 0.0), (5.0, 0.0), (5.0, 4.0)]),
+		];
+		carve_annotations(&mut annotations, &seams);
+		assert_eq!(
+			annotations,
+			[
+				Annotation::Point { x: 2.5, y: 1.0 },
+				Annotation::Point { x: 0.5, y: 1.0 },
+				Annotation::Rect {
+					x: 1.0,
+					y: 0.0,
+					width: 2.0,
+					height: 2.0,
+				},
+				Annotation::Polygon(vec![(0.0, 0.0), (3.0, 0.0), (3.0, 4.0)]),
+			]
+		);
+	}
+
+	#[test]
+	fn horizontal_seams_move_the_other_axis() {
+		let mut seams = SeamSequence::new();
+		seams.push(Orientation::Horizontal, vec![0, 0, 1, 1]);
+		let mut annotations = vec![Annotation::Point { x: 3.0, y: 2.5 }];
+		carve_annotations(&mut annotations, &seams);
+		assert_eq!(annotations, [Annotation::Point { x: 3.0, y: 1.5 }]);
+	}
+}
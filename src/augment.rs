@@ -0,0 +1,140 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Augmentation
+//!
+//! A model trained on retargeted images should see more than one
+//! retargeting of each.  A carve that draws its seams from among the
+//! nearly cheapest comes out differently for every seed, each as
+//! plausible as the cheapest carve, so a handful of seeds makes a
+//! handful of variants of the one image.  Each variant is handed back
+//! with its seed, so that a pipeline can record it and make the same
+//! variant again.
+//!
+//! With the `threaded` feature the variants are carved on rayon's
+//! threads, or on a pool of `threads` of them if the options ask;
+//! without it, one after another.
+
+use crate::error::CarveError;
+use crate::pool;
+use crate::sampling::NearOptimal;
+use crate::seamcarver::{seamcarve_with_options, CarveOptions};
+use image::{GenericImageView, ImageBuffer, Pixel, Primitive};
+#[cfg(feature = "threaded")]
+use rayon::prelude::*;
+
+/// How to make the variants of an image.
+#[derive(Debug, Clone)]
+pub struct AugmentParams {
+	/// The width to carve each variant to.
+	pub width: u32,
+
+	/// The height to carve each variant to.
+	pub height: u32,
+
+	/// How much dearer than the cheapest each seam may be, as a
+	/// fraction; see `NearOptimal`.
+	pub tolerance: f64,
+
+	/// The seed of the first variant.  Each variant after it takes
+	/// the next.
+	pub seed: u64,
+
+	/// The options every variant is carved with.  Their
+	/// `near_optimal` is replaced with each variant's own.
+	pub options: CarveOptions,
+}
+
+impl AugmentParams {
+	/// Variants of the given size, each seam within 5% of the
+	/// cheapest, seeded from zero, with the default options.
+	pub fn new(width: u32, height: u32) -> Self {
+		AugmentParams {
+			width,
+			height,
+			tolerance: 0.05,
+			seed: 0,
+			options: CarveOptions::default(),
+		}
+	}
+
+	// The options one variant is carved with.
+	fn variant(&self, seed: u64) -> CarveOptions {
+		CarveOptions {
+			near_optimal: Some(NearOptimal::new(self.tolerance, seed)),
+			..self.options.clone()
+		}
+	}
+}
+
+/// One variant of an image, and the seed its seams were drawn from.
+#[derive(Debug, Clone)]
+pub struct Variant<P, S>
+where
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	/// The carved image.
+	pub image: ImageBuffer<P, Vec<S>>,
+
+	/// The seed to give `NearOptimal` to carve it again.
+	pub seed: u64,
+}
+
+/// Carve `n` variants of the image, each with its seams drawn from
+/// its own seed, in the order of their seeds.  Any variant that can't
+/// be carved fails the lot, as they'd all fail for the same reason.
+pub fn augment<I, P, S>(
+	image: &I,
+	n: usize,
+	params: &AugmentParams,
+) -> Result<Vec<Variant<P, S>>, CarveError>
+where
+	I: GenericImageView<Pixel = P> + Sync,
+	P: Pixel<Subpixel = S> + Send + Sync + 'static,
+	S: Primitive + Send + Sync + 'static,
+{
+	let seeds: Vec<u64> = (0..n as u64).map(|i| params.seed.wrapping_add(i)).collect();
+	let carve = |&seed: &u64| {
+		let options = params.variant(seed);
+		seamcarve_with_options(image, params.width, params.height, &options)
+			.map(|image| Variant { image, seed })
+	};
+	pool::with_threads(params.options.threads, || {
+		#[cfg(feature = "threaded")]
+		let variants = pool::install(|| seeds.par_iter().map(carve).collect());
+		#[cfg(not(feature = "threaded"))]
+		let variants = seeds.iter().map(carve).collect();
+		variants
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use image::Luma;
+
+	#[test]
+	fn variants_differ_and_come_again_from_their_seeds() {
+		let buf: ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_fn(24, 12, |x, y| {
+			Luma([((x * 37 + y * 11 + (x ^ y) * 5) % 64) as u8])
+		});
+		let params = AugmentParams {
+			tolerance: 0.5,
+			seed: 40,
+			..AugmentParams::new(16, 12)
+		};
+		let variants = augment(&buf, 4, &params).unwrap();
+		let seeds: Vec<u64> = variants.iter().map(|v| v.seed).collect();
+		assert_eq!(seeds, [40, 41, 42, 43]);
+		assert!(variants.iter().all(|v| v.image.dimensions() == (16, 12)));
+		let raw: Vec<&[u8]> = variants.iter().map(|v| &*v.image).collect();
+		assert!(raw[1..].iter().any(|image| *image != raw[0]));
+
+		let again = AugmentParams { seed: 42, ..params };
+		let variant = augment(&buf, 1, &again).unwrap().remove(0);
+		assert_eq!(&*variant.image, raw[2]);
+		assert!(augment(&buf, 0, &again).unwrap().is_empty());
+	}
+}
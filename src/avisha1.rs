@@ -9,11 +9,24 @@
 //! straightforward of the energy map algorithms, the one with no
 //! forward energy calculation, although that is coming.
 
+use crate::avisha2::Adjust;
+use crate::banded::corridor_seam;
+use crate::border::BorderPolicy;
 use crate::cq;
-use crate::pixelpairs::energy_of_pair_luma as energy_of_pixel_pair;
-use crate::seamfinder::SeamFinder;
-use crate::twodmap::{EnergyAndBackPointer, TwoDimensionalMap};
-use image::{GenericImageView, Pixel, Primitive};
+use crate::dprow::{min_parents_by, TieBreak};
+use crate::energyopts::EnergyOptions;
+use crate::pixelpairs::{luma_max, luma_plane};
+#[cfg(feature = "threaded")]
+use crate::pool;
+use crate::pyramid::coarse_corridor;
+use crate::scalar::EnergyScalar;
+use crate::seamfinder::{CostTable, Orientation, Seam, SeamAndCosts, SeamFinder};
+use crate::slope::sloped_seam;
+use crate::twodmap::TwoDimensionalMap;
+use image::{GenericImageView, ImageBuffer, Pixel, Primitive};
+#[cfg(feature = "threaded")]
+use rayon::prelude::*;
+use std::marker::PhantomData;
 // use num_cpus;
 
 // TODO : How do we carve this up into uniform segments? The cheapest
@@ -27,7 +40,8 @@ use image::{GenericImageView, Pixel, Primitive};
 /// calculator, rather than differentiating between the greyscale and
 /// RGB calculators.  Also, the energy formula is the base one, and
 /// none of the alternative energy algorithms described in [Avidan &
-/// Shamir (2007)] are implemented.
+/// Shamir (2007)] are implemented.  Edge pixels are clamped; see
+/// `calculate_energy_with_border` for the alternatives.
 // TODO: Implement alternative energy calculations?
 pub fn calculate_energy<I, P, S>(image: &I) -> TwoDimensionalMap<u32>
 where
@@ -35,139 +49,487 @@ where
 	P: Pixel<Subpixel = S> + 'static,
 	S: Primitive + 'static,
 {
-	let (width, height) = image.dimensions();
-	let (mw, mh) = (width - 1, height - 1);
+	calculate_energy_with_border(image, BorderPolicy::Clamp)
+}
 
+/// As `calculate_energy`, with the neighbors of edge pixels that fall
+/// outside the image supplied by the given policy.
+pub fn calculate_energy_with_border<I, P, S>(
+	image: &I,
+	border: BorderPolicy,
+) -> TwoDimensionalMap<u32>
+where
+	I: GenericImageView<Pixel = P>,
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	calculate_energy_as(image, border)
+}
+
+/// As `calculate_energy_with_border`, with the energy kept in the
+/// given scalar type.  As an `f32`, each pixel pair's energy is the
+/// squared difference of lumas scaled to the unit range.
+pub fn calculate_energy_as<E, I, P, S>(image: &I, border: BorderPolicy) -> TwoDimensionalMap<E>
+where
+	E: EnergyScalar,
+	I: GenericImageView<Pixel = P>,
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	plane_energy((&luma_plane(image), luma_max::<S>()), border)
+}
+
+// The energy map of a luma plane.
+pub(crate) fn plane_energy<E: EnergyScalar>(
+	(lumas, max): (&TwoDimensionalMap<u32>, u32),
+	border: BorderPolicy,
+) -> TwoDimensionalMap<E> {
+	let (width, height) = (lumas.width, lumas.height);
+
+	// Every pixel's energy depends only on the luma plane, so with the
+	// `threaded` feature on the rows are shared out among threads.
 	let mut emap = TwoDimensionalMap::new(width, height);
-	for y in 0..height {
-		for x in 0..width {
-			let current_pixel = image.get_pixel(x, y);
-			let (leftpixel, rightpixel, uppixel, downpixel) = (
-				cq!(x == 0, current_pixel, image.get_pixel(x - 1, y)),
-				cq!(x >= mw, current_pixel, image.get_pixel(x + 1, y)),
-				cq!(y == 0, current_pixel, image.get_pixel(x, y - 1)),
-				cq!(y >= mh, current_pixel, image.get_pixel(x, y + 1)),
-			);
-			emap[(x, y)] = energy_of_pixel_pair(&leftpixel, &rightpixel)
-				+ energy_of_pixel_pair(&uppixel, &downpixel);
+	let row = |(y, energies): (usize, &mut [E])| {
+		for (x, energy) in energies.iter_mut().enumerate() {
+			*energy = pixel_energy((lumas, max), border, (x as u32, y as u32));
 		}
-	}
+	};
+	#[cfg(feature = "threaded")]
+	pool::install(|| emap.par_rows_mut().enumerate().for_each(row));
+	#[cfg(not(feature = "threaded"))]
+	emap.rows_mut().enumerate().for_each(row);
 	emap
 }
 
-// Again, the trick here is to divvy up the width into segments,
-// breaking the target into mut_chunks and readdressing them
-// afterward for each row.
+// The energy of one pixel of a luma plane, with the neighbors that
+// fall outside the plane supplied by the border policy.
+pub(crate) fn pixel_energy<E: EnergyScalar>(
+	(lumas, max): (&TwoDimensionalMap<u32>, u32),
+	border: BorderPolicy,
+	(x, y): (u32, u32),
+) -> E {
+	let (width, height) = (lumas.width, lumas.height);
+	// Outside the image, a zero pixel has zero luma.
+	let neighbor = |dx: i64, dy: i64| match (
+		border.resolve(i64::from(x) + dx, width),
+		border.resolve(i64::from(y) + dy, height),
+	) {
+		(Some(nx), Some(ny)) => lumas[(nx, ny)],
+		_ => 0,
+	};
+	let (leftluma, rightluma, upluma, downluma) = (
+		neighbor(-1, 0),
+		neighbor(1, 0),
+		neighbor(0, -1),
+		neighbor(0, 1),
+	);
+	E::of_lumas(leftluma, rightluma, max).saturating_add(E::of_lumas(upluma, downluma, max))
+}
+
+/// The backward energy map of a luma plane, kept between seams.
+///
+/// A pixel's energy depends only on its four neighbors, so removing a
+/// seam changes only the energies of the pixels beside it, and of
+/// those whose neighbor above or below moved past them as it went.
+/// Rather than recalculating the whole map for the next seam the table
+/// recalculates those and shifts the rest.  As with `ForwardTable`,
+/// only the adjustments carved along with the image may be used with
+/// it, and the energies must not be normalized, which would look at
+/// the map as a whole.
+pub(crate) struct EnergyTable<E: EnergyScalar> {
+	lumas: TwoDimensionalMap<u32>,
+	max: u32,
+	border: BorderPolicy,
+	energy: TwoDimensionalMap<E>,
+}
+
+impl<E: EnergyScalar> EnergyTable<E> {
+	// The whole map, for a luma plane not yet carved.
+	pub(crate) fn new(lumas: TwoDimensionalMap<u32>, max: u32, border: BorderPolicy) -> Self {
+		let energy = plane_energy((&lumas, max), border);
+		EnergyTable {
+			lumas,
+			max,
+			border,
+			energy,
+		}
+	}
+
+	// The cheapest vertical seam through the adjusted map, found in
+	// the scratch buffers.
+	pub(crate) fn seam(
+		&self,
+		ties: TieBreak,
+		adjust: &Adjust<E>,
+		scratch: &mut CarveScratch<E>,
+	) -> Vec<u32> {
+		let (width, height) = (self.energy.width, self.energy.height);
+		let adjusted = &mut scratch.adjusted;
+		adjusted.reshape(width, height);
+		for y in 0..height {
+			let cells = adjusted.row_mut(y).iter_mut().zip(self.energy.row(y));
+			for (x, (cell, energy)) in cells.enumerate() {
+				*cell = adjust(x as u32, y, *energy);
+			}
+		}
+		let rows = &mut scratch.rows;
+		vertical_costs_in(adjusted, ties, None, rows);
+		trace_vertical_seam(&rows.parents, ties.end_column(&rows.above))
+	}
+
+	// Take a seam out of the plane and recalculate the energies around
+	// it.
+	pub(crate) fn remove(&mut self, seam: &[u32]) {
+		self.lumas.remove_seam(seam);
+		self.energy.remove_seam(seam);
+		let (width, height) = (self.lumas.width, self.lumas.height);
+		if width == 0 {
+			return;
+		}
+
+		let last = width - 1;
+		for y in 0..height {
+			let at = seam[y as usize];
+			// The pixels either side of the seam lost a neighbor, and
+			// the edges' neighbors past the edge may be found across
+			// the plane.
+			let mut spans = [
+				(at.saturating_sub(1), at),
+				(0, 0),
+				(last, last),
+				(1, 0),
+				(1, 0),
+			];
+			// A pixel's neighbor above or below moved if the seam
+			// passed between them: if it's right of the seam in one
+			// row and not in the other.
+			for (dy, span) in [-1, 1].iter().zip(spans[3..].iter_mut()) {
+				if let Some(ny) = self.border.resolve(i64::from(y) + dy, height) {
+					let other = seam[ny as usize];
+					if other != at {
+						*span = (std::cmp::min(at, other), std::cmp::max(at, other) - 1);
+					}
+				}
+			}
+			for &(a, b) in spans.iter() {
+				for x in a..=std::cmp::min(b, last) {
+					self.energy[(x, y)] =
+						pixel_energy((&self.lumas, self.max), self.border, (x, y));
+				}
+			}
+		}
+	}
+}
 
 /// Given an energy map, return the list of x-coordinates that, when
 /// mapped with the range (0..height), give the XY coordinates for each
 /// pixel in the seam to be removed.
-pub fn energy_to_vertical_seam(energy: &TwoDimensionalMap<u32>) -> Vec<u32> {
+pub fn energy_to_vertical_seam<E: EnergyScalar>(energy: &TwoDimensionalMap<E>) -> Vec<u32> {
+	energy_to_vertical_seam_by(energy, TieBreak::Leftmost)
+}
+
+/// As `energy_to_vertical_seam`, with ties broken as given.
+pub fn energy_to_vertical_seam_by<E: EnergyScalar>(
+	energy: &TwoDimensionalMap<E>,
+	ties: TieBreak,
+) -> Vec<u32> {
+	let (bottom, parents) = vertical_costs(energy, ties, None);
+
+	// Trace back from the x coordinate of the bottommost seam with
+	// the least energy.
+	trace_vertical_seam(&parents, ties.end_column(&bottom))
+}
+
+/// Given an energy map, return the cumulative cost map the vertical
+/// seam is traced back through: the cost of the cheapest seam from
+/// the top row down to every pixel.  The bottom row is the cost of the
+/// cheapest seam ending at each column.  For horizontal seams, pass
+/// the transposed map, and transpose the costs back.
+pub fn energy_to_vertical_costs<E: EnergyScalar>(
+	energy: &TwoDimensionalMap<E>,
+) -> TwoDimensionalMap<E::Sum> {
+	let mut record = TwoDimensionalMap::new(energy.width, energy.height);
+	vertical_costs(energy, TieBreak::Leftmost, Some(&mut record));
+	record
+}
+
+// The cost of the cheapest seam ending at every pixel of the bottom
+// row of an energy map, and the parent of every pixel on the way.
+// The costs of the rows above are kept in `record`, if it's given.
+fn vertical_costs<E: EnergyScalar>(
+	energy: &TwoDimensionalMap<E>,
+	ties: TieBreak,
+	record: Option<&mut TwoDimensionalMap<E::Sum>>,
+) -> (Vec<E::Sum>, TwoDimensionalMap<u32>) {
+	let mut rows = DpRows::new();
+	vertical_costs_in(energy, ties, record, &mut rows);
+	(rows.above, rows.parents)
+}
+
+// The running costs and back pointers of the backward DP.
+struct DpRows<C> {
+	above: Vec<C>,
+	costs: Vec<C>,
+	parents: TwoDimensionalMap<u32>,
+}
+
+impl<C> DpRows<C> {
+	fn new() -> Self {
+		DpRows {
+			above: Vec::new(),
+			costs: Vec::new(),
+			parents: TwoDimensionalMap::new(0, 0),
+		}
+	}
+}
+
+/// The buffers a carve finds its backward-energy seams in, kept from
+/// one seam to the next so that each seam's energies and DP rows are
+/// written over the last's rather than allocated afresh.
+pub(crate) struct CarveScratch<E: EnergyScalar> {
+	adjusted: TwoDimensionalMap<E>,
+	rows: DpRows<E::Sum>,
+}
+
+impl<E: EnergyScalar> CarveScratch<E> {
+	pub(crate) fn new() -> Self {
+		CarveScratch {
+			adjusted: TwoDimensionalMap::new(0, 0),
+			rows: DpRows::new(),
+		}
+	}
+}
+
+// As `vertical_costs`, in the given rows: the bottom row's costs are
+// left in `above`.
+fn vertical_costs_in<E: EnergyScalar>(
+	energy: &TwoDimensionalMap<E>,
+	ties: TieBreak,
+	mut record: Option<&mut TwoDimensionalMap<E::Sum>>,
+	rows: &mut DpRows<E::Sum>,
+) {
 	let (width, height) = (energy.width, energy.height);
-	let mut target: TwoDimensionalMap<EnergyAndBackPointer<u32>> =
-		TwoDimensionalMap::new(width, height);
+	let DpRows {
+		above,
+		costs,
+		parents,
+	} = rows;
+	parents.reshape(width, height);
+	if height > 0 {
+		// The first row has no parents.
+		parents.row_mut(0).iter_mut().for_each(|parent| *parent = 0);
+	}
 
-	// Populate the first row with their native energies.
-	for i in 0..width {
-		target[(i, 0)].energy = energy[(i, 0)];
+	// Only two rows of running costs are needed at a time: the row
+	// above, which starts with the first row's native energies, and
+	// the row being worked on.
+	above.clear();
+	above.extend(energy.row(0).iter().map(|e| e.widen()));
+	costs.clear();
+	costs.resize(width as usize, E::Sum::default());
+	if let Some(record) = &mut record {
+		record.row_mut(0).copy_from_slice(above);
 	}
 
-	let maxwidth = width - 1;
 	// For every subsequent row, populate the target cell with the sum
 	// of the *lowest adjacent upper energy* and the *x coordinate of
 	// that energy*
 	for y in 1..height {
-		for x in 0..width {
-			let erg = energy[(x, y)];
-			let range = cq!(x == 0, 0, x - 1)..=cq!(x == maxwidth, maxwidth, x + 1);
-			let parent_x = range.min_by_key(|x| target[(*x, (y - 1))].energy).unwrap();
-			let parent = target[(parent_x, (y - 1))];
-			target[(x, y)] = EnergyAndBackPointer {
-				energy: erg + parent.energy,
-				parent: parent_x,
-			};
+		min_parents_by(above, costs, parents.row_mut(y), ties, y);
+		for (cost, erg) in costs.iter_mut().zip(energy.row(y)) {
+			*cost = *cost + erg.widen();
+		}
+		if let Some(record) = &mut record {
+			record.row_mut(y).copy_from_slice(costs);
 		}
+		std::mem::swap(above, costs);
 	}
+}
 
-	// Find the x coordinate of the bottomost seam with the least energy.
-	let mut seam_col = (0..width)
-		.min_by_key(|x| target[(*x, height - 1)].energy)
-		.unwrap();
+// The costs alone, as floats.
+fn projection<C: EnergyScalar>(costs: &TwoDimensionalMap<C>) -> TwoDimensionalMap<f64> {
+	TwoDimensionalMap {
+		width: costs.width,
+		height: costs.height,
+		energy: costs.energy.iter().map(|cost| cost.to_f64()).collect(),
+	}
+}
+
+// The seam ending at the given column of the bottom row.
+fn trace_vertical_seam(parents: &TwoDimensionalMap<u32>, mut seam_col: u32) -> Vec<u32> {
 	// Working backwards, generate a vec of x coordinates that that map to
 	// the seam, reverse and return.
-	(0..height)
+	(0..parents.height)
 		.rev()
-		.fold(Vec::<u32>::with_capacity(height as usize), |mut acc, y| {
-			acc.push(seam_col);
-			seam_col = target[(seam_col, y)].parent;
-			acc
-		})
+		.fold(
+			Vec::<u32>::with_capacity(parents.height as usize),
+			|mut acc, y| {
+				acc.push(seam_col);
+				seam_col = parents[(seam_col, y)];
+				acc
+			},
+		)
 		.into_iter()
 		.rev()
 		.collect()
 }
 
-// This would be much harder.  The column is broken up into
-// segments, but reassembling those becomes a bit nightmarish.
-// It's a completely different algorithm!
-
 /// Given an energy map, return the list of y-coordinates that, when
 /// mapped with the range (0..width), give the XY coordinates for each
-/// pixel in the seam to be removed.
-pub fn energy_to_horizontal_seam(energy: &TwoDimensionalMap<u32>) -> Vec<u32> {
+/// pixel in the seam to be removed.  Columns aren't contiguous in
+/// memory, so the map is transposed once up front and the rows of the
+/// transposition carved instead.
+pub fn energy_to_horizontal_seam<E: EnergyScalar>(energy: &TwoDimensionalMap<E>) -> Vec<u32> {
+	energy_to_vertical_seam(&energy.transposed())
+}
+
+/// Given an energy map, return the cheapest seams ending at each of
+/// the `count` cheapest columns of the bottom row, cheapest first,
+/// with their costs.  The first is the seam `energy_to_vertical_seam`
+/// finds.  The rest aren't carved around the ones before them, so
+/// they may share pixels, and often run together for most of their
+/// length; how quickly the costs rise says how much room there is to
+/// carve before the seams have to go through something.
+pub fn energy_to_ranked_vertical_seams<E: EnergyScalar>(
+	energy: &TwoDimensionalMap<E>,
+	count: usize,
+) -> Vec<(Vec<u32>, E::Sum)> {
+	let (bottom, parents) = vertical_costs(energy, TieBreak::Leftmost, None);
+	let mut columns: Vec<u32> = (0..energy.width).collect();
+	// A stable sort, so that the leftmost wins a tie.
+	columns.sort_by(|a, b| {
+		bottom[*a as usize]
+			.partial_cmp(&bottom[*b as usize])
+			.unwrap_or(std::cmp::Ordering::Equal)
+	});
+	columns
+		.into_iter()
+		.take(count)
+		.map(|x| (trace_vertical_seam(&parents, x), bottom[x as usize]))
+		.collect()
+}
+
+/// As `energy_to_ranked_vertical_seams`, for horizontal seams.
+pub fn energy_to_ranked_horizontal_seams<E: EnergyScalar>(
+	energy: &TwoDimensionalMap<E>,
+	count: usize,
+) -> Vec<(Vec<u32>, E::Sum)> {
+	energy_to_ranked_vertical_seams(&energy.transposed(), count)
+}
+
+// As `energy_to_vertical_seam`, stepping sideways at most once in
+// any `rows` consecutive rows.
+fn sloped_vertical_seam<E: EnergyScalar>(
+	energy: &TwoDimensionalMap<E>,
+	rows: u32,
+) -> (Vec<u32>, TwoDimensionalMap<E::Sum>) {
+	sloped_seam(
+		energy.width,
+		energy.height,
+		rows,
+		&|x| energy[(x, 0)].widen(),
+		&|x, y, _, cost| cost + energy[(x, y)].widen(),
+	)
+}
+
+/// Given an energy map, find up to `count` vertical seams that share
+/// no pixels, in the order of their cost.  The energy map is found only
+/// once; each seam's pixels are then marked as used, and the next seam
+/// routed around them.  This is a greedy approximation: the seams
+/// found are each the cheapest that avoids the ones before it, which
+/// isn't quite the same as the cheapest set of seams, but it's close,
+/// and much cheaper to find than re-finding the energy for every seam.
+///
+/// All of the coordinates are in the map the seams were found in, so
+/// the seams can't be removed one after the other as they are; take
+/// all of their pixels out of each row at once instead.  Fewer than
+/// `count` seams are returned if the marked pixels leave no way from
+/// the top of the map to the bottom.
+pub fn energy_to_disjoint_vertical_seams<E: EnergyScalar>(
+	energy: &TwoDimensionalMap<E>,
+	count: usize,
+) -> Vec<Vec<u32>> {
 	let (width, height) = (energy.width, energy.height);
-	let mut target: TwoDimensionalMap<EnergyAndBackPointer<u32>> =
-		TwoDimensionalMap::new(width, height);
+	let mut used: TwoDimensionalMap<bool> = TwoDimensionalMap::new(width, height);
+	let mut parents: TwoDimensionalMap<u32> = TwoDimensionalMap::new(width, height);
+	let mut seams = Vec::with_capacity(count);
 
-	// Populate the first row with their native energies.
-	for i in 0..height {
-		target[(0, i)].energy = energy[(0, i)];
-	}
+	while seams.len() < count {
+		// As in `energy_to_vertical_seam`, but a used pixel has no cost
+		// at all, and can be the parent of nothing.
+		let mut above: Vec<Option<E::Sum>> = energy
+			.row(0)
+			.iter()
+			.zip(used.row(0))
+			.map(|(e, used)| cq!(*used, None, Some(e.widen())))
+			.collect();
+		let mut costs: Vec<Option<E::Sum>> = vec![None; width as usize];
+		for y in 1..height {
+			for x in 0..width {
+				costs[x as usize] = None;
+				if used[(x, y)] {
+					continue;
+				}
+				let (left, right) = (x.saturating_sub(1), std::cmp::min(x + 1, width - 1));
+				let parent = cheapest(&above, left..=right);
+				if let Some(px) = parent {
+					parents[(x, y)] = px;
+					costs[x as usize] = above[px as usize].map(|c| c + energy[(x, y)].widen());
+				}
+			}
+			std::mem::swap(&mut above, &mut costs);
+		}
 
-	let maxheight = height - 1;
-	// For every subsequent column, populate the target cell with the sum
-	// of the *lowest adjacent leftmost energy* and the *y coordinate of
-	// that energy*
-	for x in 1..width {
-		for y in 0..height {
-			let erg = energy[(x, y)];
-			let range = cq!(y == 0, 0, y - 1)..=cq!(y == maxheight, maxheight, y + 1);
-			let parent_y = range.min_by_key(|y| target[(x - 1, *y)].energy).unwrap();
-			let parent = target[(x - 1, parent_y)];
-			target[(x, y)] = EnergyAndBackPointer {
-				energy: erg + parent.energy,
-				parent: parent_y,
-			};
+		let bottom = cheapest(&above, 0..width);
+		let mut seam_col = match bottom {
+			Some(x) => x,
+			None => break,
+		};
+		let mut seam = vec![0; height as usize];
+		for y in (0..height).rev() {
+			seam[y as usize] = seam_col;
+			used[(seam_col, y)] = true;
+			seam_col = parents[(seam_col, y)];
 		}
+		seams.push(seam);
 	}
+	seams
+}
 
-	// Find the y coordinate of the rightmost seam with the least
-	// energy.
-	let mut seam_col = (0..height)
-		.min_by_key(|x| target[(width - 1, *x)].energy)
-		.unwrap();
-	// Working backwards, generate a vec of y coordinates that map to
-	// the seam, reverse and return.
-	(0..width)
-		.rev()
-		.fold(Vec::<u32>::with_capacity(width as usize), |mut acc, x| {
-			acc.push(seam_col);
-			seam_col = target[(x, seam_col)].parent;
-			acc
-		})
-		.into_iter()
-		.rev()
-		.collect()
+// The position of the cheapest cost among the candidates, skipping
+// those with no cost.  As elsewhere, the leftmost wins a tie.
+fn cheapest<C: PartialOrd + Copy>(
+	costs: &[Option<C>],
+	candidates: impl Iterator<Item = u32>,
+) -> Option<u32> {
+	candidates.fold(None, |best, x| match (costs[x as usize], best) {
+		(None, _) => best,
+		(Some(_), None) => Some(x),
+		(Some(cost), Some(b)) => cq!(Some(cost) < costs[b as usize], Some(x), best),
+	})
+}
+
+/// As `energy_to_disjoint_vertical_seams`, for horizontal seams.
+pub fn energy_to_disjoint_horizontal_seams<E: EnergyScalar>(
+	energy: &TwoDimensionalMap<E>,
+	count: usize,
+) -> Vec<Vec<u32>> {
+	energy_to_disjoint_vertical_seams(&energy.transposed(), count)
 }
 
-/// The basic seam enigen: just a simple image reference holder.
-pub struct AviShaOne<'a, I, P, S>
+/// The basic seam enigen: just a simple image reference holder.  The
+/// energy is found in the scalar type `E`, `u32` unless the engine is
+/// switched over with `in_domain`.
+pub struct AviShaOne<'a, I, P, S, E = u32>
 where
 	I: GenericImageView<Pixel = P>,
 	P: Pixel<Subpixel = S> + 'static,
 	S: Primitive + 'static,
+	E: EnergyScalar,
 {
 	image: &'a I,
+	options: EnergyOptions,
+	domain: PhantomData<E>,
 }
 
 impl<'a, I, P, S> AviShaOne<'a, I, P, S>
@@ -178,22 +540,234 @@ where
 {
 	/// Takes a reference to an image, and holds onto it.
 	pub fn new(image: &'a I) -> Self {
-		AviShaOne { image }
+		AviShaOne {
+			image,
+			options: EnergyOptions::default(),
+			domain: PhantomData,
+		}
 	}
 }
 
-impl<'a, I, P, S> SeamFinder for AviShaOne<'a, I, P, S>
+impl<'a, I, P, S, E> AviShaOne<'a, I, P, S, E>
 where
 	I: GenericImageView<Pixel = P>,
 	P: Pixel<Subpixel = S> + 'static,
 	S: Primitive + 'static,
+	E: EnergyScalar,
 {
-	fn find_horizontal_seam(&self) -> Vec<u32> {
-		energy_to_horizontal_seam(&calculate_energy(self.image))
+	/// Smooth the image with a Gaussian of the given sigma before
+	/// calculating its energy.  Sensor noise reads as a field of tiny
+	/// edges, which attracts seams into grainy flat areas; a sigma of
+	/// 1.0 to 2.0 is usually enough to quiet it.
+	pub fn with_blur(mut self, sigma: f32) -> Self {
+		self.options = self.options.blur(sigma);
+		self
+	}
+
+	/// Replace the energy adjustments wholesale.
+	pub fn with_options(mut self, options: EnergyOptions) -> Self {
+		self.options = options;
+		self
+	}
+
+	/// Keep the energy, and the running seam costs, in another scalar
+	/// type.  In `f32`, fractional weights and saliency scores are
+	/// multiplied in without being rounded to whole energies.
+	pub fn in_domain<F: EnergyScalar>(self) -> AviShaOne<'a, I, P, S, F> {
+		AviShaOne {
+			image: self.image,
+			options: self.options,
+			domain: PhantomData,
+		}
+	}
+
+	/// Find up to `count` vertical seams that share no pixels, from a
+	/// single energy map.  See `energy_to_disjoint_vertical_seams`.
+	pub fn find_disjoint_vertical_seams(&self, count: usize) -> Vec<Vec<u32>> {
+		energy_to_disjoint_vertical_seams(&self.energy(), count)
 	}
 
-	fn find_vertical_seam(&self) -> Vec<u32> {
-		energy_to_vertical_seam(&calculate_energy(self.image))
+	/// Find up to `count` horizontal seams that share no pixels, from
+	/// a single energy map.
+	pub fn find_disjoint_horizontal_seams(&self, count: usize) -> Vec<Vec<u32>> {
+		energy_to_disjoint_horizontal_seams(&self.energy(), count)
+	}
+
+	/// Find the `count` cheapest vertical seams, each ending at a
+	/// different column, cheapest first.  See
+	/// `energy_to_ranked_vertical_seams`.  The seams are found with the
+	/// plain DP through the whole energy map, whatever slope or
+	/// pyramid the options ask for.
+	pub fn find_vertical_seams_ranked(&self, count: usize) -> Vec<Seam> {
+		self.ranked(false, count)
+	}
+
+	/// Find the `count` cheapest horizontal seams, each ending at a
+	/// different row, cheapest first.
+	pub fn find_horizontal_seams_ranked(&self, count: usize) -> Vec<Seam> {
+		self.ranked(true, count)
+	}
+
+	/// The whole DP the seams running the given way are traced
+	/// through; see `CostTable`.  As with the ranked seams, it's the
+	/// plain DP through the whole energy map.
+	pub fn cost_table(&self, orientation: Orientation) -> CostTable {
+		let horizontal = orientation == Orientation::Horizontal;
+		let energy = self.energy();
+		let energy = cq!(horizontal, energy.transposed(), energy);
+		let mut record = TwoDimensionalMap::new(energy.width, energy.height);
+		let (_, parents) = vertical_costs(&energy, self.options.ties, Some(&mut record));
+		let costs = projection(&record);
+		CostTable {
+			orientation,
+			costs: cq!(horizontal, costs.transposed(), costs),
+			parents: cq!(horizontal, parents.transposed(), parents),
+		}
+	}
+
+	fn ranked(&self, horizontal: bool, count: usize) -> Vec<Seam> {
+		let energy = self.energy();
+		let ranked = cq!(
+			horizontal,
+			energy_to_ranked_horizontal_seams(&energy, count),
+			energy_to_ranked_vertical_seams(&energy, count)
+		);
+		let orientation = cq!(horizontal, Orientation::Horizontal, Orientation::Vertical);
+		ranked
+			.into_iter()
+			.map(|(path, cost)| {
+				Seam::traced(orientation, path, cost.to_f64(), self.image.dimensions())
+			})
+			.collect()
+	}
+
+	// The energy map the seams are found in, after smoothing and
+	// adjustment.
+	fn energy(&self) -> TwoDimensionalMap<E> {
+		let border = self.options.border;
+		let mut energy = match self.options.smooth(self.image) {
+			Some(image) => calculate_energy_as(&image, border),
+			None => calculate_energy_as(self.image, border),
+		};
+		energy.normalize(self.options.normalization);
+		self.options.fit(self.image).apply(&mut energy);
+		energy
+	}
+
+	// The seam found coarse to fine, if the options ask for it and the
+	// image is big enough.  Only the energies inside the corridor are
+	// calculated.
+	fn pyramid_seam(&self, horizontal: bool) -> Option<(Vec<u32>, f64, TwoDimensionalMap<f64>)> {
+		let find = |image: &ImageBuffer<P, Vec<S>>, options| {
+			let coarse = AviShaOne::new(image).with_options(options).in_domain::<E>();
+			cq!(
+				horizontal,
+				coarse.find_horizontal_seam(),
+				coarse.find_vertical_seam()
+			)
+			.into_path()
+		};
+		let bands = coarse_corridor(self.image, &self.options, horizontal, &find)?;
+		let adjustments = self.options.fit(self.image);
+		let lumas = match self.options.smooth(self.image) {
+			Some(image) => luma_plane(&image),
+			None => luma_plane(self.image),
+		};
+		let lumas = cq!(horizontal, lumas.transposed(), lumas);
+		let (max, border) = (luma_max::<S>(), self.options.border);
+		let energy = |x, y| {
+			let e = pixel_energy::<E>((&lumas, max), border, (x, y));
+			cq!(
+				horizontal,
+				adjustments.adjust(y, x, e),
+				adjustments.adjust(x, y, e)
+			)
+			.widen()
+		};
+		let (seam, costs) =
+			corridor_seam(lumas.width, &bands, &|x| energy(x, 0), &|x, y, _, cost| {
+				cost + energy(x, y)
+			});
+		let bottom = lumas.height - 1;
+		let cost = costs[(seam[bottom as usize], bottom)];
+		Some((seam, cost, costs))
+	}
+
+	// The seam, coarse to fine if the options ask for it, and the
+	// cumulative costs it was traced through if `keep` asks for them.
+	// Within a pyramid's corridor, or with a constrained slope, the
+	// costs come along anyway.  A horizontal seam is found as a
+	// vertical one through the transposed energy map.
+	fn seam(&self, horizontal: bool, keep: bool) -> SeamAndCosts {
+		let (path, cost, costs) = match self.pyramid_seam(horizontal) {
+			Some((path, cost, costs)) => (path, cost, Some(costs)),
+			None => {
+				let energy = self.energy();
+				let energy = cq!(horizontal, energy.transposed(), energy);
+				let (path, costs) = match self.options.slope {
+					Some(rows) => {
+						let (path, costs) = sloped_vertical_seam(&energy, rows);
+						(path, Some(projection(&costs)))
+					}
+					None if keep => {
+						let mut record = TwoDimensionalMap::new(energy.width, energy.height);
+						let ties = self.options.ties;
+						let (bottom, parents) = vertical_costs(&energy, ties, Some(&mut record));
+						let path = trace_vertical_seam(&parents, ties.end_column(&bottom));
+						(path, Some(projection(&record)))
+					}
+					None => (energy_to_vertical_seam_by(&energy, self.options.ties), None),
+				};
+				let cost = (path.iter().enumerate())
+					.map(|(y, &x)| energy[(x, y as u32)].to_f64())
+					.sum();
+				(path, cost, costs)
+			}
+		};
+		let orientation = cq!(horizontal, Orientation::Horizontal, Orientation::Vertical);
+		let seam = Seam::traced(orientation, path, cost, self.image.dimensions());
+		let costs = costs.map(|costs| cq!(horizontal, costs.transposed(), costs));
+		(seam, costs)
+	}
+}
+
+impl<'a, I, P, S, E> SeamFinder for AviShaOne<'a, I, P, S, E>
+where
+	I: GenericImageView<Pixel = P>,
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+	E: EnergyScalar,
+{
+	fn find_horizontal_seam(&self) -> Seam {
+		self.seam(true, false).0
+	}
+
+	fn find_vertical_seam(&self) -> Seam {
+		self.seam(false, false).0
+	}
+
+	fn find_horizontal_seam_with_costs(&self) -> SeamAndCosts {
+		self.seam(true, true)
+	}
+
+	fn find_vertical_seam_with_costs(&self) -> SeamAndCosts {
+		self.seam(false, true)
+	}
+
+	// The energy map is found once for the whole batch.
+	fn find_seams(&self, orientation: Orientation, count: usize) -> Vec<Seam> {
+		let energy = self.energy();
+		let horizontal = orientation == Orientation::Horizontal;
+		let energy = cq!(horizontal, energy.transposed(), energy);
+		energy_to_disjoint_vertical_seams(&energy, count)
+			.into_iter()
+			.map(|path| {
+				let cost = (path.iter().enumerate())
+					.map(|(y, &x)| energy[(x, y as u32)].to_f64())
+					.sum();
+				Seam::traced(orientation, path, cost, self.image.dimensions())
+			})
+			.collect()
 	}
 }
 
@@ -201,7 +775,8 @@ where
 mod tests {
 	/// Given an image, calculate an energy grid.
 	use super::*;
-	use image::{ImageBuffer, Luma};
+	use crate::error::CarveError;
+	use image::{imageops, ImageBuffer, Luma};
 
 	const IMAGE_DATA: [u8; 20] = [9, 9, 0, 9, 9, 9, 1, 9, 8, 9, 9, 9, 9, 9, 0, 9, 9, 9, 0, 9];
 	const IMAGE_ENERGY: [u32; 20] = [
@@ -218,23 +793,306 @@ mod tests {
 
 	#[test]
 	fn energy_grid_to_vertical_seam() {
-		let energies = TwoDimensionalMap {
-			width: 5,
-			height: 4,
-			energy: ENERGY_DATA.to_vec(),
-		};
+		let energies = TwoDimensionalMap::from_vec(5, 4, ENERGY_DATA.to_vec()).unwrap();
 		let expected = [2, 3, 4, 3];
 		assert_eq!(energy_to_vertical_seam(&energies), expected);
 	}
 
 	#[test]
 	fn energy_grid_to_horizontal_seam() {
-		let energies = TwoDimensionalMap {
-			width: 5,
-			height: 4,
-			energy: ENERGY_DATA.to_vec(),
-		};
+		let energies = TwoDimensionalMap::from_vec(5, 4, ENERGY_DATA.to_vec()).unwrap();
 		let expected = [0, 1, 0, 1, 2];
 		assert_eq!(energy_to_horizontal_seam(&energies), expected);
 	}
+
+	#[test]
+	fn cumulative_costs_are_kept_on_request() {
+		let energies = TwoDimensionalMap::from_vec(5, 4, ENERGY_DATA.to_vec()).unwrap();
+		let costs = energy_to_vertical_costs(&energies);
+		assert_eq!(costs.row(1), &[18, 1, 9, 8, 18]);
+		assert_eq!(costs.row(3), &[19, 19, 19, 8, 17]);
+
+		let buf: ImageBuffer<Luma<u8>, _> = ImageBuffer::from_raw(5, 4, &IMAGE_DATA[..]).unwrap();
+		let finder = AviShaOne::new(&buf);
+		let (seam, costs) = finder.find_horizontal_seam_with_costs();
+		let costs = costs.unwrap();
+		assert_eq!((costs.width, costs.height), (5, 4));
+		assert_eq!(seam, finder.find_horizontal_seam());
+		let right = (0..4).map(|y| costs[(4, y)]).fold(f64::INFINITY, f64::min);
+		assert_eq!(right, seam.cost());
+	}
+
+	#[test]
+	fn cost_tables_trace_every_seam() {
+		let pixel = |x: u32, y: u32| Luma([((x * 53 + y * 29 + x * y * 7) % 256) as u8]);
+		let buf: ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_fn(9, 6, pixel);
+		let finder = AviShaOne::new(&buf);
+		for orientation in [Orientation::Vertical, Orientation::Horizontal].iter() {
+			let table = finder.cost_table(*orientation);
+			let ranked = cq!(
+				*orientation == Orientation::Vertical,
+				finder.find_vertical_seams_ranked(1),
+				finder.find_horizontal_seams_ranked(1)
+			);
+			let across = cq!(*orientation == Orientation::Vertical, 9, 6);
+			let best = (0..across)
+				.map(|end| table.trace(end).unwrap())
+				.fold(None, |best: Option<Seam>, seam| match best {
+					Some(b) if b.cost() <= seam.cost() => Some(b),
+					_ => Some(seam),
+				})
+				.unwrap();
+			assert_eq!(best.path(), ranked[0].path());
+			assert_eq!(
+				table.trace(across),
+				Err(CarveError::NoSeamEnd {
+					end: across,
+					ends: across
+				})
+			);
+
+			// Every seam passes through one pixel of every row, and the
+			// counts of a row are the seams.
+			let confluence = table.confluence();
+			let total: u32 = confluence.energy.iter().sum();
+			let along = cq!(*orientation == Orientation::Vertical, 6, 9);
+			assert_eq!(total, across * along);
+		}
+	}
+
+	#[test]
+	fn border_policies_change_edge_energy() {
+		let buf: ImageBuffer<Luma<u8>, _> = ImageBuffer::from_raw(5, 4, &IMAGE_DATA[..]).unwrap();
+		let clamped = calculate_energy_with_border(&buf, BorderPolicy::Clamp);
+		assert_eq!(clamped.energy, IMAGE_ENERGY);
+		// Pixel (0, 0) is 9, with 9 to its right and 9 below.  Mirrored,
+		// both of its gradients vanish; wrapped, its left neighbor is
+		// (4, 0) = 9 and the one above is (0, 3) = 9, so they also
+		// vanish; against black, both gradients are 9.
+		assert_eq!(
+			calculate_energy_with_border(&buf, BorderPolicy::Mirror)[(0, 0)],
+			0
+		);
+		assert_eq!(
+			calculate_energy_with_border(&buf, BorderPolicy::Wrap)[(0, 0)],
+			0
+		);
+		assert_eq!(
+			calculate_energy_with_border(&buf, BorderPolicy::Zero)[(0, 0)],
+			162
+		);
+		// Pixel (2, 0) is 0, between two 9s, with 9 below it.  Clamped,
+		// the vertical gradient is 9 - 0; mirrored, it's 9 - 9.
+		assert_eq!(clamped[(2, 0)], 81);
+		assert_eq!(
+			calculate_energy_with_border(&buf, BorderPolicy::Mirror)[(2, 0)],
+			0
+		);
+	}
+
+	#[test]
+	fn tall_seams_do_not_wrap_their_costs() {
+		// Summed straight down, the first two columns pass u32::MAX and
+		// would wrap to less than the third.
+		let height = 40_000;
+		let energies = TwoDimensionalMap::from_vec(
+			3,
+			height,
+			(0..3 * height)
+				.map(|i| [200_000u32, 150_000, 100_000][i as usize % 3])
+				.collect(),
+		)
+		.unwrap();
+		assert!(energy_to_vertical_seam(&energies).iter().all(|x| *x == 2));
+	}
+
+	#[test]
+	fn blur_quiets_isolated_noise() {
+		let mut buf: ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_pixel(9, 9, Luma([100]));
+		buf.put_pixel(4, 4, Luma([200]));
+		let sharp = calculate_energy(&buf);
+		let soft = calculate_energy(&imageops::blur(&buf, 1.5));
+		let peak = |m: &TwoDimensionalMap<u32>| *m.energy.iter().max().unwrap();
+		assert!(peak(&soft) < peak(&sharp) / 4);
+		assert_eq!(
+			AviShaOne::new(&buf)
+				.with_blur(1.5)
+				.find_vertical_seam()
+				.len(),
+			9
+		);
+	}
+
+	#[test]
+	fn importance_map_steers_the_seam() {
+		let buf: ImageBuffer<Luma<u8>, Vec<u8>> =
+			ImageBuffer::from_fn(8, 6, |x, y| Luma([((x * 37 + y * 91) % 256) as u8]));
+		let importance: ImageBuffer<Luma<u8>, Vec<u8>> =
+			ImageBuffer::from_fn(4, 3, |x, _| Luma([if x == 3 { 0 } else { 255 }]));
+		let seam = AviShaOne::new(&buf)
+			.with_options(EnergyOptions::new().importance(&importance))
+			.find_vertical_seam();
+		assert!(seam.iter().all(|x| *x >= 6), "{:?}", seam);
+	}
+
+	#[test]
+	fn float_energies_match_the_integer_ones() {
+		let buf: ImageBuffer<Luma<u8>, _> = ImageBuffer::from_raw(5, 4, &IMAGE_DATA[..]).unwrap();
+		let energy: TwoDimensionalMap<f32> = calculate_energy_as(&buf, BorderPolicy::Clamp);
+		for (float, int) in energy.energy.iter().zip(IMAGE_ENERGY.iter()) {
+			assert!((float * 255.0 * 255.0 - *int as f32).abs() < 1e-3);
+		}
+		let seam = AviShaOne::new(&buf).find_vertical_seam();
+		assert_eq!(
+			AviShaOne::new(&buf).in_domain::<f32>().find_vertical_seam(),
+			seam
+		);
+	}
+
+	#[test]
+	fn float_weights_survive_without_quantization() {
+		let buf: ImageBuffer<Luma<u8>, Vec<u8>> =
+			ImageBuffer::from_fn(8, 6, |x, y| Luma([((x * 37 + y * 91) % 256) as u8]));
+		// Small enough that every integer energy rounds to zero.
+		let weights = TwoDimensionalMap::from_vec(
+			8,
+			6,
+			(0..48)
+				.map(|i| if i % 8 == 3 { 1e-9 } else { 2e-6 })
+				.collect(),
+		)
+		.unwrap();
+		let options = EnergyOptions::new().weights(&weights);
+		let float = AviShaOne::new(&buf)
+			.with_options(options.clone())
+			.in_domain::<f32>()
+			.find_vertical_seam();
+		assert_eq!(float.path(), [3; 6]);
+		let int = AviShaOne::new(&buf)
+			.with_options(options)
+			.find_vertical_seam();
+		assert_ne!(int.path(), [3; 6]);
+	}
+
+	#[test]
+	fn disjoint_seams_share_no_pixels() {
+		let mut energy: TwoDimensionalMap<u32> = TwoDimensionalMap::new(7, 9);
+		for y in 0..9 {
+			for x in 0..7 {
+				energy[(x, y)] = (x * 7 + y * 3) % 5;
+			}
+		}
+		let seams = energy_to_disjoint_vertical_seams(&energy, 4);
+		assert_eq!(seams.len(), 4);
+		assert_eq!(seams[0], energy_to_vertical_seam(&energy));
+		for y in 0..9 {
+			let mut row: Vec<u32> = seams.iter().map(|seam| seam[y]).collect();
+			row.sort();
+			row.dedup();
+			assert_eq!(row.len(), 4, "row {}: {:?}", y, row);
+		}
+		for seam in &seams {
+			assert!(seam
+				.windows(2)
+				.all(|w| (w[0] as i64 - w[1] as i64).abs() <= 1));
+		}
+		assert!(energy_to_disjoint_vertical_seams(&energy, 9).len() <= 7);
+	}
+
+	#[test]
+	fn batched_seams_come_from_one_energy_map() {
+		let buf: ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_fn(9, 7, |x, y| {
+			Luma([((x * 53 + y * 29 + x * y * 7) % 256) as u8])
+		});
+		let finder = AviShaOne::new(&buf);
+		let seams = finder.find_seams(Orientation::Vertical, 3);
+		assert_eq!(seams.len(), 3);
+		assert_eq!(seams[0], finder.find_vertical_seam());
+		let energy = calculate_energy(&buf);
+		assert_eq!(
+			seams
+				.iter()
+				.map(|seam| seam.clone().into_path())
+				.collect::<Vec<_>>(),
+			energy_to_disjoint_vertical_seams(&energy, 3)
+		);
+		let across = finder.find_seams(Orientation::Horizontal, 2);
+		assert!(across.iter().all(|seam| seam.len() == 9));
+	}
+
+	#[test]
+	fn ranked_seams_start_with_the_cheapest() {
+		let buf: ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_fn(9, 7, |x, y| {
+			Luma([((x * 53 + y * 29 + x * y * 7) % 256) as u8])
+		});
+		let finder = AviShaOne::new(&buf);
+		let ranked = finder.find_vertical_seams_ranked(4);
+		assert_eq!(ranked.len(), 4);
+		assert_eq!(ranked[0], finder.find_vertical_seam());
+		assert!(ranked.windows(2).all(|w| w[0].cost() <= w[1].cost()));
+		let energy = calculate_energy(&buf);
+		for seam in &ranked {
+			let total: u32 = seam.pixels().map(|at| energy[at]).sum();
+			assert_eq!(seam.cost(), f64::from(total));
+		}
+		let mut ends: Vec<u32> = ranked.iter().map(|seam| seam[6]).collect();
+		ends.sort();
+		ends.dedup();
+		assert_eq!(ends.len(), 4);
+		assert_eq!(finder.find_horizontal_seams_ranked(20).len(), 7);
+	}
+
+	#[test]
+	fn a_wide_corridor_finds_an_equally_cheap_seam() {
+		let pixel = |x: u32, y: u32| Luma([((x * 53 + y * 29 + x * y * 7) % 256) as u8]);
+		let buf: ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_fn(16, 12, pixel);
+		let energy = calculate_energy(&buf);
+		let cost = |seam: &[u32]| -> u32 { (0..12).map(|y| energy[(seam[y], y as u32)]).sum() };
+		let full = AviShaOne::new(&buf).find_vertical_seam();
+		let coarse = AviShaOne::new(&buf)
+			.with_options(EnergyOptions::new().pyramid(4, 16))
+			.find_vertical_seam();
+		assert_eq!(cost(&full), cost(&coarse));
+		let narrow = AviShaOne::new(&buf)
+			.with_options(EnergyOptions::new().pyramid(4, 1))
+			.find_horizontal_seam();
+		assert_eq!(narrow.len(), 16);
+		assert!(narrow
+			.windows(2)
+			.all(|w| (w[0] as i64 - w[1] as i64).abs() <= 1));
+	}
+
+	#[test]
+	fn patched_energies_match_recalculated_ones() {
+		let pixel = |x: u32, y: u32| Luma([((x * x * 11 + y * 23 + x * y * 5) % 256) as u8]);
+		let buf: ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_fn(14, 10, pixel);
+		let adjust = |_, _, e| e;
+		let borders = [
+			BorderPolicy::Clamp,
+			BorderPolicy::Mirror,
+			BorderPolicy::Wrap,
+			BorderPolicy::Zero,
+		];
+		for border in borders.iter() {
+			let mut lumas = luma_plane(&buf);
+			let mut table = EnergyTable::<u64>::new(lumas.clone(), 255, *border);
+			let mut scratch = CarveScratch::new();
+			for _ in 0..10 {
+				let fresh = plane_energy::<u64>((&lumas, 255), *border);
+				assert_eq!(table.energy.energy, fresh.energy, "{:?}", border);
+				let seam = table.seam(TieBreak::Leftmost, &adjust, &mut scratch);
+				assert_eq!(seam, energy_to_vertical_seam(&fresh));
+				let mut carved = TwoDimensionalMap::new(lumas.width - 1, lumas.height);
+				for y in 0..lumas.height {
+					let row: Vec<u32> = (0..lumas.width)
+						.filter(|&x| x != seam[y as usize])
+						.map(|x| lumas[(x, y)])
+						.collect();
+					carved.row_mut(y).copy_from_slice(&row);
+				}
+				lumas = carved;
+				table.remove(&seam);
+			}
+		}
+	}
 }
@@ -5,19 +5,45 @@
 //! Calculate the energy of an image using the Avidan & Shamir
 //! "Forward Energy" algorithm.
 //!
-//! Given an image, calucate the energy map and either a horizontal or
-//! vertical seam for that image.  Currently uses the most
-//! straightforward of the energy map algorithms, the one with no
-//! forward energy calculation, although that is coming.
-
-use crate::flipper::Flipper;
-use crate::pixelpairs::energy_of_pair_luma as energy_of_pixel_pair;
-use crate::seamfinder::SeamFinder;
+//! Rather than the energy of the pixels a seam passes through, the
+//! cost of a step is the energy its removal would bring into the
+//! image: the difference between the pixels left newly side by side.
+//! The costs are found from a plane of lumas, and kept in a table of
+//! cells, each a running cost in the energy's summing type and a
+//! one-byte step to the column of the row above it came from.  The
+//! table is filled a row at a time, or with the `threaded` feature in
+//! tiles along a wavefront, and the seam traced back up its steps.
+//!
+//! The energy options' adjustments, importance maps and masks among
+//! them, are made to the cost of each step, not to the path to it.  A
+//! `ForwardTable` is kept from seam to seam, and only the cells whose
+//! costs a removal changed are worked again.
+
+use crate::banded::corridor_seam;
+use crate::cancel::CancelToken;
+use crate::energyopts::EnergyOptions;
+use crate::pixelpairs::{luma_max, luma_plane};
+use crate::pool;
+use crate::pyramid::coarse_corridor;
+use crate::report::StageTimings;
+use crate::sampling::near_optimal;
+use crate::scalar::EnergyScalar;
+use crate::seamfinder::{CostTable, Orientation, Seam, SeamAndCosts, SeamFinder};
+use crate::slope::sloped_seam;
 use crate::twodmap::{EnergyAndBackPointer, TwoDimensionalMap};
 
-use image::{GenericImageView, Pixel, Primitive};
+use crate::cq;
+use crate::dprow::TieBreak;
+use image::{GenericImageView, ImageBuffer, Pixel, Primitive};
+#[cfg(feature = "threaded")]
+use rayon::prelude::*;
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::marker::PhantomData;
+use std::time::{Duration, Instant};
 
-type EnergyMap = TwoDimensionalMap<EnergyAndBackPointer<u32>>;
+// The running costs, kept in the energy's summing type.
+type EnergyMap<C> = TwoDimensionalMap<EnergyAndBackPointer<C>>;
 
 // 1. Given a pixel coordinate *not* in the first row,
 // 2. There exist three possible seams to which that pixel contributes,
@@ -52,113 +78,372 @@ type EnergyMap = TwoDimensionalMap<EnergyAndBackPointer<u32>>;
 //           ⎩ M(x+1,y−1)+CR(x,y)
 //
 
-fn cost_candidate_pixel<I, P, S>(
-	image: &I,
-	energy: &EnergyMap,
+// The cost of reaching (x, y) from column `x_above` of the row above:
+// the new neighbors the removal brings together.  The lumas of the
+// image are passed in rather than the image, along with the largest
+// luma its pixels can have.
+fn transition_cost<E: EnergyScalar>(
+	(lumas, max): (&TwoDimensionalMap<u32>, u32),
 	(x, y): (u32, u32),
-) -> EnergyAndBackPointer<u32>
-where
-	I: GenericImageView<Pixel = P>,
-	P: Pixel<Subpixel = S> + 'static,
-	S: Primitive + 'static,
-{
-	let epp = |(x1, y1), (x2, y2)| {
-		energy_of_pixel_pair(&image.get_pixel(x1, y1), &image.get_pixel(x2, y2))
+	x_above: u32,
+) -> E::Sum {
+	let (row, up) = (lumas.row(y), lumas.row(y - 1));
+	let cost_up = up_cost::<E>((up, max), x);
+	cq!(
+		x_above == x,
+		cost_up,
+		cost_up + side_cost::<E>((row, up, max), x, x_above)
+	)
+}
+
+// The part of every transition into column x that doesn't depend on
+// where it came from: the neighbors in the row above that the removal
+// brings together.  `up` is the row above's lumas.
+#[inline]
+fn up_cost<E: EnergyScalar>((up, max): (&[u32], u32), x: u32) -> E::Sum {
+	let (x, last) = (x as usize, up.len() - 1);
+	let (left, right) = match x {
+		0 => (0, std::cmp::min(1, last)),
+		_ if x == last => (x - 1, x),
+		_ => (x - 1, x + 1),
 	};
+	E::of_lumas(up[left], up[right], max).widen()
+}
 
-	let y_above = y - 1;
-	let max_width = image.width() - 1;
+// The rest of a transition into column x from `x_above` beside it:
+// the pixel above brought together with the one the seam came from.
+#[inline]
+fn side_cost<E: EnergyScalar>(
+	(row, up, max): (&[u32], &[u32], u32),
+	x: u32,
+	x_above: u32,
+) -> E::Sum {
+	E::of_lumas(up[x as usize], row[x_above as usize], max).widen()
+}
 
-	let cost_up = if x == 0 {
-		epp((x, y_above), (x + 1, y_above))
-	} else if x == max_width {
-		epp((x - 1, y_above), (x, y_above))
-	} else {
-		epp((x - 1, y_above), (x + 1, y_above))
-	};
+// The cost of a seam starting at column x of the top row.
+fn first_row_cost<E: EnergyScalar>((lumas, max): (&TwoDimensionalMap<u32>, u32), x: u32) -> E::Sum {
+	let mw = lumas.width - 1;
+	let (xl, xr) = (x.saturating_sub(1), cq!(x == mw, mw, x + 1));
+	E::of_lumas(lumas[(xl, 0)], lumas[(xr, 0)], max).widen()
+}
 
-	let mut current_cost = EnergyAndBackPointer {
-		energy: cost_up + energy[(x, y_above)].energy,
-		parent: x,
+// The cost of reaching a pixel, from the costs of the row above it,
+// looked up by column.
+fn cost_candidate_pixel<E: EnergyScalar>(
+	(lumas, max): (&TwoDimensionalMap<u32>, u32),
+	above: impl Fn(u32) -> E::Sum,
+	(x, y): (u32, u32),
+	ties: TieBreak,
+	adjust: &(impl Fn(u32, u32, E::Sum) -> E::Sum + Sync + ?Sized),
+) -> EnergyAndBackPointer<E::Sum> {
+	let rows = (lumas.row(y), lumas.row(y - 1), max);
+	cost_candidate_at::<E>(rows, above, (x, y), ties, adjust)
+}
+
+// As `cost_candidate_pixel`, with the lumas of the pixel's row and the
+// row above given as slices, wherever they came from.  The DP's loops
+// take the rows out of the plane once a row, rather than finding them
+// again, and checking both coordinates against the plane, for every
+// lookup of every pixel; the part of the transitions the three
+// candidates share is found once.  On the forward-energy benchmark in
+// `benches/dp.rs` that took a 640x480 seam from about 17ms to 11ms,
+// with no unchecked indexing.
+#[inline]
+fn cost_candidate_at<E: EnergyScalar>(
+	(row, up, max): (&[u32], &[u32], u32),
+	above: impl Fn(u32) -> E::Sum,
+	(x, y): (u32, u32),
+	ties: TieBreak,
+	adjust: &(impl Fn(u32, u32, E::Sum) -> E::Sum + Sync + ?Sized),
+) -> EnergyAndBackPointer<E::Sum> {
+	let max_width = row.len() as u32 - 1;
+	let cost_up = up_cost::<E>((up, max), x);
+	// Each step's own energy is adjusted before it's added to the cost
+	// of the seam it extends.
+	let cost = |x_above: u32| {
+		let step = cost_up + side_cost::<E>((row, up, max), x, x_above);
+		above(x_above) + adjust(x, y, step)
 	};
 
-	let ccc = |x_above, current_cost: EnergyAndBackPointer<u32>| {
-		let n = cost_up + energy[(x_above, y_above)].energy + epp((x, y_above), (x_above, y));
-		if n < current_cost.energy {
-			EnergyAndBackPointer {
-				energy: n,
-				parent: x_above,
-			}
-		} else {
-			current_cost
+	// A missing neighbor stands in as the center, as in the plain DP.
+	let center = above(x) + adjust(x, y, cost_up);
+	let left = cq!(x != 0, cost(x.saturating_sub(1)), center);
+	let right = cq!(x != max_width, cost(x + 1), center);
+	let (cost, parent) = ties.pick(y, (left, center, right), x, max_width);
+	EnergyAndBackPointer::new(cost, x, parent)
+}
+
+// How the energy of each step of a seam is adjusted before it's added
+// to the cost of the seam so far: an importance map scales the step
+// into a pixel, not everything the seam crossed to get there.  The
+// adjustment is shared between the threads working on a row when the
+// carve is threaded.
+pub(crate) type Adjust<'a, C> = dyn Fn(u32, u32, C) -> C + Sync + 'a;
+
+// With the `threaded` feature on, each row of the DP is shared out in
+// segments among rayon's threads, which are started once and kept for
+// every row of every seam.  Narrow rows aren't worth the handoff.
+#[cfg(feature = "threaded")]
+const PARALLEL_ROW_WIDTH: u32 = 512;
+#[cfg(feature = "threaded")]
+const ROW_SEGMENT: usize = 128;
+
+// Planes that wide and more than two tiles tall are worked in square
+// tiles of this size instead; see `calculate_cost_wavefront`.
+#[cfg(feature = "threaded")]
+const TILE: u32 = 64;
+
+// The costs of row y of a plane, from the costs of the row above it,
+// with the lumas a strip of the plane's rows: the row's at row `at`,
+// and the row above's just before it.  The first row of the plane has
+// no row above, and must be the first of its strip.
+pub(crate) fn strip_row_costs<E: EnergyScalar>(
+	(strip, max): (&TwoDimensionalMap<u32>, u32),
+	(at, y): (u32, u32),
+	above: &[E::Sum],
+	ties: TieBreak,
+	row: &mut [EnergyAndBackPointer<E::Sum>],
+) {
+	if y == 0 {
+		for (x, cell) in (0..strip.width).zip(row.iter_mut()) {
+			*cell = EnergyAndBackPointer::new(first_row_cost::<E>((strip, max), x), x, x);
 		}
-	};
+		return;
+	}
+	let rows = (strip.row(at), strip.row(at - 1), max);
+	let above = |x_above: u32| above[x_above as usize];
+	for (x, cell) in (0..strip.width).zip(row.iter_mut()) {
+		*cell = cost_candidate_at::<E>(rows, above, (x, y), ties, &|_, _, cost| cost);
+	}
+}
 
-	if x != 0 {
-		current_cost = ccc(x - 1, current_cost)
+// The forward-energy costs of a plane, unadjusted with ties going
+// left, and the column of the row above each cost came from: what a
+// compute backend finds, for a plane too large for its device.
+pub(crate) fn plane_costs<E: EnergyScalar>(
+	lumas: &TwoDimensionalMap<u32>,
+	max: u32,
+) -> (TwoDimensionalMap<E::Sum>, TwoDimensionalMap<u32>) {
+	let emap = calculate_cost::<E>(lumas, max, TieBreak::Leftmost, &|_, _, cost| cost);
+	let mut costs = TwoDimensionalMap::new(emap.width, emap.height);
+	let mut parents = TwoDimensionalMap::new(emap.width, emap.height);
+	for y in 0..emap.height {
+		for x in 0..emap.width {
+			let cell = emap[(x, y)];
+			costs[(x, y)] = cell.energy;
+			parents[(x, y)] = cell.parent(x);
+		}
 	}
+	(costs, parents)
+}
 
-	if x != max_width {
-		current_cost = ccc(x + 1, current_cost)
-	};
+// The adjustment function is handed the (x, y) of each pixel as the
+// luma plane passed in sees it, along with the energy of a step into
+// the pixel, and returns the energy to add to the seam's cost.  The
+// DP runs over the plane rather than the image, so a horizontal pass
+// can be made over a transposed copy of the plane, contiguous in
+// memory, instead of turning every pixel access of the image on its
+// side.
+fn calculate_cost<E: EnergyScalar>(
+	lumas: &TwoDimensionalMap<u32>,
+	max: u32,
+	ties: TieBreak,
+	adjust: &(impl Fn(u32, u32, E::Sum) -> E::Sum + Sync + ?Sized),
+) -> EnergyMap<E::Sum> {
+	calculate_cost_until::<E>(lumas, max, ties, adjust, None)
+}
 
-	current_cost
+// As `calculate_cost`, abandoned between rows once the token is
+// raised.  What's left of the table is meaningless, and the caller
+// must look at the token again before using it.  The table is filled
+// on the carve's pool of threads, if it has one.
+fn calculate_cost_until<E: EnergyScalar>(
+	lumas: &TwoDimensionalMap<u32>,
+	max: u32,
+	ties: TieBreak,
+	adjust: &(impl Fn(u32, u32, E::Sum) -> E::Sum + Sync + ?Sized),
+	cancel: Option<&CancelToken>,
+) -> EnergyMap<E::Sum> {
+	pool::install(|| fill_cost_table::<E>((lumas, max), ties, adjust, cancel))
 }
 
-fn calculate_cost<I, P, S>(image: &I) -> EnergyMap
-where
-	I: GenericImageView<Pixel = P>,
-	P: Pixel<Subpixel = S> + 'static,
-	S: Primitive + 'static,
-{
-	let (width, height) = image.dimensions();
+fn fill_cost_table<E: EnergyScalar>(
+	(lumas, max): (&TwoDimensionalMap<u32>, u32),
+	ties: TieBreak,
+	adjust: &(impl Fn(u32, u32, E::Sum) -> E::Sum + Sync + ?Sized),
+	cancel: Option<&CancelToken>,
+) -> EnergyMap<E::Sum> {
+	let (width, height) = (lumas.width, lumas.height);
 	let mut emap = EnergyMap::new(width, height);
-	let mw = width - 1;
-
-	let nebp = |(xl, yl), (xr, yr), parent| EnergyAndBackPointer {
-		energy: energy_of_pixel_pair(&image.get_pixel(xl, yl), &image.get_pixel(xr, yr)),
-		parent: parent,
-	};
-
-	// The upper corners are super-special cases!
-	emap[(0, 0)] = nebp((0, 0), (1, 0), 0);
-	emap[(mw, 0)] = nebp((mw - 1, 0), (mw, 0), 0);
+	for x in 0..width {
+		let cost = adjust(x, 0, first_row_cost::<E>((lumas, max), x));
+		emap[(x, 0)] = EnergyAndBackPointer::new(cost, x, x);
+	}
 
-	// The top row is a special case.  Using the RangeInclusive
-	// operator to make explicit that I'm avoiding the corners.
-	for x in 1..=(mw - 1) {
-		emap[(x, 0)] = nebp((x - 1, 0), (x + 1, 0), 0);
+	#[cfg(feature = "threaded")]
+	{
+		if width >= PARALLEL_ROW_WIDTH && height > 2 * TILE {
+			calculate_cost_wavefront::<E>((lumas, max), ties, adjust, cancel, &mut emap);
+			return emap;
+		}
 	}
 
+	let stride = width as usize;
 	for y in 1..height {
-		for x in 0..width {
-			emap[(x, y)] = cost_candidate_pixel(image, &emap, (x, y));
+		if cancel.map_or(false, CancelToken::is_cancelled) {
+			break;
+		}
+		let (done, rest) = emap.split_at_row(y);
+		let above = &done[done.len() - stride..];
+		let rows = (lumas.row(y), lumas.row(y - 1), max);
+		let cell = |(x, cell): (usize, &mut EnergyAndBackPointer<E::Sum>)| {
+			let above = |x_above: u32| above[x_above as usize].energy;
+			*cell = cost_candidate_at::<E>(rows, above, (x as u32, y), ties, adjust);
+		};
+		let row = &mut rest[..stride];
+		#[cfg(feature = "threaded")]
+		{
+			if width >= PARALLEL_ROW_WIDTH {
+				let segments = row.par_iter_mut().enumerate();
+				segments.with_min_len(ROW_SEGMENT).for_each(cell);
+				continue;
+			}
 		}
+		row.iter_mut().enumerate().for_each(cell);
 	}
 	emap
 }
 
-// Again, the trick here is to divvy up the width into segments,
-// breaking the target into mut_chunks and readdressing them
-// afterward for each row.
+// Splitting each row among the threads costs a handoff per row, and
+// every thread waits at the end of each one for the slowest.  Each
+// pixel's cost depends only on the three above it, so on a plane
+// skewed a column left for every row down, (x, y) to (x + y, y), a
+// pixel depends only on pixels above it and to its left.  Cut into
+// square tiles, the skewed plane's tiles depend only on the tiles
+// above them, above and to the left, and to the left, and the tiles
+// along each anti-diagonal can all be worked at once: a wave of them
+// per handoff, rather than a row.  The first row of the table must be
+// filled in already.
+#[cfg(feature = "threaded")]
+fn calculate_cost_wavefront<E: EnergyScalar>(
+	(lumas, max): (&TwoDimensionalMap<u32>, u32),
+	ties: TieBreak,
+	adjust: &(impl Fn(u32, u32, E::Sum) -> E::Sum + Sync + ?Sized),
+	cancel: Option<&CancelToken>,
+	emap: &mut EnergyMap<E::Sum>,
+) {
+	let (width, height) = (lumas.width, lumas.height);
+	// Bands of rows from the second down, and columns of the skewed
+	// plane, one tile each.
+	let bands = (height - 1 + TILE - 1) / TILE;
+	let columns = (width + height - 1 + TILE - 1) / TILE;
+	for wave in 0..bands + columns - 1 {
+		if cancel.map_or(false, CancelToken::is_cancelled) {
+			return;
+		}
+		let tiles: Vec<(u32, u32)> = (0..bands)
+			.filter(|&band| band <= wave && wave - band < columns)
+			.map(|band| (wave - band, band))
+			.collect();
+		let costs: Vec<_> = tiles
+			.par_iter()
+			.map(|&tile| cost_tile::<E>((lumas, max), ties, adjust, emap, tile))
+			.collect();
+		for (&(column, band), cells) in tiles.iter().zip(costs) {
+			let mut cells = cells.into_iter();
+			for y in band_rows(band, height) {
+				let (left, right) = tile_span(column, y, width);
+				for cell in &mut emap.row_mut(y)[left as usize..right as usize] {
+					*cell = cells.next().unwrap();
+				}
+			}
+		}
+	}
+}
+
+// The rows of a band of tiles.
+#[cfg(feature = "threaded")]
+fn band_rows(band: u32, height: u32) -> std::ops::Range<u32> {
+	1 + band * TILE..std::cmp::min(1 + (band + 1) * TILE, height)
+}
+
+// The columns of the plane a column of skewed tiles covers in a row,
+// which may be none.
+#[cfg(feature = "threaded")]
+fn tile_span(column: u32, y: u32, width: u32) -> (u32, u32) {
+	let left = (column * TILE).saturating_sub(y);
+	let right = ((column + 1) * TILE).saturating_sub(y);
+	(std::cmp::min(left, width), std::cmp::min(right, width))
+}
+
+// The costs of one tile, row by row.  The rows above the tile's own
+// are read from the table, which has every tile of the earlier waves.
+#[cfg(feature = "threaded")]
+fn cost_tile<E: EnergyScalar>(
+	(lumas, max): (&TwoDimensionalMap<u32>, u32),
+	ties: TieBreak,
+	adjust: &(impl Fn(u32, u32, E::Sum) -> E::Sum + Sync + ?Sized),
+	emap: &EnergyMap<E::Sum>,
+	(column, band): (u32, u32),
+) -> Vec<EnergyAndBackPointer<E::Sum>> {
+	let mut cells: Vec<EnergyAndBackPointer<E::Sum>> = Vec::with_capacity((TILE * TILE) as usize);
+	// Where the tile's last row starts, in the plane and in `cells`.
+	let mut last: Option<(u32, usize)> = None;
+	for y in band_rows(band, lumas.height) {
+		let (left, right) = tile_span(column, y, lumas.width);
+		let start = cells.len();
+		let rows = (lumas.row(y), lumas.row(y - 1), max);
+		for x in left..right {
+			let above = |x_above: u32| match last {
+				Some((from, at)) if x_above >= from && (x_above - from) as usize + at < start => {
+					cells[at + (x_above - from) as usize].energy
+				}
+				_ => emap[(x_above, y - 1)].energy,
+			};
+			let cell = cost_candidate_at::<E>(rows, above, (x, y), ties, adjust);
+			cells.push(cell);
+		}
+		last = Some((left, start));
+	}
+	cells
+}
 
 /// Given an energy map, return the list of x-coordinates that, when
 /// mapped with the range (0..height), give the XY coordinates for each
 /// pixel in the seam to be removed.
-fn energy_to_seam(energy: &EnergyMap) -> Vec<u32> {
-	let (width, height) = (energy.width, energy.height);
+fn energy_to_seam<C: EnergyScalar>(energy: &EnergyMap<C>, ties: TieBreak) -> Vec<u32> {
+	// Trace back from the x coordinate of the bottommost seam with the
+	// least energy.
+	let bottom: Vec<C> = energy
+		.row(energy.height - 1)
+		.iter()
+		.map(|cell| cell.energy)
+		.collect();
+	trace_seam(energy, ties.end_column(&bottom))
+}
+
+// As `energy_to_seam`, but with the seam drawn from among the
+// near-optimal ones, given a tolerance and a draw.
+fn energy_to_sampled_seam<C: EnergyScalar>(energy: &EnergyMap<C>, pick: (f64, u64)) -> Vec<u32> {
+	let bottom: Vec<f64> = energy
+		.row(energy.height - 1)
+		.iter()
+		.map(|cell| cell.energy.to_f64())
+		.collect();
+	trace_seam(energy, near_optimal(&bottom, pick))
+}
 
-	// Find the x coordinate of the bottomost seam with the least energy.
-	let mut seam_col = (0..width)
-		.min_by_key(|x| energy[(*x, height - 1)].energy)
-		.unwrap();
+// The seam ending at the given column of the bottom row.
+fn trace_seam<C: EnergyScalar>(energy: &EnergyMap<C>, mut seam_col: u32) -> Vec<u32> {
+	let height = energy.height;
 	// Working backwards, generate a vec of x coordinates that that map to
 	// the seam, reverse and return.
 	(0..height)
 		.rev()
 		.fold(Vec::<u32>::with_capacity(height as usize), |mut acc, y| {
 			acc.push(seam_col);
-			seam_col = energy[(seam_col, y)].parent;
+			seam_col = energy[(seam_col, y)].parent(seam_col);
 			acc
 		})
 		.into_iter()
@@ -166,16 +451,297 @@ fn energy_to_seam(energy: &EnergyMap) -> Vec<u32> {
 		.collect()
 }
 
+// The costs alone, as floats.
+fn cost_projection<C: EnergyScalar>(costs: &EnergyMap<C>) -> TwoDimensionalMap<f64> {
+	let mut projection = TwoDimensionalMap::new(costs.width, costs.height);
+	for (cost, source) in projection.energy.iter_mut().zip(costs.energy.iter()) {
+		*cost = source.energy.to_f64();
+	}
+	projection
+}
+
+// The parents alone.
+fn parent_projection<C: EnergyScalar>(costs: &EnergyMap<C>) -> TwoDimensionalMap<u32> {
+	let mut projection = TwoDimensionalMap::new(costs.width, costs.height);
+	// The first row has no parents, and they're left at zero.
+	for y in 1..costs.height {
+		for (x, (parent, source)) in (projection.row_mut(y).iter_mut())
+			.zip(costs.row(y).iter())
+			.enumerate()
+		{
+			*parent = source.parent(x as u32);
+		}
+	}
+	projection
+}
+
+/// A forward-energy cost table kept from one seam to the next.
+/// Removing a seam only changes the costs of the pixels that were its
+/// neighbors, and of the pixels below them whose cheapest way down
+/// ran through one of those, so rather than recalculating every cost
+/// for the next seam the table patches that cone and leaves the rest.
+/// Only the adjustments that are carved along with the image, the
+/// fitted maps, may be used with it: anything that looks at the image
+/// as a whole, smoothing or edge walls or penalties, may change costs
+/// anywhere.
+pub(crate) struct ForwardTable<E: EnergyScalar> {
+	lumas: TwoDimensionalMap<u32>,
+	max: u32,
+	ties: TieBreak,
+	costs: EnergyMap<E::Sum>,
+}
+
+impl<E: EnergyScalar> ForwardTable<E> {
+	// The whole table, for a luma plane not yet carved, unless the
+	// token is raised first.
+	pub(crate) fn new(
+		lumas: TwoDimensionalMap<u32>,
+		max: u32,
+		ties: TieBreak,
+		adjust: &Adjust<E::Sum>,
+		cancel: Option<&CancelToken>,
+	) -> Self {
+		let costs = calculate_cost_until::<E>(&lumas, max, ties, adjust, cancel);
+		ForwardTable {
+			lumas,
+			max,
+			ties,
+			costs,
+		}
+	}
+
+	// The cheapest vertical seam through the plane, or one drawn from
+	// among the nearly cheapest.
+	pub(crate) fn seam(&self, pick: Option<(f64, u64)>) -> Vec<u32> {
+		match pick {
+			Some(pick) => energy_to_sampled_seam(&self.costs, pick),
+			None => energy_to_seam(&self.costs, self.ties),
+		}
+	}
+
+	// The cheapest seam through the plane, and up to `count - 1` more:
+	// the cheapest of the rest, each kept `APART` columns from the
+	// seams taken before it in every row, so that their removals may
+	// be patched apart.  They're returned left to right.
+	pub(crate) fn seams(&self, count: usize) -> Vec<Vec<u32>> {
+		let mut seams = vec![energy_to_seam(&self.costs, self.ties)];
+		let bottom = self.costs.row(self.costs.height - 1);
+		let mut ends: Vec<u32> = (0..self.costs.width).collect();
+		ends.sort_by(|a, b| {
+			let (a, b) = (bottom[*a as usize].energy, bottom[*b as usize].energy);
+			a.partial_cmp(&b).unwrap_or(Ordering::Equal)
+		});
+		let apart = |a: u32, b: u32| cq!(a > b, a - b, b - a) >= APART;
+		let mut traced = 0;
+		for end in ends {
+			if seams.len() >= count || traced >= count * CANDIDATES {
+				break;
+			}
+			// A seam can't get far from the others if it ends beside one.
+			if !seams.iter().all(|seam| apart(seam[seam.len() - 1], end)) {
+				continue;
+			}
+			traced += 1;
+			let seam = trace_seam(&self.costs, end);
+			let clear =
+				|taken: &Vec<u32>| taken.iter().zip(seam.iter()).all(|(a, b)| apart(*a, *b));
+			if seams.iter().all(clear) {
+				seams.push(seam);
+			}
+		}
+		seams.sort_by_key(|seam| seam[0]);
+		seams
+	}
+
+	// Take a seam out of the plane and patch the costs around it.  The
+	// adjustments are those of the plane after the seam is gone.
+	pub(crate) fn remove(&mut self, seam: &[u32], adjust: &Adjust<E::Sum>) {
+		self.remove_all(&[seam], adjust);
+	}
+
+	// Take several seams out of the plane in one splice, and patch the
+	// costs around each apart from the others, in parallel.  The seams
+	// are given left to right, in the plane's columns before any is
+	// gone, and mustn't cross.  Each patch's cone is found as it's
+	// patched; when two turn out to reach one another, neither patch
+	// can be trusted, and the costs are found afresh instead.  Returns
+	// whether the seams were patched apart.
+	pub(crate) fn remove_all(&mut self, seams: &[&[u32]], adjust: &Adjust<E::Sum>) -> bool {
+		self.lumas.remove_seams(seams);
+		// Parents right of a seam in the row above move left with it;
+		// children of the removed pixels are all patched below.  The
+		// first row's cells are their own parents.
+		self.costs.remove_seams_with(seams, |y, (x, was), cell| {
+			let parent = match y {
+				0 => x,
+				_ => {
+					let parent = cell.parent(was);
+					let left = seams.iter().filter(|seam| seam[y as usize - 1] < parent);
+					parent - left.count() as u32
+				}
+			};
+			EnergyAndBackPointer::new(cell.energy, x, parent)
+		});
+		if self.lumas.width == 0 {
+			return true;
+		}
+
+		// Each seam in the columns of the plane without any of them.
+		let (lumas, costs, ties) = ((&self.lumas, self.max), &self.costs, self.ties);
+		let patch = |k: usize| {
+			let seam = |y: u32| seams[k][y as usize] - k as u32;
+			Patch::<E::Sum>::of::<E>(lumas, costs, seam, ties, adjust)
+		};
+		#[cfg(feature = "threaded")]
+		let patches: Vec<_> = pool::install(|| (0..seams.len()).into_par_iter().map(patch).collect());
+		#[cfg(not(feature = "threaded"))]
+		let patches: Vec<_> = (0..seams.len()).map(patch).collect();
+
+		let apart = patches.windows(2).all(|pair| pair[0].clear_of(&pair[1]));
+		if apart {
+			for patch in &patches {
+				patch.apply(&mut self.costs);
+			}
+		} else {
+			self.costs = calculate_cost_until::<E>(&self.lumas, self.max, self.ties, adjust, None);
+		}
+		apart
+	}
+}
+
+// How far apart, in columns, the seams `ForwardTable::seams` takes
+// together are kept.  A removal changes the costs of the pixels within
+// a couple of columns of the seam, and the change spreads down from
+// there only while it changes which way is cheapest; it's seldom more
+// than a few columns wide.
+const APART: u32 = 16;
+
+// How many seams `ForwardTable::seams` traces, for each it's asked
+// for, before it gives up on finding more far enough apart.
+const CANDIDATES: usize = 4;
+
+// The costs a seam's removal changed, as its patch found them: the
+// columns patched in each row, and the patched cells, row after row.
+struct Patch<C: Default + Copy> {
+	spans: Vec<(u32, u32)>,
+	cells: Vec<EnergyAndBackPointer<C>>,
+}
+
+impl<C: Copy + Default + PartialEq> Patch<C> {
+	// Patch the costs of a plane around a seam taken out of it, given
+	// by row in the columns of the plane without it.  The costs are
+	// read from the table as it was, and from the patch's own rows
+	// above, and left alone.  The pixels whose transition costs the
+	// seam changed are those within a couple of columns of it, in this
+	// row or the one below, and below them those whose costs changed
+	// from one a row up.
+	fn of<E: EnergyScalar<Sum = C>>(
+		(lumas, max): (&TwoDimensionalMap<u32>, u32),
+		costs: &EnergyMap<C>,
+		seam: impl Fn(u32) -> u32,
+		ties: TieBreak,
+		adjust: &Adjust<C>,
+	) -> Self {
+		let (width, height) = (lumas.width, lumas.height);
+		let near = |y: u32| {
+			let at = seam(y);
+			(at.saturating_sub(2), std::cmp::min(at + 1, width - 1))
+		};
+		let hull =
+			|(a, b): (u32, u32), (c, d): (u32, u32)| (std::cmp::min(a, c), std::cmp::max(b, d));
+		let mut patch = Patch {
+			spans: Vec::with_capacity(height as usize),
+			cells: Vec::new(),
+		};
+		let (mut changed, mut above): (Option<(u32, u32)>, usize) = (None, 0);
+		for y in 0..height {
+			let mut span = near(y);
+			if y > 0 {
+				span = hull(span, near(y - 1));
+			}
+			if let Some((left, right)) = changed {
+				span = hull(
+					span,
+					(left.saturating_sub(1), std::cmp::min(right + 1, width - 1)),
+				);
+			}
+			changed = None;
+			let start = patch.cells.len();
+			for x in span.0..=span.1 {
+				let cell = match y {
+					0 => {
+						let cost = first_row_cost::<E>((lumas, max), x);
+						EnergyAndBackPointer::new(adjust(x, 0, cost), x, x)
+					}
+					_ => {
+						let (left, right) = patch.spans[y as usize - 1];
+						let patched = &patch.cells[above..];
+						let cost = |x_above: u32| match x_above {
+							_ if x_above < left || x_above > right => {
+								costs[(x_above, y - 1)].energy
+							}
+							_ => patched[(x_above - left) as usize].energy,
+						};
+						cost_candidate_pixel::<E>((lumas, max), cost, (x, y), ties, adjust)
+					}
+				};
+				let old = costs[(x, y)];
+				if old.energy != cell.energy || old.step != cell.step {
+					changed = Some(changed.map_or((x, x), |span| hull(span, (x, x))));
+				}
+				patch.cells.push(cell);
+			}
+			patch.spans.push(span);
+			above = start;
+		}
+		patch
+	}
+
+	// Whether this patch, of a seam left of the other's, kept clear of
+	// the other: neither wrote a cell the other wrote or read.  Each
+	// row's cells are read from the columns beside them in the row
+	// above.
+	fn clear_of(&self, right: &Patch<C>) -> bool {
+		let last = self.spans.len() - 1;
+		(0..=last).all(|y| {
+			let (mut reach, mut from) = (self.spans[y].1, right.spans[y].0);
+			if y < last {
+				reach = std::cmp::max(reach, self.spans[y + 1].1 + 1);
+				from = std::cmp::min(from, right.spans[y + 1].0.saturating_sub(1));
+			}
+			reach < from
+		})
+	}
+
+	// Write the patched cells into the table.
+	fn apply(&self, costs: &mut EnergyMap<C>) {
+		let mut cells = self.cells.iter();
+		for (y, (left, right)) in self.spans.iter().enumerate() {
+			for x in *left..=*right {
+				costs[(x, y as u32)] = *cells.next().unwrap();
+			}
+		}
+	}
+}
+
 /// The basic seam engine: just a simple image reference holder, and the pair of functions
-/// needed to invoke the AviSha algorithm.
-pub struct AviShaTwo<'a, I, P, S>
+/// needed to invoke the AviSha algorithm.  As with `AviShaOne`, the
+/// costs are kept in the scalar type `E`, `u32` unless switched with
+/// `in_domain`.
+pub struct AviShaTwo<'a, I, P, S, E = u32>
 where
 	I: GenericImageView<Pixel = P>,
 	P: Pixel<Subpixel = S> + 'static,
 	S: Primitive + 'static,
+	E: EnergyScalar,
 {
 	/// A reference to the image we'll be manipulating.
 	pub image: &'a I,
+	options: EnergyOptions,
+	timings: Cell<StageTimings>,
+	pick: Option<(f64, u64)>,
+	cancel: Option<CancelToken>,
+	domain: PhantomData<E>,
 }
 
 impl<'a, I, P, S> AviShaTwo<'a, I, P, S>
@@ -186,21 +752,491 @@ where
 {
 	/// Takes a reference to an image, and holds onto it.
 	pub fn new(image: &'a I) -> Self {
-		AviShaTwo { image }
+		AviShaTwo {
+			image,
+			options: EnergyOptions::default(),
+			timings: Cell::new(StageTimings::default()),
+			pick: None,
+			cancel: None,
+			domain: PhantomData,
+		}
 	}
 }
 
-impl<'a, I, P, S> SeamFinder for AviShaTwo<'a, I, P, S>
+impl<'a, I, P, S, E> AviShaTwo<'a, I, P, S, E>
 where
 	I: GenericImageView<Pixel = P>,
 	P: Pixel<Subpixel = S> + 'static,
 	S: Primitive + 'static,
+	E: EnergyScalar,
 {
-	fn find_horizontal_seam(&self) -> Vec<u32> {
-		energy_to_seam(&calculate_cost(&Flipper { image: self.image }))
+	/// Smooth the image with a Gaussian of the given sigma before
+	/// calculating the forward costs, so that sensor noise doesn't
+	/// register as edges the seams have to route around.
+	pub fn with_blur(mut self, sigma: f32) -> Self {
+		self.options = self.options.blur(sigma);
+		self
+	}
+
+	/// Replace the energy adjustments wholesale.
+	pub fn with_options(mut self, options: EnergyOptions) -> Self {
+		self.options = options;
+		self
+	}
+
+	// Draw the seam from among the nearly cheapest, given a tolerance
+	// and a draw.  Only the full DP draws; seams found in a corridor or
+	// with a constrained slope are traced back as the DP goes.
+	pub(crate) fn sampled(mut self, pick: Option<(f64, u64)>) -> Self {
+		self.pick = pick;
+		self
+	}
+
+	// Abandon the full DP between rows once the token is raised.
+	pub(crate) fn cancelled_by(mut self, cancel: Option<CancelToken>) -> Self {
+		self.cancel = cancel;
+		self
+	}
+
+	/// How long the seams found so far spent in each stage.  Only the
+	/// energy, DP, and traceback stages are the finder's to time.
+	pub fn timings(&self) -> StageTimings {
+		self.timings.get()
+	}
+
+	// Add the time since `start` to one of the stages.
+	fn clock(&self, start: Instant, stage: fn(&mut StageTimings) -> &mut Duration) {
+		let mut timings = self.timings.get();
+		*stage(&mut timings) += start.elapsed();
+		self.timings.set(timings);
+	}
+
+	// The corridor around the seam through a shrunken copy of the
+	// image, if the options ask for one.
+	fn corridor(&self, horizontal: bool) -> Option<Vec<(u32, u32)>> {
+		let find = |image: &ImageBuffer<P, Vec<S>>, options| {
+			let coarse = AviShaTwo::new(image).with_options(options).in_domain::<E>();
+			cq!(
+				horizontal,
+				coarse.find_horizontal_seam(),
+				coarse.find_vertical_seam()
+			)
+			.into_path()
+		};
+		coarse_corridor(self.image, &self.options, horizontal, &find)
+	}
+
+	// The vertical seam through a luma plane, and the costs it was
+	// found in: within the corridor if there is one, with the slope
+	// constrained if the options ask for it, or over the whole plane.
+	// The corridor and constrained DPs trace their seams back as part
+	// of the DP.
+	fn seam_and_costs(
+		&self,
+		lumas: &TwoDimensionalMap<u32>,
+		corridor: Option<Vec<(u32, u32)>>,
+		adjust: &(impl Fn(u32, u32, E::Sum) -> E::Sum + Sync + ?Sized),
+	) -> (Vec<u32>, TwoDimensionalMap<f64>) {
+		let max = luma_max::<S>();
+		let start = Instant::now();
+		let found = match (corridor, self.options.slope) {
+			(Some(bands), _) => corridor_seam(
+				lumas.width,
+				&bands,
+				&|x| adjust(x, 0, first_row_cost::<E>((lumas, max), x)),
+				&|x, y, from, cost| {
+					cost + adjust(x, y, transition_cost::<E>((lumas, max), (x, y), from))
+				},
+			),
+			(None, Some(rows)) => {
+				let (seam, costs) = sloped_seam(
+					lumas.width,
+					lumas.height,
+					rows,
+					&|x| adjust(x, 0, first_row_cost::<E>((lumas, max), x)),
+					&|x, y, from, cost| {
+						cost + adjust(x, y, transition_cost::<E>((lumas, max), (x, y), from))
+					},
+				);
+				let mut projection = TwoDimensionalMap::new(costs.width, costs.height);
+				for (cost, source) in projection.energy.iter_mut().zip(costs.energy.iter()) {
+					*cost = source.to_f64();
+				}
+				(seam, projection)
+			}
+			(None, None) => {
+				let cancel = self.cancel.as_ref();
+				let costs =
+					calculate_cost_until::<E>(lumas, max, self.options.ties, adjust, cancel);
+				self.clock(start, |t| &mut t.dp);
+				let start = Instant::now();
+				let seam = match self.pick {
+					Some(pick) => energy_to_sampled_seam(&costs, pick),
+					None => energy_to_seam(&costs, self.options.ties),
+				};
+				self.clock(start, |t| &mut t.traceback);
+				return (seam, cost_projection(&costs));
+			}
+		};
+		self.clock(start, |t| &mut t.dp);
+		found
+	}
+
+	// The seam traced through a plane's costs, costing what the costs
+	// say it cost to reach its last pixel.
+	fn traced(
+		&self,
+		orientation: Orientation,
+		path: Vec<u32>,
+		costs: &TwoDimensionalMap<f64>,
+	) -> Seam {
+		let bottom = costs.height - 1;
+		let cost = costs[(path[bottom as usize], bottom)];
+		Seam::traced(orientation, path, cost, self.image.dimensions())
+	}
+
+	/// The whole forward-energy DP the seams running the given way are
+	/// traced through; see `CostTable`.  It's found over the whole
+	/// image, whatever corridor or slope the options ask for.
+	pub fn cost_table(&self, orientation: Orientation) -> CostTable {
+		let horizontal = orientation == Orientation::Horizontal;
+		let adjustments = self.options.fit(self.image);
+		let adjust = |x, y, e: E::Sum| {
+			cq!(
+				horizontal,
+				adjustments.adjust(y, x, e),
+				adjustments.adjust(x, y, e)
+			)
+		};
+		let lumas = match self.options.smooth(self.image) {
+			Some(image) => luma_plane(&image),
+			None => luma_plane(self.image),
+		};
+		let lumas = cq!(horizontal, lumas.transposed(), lumas);
+		let table = calculate_cost::<E>(&lumas, luma_max::<S>(), self.options.ties, &adjust);
+		let (costs, parents) = (cost_projection(&table), parent_projection(&table));
+		CostTable {
+			orientation,
+			costs: cq!(horizontal, costs.transposed(), costs),
+			parents: cq!(horizontal, parents.transposed(), parents),
+		}
+	}
+
+	/// Keep the forward costs in another scalar type.
+	pub fn in_domain<F: EnergyScalar>(self) -> AviShaTwo<'a, I, P, S, F> {
+		AviShaTwo {
+			image: self.image,
+			options: self.options,
+			timings: self.timings,
+			pick: self.pick,
+			cancel: self.cancel,
+			domain: PhantomData,
+		}
+	}
+}
+
+impl<'a, I, P, S, E> SeamFinder for AviShaTwo<'a, I, P, S, E>
+where
+	I: GenericImageView<Pixel = P>,
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+	E: EnergyScalar,
+{
+	fn find_horizontal_seam(&self) -> Seam {
+		self.find_horizontal_seam_with_costs().0
+	}
+
+	fn find_vertical_seam(&self) -> Seam {
+		self.find_vertical_seam_with_costs().0
+	}
+
+	// The transposed plane swaps x and y, so the adjustments have to
+	// be asked about the pixel in the original's coordinates, and the
+	// costs flipped back.  With nothing to adjust, the DP is handed a
+	// closure that leaves each step alone, and compiled without the
+	// call.
+	fn find_horizontal_seam_with_costs(&self) -> SeamAndCosts {
+		let start = Instant::now();
+		let adjustments = self.options.fit(self.image);
+		let adjust = |x, y, e: E::Sum| adjustments.adjust(y, x, e);
+		let lumas = match self.options.smooth(self.image) {
+			Some(image) => luma_plane(&image),
+			None => luma_plane(self.image),
+		}
+		.transposed();
+		self.clock(start, |t| &mut t.energy);
+		let corridor = self.corridor(true);
+		let (seam, costs) = if adjustments.is_empty() {
+			self.seam_and_costs(&lumas, corridor, &|_: u32, _: u32, e: E::Sum| e)
+		} else {
+			self.seam_and_costs(&lumas, corridor, &adjust)
+		};
+		let seam = self.traced(Orientation::Horizontal, seam, &costs);
+		(seam, Some(costs.transposed()))
 	}
 
-	fn find_vertical_seam(&self) -> Vec<u32> {
-		energy_to_seam(&calculate_cost(self.image))
+	fn find_vertical_seam_with_costs(&self) -> SeamAndCosts {
+		let start = Instant::now();
+		let adjustments = self.options.fit(self.image);
+		let adjust = |x, y, e: E::Sum| adjustments.adjust(x, y, e);
+		let lumas = match self.options.smooth(self.image) {
+			Some(image) => luma_plane(&image),
+			None => luma_plane(self.image),
+		};
+		self.clock(start, |t| &mut t.energy);
+		let corridor = self.corridor(false);
+		let (seam, costs) = if adjustments.is_empty() {
+			self.seam_and_costs(&lumas, corridor, &|_: u32, _: u32, e: E::Sum| e)
+		} else {
+			self.seam_and_costs(&lumas, corridor, &adjust)
+		};
+		let seam = self.traced(Orientation::Vertical, seam, &costs);
+		(seam, Some(costs))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use image::{ImageBuffer, Luma};
+
+	#[test]
+	fn tall_seams_do_not_wrap_their_costs() {
+		// A flat image has no forward energy of its own, so the costs
+		// are all in the adjustment: summed straight down, the first
+		// two columns pass u32::MAX and would wrap to less than the
+		// third.
+		let buf: ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::new(3, 40_000);
+		let adjust = |x: u32, _, e: u64| e + [200_000, 150_000, 100_000][x as usize];
+		let costs = calculate_cost::<u32>(&luma_plane(&buf), 255, TieBreak::Leftmost, &adjust);
+		assert!(energy_to_seam(&costs, TieBreak::Leftmost)
+			.iter()
+			.all(|x| *x == 2));
+	}
+
+	#[test]
+	fn importance_scales_a_step_not_the_path_to_it() {
+		// Busy on the left, flat on the right, and one unimportant
+		// pixel at the bottom of the busy side.  Scaling the whole cost
+		// there would zero everything the seam crossed to reach it.
+		let (width, height) = (8, 10);
+		let buf: ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_fn(width, height, |x, y| {
+			Luma([cq!(x < 4, ((x * 53 + y * 29 + x * y * 7) % 256) as u8, 128)])
+		});
+		let importance: ImageBuffer<Luma<u8>, Vec<u8>> =
+			ImageBuffer::from_fn(width, height, |x, y| {
+				Luma([cq!((x, y) == (1, height - 1), 0, 255)])
+			});
+		let options = EnergyOptions::new().importance(&importance);
+		let seam = AviShaTwo::new(&buf)
+			.with_options(options)
+			.find_vertical_seam();
+		assert!(seam.iter().all(|&x| x >= 4), "{:?}", seam);
+	}
+
+	#[cfg(feature = "threaded")]
+	#[test]
+	fn the_wavefront_finds_the_costs_row_by_row_does() {
+		let buf: ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_fn(600, 200, |x, y| {
+			Luma([((x * 53 + y * 29 + x * y * 7) % 256) as u8])
+		});
+		let lumas = luma_plane(&buf);
+		let adjust = |x: u32, y: u32, e: u64| e + u64::from((x ^ y) % 5);
+		let ties = TieBreak::Center;
+		let costs = calculate_cost::<u32>(&lumas, 255, ties, &adjust);
+		let mut rows = EnergyMap::new(600, 200);
+		for x in 0..600 {
+			rows[(x, 0)] = costs[(x, 0)];
+		}
+		for y in 1..200 {
+			for x in 0..600 {
+				let above = |x_above| rows[(x_above, y - 1)].energy;
+				let cell = cost_candidate_pixel::<u32>((&lumas, 255), above, (x, y), ties, &adjust);
+				assert_eq!(
+					(costs[(x, y)].energy, costs[(x, y)].step),
+					(cell.energy, cell.step)
+				);
+				rows[(x, y)] = cell;
+			}
+		}
+	}
+
+	#[test]
+	fn horizontal_seams_are_vertical_seams_of_the_transpose() {
+		let pixel = |x: u32, y: u32| Luma([((x * 53 + y * 29 + x * y * 7) % 256) as u8]);
+		let buf: ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_fn(9, 6, pixel);
+		let transposed: ImageBuffer<Luma<u8>, Vec<u8>> =
+			ImageBuffer::from_fn(6, 9, |x, y| pixel(y, x));
+		let (across, down) = (
+			AviShaTwo::new(&buf).find_horizontal_seam(),
+			AviShaTwo::new(&transposed).find_vertical_seam(),
+		);
+		assert_eq!(across.path(), down.path());
+		assert_eq!(across.cost(), down.cost());
+	}
+
+	#[test]
+	fn the_cost_table_traces_the_seam_found() {
+		let pixel = |x: u32, y: u32| Luma([((x * 53 + y * 29 + x * y * 7) % 256) as u8]);
+		let buf: ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_fn(9, 6, pixel);
+		let finder = AviShaTwo::new(&buf);
+		let seam = finder.find_horizontal_seam();
+		let table = finder.cost_table(Orientation::Horizontal);
+		assert_eq!(table.trace(seam[8]).unwrap(), seam);
+		assert_eq!(table.parents.width, 9);
+		assert!((1..9).all(|x| table.parents[(x, seam[x as usize])] == seam[x as usize - 1]));
+	}
+
+	#[test]
+	fn slope_limits_how_often_seams_step_sideways() {
+		let pixel = |x: u32, y: u32| Luma([((x * 53 + y * 29 + x * y * 7) % 256) as u8]);
+		let buf: ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_fn(9, 8, pixel);
+
+		// A slope of one row is no constraint at all, and the
+		// constrained DP finds what the plain one does.
+		let mut options = EnergyOptions::new();
+		options.slope = Some(1);
+		let free = AviShaTwo::new(&buf).find_vertical_seam_with_costs();
+		let sloped = AviShaTwo::new(&buf)
+			.with_options(options)
+			.find_vertical_seam_with_costs();
+		assert_eq!(free.0, sloped.0);
+		assert_eq!(free.1.unwrap().energy, sloped.1.unwrap().energy);
+
+		let seam = AviShaTwo::new(&buf)
+			.with_options(EnergyOptions::new().max_slope(4))
+			.find_vertical_seam();
+		let steps: Vec<usize> = (1..8).filter(|&y| seam[y] != seam[y - 1]).collect();
+		assert!(steps.windows(2).all(|w| w[1] - w[0] >= 4), "{:?}", seam);
+	}
+
+	#[test]
+	fn a_wide_corridor_finds_the_full_costs() {
+		let pixel = |x: u32, y: u32| Luma([((x * 53 + y * 29 + x * y * 7) % 256) as u8]);
+		let buf: ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_fn(16, 12, pixel);
+		let full = AviShaTwo::new(&buf).find_vertical_seam_with_costs();
+		let coarse = AviShaTwo::new(&buf)
+			.with_options(EnergyOptions::new().pyramid(4, 16))
+			.find_vertical_seam_with_costs();
+		let (full_costs, coarse_costs) = (full.1.unwrap(), coarse.1.unwrap());
+		assert_eq!(full_costs.energy, coarse_costs.energy);
+		assert_eq!(
+			full_costs[(full.0[11], 11)],
+			coarse_costs[(coarse.0[11], 11)]
+		);
+
+		// A narrow corridor leaves most of each row unexplored.
+		let narrow = AviShaTwo::new(&buf)
+			.with_options(EnergyOptions::new().pyramid(4, 2))
+			.find_horizontal_seam_with_costs();
+		assert_eq!(narrow.0.len(), 16);
+		assert!(narrow.1.unwrap().energy.iter().any(|c| c.is_infinite()));
+	}
+
+	// A view that counts how often its pixels are fetched.
+	struct Counting<'a>(&'a ImageBuffer<Luma<u8>, Vec<u8>>, Cell<usize>);
+
+	impl<'a> GenericImageView for Counting<'a> {
+		type Pixel = Luma<u8>;
+		type InnerImageView = ImageBuffer<Luma<u8>, Vec<u8>>;
+
+		fn dimensions(&self) -> (u32, u32) {
+			self.0.dimensions()
+		}
+
+		fn bounds(&self) -> (u32, u32, u32, u32) {
+			self.0.bounds()
+		}
+
+		fn get_pixel(&self, x: u32, y: u32) -> Luma<u8> {
+			self.1.set(self.1.get() + 1);
+			self.0[(x, y)]
+		}
+
+		fn inner(&self) -> &Self::InnerImageView {
+			self.0
+		}
+	}
+
+	#[test]
+	fn each_pixel_is_fetched_once_per_seam() {
+		let pixel = |x: u32, y: u32| Luma([((x * 53 + y * 29 + x * y * 7) % 256) as u8]);
+		let buf: ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_fn(9, 6, pixel);
+		let view = Counting(&buf, Cell::new(0));
+		let seam = AviShaTwo::new(&view).find_vertical_seam();
+		assert_eq!(seam, AviShaTwo::new(&buf).find_vertical_seam());
+		assert_eq!(view.1.get(), 9 * 6);
+		AviShaTwo::new(&view).find_horizontal_seam();
+		assert_eq!(view.1.get(), 2 * 9 * 6);
+	}
+
+	#[test]
+	fn patched_tables_match_recalculated_ones() {
+		let pixel = |x: u32, y: u32| Luma([((x * x * 11 + y * 23 + x * y * 5) % 256) as u8]);
+		let buf: ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_fn(14, 10, pixel);
+		let adjust = |_, _, e| e;
+		let mut lumas = luma_plane(&buf);
+		let mut table =
+			ForwardTable::<u32>::new(lumas.clone(), 255, TieBreak::Leftmost, &adjust, None);
+		for _ in 0..10 {
+			let fresh = calculate_cost::<u32>(&lumas, 255, TieBreak::Leftmost, &adjust);
+			let seam = table.seam(None);
+			assert_eq!(seam, energy_to_seam(&fresh, TieBreak::Leftmost));
+			for (patched, full) in table.costs.energy.iter().zip(fresh.energy.iter()) {
+				assert_eq!((patched.energy, patched.step), (full.energy, full.step));
+			}
+			let mut carved = TwoDimensionalMap::new(lumas.width - 1, lumas.height);
+			for y in 0..lumas.height {
+				let row: Vec<u32> = (0..lumas.width)
+					.filter(|&x| x != seam[y as usize])
+					.map(|x| lumas[(x, y)])
+					.collect();
+				carved.row_mut(y).copy_from_slice(&row);
+			}
+			lumas = carved;
+			table.remove(&seam, &adjust);
+		}
+	}
+
+	#[test]
+	fn seams_removed_at_once_match_seams_removed_in_turn() {
+		let pixel = |x: u32, y: u32| Luma([((x * x * 11 + y * 23 + x * y * 5) % 256) as u8]);
+		let buf: ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_fn(90, 6, pixel);
+		let adjust = |_, _, e| e;
+		let table =
+			|| ForwardTable::<u32>::new(luma_plane(&buf), 255, TieBreak::Leftmost, &adjust, None);
+		let remove_all = |seams: &[Vec<u32>]| {
+			let (mut together, mut apart) = (table(), table());
+			let seams: Vec<&[u32]> = seams.iter().map(|seam| &seam[..]).collect();
+			let patched = together.remove_all(&seams, &adjust);
+			// Right to left, each seam's columns are still those it was
+			// found in.
+			for seam in seams.iter().rev() {
+				apart.remove(seam, &adjust);
+			}
+			let fresh = calculate_cost::<u32>(&apart.lumas, 255, TieBreak::Leftmost, &adjust);
+			for ((together, apart), fresh) in (together.costs.energy.iter())
+				.zip(apart.costs.energy.iter())
+				.zip(fresh.energy.iter())
+			{
+				assert_eq!((together.energy, together.step), (apart.energy, apart.step));
+				assert_eq!((together.energy, together.step), (fresh.energy, fresh.step));
+			}
+			assert_eq!(together.lumas.energy, apart.lumas.energy);
+			patched
+		};
+
+		let seams = table().seams(3);
+		assert_eq!(seams.len(), 3);
+		assert!(seams.contains(&energy_to_seam(&table().costs, TieBreak::Leftmost)));
+		assert!(seams
+			.windows(2)
+			.all(|pair| pair[0][0] + APART <= pair[1][0]));
+		assert!(remove_all(&seams));
+
+		// Seams side by side can't be patched apart, and the costs are
+		// found afresh.
+		let close = [vec![40; 6], vec![42; 6]];
+		assert!(!remove_all(&close));
 	}
 }
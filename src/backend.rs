@@ -0,0 +1,324 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Compute backends
+//!
+//! The energy map and the forward-energy DP can be sent to a device:
+//! a GPU through wgpu with the `gpu` feature, or anything with an
+//! OpenCL driver with the `opencl` feature, for the older servers and
+//! headless boxes wgpu can't reach.  Both work in `f32`, with ties
+//! going left and the image's edge clamped, so a seam found on either
+//! is the one `AviShaTwo::in_domain::<f32>` finds.  None of the energy
+//! options are carried over.
+//!
+//! Each backend's constructor returns None when there's no device to
+//! be had; `any_backend` tries each that was compiled in, in turn,
+//! and `DeviceSeamFinder` finds seams on whichever it gets.  A plane
+//! too large for the device's buffers, or one the device fails on, is
+//! worked on the CPU instead, with the same sums.
+//!
+//! A carve whose options give `device_above` is planned here: an image
+//! of at least that many pixels has its forward costs worked out on
+//! the device, and its seams traced and spliced out on the CPU.  If
+//! the device fails, the rest of the carve is done on the CPU, and the
+//! report says so.  Without a device compiled in, every carve is done
+//! on the CPU.
+
+use crate::avisha1::plane_energy;
+use crate::avisha2::plane_costs;
+use crate::border::BorderPolicy;
+use crate::cq;
+use crate::pixelpairs::{luma_max, luma_plane};
+use crate::seamcarver::{Algorithm, CarveOptions};
+use crate::seamfinder::{Orientation, Seam, SeamAndCosts, SeamFinder};
+use crate::twodmap::TwoDimensionalMap;
+use image::{GenericImageView, Pixel, Primitive};
+use std::marker::PhantomData;
+
+/// A device the energy map and the forward-energy DP can run on.
+pub trait ComputeBackend {
+	/// The backward energy map of a luma plane whose lumas run up to
+	/// `max`, or None if the device couldn't find it.
+	fn try_energy_map_of(
+		&self,
+		lumas: &TwoDimensionalMap<u32>,
+		max: u32,
+	) -> Option<TwoDimensionalMap<f32>>;
+
+	/// The forward-energy costs of a luma plane, and the column of the
+	/// row above each cost came from, or None if the device couldn't
+	/// find them.
+	fn try_forward_costs(
+		&self,
+		lumas: &TwoDimensionalMap<u32>,
+		max: u32,
+	) -> Option<(TwoDimensionalMap<f32>, TwoDimensionalMap<u32>)>;
+
+	/// The backward energy map of a luma plane whose lumas run up to
+	/// `max`, found on the CPU if the device couldn't.
+	fn energy_map_of(&self, lumas: &TwoDimensionalMap<u32>, max: u32) -> TwoDimensionalMap<f32> {
+		self.try_energy_map_of(lumas, max)
+			.unwrap_or_else(|| cpu_energy_map_of(lumas, max))
+	}
+
+	/// The forward-energy costs of a luma plane, and the column of the
+	/// row above each cost came from, found on the CPU if the device
+	/// couldn't.
+	fn forward_costs(
+		&self,
+		lumas: &TwoDimensionalMap<u32>,
+		max: u32,
+	) -> (TwoDimensionalMap<f32>, TwoDimensionalMap<u32>) {
+		self.try_forward_costs(lumas, max)
+			.unwrap_or_else(|| cpu_forward_costs(lumas, max))
+	}
+
+	/// The backward energy map of the image, as
+	/// `calculate_energy_as::<f32, _, _, _>` finds it with the edge
+	/// clamped.
+	fn energy_map<I, P, S>(&self, image: &I) -> TwoDimensionalMap<f32>
+	where
+		Self: Sized,
+		I: GenericImageView<Pixel = P>,
+		P: Pixel<Subpixel = S> + 'static,
+		S: Primitive + 'static,
+	{
+		self.energy_map_of(&luma_plane(image), luma_max::<S>())
+	}
+}
+
+// The backward energy map of a plane, on the CPU, for a backend that
+// can't find it.
+pub(crate) fn cpu_energy_map_of(
+	lumas: &TwoDimensionalMap<u32>,
+	max: u32,
+) -> TwoDimensionalMap<f32> {
+	plane_energy::<f32>((lumas, max), BorderPolicy::Clamp)
+}
+
+// The forward-energy costs of a plane and their parents, on the CPU,
+// for a backend that can't find them.
+pub(crate) fn cpu_forward_costs(
+	lumas: &TwoDimensionalMap<u32>,
+	max: u32,
+) -> (TwoDimensionalMap<f32>, TwoDimensionalMap<u32>) {
+	plane_costs::<f32>(lumas, max)
+}
+
+/// The first backend compiled in that has a device: wgpu's, then
+/// OpenCL's.
+pub fn any_backend() -> Option<Box<dyn ComputeBackend>> {
+	#[cfg(feature = "gpu")]
+	{
+		if let Some(gpu) = crate::gpu::Gpu::new() {
+			return Some(Box::new(gpu));
+		}
+	}
+	#[cfg(feature = "opencl")]
+	{
+		if let Some(opencl) = crate::opencl::OpenCl::new() {
+			return Some(Box::new(opencl));
+		}
+	}
+	None
+}
+
+/// Where a carve's seams were found.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum Backend {
+	/// Everything was done on the CPU.
+	#[default]
+	Cpu,
+	/// The forward costs of every seam were worked out on a compute
+	/// device, and the seams traced and spliced out on the CPU.
+	Device,
+}
+
+// Where the devices for a carve come from: `any_backend`, unless a
+// test says otherwise.
+pub(crate) type Devices = fn() -> Option<Box<dyn ComputeBackend>>;
+
+// The device to find a carve's seams on, if its options send an image
+// of this size to one and there's one to be had.  Only a plain
+// forward-energy carve is sent: the device carries none of the energy
+// options over, and traces only the cheapest seam.
+pub(crate) fn plan(
+	options: &CarveOptions,
+	(width, height): (u32, u32),
+	devices: Devices,
+) -> Option<Box<dyn ComputeBackend>> {
+	let above = options.device_above?;
+	let plain = options.algorithm == Algorithm::Forward
+		&& options.energy.is_plain()
+		&& options.region_budget.is_none()
+		&& options.region_of_interest.is_none()
+		&& options.near_optimal.is_none();
+	cq!(
+		plain && u64::from(width) * u64::from(height) >= above,
+		devices(),
+		None
+	)
+}
+
+/// A seam finder running the forward-energy DP on a compute backend.
+/// Only the traceback is done on the CPU.
+pub struct DeviceSeamFinder<'a, I, P, S, B>
+where
+	I: GenericImageView<Pixel = P>,
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+	B: ComputeBackend + ?Sized,
+{
+	image: &'a I,
+	backend: &'a B,
+	pixel: PhantomData<P>,
+}
+
+impl<'a, I, P, S, B> DeviceSeamFinder<'a, I, P, S, B>
+where
+	I: GenericImageView<Pixel = P>,
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+	B: ComputeBackend + ?Sized,
+{
+	/// A finder for the image, on the given backend.
+	pub fn new(image: &'a I, backend: &'a B) -> Self {
+		DeviceSeamFinder {
+			image,
+			backend,
+			pixel: PhantomData,
+		}
+	}
+
+	// The seam through the plane, and the plane's costs.
+	fn seam_through(
+		&self,
+		orientation: Orientation,
+		lumas: &TwoDimensionalMap<u32>,
+	) -> (Seam, TwoDimensionalMap<f64>) {
+		let (costs, parents) = self.backend.forward_costs(lumas, luma_max::<S>());
+		let seam = self.traced(orientation, &costs, &parents);
+		let mut projection = TwoDimensionalMap::new(costs.width, costs.height);
+		for (wide, cost) in projection.energy.iter_mut().zip(costs.energy.iter()) {
+			*wide = f64::from(*cost);
+		}
+		(seam, projection)
+	}
+
+	// The seam running the given way, or None if the device failed on
+	// the image.
+	pub(crate) fn try_find_seam(&self, orientation: Orientation) -> Option<Seam> {
+		let lumas = luma_plane(self.image);
+		let lumas = cq!(
+			orientation == Orientation::Vertical,
+			lumas,
+			lumas.transposed()
+		);
+		let (costs, parents) = self.backend.try_forward_costs(&lumas, luma_max::<S>())?;
+		Some(self.traced(orientation, &costs, &parents))
+	}
+
+	// The cheapest seam through the costs, traced back through the
+	// parents.
+	fn traced(
+		&self,
+		orientation: Orientation,
+		costs: &TwoDimensionalMap<f32>,
+		parents: &TwoDimensionalMap<u32>,
+	) -> Seam {
+		let bottom = costs.height - 1;
+		let mut end = 0;
+		for x in 1..costs.width {
+			if costs[(x, bottom)] < costs[(end, bottom)] {
+				end = x;
+			}
+		}
+		let mut path = vec![end; costs.height as usize];
+		for y in (1..costs.height).rev() {
+			path[y as usize - 1] = parents[(path[y as usize], y)];
+		}
+		let cost = f64::from(costs[(end, bottom)]);
+		Seam::traced(orientation, path, cost, self.image.dimensions())
+	}
+}
+
+impl<'a, I, P, S, B> SeamFinder for DeviceSeamFinder<'a, I, P, S, B>
+where
+	I: GenericImageView<Pixel = P>,
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+	B: ComputeBackend + ?Sized,
+{
+	fn find_horizontal_seam(&self) -> Seam {
+		self.find_horizontal_seam_with_costs().0
+	}
+
+	fn find_vertical_seam(&self) -> Seam {
+		self.find_vertical_seam_with_costs().0
+	}
+
+	fn find_horizontal_seam_with_costs(&self) -> SeamAndCosts {
+		let lumas = luma_plane(self.image).transposed();
+		let (seam, costs) = self.seam_through(Orientation::Horizontal, &lumas);
+		(seam, Some(costs.transposed()))
+	}
+
+	fn find_vertical_seam_with_costs(&self) -> SeamAndCosts {
+		let lumas = luma_plane(self.image);
+		let (seam, costs) = self.seam_through(Orientation::Vertical, &lumas);
+		(seam, Some(costs))
+	}
+}
+
+// Check a backend against the CPU, if it has a device.
+#[cfg(test)]
+pub(crate) fn agrees_with_the_cpu<B: ComputeBackend>(backend: &B) {
+	use crate::avisha1::calculate_energy_as;
+	use crate::avisha2::AviShaTwo;
+	use crate::border::BorderPolicy;
+	use image::{ImageBuffer, Luma};
+
+	let buf: ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_fn(300, 40, |x, y| {
+		Luma([((x * 53 + y * 29 + x * y * 7) % 256) as u8])
+	});
+	let energy = backend.energy_map(&buf);
+	let cpu = calculate_energy_as::<f32, _, _, _>(&buf, BorderPolicy::Clamp);
+	assert_eq!(energy.energy, cpu.energy);
+
+	let finder = DeviceSeamFinder::new(&buf, backend);
+	let cpu = AviShaTwo::new(&buf).in_domain::<f32>();
+	assert_eq!(finder.find_vertical_seam(), cpu.find_vertical_seam());
+	assert_eq!(finder.find_horizontal_seam(), cpu.find_horizontal_seam());
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// A backend that has only the CPU to fall back on.
+	struct Fallback;
+
+	impl ComputeBackend for Fallback {
+		fn try_energy_map_of(
+			&self,
+			_: &TwoDimensionalMap<u32>,
+			_: u32,
+		) -> Option<TwoDimensionalMap<f32>> {
+			None
+		}
+
+		fn try_forward_costs(
+			&self,
+			_: &TwoDimensionalMap<u32>,
+			_: u32,
+		) -> Option<(TwoDimensionalMap<f32>, TwoDimensionalMap<u32>)> {
+			None
+		}
+	}
+
+	#[test]
+	fn the_cpu_fallback_finds_what_the_cpu_does() {
+		agrees_with_the_cpu(&Fallback);
+	}
+}
@@ -0,0 +1,274 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Banded seam finding
+//!
+//! When an image is being carved interactively, one seam after
+//! another, the next seam is usually found close to the last one: the
+//! energy there has only just changed, and the rest of the image is
+//! as it was.  This finder evaluates only a band of columns around the
+//! previous seam, row by row, rather than the whole width.  That's
+//! much cheaper on a wide image, and the seam it finds is the best
+//! one inside the band, which isn't necessarily the best one in the
+//! image; it's meant for previews, where that doesn't matter.
+//!
+//! The energy is the plain gradient energy of `AviShaOne`, with the
+//! same border policy and adjustments.  Normalization needs the whole
+//! energy map, so it's ignored here.
+
+use crate::avisha1::{pixel_energy, AviShaOne};
+use crate::cq;
+use crate::energyopts::EnergyOptions;
+use crate::pixelpairs::{luma_max, luma_plane};
+use crate::scalar::EnergyScalar;
+use crate::seamfinder::{Orientation, Seam, SeamFinder};
+use crate::twodmap::TwoDimensionalMap;
+use image::{GenericImageView, Pixel, Primitive};
+
+/// A seam finder that only looks within `band` columns (or rows) of
+/// a previous seam.  Without a previous seam, or with one that
+/// doesn't fit the image, it searches the whole image as `AviShaOne`
+/// does.
+pub struct BandedSeamFinder<'a, I, P, S>
+where
+	I: GenericImageView<Pixel = P>,
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	image: &'a I,
+	options: EnergyOptions,
+	band: u32,
+	previous: Option<Vec<u32>>,
+}
+
+impl<'a, I, P, S> BandedSeamFinder<'a, I, P, S>
+where
+	I: GenericImageView<Pixel = P>,
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	/// Takes a reference to an image, and the half-width of the band
+	/// to search.
+	pub fn new(image: &'a I, band: u32) -> Self {
+		BandedSeamFinder {
+			image,
+			options: EnergyOptions::default(),
+			band,
+			previous: None,
+		}
+	}
+
+	/// Replace the energy adjustments wholesale.
+	pub fn with_options(mut self, options: EnergyOptions) -> Self {
+		self.options = options;
+		self
+	}
+
+	/// Search around this seam, usually the one just removed from the
+	/// image.  It must run the same way as the seam being asked for.
+	pub fn following(mut self, seam: &[u32]) -> Self {
+		self.previous = Some(seam.to_vec());
+		self
+	}
+
+	// The vertical seam through a luma plane, within the band around
+	// the previous seam, and its cost.
+	fn banded_seam(
+		&self,
+		lumas: &TwoDimensionalMap<u32>,
+		previous: &[u32],
+		adjust: &dyn Fn(u32, u32, u32) -> u32,
+	) -> (Vec<u32>, f64) {
+		let max = luma_max::<S>();
+		let border = self.options.border;
+		let energy = |x, y| {
+			u64::from(adjust(
+				x,
+				y,
+				pixel_energy::<u32>((lumas, max), border, (x, y)),
+			))
+		};
+		let bands = bands_around(previous, lumas.width, self.band);
+		let (seam, costs) =
+			corridor_seam(lumas.width, &bands, &|x| energy(x, 0), &|x, y, _, cost| {
+				cost + energy(x, y)
+			});
+		let bottom = lumas.height - 1;
+		let cost = costs[(seam[bottom as usize], bottom)];
+		(seam, cost)
+	}
+
+	// The previous seam, if there is one and it has one position for
+	// every row (or column) of the plane a seam is being found in.
+	fn previous_for(&self, length: u32) -> Option<&[u32]> {
+		self.previous
+			.as_ref()
+			.filter(|seam| seam.len() == length as usize && length > 0)
+			.map(|seam| &seam[..])
+	}
+}
+
+// The bands `margin` columns either side of a line of centers, one
+// per row of a plane `width` wide.  The centers are first pulled in so
+// that they move at most one column a row, which guarantees that every
+// pixel in a band has a parent in the band above.
+pub(crate) fn bands_around(centers: &[u32], width: u32, margin: u32) -> Vec<(u32, u32)> {
+	let mut bands = Vec::with_capacity(centers.len());
+	let mut last: Option<u32> = None;
+	for &center in centers {
+		let mut center = std::cmp::min(center, width - 1);
+		if let Some(last) = last {
+			center = center.clamp(last.saturating_sub(1), last + 1);
+		}
+		last = Some(center);
+		bands.push((
+			center.saturating_sub(margin),
+			std::cmp::min(center + margin, width - 1),
+		));
+	}
+	bands
+}
+
+// The cheapest vertical seam through a plane `width` wide, looking
+// only within the given band of each row; as `slope::sloped_seam`,
+// `start` and `extend` give the costs of starting a seam and of
+// stepping down a row from a given column.  Returns the seam, and
+// the cost of reaching every pixel, infinite outside the bands.  Each
+// row keeps the costs of its band alone, starting from the band's
+// left edge; ties go to the leftmost candidate.
+pub(crate) fn corridor_seam<C: EnergyScalar>(
+	width: u32,
+	bands: &[(u32, u32)],
+	start: &dyn Fn(u32) -> C,
+	extend: &dyn Fn(u32, u32, u32, C) -> C,
+) -> (Vec<u32>, TwoDimensionalMap<f64>) {
+	let height = bands.len() as u32;
+	let mut costs = TwoDimensionalMap::new(width, height);
+	for cost in costs.energy.iter_mut() {
+		*cost = f64::INFINITY;
+	}
+
+	let (left, right) = bands[0];
+	let mut above: Vec<C> = (left..=right).map(start).collect();
+	for (x, cost) in (left..=right).zip(above.iter()) {
+		costs[(x, 0)] = cost.to_f64();
+	}
+	let mut parents: Vec<Vec<u32>> = vec![Vec::new(); height as usize];
+	for y in 1..height {
+		let (left, right) = bands[y as usize];
+		let (above_left, above_right) = bands[y as usize - 1];
+		let mut row_costs = Vec::with_capacity((right - left + 1) as usize);
+		let row = &mut parents[y as usize];
+		for x in left..=right {
+			let candidates =
+				std::cmp::max(x.saturating_sub(1), above_left)..=std::cmp::min(x + 1, above_right);
+			let (parent, cost) = candidates
+				.map(|p| (p, extend(x, y, p, above[(p - above_left) as usize])))
+				.fold(None, |best: Option<(u32, C)>, (p, cost)| match best {
+					Some((_, b)) if b <= cost => best,
+					_ => Some((p, cost)),
+				})
+				.expect("the band has come apart from the one above");
+			costs[(x, y)] = cost.to_f64();
+			row.push(parent);
+			row_costs.push(cost);
+		}
+		above = row_costs;
+	}
+
+	let (left, _) = bands[height as usize - 1];
+	let bottom = (0..above.len()).fold(0, |best, i| cq!(above[i] < above[best], i, best));
+	let mut seam_col = left + bottom as u32;
+	let mut seam = vec![0; height as usize];
+	for y in (0..height).rev() {
+		seam[y as usize] = seam_col;
+		if y > 0 {
+			let (left, _) = bands[y as usize];
+			seam_col = parents[y as usize][(seam_col - left) as usize];
+		}
+	}
+	(seam, costs)
+}
+
+impl<'a, I, P, S> SeamFinder for BandedSeamFinder<'a, I, P, S>
+where
+	I: GenericImageView<Pixel = P>,
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	fn find_horizontal_seam(&self) -> Seam {
+		match self.previous_for(self.image.width()) {
+			Some(previous) => {
+				let adjustments = self.options.fit(self.image);
+				let adjust = |x, y, e: u32| adjustments.adjust(y, x, e);
+				let lumas = match self.options.smooth(self.image) {
+					Some(image) => luma_plane(&image),
+					None => luma_plane(self.image),
+				};
+				let (path, cost) = self.banded_seam(&lumas.transposed(), previous, &adjust);
+				Seam::traced(Orientation::Horizontal, path, cost, self.image.dimensions())
+			}
+			None => AviShaOne::new(self.image)
+				.with_options(self.options.clone())
+				.find_horizontal_seam(),
+		}
+	}
+
+	fn find_vertical_seam(&self) -> Seam {
+		match self.previous_for(self.image.height()) {
+			Some(previous) => {
+				let adjustments = self.options.fit(self.image);
+				let adjust = |x, y, e: u32| adjustments.adjust(x, y, e);
+				let lumas = match self.options.smooth(self.image) {
+					Some(image) => luma_plane(&image),
+					None => luma_plane(self.image),
+				};
+				let (path, cost) = self.banded_seam(&lumas, previous, &adjust);
+				Seam::traced(Orientation::Vertical, path, cost, self.image.dimensions())
+			}
+			None => AviShaOne::new(self.image)
+				.with_options(self.options.clone())
+				.find_vertical_seam(),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use image::{ImageBuffer, Luma};
+
+	#[test]
+	fn a_wide_band_finds_the_exact_seam() {
+		let pixel = |x: u32, y: u32| Luma([((x * 53 + y * 29 + x * y * 7) % 256) as u8]);
+		let buf: ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_fn(12, 9, pixel);
+		let exact = AviShaOne::new(&buf).find_vertical_seam();
+		let banded = BandedSeamFinder::new(&buf, 12)
+			.following(&[5; 9])
+			.find_vertical_seam();
+		assert_eq!(banded, exact);
+		assert_eq!(
+			BandedSeamFinder::new(&buf, 12)
+				.following(&[4; 12])
+				.find_horizontal_seam(),
+			AviShaOne::new(&buf).find_horizontal_seam()
+		);
+	}
+
+	#[test]
+	fn a_narrow_band_stays_near_the_previous_seam() {
+		let pixel = |x: u32, y: u32| Luma([((x * 53 + y * 29 + x * y * 7) % 256) as u8]);
+		let buf: ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_fn(12, 9, pixel);
+		let previous: Vec<u32> = vec![3, 4, 5, 6, 6, 6, 5, 4, 3];
+		let seam = BandedSeamFinder::new(&buf, 1)
+			.following(&previous)
+			.find_vertical_seam();
+		for (found, was) in seam.iter().zip(previous.iter()) {
+			assert!((*found as i64 - *was as i64).abs() <= 1, "{:?}", seam);
+		}
+		assert!(seam
+			.windows(2)
+			.all(|w| (w[0] as i64 - w[1] as i64).abs() <= 1));
+	}
+}
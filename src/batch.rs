@@ -0,0 +1,185 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Batches of images
+//!
+//! A thumbnailing service carves thousands of images to the same
+//! spec, and would otherwise have to build its own parallelism and
+//! backpressure around the carver.  `carve_batch` takes the images
+//! from an iterator a few at a time, carves those together, and hands
+//! each result back in order before it takes any more, so no more
+//! than a handful of images are ever held at once however long the
+//! batch.  With the `threaded` feature the images in hand are carved
+//! on rayon's threads, or on a pool of `threads` of them if the
+//! options ask; without it, one after another.
+
+use crate::contactsheet::fit_to_cell;
+use crate::error::CarveError;
+use crate::seamcarver::{seamcarve_with_options, CarveOptions};
+use image::{ImageBuffer, Pixel, Primitive};
+#[cfg(feature = "threaded")]
+use rayon::prelude::*;
+
+/// The size to bring every image of a batch to.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum BatchTarget {
+	/// Carve each image to exactly this width and height.
+	Size(u32, u32),
+	/// Scale each image to cover a cell of this width and height, and
+	/// carve away what still overflows, as `fit_to_cell` does.
+	Cell(u32, u32),
+}
+
+/// How to carve a batch of images.
+#[derive(Debug, Clone)]
+pub struct BatchSpec {
+	/// The size to bring each image to.
+	pub target: BatchTarget,
+
+	/// The options every image is carved with.
+	pub options: CarveOptions,
+
+	/// The most images taken from the batch and not yet handed back at
+	/// any one time; as many as there are threads, if not given.
+	pub in_flight: Option<usize>,
+}
+
+impl BatchSpec {
+	/// Carve every image to the target with the default options.
+	pub fn new(target: BatchTarget) -> Self {
+		BatchSpec {
+			target,
+			options: CarveOptions::default(),
+			in_flight: None,
+		}
+	}
+
+	// Carve one image of the batch.
+	fn carve<P, S>(
+		&self,
+		image: &ImageBuffer<P, Vec<S>>,
+	) -> Result<ImageBuffer<P, Vec<S>>, CarveError>
+	where
+		P: Pixel<Subpixel = S> + 'static,
+		S: Primitive + 'static,
+	{
+		match self.target {
+			BatchTarget::Size(width, height) => {
+				seamcarve_with_options(image, width, height, &self.options)
+			}
+			BatchTarget::Cell(width, height) => fit_to_cell(image, width, height, &self.options),
+		}
+	}
+}
+
+// An image of the batch, carved or not.
+type Carved<P, S> = Result<ImageBuffer<P, Vec<S>>, CarveError>;
+
+/// Carve every image of the batch to the spec, handing `done` each
+/// result in turn with the image's place in the batch.  An image that
+/// can't be carved is handed back as its error, and the rest of the
+/// batch carries on.
+pub fn carve_batch<It, P, S, F>(images: It, spec: &BatchSpec, done: F)
+where
+	It: IntoIterator<Item = ImageBuffer<P, Vec<S>>>,
+	P: Pixel<Subpixel = S> + Send + Sync + 'static,
+	S: Primitive + Send + Sync + 'static,
+	F: FnMut(usize, Carved<P, S>),
+{
+	let images = images.into_iter();
+
+	#[cfg(feature = "threaded")]
+	{
+		let pool = spec.options.threads.and_then(|threads| {
+			rayon::ThreadPoolBuilder::new()
+				.num_threads(threads)
+				.build()
+				.ok()
+		});
+		let carve = |hand: &[ImageBuffer<P, Vec<S>>]| -> Vec<_> {
+			let carve = || hand.par_iter().map(|image| spec.carve(image)).collect();
+			match &pool {
+				Some(pool) => pool.install(carve),
+				None => carve(),
+			}
+		};
+		let threads = pool
+			.as_ref()
+			.map_or_else(rayon::current_num_threads, |pool| {
+				pool.current_num_threads()
+			});
+		in_hands(images, spec.in_flight.unwrap_or(threads), carve, done);
+	}
+	#[cfg(not(feature = "threaded"))]
+	{
+		let carve = |hand: &[ImageBuffer<P, Vec<S>>]| -> Vec<_> {
+			hand.iter().map(|image| spec.carve(image)).collect()
+		};
+		in_hands(images, spec.in_flight.unwrap_or(1), carve, done);
+	}
+}
+
+// Take the images `in_flight` at a time, carve each handful, and hand
+// back what was carved before taking more.
+fn in_hands<P, S>(
+	mut images: impl Iterator<Item = ImageBuffer<P, Vec<S>>>,
+	in_flight: usize,
+	carve: impl Fn(&[ImageBuffer<P, Vec<S>>]) -> Vec<Carved<P, S>>,
+	mut done: impl FnMut(usize, Carved<P, S>),
+) where
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	let mut place = 0;
+	loop {
+		let hand: Vec<_> = images.by_ref().take(std::cmp::max(in_flight, 1)).collect();
+		if hand.is_empty() {
+			return;
+		}
+		for result in carve(&hand) {
+			done(place, result);
+			place += 1;
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use image::Luma;
+
+	#[test]
+	fn a_batch_carves_each_image_as_it_would_alone() {
+		let image = |seed: u32, width: u32| -> ImageBuffer<Luma<u8>, Vec<u8>> {
+			ImageBuffer::from_fn(width, 10, |x, y| {
+				Luma([((x * 53 + y * 29 + x * y * seed) % 256) as u8])
+			})
+		};
+		// The third has nothing in it to carve.
+		let images = vec![
+			image(3, 14),
+			image(5, 12),
+			image(7, 0),
+			image(9, 16),
+			image(11, 13),
+		];
+		let spec = BatchSpec {
+			in_flight: Some(2),
+			..BatchSpec::new(BatchTarget::Size(8, 9))
+		};
+		let mut results = Vec::new();
+		carve_batch(images.clone(), &spec, |place, result| {
+			results.push((place, result))
+		});
+
+		assert_eq!(results.len(), images.len());
+		for (at, (place, result)) in results.into_iter().enumerate() {
+			assert_eq!(place, at);
+			match seamcarve_with_options(&images[at], 8, 9, &spec.options) {
+				Ok(alone) => assert_eq!(result.unwrap().into_raw(), alone.into_raw()),
+				Err(_) => assert!(result.is_err()),
+			}
+		}
+	}
+}
@@ -0,0 +1,94 @@
+use pnmseam::contactsheet::{contact_sheet, SheetStyle};
+use pnmseam::CarveOptions;
+
+extern crate clap;
+extern crate image;
+
+use clap::{App, Arg};
+use image::Rgb;
+use std::fs;
+use std::process;
+
+fn main() {
+    let matches = App::new("contactsheet")
+        .version("0.1.0")
+        .author("Elf M. Sternberg <elf.sternberg@gmail.com>")
+        .about("Lay a directory of images out on a contact sheet, seam carving each to its cell")
+        .arg(
+            Arg::with_name("directory")
+                .help("The directory of images")
+                .required(true)
+                .index(1),
+        )
+        .arg(
+            Arg::with_name("output")
+                .help("Where to write the sheet")
+                .short("o")
+                .long("output")
+                .default_value("contact-sheet.png"),
+        )
+        .arg(
+            Arg::with_name("columns")
+                .help("The number of cells across the sheet")
+                .long("columns")
+                .default_value("6"),
+        )
+        .arg(
+            Arg::with_name("cell")
+                .help("The size of each cell, as WIDTHxHEIGHT")
+                .long("cell")
+                .default_value("240x160"),
+        )
+        .arg(
+            Arg::with_name("gutter")
+                .help("The space between cells, in pixels")
+                .long("gutter")
+                .default_value("8"),
+        )
+        .get_matches();
+
+    let fail = |message: String| -> ! {
+        eprintln!("contactsheet: {}", message);
+        process::exit(1)
+    };
+    let number = |name: &str, value: &str| -> u32 {
+        value
+            .parse()
+            .unwrap_or_else(|_| fail(format!("--{} must be a number, not {:?}", name, value)))
+    };
+    let cell = matches.value_of("cell").unwrap();
+    let (cell_width, cell_height) = match cell.split('x').collect::<Vec<_>>().as_slice() {
+        [width, height] => (number("cell", width), number("cell", height)),
+        _ => fail(format!("--cell must look like 240x160, not {:?}", cell)),
+    };
+
+    // Anything in the directory that decodes as an image goes on the
+    // sheet, in the order of the file names.
+    let directory = matches.value_of("directory").unwrap();
+    let mut paths: Vec<_> = fs::read_dir(directory)
+        .unwrap_or_else(|e| fail(format!("{}: {}", directory, e)))
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file())
+        .collect();
+    paths.sort();
+    let images: Vec<_> = paths
+        .iter()
+        .filter_map(|path| image::open(path).ok().map(|image| image.to_rgb()))
+        .collect();
+    if images.is_empty() {
+        fail(format!("{}: no images found", directory));
+    }
+
+    let style = SheetStyle {
+        columns: number("columns", matches.value_of("columns").unwrap()),
+        cell_width,
+        cell_height,
+        gutter: number("gutter", matches.value_of("gutter").unwrap()),
+        background: Rgb([255, 255, 255]),
+    };
+    let sheet = contact_sheet(&images, style, &CarveOptions::default()).unwrap_or_else(|e| fail(e));
+    let output = matches.value_of("output").unwrap();
+    sheet
+        .save(output)
+        .unwrap_or_else(|e| fail(format!("{}: {}", output, e)));
+}
@@ -1,12 +1,16 @@
-use pnmseam::seamcarve;
+use pnmseam::{
+    Algorithm, CarveOptions, InsertionCap, NearOptimal, OversizePolicy, Palette, RegionOfInterest,
+    RemovalMask, SeamCarver,
+};
 
 extern crate clap;
 extern crate image;
 
 use clap::{App, Arg};
+use image::Rgb;
 
 fn main() {
-    let matches = App::new("pnmseam")
+    let app = App::new("pnmseam")
         .version("0.1.0")
         .author("Elf M. Sternberg <elf.sternberg@gmail.com>")
         .about("Seam carving for portable anymap")
@@ -16,9 +20,176 @@ fn main() {
                 .required(true)
                 .index(1),
         )
-        .get_matches();
+        .arg(
+            Arg::with_name("timings")
+                .help("Print how long each stage of the carve took")
+                .long("timings"),
+        )
+        .arg(
+            Arg::with_name("insertion-cap")
+                .help("When enlarging, insert at most LIMIT seams in any WINDOW pixels, as WINDOW:LIMIT")
+                .long("insertion-cap")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("insertion-order")
+                .help("When enlarging, save a picture of the order the seams were inserted in")
+                .long("insertion-order")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("palette")
+                .help("The palette to draw pictures in: gray, viridis, or cividis")
+                .long("palette")
+                .default_value("viridis"),
+        )
+        .arg(
+            Arg::with_name("max-pixels")
+                .help("Don't carve images with more pixels than this as they are")
+                .long("max-pixels")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("oversize")
+                .help("What to do with an image over --max-pixels: refuse, pyramid, or downscale")
+                .long("oversize")
+                .possible_values(&["refuse", "pyramid", "downscale"])
+                .default_value("refuse"),
+        )
+        .arg(
+            Arg::with_name("near-optimal")
+                .help("Take seams at random from those within this fraction of the cheapest")
+                .long("near-optimal")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("roi")
+                .help("Carve only within this rectangle, as X,Y,WIDTHxHEIGHT")
+                .long("roi")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("remove")
+                .help("A mask of an object to carve out of the image, light where it is")
+                .long("remove")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("algorithm")
+                .help("The seam finder: forward or backward energy")
+                .long("algorithm")
+                .possible_values(&["forward", "backward"])
+                .default_value("forward"),
+        )
+        .arg(
+            Arg::with_name("carve-fraction")
+                .help("Carve only this fraction of the change in size, and scale the rest")
+                .long("carve-fraction")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("allow-extreme")
+                .help("Carve even to a sliver, or away most of the image")
+                .long("allow-extreme"),
+        )
+        .arg(
+            Arg::with_name("seed")
+                .help("The seed for --near-optimal")
+                .long("seed")
+                .default_value("0"),
+        );
+
+    #[cfg(feature = "mapio")]
+    let app = app.arg(
+        Arg::with_name("weights")
+            .help("A per-pixel weight map (.npy or .pfm) to multiply into the energy")
+            .long("weights")
+            .takes_value(true),
+    );
+
+    let matches = app.get_matches();
+
+    let mut options = CarveOptions {
+        track_insertion_order: matches.is_present("insertion-order"),
+        allow_extreme: matches.is_present("allow-extreme"),
+        ..CarveOptions::default()
+    };
+    if let Some(cap) = matches.value_of("insertion-cap") {
+        let numbers: Vec<u32> = cap.split(':').filter_map(|n| n.parse().ok()).collect();
+        match numbers.as_slice() {
+            [window, limit] => options.insertion_cap = Some(InsertionCap::new(*window, *limit)),
+            _ => panic!("--insertion-cap must look like 8:2, not {:?}", cap),
+        }
+    }
+
+    if let Some(roi) = matches.value_of("roi") {
+        let numbers: Vec<u32> = roi
+            .split(&[',', 'x'][..])
+            .filter_map(|n| n.parse().ok())
+            .collect();
+        match numbers.as_slice() {
+            [x, y, width, height] => {
+                options.region_of_interest = Some(RegionOfInterest::new(*x, *y, *width, *height))
+            }
+            _ => panic!("--roi must look like 100,0,800x600, not {:?}", roi),
+        }
+    }
+
+    if let Some(path) = matches.value_of("remove") {
+        let mask = RemovalMask::from_luma(&image::open(path).unwrap());
+        options.energy = options.energy.removal_mask(&mask);
+    }
+
+    options.max_pixels = matches.value_of("max-pixels").map(|n| n.parse().unwrap());
+    options.oversize = match matches.value_of("oversize").unwrap() {
+        "pyramid" => OversizePolicy::Pyramid,
+        "downscale" => OversizePolicy::Downscale,
+        _ => OversizePolicy::Refuse,
+    };
+
+    options.carve_fraction = matches
+        .value_of("carve-fraction")
+        .map(|f| f.parse().unwrap());
+
+    if let Some(tolerance) = matches.value_of("near-optimal") {
+        let seed = matches.value_of("seed").unwrap().parse().unwrap();
+        options.near_optimal = Some(NearOptimal::new(tolerance.parse().unwrap(), seed));
+    }
+
+    #[cfg(feature = "mapio")]
+    {
+        if let Some(path) = matches.value_of("weights") {
+            let weights = pnmseam::mapio::load_weights(path).unwrap();
+            options.energy = options.energy.weights(&weights);
+        }
+    }
 
     let image = image::open(matches.value_of("imagefile").unwrap()).unwrap();
-    let newimage = seamcarve(&image, 896, 1079).unwrap();
+    let algorithm = match matches.value_of("algorithm").unwrap() {
+        "backward" => Algorithm::Backward,
+        _ => Algorithm::Forward,
+    };
+    let (newimage, mut report) = SeamCarver::new(&image)
+        .options(options)
+        .algorithm(algorithm)
+        .carve_with_report(896, 1079)
+        .unwrap();
+    let start = std::time::Instant::now();
     newimage.save("test-resize.png").unwrap();
+    report.timings.encode = start.elapsed();
+    for warning in &report.warnings {
+        eprintln!("pnmseam: {}", warning);
+    }
+
+    if let Some(path) = matches.value_of("insertion-order") {
+        let palette: Palette = matches.value_of("palette").unwrap().parse().unwrap();
+        match report.insertion_order_image_with(&palette, Rgb([255, 255, 255])) {
+            Some(picture) => picture.save(path).unwrap(),
+            None => eprintln!("pnmseam: the carve didn't enlarge, so there's no insertion order"),
+        }
+    }
+
+    if matches.is_present("timings") {
+        eprintln!("{:#?}", report.timings);
+    }
 }
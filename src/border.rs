@@ -0,0 +1,65 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Border handling
+//!
+//! The energy of a pixel is found from its neighbors, and the pixels
+//! along the edge of the image are missing some of them.  What stands
+//! in for a missing neighbor matters more than it looks: substituting
+//! the pixel itself, as the original calculator did, halves the
+//! gradient at the border, and the seams go there to take advantage.
+
+/// What to use in place of a neighbor that falls outside the image.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum BorderPolicy {
+	/// Repeat the edge pixel.  This is the historical behavior.
+	#[default]
+	Clamp,
+	/// Reflect about the edge pixel, so the neighbor of column 0 is
+	/// column 1.
+	Mirror,
+	/// Wrap around to the far edge, as if the image were tiled.
+	Wrap,
+	/// Treat everything outside the image as black.
+	Zero,
+}
+
+impl BorderPolicy {
+	// Map a coordinate that may lie outside (0..len) back into it, or
+	// None if the policy is to use a zero pixel instead.
+	pub(crate) fn resolve(self, at: i64, len: u32) -> Option<u32> {
+		let len = i64::from(len);
+		if at >= 0 && at < len {
+			return Some(at as u32);
+		}
+		let last = len - 1;
+		match self {
+			BorderPolicy::Clamp => Some(if at < 0 { 0 } else { last } as u32),
+			BorderPolicy::Mirror => {
+				let reflected = if at < 0 { -at } else { 2 * last - at };
+				Some(reflected.max(0).min(last) as u32)
+			}
+			BorderPolicy::Wrap => Some(at.rem_euclid(len) as u32),
+			BorderPolicy::Zero => None,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn policies_resolve_out_of_range_neighbors() {
+		assert_eq!(BorderPolicy::Clamp.resolve(-1, 5), Some(0));
+		assert_eq!(BorderPolicy::Clamp.resolve(5, 5), Some(4));
+		assert_eq!(BorderPolicy::Mirror.resolve(-1, 5), Some(1));
+		assert_eq!(BorderPolicy::Mirror.resolve(5, 5), Some(3));
+		assert_eq!(BorderPolicy::Mirror.resolve(-1, 1), Some(0));
+		assert_eq!(BorderPolicy::Wrap.resolve(-1, 5), Some(4));
+		assert_eq!(BorderPolicy::Wrap.resolve(5, 5), Some(0));
+		assert_eq!(BorderPolicy::Zero.resolve(-1, 5), None);
+		assert_eq!(BorderPolicy::Zero.resolve(2, 5), Some(2));
+	}
+}
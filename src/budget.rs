@@ -0,0 +1,264 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Region budgets
+//!
+//! Left to its own devices, the carver will happily take every seam
+//! out of the same low-energy corner of an image until that side of
+//! the composition is gone.  A region budget lays a rule-of-thirds
+//! grid over the image and limits how many seams may pass through
+//! each cell.  As a cell spends its budget its pixels are penalized,
+//! pushing later seams elsewhere; a cell that has spent all of it is
+//! walled off unless every route is walled.
+//!
+//! Enlarging has the opposite problem: the seams inserted are the
+//! cheapest ones, and on an image with one small smooth patch they
+//! all go through it, smearing it into a band.  An insertion cap
+//! limits how many seams may be inserted within any few consecutive
+//! pixels of each row (or column), walling off the rest of the
+//! window once the limit is reached.
+
+use crate::seamcarver::Carve;
+use crate::twodmap::TwoDimensionalMap;
+use image::{ImageBuffer, Luma};
+use std::cmp::{max, min};
+
+/// A limit on how many seams may pass through each cell of a
+/// rule-of-thirds grid.  The grid is measured on the image as it is
+/// when each seam is found, so the cells shrink along with the image.
+#[derive(Debug, Copy, Clone)]
+pub struct RegionBudget {
+	across: u32,
+	along: u32,
+	limit: u32,
+}
+
+impl RegionBudget {
+	/// Divide the image into thirds across the direction of the seam:
+	/// column thirds for vertical seams, row thirds for horizontal
+	/// ones.  At most `limit` seams may pass through each third.
+	pub fn thirds(limit: u32) -> Self {
+		RegionBudget {
+			across: 3,
+			along: 1,
+			limit,
+		}
+	}
+
+	/// Divide the image into ninths.  At most `limit` seams may pass
+	/// through each ninth.
+	pub fn ninths(limit: u32) -> Self {
+		RegionBudget {
+			across: 3,
+			along: 3,
+			limit,
+		}
+	}
+}
+
+/// A limit of `limit` seams inserted within any `window` consecutive
+/// columns of a row, when widening, or rows of a column, when
+/// heightening.  Positions are those of the image as it was before
+/// the insertions.
+#[derive(Debug, Copy, Clone)]
+pub struct InsertionCap {
+	window: u32,
+	limit: u32,
+}
+
+impl InsertionCap {
+	/// At most `limit` seams in any `window` consecutive pixels.  A
+	/// window of 1 limits the insertions at each pixel alone.
+	pub fn new(window: u32, limit: u32) -> Self {
+		InsertionCap {
+			window: max(window, 1),
+			limit,
+		}
+	}
+}
+
+// Where each pixel of an image being enlarged was along its line
+// before the enlarging started.
+pub(crate) type Origins = ImageBuffer<Luma<u32>, Vec<u32>>;
+
+// The insertions made so far along each line of an image being
+// enlarged, by position in the image before enlarging.
+pub(crate) struct InsertionTracker {
+	cap: InsertionCap,
+	counts: Vec<Vec<u32>>,
+}
+
+impl InsertionTracker {
+	pub fn new(cap: InsertionCap, lines: u32, length: u32) -> Self {
+		InsertionTracker {
+			cap,
+			counts: vec![vec![0; length as usize]; lines as usize],
+		}
+	}
+
+	pub fn record(&mut self, line: usize, position: u32) {
+		self.counts[line][position as usize] += 1;
+	}
+
+	// The positions along a line lying in a window that has had its
+	// limit of insertions.
+	fn full(&self, line: usize) -> Vec<bool> {
+		let counts = &self.counts[line];
+		let length = counts.len();
+		let window = min(self.cap.window as usize, length);
+		// Mark each full window by its ends, and sweep the marks into
+		// the positions they cover.
+		let mut marks = vec![0i64; length + 1];
+		let mut sum: u32 = counts[..window].iter().sum();
+		for start in 0..=length - window {
+			if start > 0 {
+				sum = sum + counts[start + window - 1] - counts[start - 1];
+			}
+			if sum >= self.cap.limit {
+				marks[start] += 1;
+				marks[start + window] -= 1;
+			}
+		}
+		let mut covering = 0;
+		marks[..length]
+			.iter()
+			.map(|mark| {
+				covering += mark;
+				covering > 0
+			})
+			.collect()
+	}
+
+	/// The penalty map to apply when finding the next seam to insert.
+	/// `origin` holds, for every pixel of the image the seam is being
+	/// found in, its position along its line before enlarging.
+	pub fn penalties(&self, origin: &Origins, carve: Carve) -> TwoDimensionalMap<u32> {
+		let (width, height) = origin.dimensions();
+		let wall = (u32::MAX / 4) / max(width, height);
+		let full: Vec<Vec<bool>> = (0..self.counts.len()).map(|line| self.full(line)).collect();
+		let mut penalties = TwoDimensionalMap::new(width, height);
+		for y in 0..height {
+			for x in 0..width {
+				let line = match carve {
+					Carve::Width => y,
+					Carve::Height => x,
+				};
+				if full[line as usize][origin[(x, y)][0] as usize] {
+					penalties[(x, y)] = wall;
+				}
+			}
+		}
+		penalties
+	}
+}
+
+// The running count of seams through each cell, kept separately for
+// vertical and horizontal seams, since they spend different
+// dimensions of the cell.
+pub(crate) struct RegionTracker {
+	budget: RegionBudget,
+	vertical: Vec<u32>,
+	horizontal: Vec<u32>,
+}
+
+impl RegionTracker {
+	pub fn new(budget: RegionBudget) -> Self {
+		let cells = (budget.across * budget.along) as usize;
+		RegionTracker {
+			budget,
+			vertical: vec![0; cells],
+			horizontal: vec![0; cells],
+		}
+	}
+
+	// The cell a pixel belongs to.  "Across" always runs perpendicular
+	// to the seam.
+	fn cell(&self, (x, y): (u32, u32), (width, height): (u32, u32), carve: Carve) -> usize {
+		let (a, l, across_len, along_len) = match carve {
+			Carve::Width => (x, y, width, height),
+			Carve::Height => (y, x, height, width),
+		};
+		let a = a * self.budget.across / across_len;
+		let l = l * self.budget.along / along_len;
+		(l * self.budget.across + a) as usize
+	}
+
+	fn counts(&self, carve: Carve) -> &Vec<u32> {
+		match carve {
+			Carve::Width => &self.vertical,
+			Carve::Height => &self.horizontal,
+		}
+	}
+
+	/// The penalty map to apply when finding the next seam in the
+	/// given direction.  The wall is sized so that a seam crossing
+	/// nothing but walls still can't overflow the seam's running sum.
+	pub fn penalties(&self, width: u32, height: u32, carve: Carve) -> TwoDimensionalMap<u32> {
+		let wall = (u32::MAX / 4) / max(width, height);
+		let limit = max(self.budget.limit, 1);
+		let counts = self.counts(carve);
+		let mut penalties = TwoDimensionalMap::new(width, height);
+		for y in 0..height {
+			for x in 0..width {
+				let count = min(counts[self.cell((x, y), (width, height), carve)], limit);
+				penalties[(x, y)] = wall / limit * count;
+			}
+		}
+		penalties
+	}
+
+	/// Charge the cells a seam passed through.  The dimensions are
+	/// those of the image the seam was found in.
+	pub fn record(&mut self, seam: &[u32], width: u32, height: u32, carve: Carve) {
+		let mut touched = vec![false; self.vertical.len()];
+		for (along, &across) in seam.iter().enumerate() {
+			let point = match carve {
+				Carve::Width => (across, along as u32),
+				Carve::Height => (along as u32, across),
+			};
+			touched[self.cell(point, (width, height), carve)] = true;
+		}
+		let counts = match carve {
+			Carve::Width => &mut self.vertical,
+			Carve::Height => &mut self.horizontal,
+		};
+		for (count, _) in counts.iter_mut().zip(touched).filter(|(_, t)| *t) {
+			*count += 1;
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn spent_thirds_are_walled_off() {
+		let mut tracker = RegionTracker::new(RegionBudget::thirds(2));
+		let seam = [0, 1, 0, 1];
+		tracker.record(&seam, 9, 4, Carve::Width);
+		let half = tracker.penalties(9, 4, Carve::Width);
+		tracker.record(&seam, 9, 4, Carve::Width);
+		tracker.record(&seam, 9, 4, Carve::Width);
+		let full = tracker.penalties(9, 4, Carve::Width);
+
+		assert!(half[(0, 0)] > 0);
+		assert_eq!(full[(2, 3)], 2 * half[(2, 3)]);
+		assert_eq!(full[(3, 0)], 0);
+		assert_eq!(full[(8, 3)], 0);
+		assert_eq!(tracker.penalties(9, 4, Carve::Height)[(0, 0)], 0);
+	}
+
+	#[test]
+	fn full_insertion_windows_are_walled_off() {
+		let mut tracker = InsertionTracker::new(InsertionCap::new(3, 2), 2, 10);
+		tracker.record(0, 4);
+		tracker.record(0, 5);
+		let origin = ImageBuffer::from_fn(10, 2, |x, _| Luma([x]));
+		let penalties = tracker.penalties(&origin, Carve::Width);
+		let walled: Vec<u32> = (0..10).filter(|&x| penalties[(x, 0)] > 0).collect();
+		assert_eq!(walled, [3, 4, 5, 6]);
+		assert!((0..10).all(|x| penalties[(x, 1)] == 0));
+	}
+}
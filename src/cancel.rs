@@ -0,0 +1,39 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Cancellation
+//!
+//! A carve of a large image can take seconds, and an application
+//! embedding the library may need to give up on one part way: the
+//! user closed the window, or asked for a different size.  A cancel
+//! token is a flag shared between the carve and whoever may abandon
+//! it.  The carve looks at it between seams, and between the rows of
+//! the forward-energy DP, and stops with `CarveError::Cancelled` once
+//! it's raised.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// A flag that abandons any carve given it, once raised.  Clones
+/// share the flag, so one can be handed to the carve and another kept
+/// to raise it from another thread.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+	/// A token not yet raised.
+	pub fn new() -> Self {
+		CancelToken::default()
+	}
+
+	/// Abandon every carve holding the token.
+	pub fn cancel(&self) {
+		self.0.store(true, Ordering::Relaxed);
+	}
+
+	/// Whether the token has been raised.
+	pub fn is_cancelled(&self) -> bool {
+		self.0.load(Ordering::Relaxed)
+	}
+}
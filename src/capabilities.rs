@@ -0,0 +1,122 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Capabilities
+//!
+//! What this build of the library can do.  Much of the crate is
+//! behind features, and an application embedding it can't always
+//! know which ones it was built with; this lets it ask, fill in its
+//! settings from the answer, and refuse a mode up front rather than
+//! failing halfway through a carve.
+
+/// The algorithms, energy functions, backends, and formats compiled
+/// into this build, by name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+	/// The seam finders: `avisha1` (backward energy), `avisha2`
+	/// (forward energy), and `banded` (approximate backward energy).
+	pub algorithms: Vec<&'static str>,
+
+	/// The ways energy can be calculated or adjusted.
+	pub energy_functions: Vec<&'static str>,
+
+	/// The scalar types energy can be kept in.
+	pub scalars: Vec<&'static str>,
+
+	/// The compute backends the seam DP can run on.
+	pub backends: Vec<&'static str>,
+
+	/// The image formats that can be read and written, and the
+	/// formats per-pixel maps can be read from and written to.
+	pub formats: Vec<&'static str>,
+}
+
+impl Capabilities {
+	/// The capabilities of the running build.
+	pub fn current() -> Self {
+		let mut energy_functions = vec![
+			"luma",
+			"luma-unit",
+			"importance",
+			"protection",
+			"edge-walls",
+			"premultiplied-alpha",
+		];
+		if cfg!(feature = "faces") {
+			energy_functions.push("faces");
+		}
+
+		// The image crate is built with its default decoders.
+		let mut formats = vec![
+			"png", "jpeg", "gif", "bmp", "ico", "tiff", "webp", "pnm", "tga", "hdr",
+		];
+		if cfg!(feature = "mapio") {
+			formats.extend(&["npy", "pfm", "png16"]);
+		}
+		if cfg!(feature = "ipc") {
+			formats.push("carve-events");
+		}
+		if cfg!(feature = "ndarray") {
+			formats.push("ndarray");
+		}
+
+		// The row kernel is written for the auto-vectorizer, so it's
+		// always present.
+		let mut backends = vec!["scalar", "autovec"];
+		if cfg!(feature = "gpu") {
+			backends.push("gpu");
+		}
+		if cfg!(feature = "opencl") {
+			backends.push("opencl");
+		}
+
+		Capabilities {
+			algorithms: vec!["avisha1", "avisha2", "banded"],
+			energy_functions,
+			scalars: vec!["u32", "u64", "f32", "f64", "fixed"],
+			backends,
+			formats,
+		}
+	}
+
+	/// Whether anything by the given name was compiled in.
+	pub fn supports(&self, name: &str) -> bool {
+		[
+			&self.algorithms,
+			&self.energy_functions,
+			&self.scalars,
+			&self.backends,
+			&self.formats,
+		]
+		.iter()
+		.any(|names| names.contains(&name))
+	}
+
+	/// As `supports`, but with an error naming the missing capability.
+	pub fn require(&self, name: &str) -> Result<(), String> {
+		if self.supports(name) {
+			Ok(())
+		} else {
+			Err(format!(
+				"'{}' is not available in this build of pnmseam",
+				name
+			))
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn reports_what_was_compiled_in() {
+		let caps = Capabilities::current();
+		assert!(caps.supports("avisha2"));
+		assert!(caps.supports("f64"));
+		assert_eq!(caps.supports("npy"), cfg!(feature = "mapio"));
+		assert_eq!(caps.supports("faces"), cfg!(feature = "faces"));
+		assert_eq!(caps.require("gpu").is_ok(), cfg!(feature = "gpu"));
+	}
+}
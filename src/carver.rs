@@ -0,0 +1,276 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! SeamCarver - the carve, configured in one place
+//!
+//! The free functions take a `CarveOptions`, which holds an
+//! `EnergyOptions`, and the seam finders are each their own type;
+//! choosing a finder, a border policy, and a mask means knowing which
+//! of them each lives in.  `SeamCarver` gathers them behind one
+//! builder: name the image, set whatever matters, and carve.
+//!
+//! ```no_run
+//! # use pnmseam::{Algorithm, BorderPolicy, SeamCarver};
+//! let image = image::open("photo.png").unwrap();
+//! let carved = SeamCarver::new(&image)
+//!     .algorithm(Algorithm::Backward)
+//!     .border(BorderPolicy::Mirror)
+//!     .carve(640, 480)
+//!     .unwrap();
+//! ```
+
+use crate::border::BorderPolicy;
+use crate::cancel::CancelToken;
+use crate::energyopts::EnergyOptions;
+use crate::error::CarveError;
+use crate::masks::{ProtectionMask, RemovalMask};
+use crate::report::CarveReport;
+use crate::seamcarver::{seamcarve_with_report, Algorithm, CarveLimits, CarveOptions};
+use crate::synthesis::Synthesis;
+use image::{GenericImageView, ImageBuffer, Pixel, Primitive};
+
+/// A carve of one image, configured a setting at a time.  Anything
+/// not set is as `CarveOptions::default()` has it.
+#[derive(Debug, Clone)]
+pub struct SeamCarver<'a, I> {
+	image: &'a I,
+	options: CarveOptions,
+}
+
+impl<'a, I, P, S> SeamCarver<'a, I>
+where
+	I: GenericImageView<Pixel = P>,
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	/// Takes a reference to an image, and holds onto it.
+	pub fn new(image: &'a I) -> Self {
+		SeamCarver {
+			image,
+			options: CarveOptions::default(),
+		}
+	}
+
+	/// Replace the carve options wholesale, for the settings without a
+	/// method of their own.  Anything set before is lost.
+	pub fn options(mut self, options: CarveOptions) -> Self {
+		self.options = options;
+		self
+	}
+
+	/// Find seams with the given algorithm.
+	pub fn algorithm(mut self, algorithm: Algorithm) -> Self {
+		self.options.algorithm = algorithm;
+		self
+	}
+
+	/// Replace the energy adjustments wholesale.  Any border policy
+	/// or mask set before is lost.
+	pub fn energy(mut self, energy: EnergyOptions) -> Self {
+		self.options.energy = energy;
+		self
+	}
+
+	/// What to use in place of the neighbors of the pixels at the
+	/// image's edge.
+	pub fn border(mut self, policy: BorderPolicy) -> Self {
+		self.options.energy = self.options.energy.border(policy);
+		self
+	}
+
+	/// Keep seams out of the marked pixels.
+	pub fn protect(mut self, mask: &ProtectionMask) -> Self {
+		self.options.energy = self.options.energy.protection_mask(mask);
+		self
+	}
+
+	/// Carve the marked object out of the image before resizing it.
+	pub fn remove(mut self, mask: &RemovalMask) -> Self {
+		self.options.energy = self.options.energy.removal_mask(mask);
+		self
+	}
+
+	/// Insert at most this fraction of the image's width or height in
+	/// one round of an enlargement.
+	pub fn insertion_ratio(mut self, ratio: f64) -> Self {
+		self.options.insertion_ratio = Some(ratio);
+		self
+	}
+
+	/// Make the pixels of inserted seams this way.
+	pub fn synthesis(mut self, synthesis: Synthesis) -> Self {
+		self.options.synthesis = synthesis;
+		self
+	}
+
+	/// Carve only this fraction of the change in size, and scale the
+	/// rest of the way.
+	pub fn carve_fraction(mut self, fraction: f64) -> Self {
+		self.options.carve_fraction = Some(fraction);
+		self
+	}
+
+	/// Refuse carves past these limits.
+	pub fn limits(mut self, limits: CarveLimits) -> Self {
+		self.options.limits = limits;
+		self
+	}
+
+	/// Carve past the limits anyway.
+	pub fn allow_extreme(mut self) -> Self {
+		self.options.allow_extreme = true;
+		self
+	}
+
+	/// Abandon the carve once the token is raised.
+	pub fn cancel_with(mut self, token: &CancelToken) -> Self {
+		self.options.cancel = Some(token.clone());
+		self
+	}
+
+	/// Use at most this many threads for the carve's DP, with the
+	/// `threaded` feature on.
+	pub fn threads(mut self, threads: usize) -> Self {
+		self.options.threads = Some(threads);
+		self
+	}
+
+	/// Time every seam, as well as the whole carve, in the report.
+	pub fn time_each_seam(mut self) -> Self {
+		self.options.track_seam_timings = true;
+		self
+	}
+
+	/// Carve the image to the new size.
+	pub fn carve(
+		&self,
+		newwidth: u32,
+		newheight: u32,
+	) -> Result<ImageBuffer<P, Vec<S>>, CarveError> {
+		self.carve_with_report(newwidth, newheight)
+			.map(|(image, _)| image)
+	}
+
+	/// As `carve`, returning a report of the carve along with the
+	/// carved image.
+	pub fn carve_with_report(
+		&self,
+		newwidth: u32,
+		newheight: u32,
+	) -> Result<(ImageBuffer<P, Vec<S>>, CarveReport), CarveError> {
+		seamcarve_with_report(self.image, newwidth, newheight, &self.options)
+	}
+
+	/// Enlarge the image to the new size, inserting seams in as many
+	/// rounds as the insertion ratio calls for.  A target smaller than
+	/// the image in either dimension is a `NotAnEnlargement`.
+	pub fn enlarge_to(
+		&self,
+		newwidth: u32,
+		newheight: u32,
+	) -> Result<ImageBuffer<P, Vec<S>>, CarveError> {
+		let (width, height) = self.image.dimensions();
+		if newwidth < width || newheight < height {
+			return Err(CarveError::NotAnEnlargement {
+				dimensions: (width, height),
+				target: (newwidth, newheight),
+			});
+		}
+		self.carve(newwidth, newheight)
+	}
+
+	/// Carve the image wider or narrower by `delta` pixels, leaving its
+	/// height alone: `carve_width(-120)` takes 120 columns out.
+	pub fn carve_width(&self, delta: i64) -> Result<ImageBuffer<P, Vec<S>>, CarveError> {
+		self.carve_by(delta, 0)
+	}
+
+	/// Carve the image taller or shorter by `delta` pixels, leaving its
+	/// width alone.
+	pub fn carve_height(&self, delta: i64) -> Result<ImageBuffer<P, Vec<S>>, CarveError> {
+		self.carve_by(0, delta)
+	}
+
+	/// Carve the image by the given change in each dimension, negative
+	/// to shrink and positive to grow.  A change that leaves nothing
+	/// of the image is an `InvalidTargetSize`.
+	pub fn carve_by(
+		&self,
+		width_delta: i64,
+		height_delta: i64,
+	) -> Result<ImageBuffer<P, Vec<S>>, CarveError> {
+		let (width, height) = self.image.dimensions();
+		self.carve(by(width, width_delta), by(height, height_delta))
+	}
+}
+
+// An extent changed by `delta`, held within what a dimension can be.
+fn by(extent: u32, delta: i64) -> u32 {
+	(i64::from(extent) + delta).clamp(0, i64::from(u32::MAX)) as u32
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::seamcarver::seamcarve_with_options;
+	use image::Luma;
+
+	#[test]
+	fn the_builder_carves_as_the_options_would() {
+		let buf: ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_fn(12, 9, |x, y| {
+			Luma([((x * 37 + y * 19 + x * y * 3) % 256) as u8])
+		});
+		let mut protection = ProtectionMask::new(12, 9);
+		for y in 0..9 {
+			protection.mark(4, y, true);
+		}
+		let carved = SeamCarver::new(&buf)
+			.algorithm(Algorithm::Backward)
+			.border(BorderPolicy::Mirror)
+			.protect(&protection)
+			.threads(2)
+			.carve(9, 9)
+			.unwrap();
+		let options = CarveOptions {
+			energy: EnergyOptions::new()
+				.border(BorderPolicy::Mirror)
+				.protection_mask(&protection),
+			algorithm: Algorithm::Backward,
+			..CarveOptions::default()
+		};
+		let expected = seamcarve_with_options(&buf, 9, 9, &options).unwrap();
+		assert_eq!(carved.into_raw(), expected.into_raw());
+
+		let forward = SeamCarver::new(&buf).carve(9, 9).unwrap();
+		assert_eq!(forward.dimensions(), (9, 9));
+		assert!(SeamCarver::new(&buf).carve(0, 9).is_err());
+	}
+
+	#[test]
+	fn deltas_carve_as_the_absolute_sizes_would() {
+		let buf: ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_fn(10, 8, |x, y| {
+			Luma([((x * 29 + y * 53 + x * y) % 256) as u8])
+		});
+		let carver = SeamCarver::new(&buf);
+		let narrower = carver.carve_width(-3).unwrap();
+		assert_eq!(narrower.into_raw(), carver.carve(7, 8).unwrap().into_raw());
+		assert_eq!(carver.carve_height(2).unwrap().dimensions(), (10, 10));
+		assert_eq!(carver.carve_by(-1, -2).unwrap().dimensions(), (9, 6));
+		assert!(matches!(
+			carver.enlarge_to(9, 12),
+			Err(CarveError::NotAnEnlargement {
+				target: (9, 12),
+				..
+			})
+		));
+		assert_eq!(carver.enlarge_to(13, 8).unwrap().dimensions(), (13, 8));
+		assert_eq!(
+			carver.carve_width(-20).unwrap_err(),
+			CarveError::InvalidTargetSize {
+				width: 0,
+				height: 8
+			}
+		);
+	}
+}
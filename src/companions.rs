@@ -0,0 +1,271 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Companion images
+//!
+//! An image seldom travels alone.  A training sample comes with its
+//! segmentation mask, a render with its depth and normal maps, and
+//! once the image is carved they no longer line up with it unless
+//! the same seams come out of them too.  Their own seams would be
+//! all wrong (a depth map's energy is nothing like the picture's), so
+//! the seams are found in the image, and replayed, from the events
+//! the carve sent, on each companion.
+//!
+//! Where the carve inserted seams, a companion's pixel is copied
+//! rather than blended with its neighbor: halfway between two labels
+//! of a mask, or two directions of a normal map, is nothing that was
+//! in either.
+
+use crate::cq;
+use crate::error::CarveError;
+use crate::report::{CarveEvent, CarveReport};
+use crate::seamcarver::{
+	insert_seams_with, remove_seam_in_place, seamcarve_with_events, Carve, CarveOptions,
+};
+use crate::seamfinder::{Orientation, Seam};
+use image::{GenericImageView, ImageBuffer, Pixel, Primitive};
+
+/// As `seamcarve_with_report`, carving the same seams out of each of
+/// the image's companions, which must all be its size, in place.
+/// Companions of other pixel types can be carved afterwards with
+/// `replay_seams`, from the events of a carve made with
+/// `seamcarve_with_events`.
+pub fn seamcarve_with_companions<I, P, S, Q, T>(
+	image: &I,
+	newwidth: u32,
+	newheight: u32,
+	options: &CarveOptions,
+	companions: &mut [ImageBuffer<Q, Vec<T>>],
+) -> Result<(ImageBuffer<P, Vec<S>>, CarveReport), CarveError>
+where
+	I: GenericImageView<Pixel = P>,
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+	Q: Pixel<Subpixel = T> + 'static,
+	T: Primitive + 'static,
+{
+	for companion in companions.iter() {
+		if companion.dimensions() != image.dimensions() {
+			return Err(CarveError::CompanionDimensionMismatch {
+				expected: image.dimensions(),
+				found: companion.dimensions(),
+			});
+		}
+	}
+	let mut events = Vec::new();
+	let (carved, report) =
+		seamcarve_with_events(image, newwidth, newheight, options, &mut |event| {
+			events.push(event.clone())
+		})?;
+	for companion in companions.iter_mut() {
+		*companion = replay_seams(&events, companion)?;
+	}
+	Ok((carved, report))
+}
+
+/// Carve the seams of a finished carve, as its events recorded them,
+/// out of another image the size of the one carved, inserting them
+/// too where the carve did.  A carve of an oversized image that was
+/// downscaled first found its seams in the smaller copy, and its
+/// events can't be replayed on anything the size of the original.
+pub fn replay_seams<C, Q, T>(
+	events: &[CarveEvent],
+	companion: &C,
+) -> Result<ImageBuffer<Q, Vec<T>>, CarveError>
+where
+	C: GenericImageView<Pixel = Q>,
+	Q: Pixel<Subpixel = T> + 'static,
+	T: Primitive + 'static,
+{
+	replay_events(events, companion, |pixel, _| *pixel)
+}
+
+// Replay the events on the image, making each inserted pixel with
+// `mix` from the seam's pixel and its neighbor.  The events may have
+// been read back from anywhere, so every seam is checked against the
+// image as it is when the seam comes to it.
+pub(crate) fn replay_events<C, Q, T>(
+	events: &[CarveEvent],
+	companion: &C,
+	mix: impl Fn(&Q, &Q) -> Q,
+) -> Result<ImageBuffer<Q, Vec<T>>, CarveError>
+where
+	C: GenericImageView<Pixel = Q>,
+	Q: Pixel<Subpixel = T> + 'static,
+	T: Primitive + 'static,
+{
+	let (width, height) = companion.dimensions();
+	let mut image = ImageBuffer::from_fn(width, height, |x, y| companion.get_pixel(x, y));
+	for event in events {
+		match event {
+			CarveEvent::Started { width, height, .. } => {
+				check(&image, (*width, *height))?;
+			}
+			CarveEvent::Removed { orientation, path } => {
+				let direction = direction_of(*orientation);
+				check(&image, expected(&image, direction, path.len()))?;
+				Seam::new(*orientation, path.clone(), 0.0, image.dimensions())?;
+				image = remove_seam_in_place(image, path, direction);
+			}
+			CarveEvent::Inserted {
+				orientation,
+				positions,
+			} => {
+				let direction = direction_of(*orientation);
+				check(&image, expected(&image, direction, positions.len()))?;
+				check_insertions(&image, *orientation, positions)?;
+				let positions: Vec<Vec<(u32, u32)>> = positions
+					.iter()
+					.map(|line| line.iter().map(|&at| (at, 0)).collect())
+					.collect();
+				image = insert_seams_with(&image, &positions, direction, &mix);
+			}
+			CarveEvent::Finished { .. } => {}
+		}
+	}
+	Ok(image)
+}
+
+fn direction_of(orientation: Orientation) -> Carve {
+	match orientation {
+		Orientation::Vertical => Carve::Width,
+		Orientation::Horizontal => Carve::Height,
+	}
+}
+
+// The dimensions an image must have for a seam `lines` long to run
+// through it in the given direction.
+fn expected<P: Pixel + 'static>(
+	image: &ImageBuffer<P, Vec<P::Subpixel>>,
+	direction: Carve,
+	lines: usize,
+) -> (u32, u32) {
+	match direction {
+		Carve::Width => (image.width(), lines as u32),
+		Carve::Height => (lines as u32, image.height()),
+	}
+}
+
+fn check<P: Pixel + 'static>(
+	image: &ImageBuffer<P, Vec<P::Subpixel>>,
+	expected: (u32, u32),
+) -> Result<(), CarveError> {
+	if image.dimensions() != expected {
+		return Err(CarveError::CompanionDimensionMismatch {
+			expected,
+			found: image.dimensions(),
+		});
+	}
+	Ok(())
+}
+
+// Refuse insertions that don't put the same number of pixels in every
+// line, or that duplicate a pixel outside the image.
+fn check_insertions<P: Pixel + 'static>(
+	image: &ImageBuffer<P, Vec<P::Subpixel>>,
+	orientation: Orientation,
+	positions: &[Vec<u32>],
+) -> Result<(), CarveError> {
+	let (width, height) = image.dimensions();
+	let across = cq!(orientation == Orientation::Vertical, width, height);
+	let count = positions.first().map_or(0, |line| line.len());
+	for line in positions {
+		if line.len() != count {
+			return Err(CarveError::InvalidSeam(format!(
+				"{:?} insertions into a {}x{} image put {} pixels in one line and {} in another",
+				orientation,
+				width,
+				height,
+				count,
+				line.len()
+			)));
+		}
+		if let Some(at) = line.iter().find(|&&at| at >= across) {
+			return Err(CarveError::InvalidSeam(format!(
+				"a {:?} seam inserted into a {}x{} image can't pass through {}",
+				orientation, width, height, at
+			)));
+		}
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use image::{Luma, Rgb};
+
+	#[test]
+	fn companions_lose_and_gain_the_same_seams() {
+		let buf: ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_fn(10, 8, |x, y| {
+			Luma([((x * 37 + y * 19 + x * y * 5) % 256) as u8])
+		});
+		// Each companion pixel says where it came from, so the carved
+		// companions can be checked against the carved image.
+		let origins: ImageBuffer<Rgb<u8>, Vec<u8>> =
+			ImageBuffer::from_fn(10, 8, |x, y| Rgb([x as u8, y as u8, 0]));
+		let options = CarveOptions::default();
+		let mut companions = [origins.clone()];
+		let (carved, _) = seamcarve_with_companions(&buf, 7, 6, &options, &mut companions).unwrap();
+		assert_eq!(companions[0].dimensions(), (7, 6));
+		for (x, y, pixel) in companions[0].enumerate_pixels() {
+			let Rgb([ox, oy, _]) = *pixel;
+			assert_eq!(carved[(x, y)], buf[(u32::from(ox), u32::from(oy))]);
+		}
+
+		// Inserted pixels are copies, one beside each seam pixel.
+		let mut companions = [origins];
+		let (_, report) =
+			seamcarve_with_companions(&buf, 10, 10, &options, &mut companions).unwrap();
+		assert_eq!(report.height_seams_inserted, 2);
+		for x in 0..10 {
+			let copies = (1..10).filter(|&y| companions[0][(x, y)] == companions[0][(x, y - 1)]);
+			assert_eq!(copies.count(), 2);
+		}
+
+		let mut wrong: [ImageBuffer<Luma<u8>, Vec<u8>>; 1] = [ImageBuffer::new(9, 8)];
+		assert_eq!(
+			seamcarve_with_companions(&buf, 7, 10, &options, &mut wrong).unwrap_err(),
+			CarveError::CompanionDimensionMismatch {
+				expected: (10, 8),
+				found: (9, 8)
+			}
+		);
+	}
+
+	#[test]
+	fn corrupted_events_are_refused() {
+		let buf: ImageBuffer<Luma<u8>, Vec<u8>> =
+			ImageBuffer::from_fn(6, 4, |x, y| Luma([(x * 40 + y * 9) as u8]));
+		let started = CarveEvent::Started {
+			width: 6,
+			height: 4,
+			target_width: 5,
+			target_height: 5,
+		};
+		let replay = |event: CarveEvent| replay_seams(&[started.clone(), event], &buf);
+		let invalid = |result: Result<ImageBuffer<Luma<u8>, Vec<u8>>, CarveError>| {
+			matches!(result, Err(CarveError::InvalidSeam(_)))
+		};
+
+		// A seam that leaves the image, and one that jumps sideways.
+		let removed = |path: Vec<u32>| CarveEvent::Removed {
+			orientation: Orientation::Vertical,
+			path,
+		};
+		assert!(invalid(replay(removed(vec![2, 3, 6, 5]))));
+		assert!(invalid(replay(removed(vec![0, 0, 4, 4]))));
+		assert!(replay(removed(vec![2, 3, 4, 5])).is_ok());
+
+		// Insertions outside the image, or more in one line than another.
+		let inserted = |positions: Vec<Vec<u32>>| CarveEvent::Inserted {
+			orientation: Orientation::Horizontal,
+			positions,
+		};
+		let lines = |line: Vec<u32>| vec![vec![1]; 5].into_iter().chain(Some(line)).collect();
+		assert!(invalid(replay(inserted(lines(vec![4])))));
+		assert!(invalid(replay(inserted(lines(vec![1, 2])))));
+		assert!(replay(inserted(lines(vec![3]))).is_ok());
+	}
+}
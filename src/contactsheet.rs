@@ -0,0 +1,142 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Contact sheets
+//!
+//! A contact sheet lays a shoot out on a grid of equal cells.  The
+//! usual ways of getting a photograph into a cell of another shape
+//! are to squash it or to crop it, and both lose something a
+//! photographer reviewing the shoot wants to see.  Here each image is
+//! first scaled until it just covers its cell, and the excess in the
+//! one direction that still overflows is carved away, so the subject
+//! keeps its proportions and the edges of the frame survive.
+
+use crate::error::CarveError;
+use crate::seamcarver::{seamcarve_with_options, CarveOptions};
+use image::{imageops, FilterType, GenericImage, GenericImageView, ImageBuffer, Pixel, Primitive};
+
+/// The shape of a contact sheet: `columns` cells across, as many rows
+/// as the images need, each cell `cell_width` by `cell_height` pixels
+/// and separated from its neighbors by `gutter` pixels of
+/// `background`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SheetStyle<P: Pixel> {
+	/// The number of cells across the sheet.
+	pub columns: u32,
+	/// The width of each cell, in pixels.
+	pub cell_width: u32,
+	/// The height of each cell, in pixels.
+	pub cell_height: u32,
+	/// The space between and around the cells, in pixels.
+	pub gutter: u32,
+	/// The color of the gutters, and of any empty cells.
+	pub background: P,
+}
+
+/// Bring an image to exactly the given size: scale it, preserving its
+/// aspect ratio, until it covers the cell, then carve away whatever
+/// still overflows.
+pub fn fit_to_cell<I, P, S>(
+	image: &I,
+	cell_width: u32,
+	cell_height: u32,
+	options: &CarveOptions,
+) -> Result<ImageBuffer<P, Vec<S>>, CarveError>
+where
+	I: GenericImageView<Pixel = P>,
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	let (width, height) = image.dimensions();
+	if width == 0 || height == 0 {
+		return Err(CarveError::ImageTooSmall { width, height });
+	}
+	let scale = f64::max(
+		f64::from(cell_width) / f64::from(width),
+		f64::from(cell_height) / f64::from(height),
+	);
+	let covering =
+		|extent: u32, cell: u32| std::cmp::max(cell, (f64::from(extent) * scale).round() as u32);
+	let scaled = imageops::resize(
+		image,
+		covering(width, cell_width),
+		covering(height, cell_height),
+		FilterType::Triangle,
+	);
+	seamcarve_with_options(&scaled, cell_width, cell_height, options)
+}
+
+/// Fit every image to a cell of the sheet, in order left to right and
+/// top to bottom, and lay them out on a new sheet.
+pub fn contact_sheet<I, P, S>(
+	images: &[I],
+	style: SheetStyle<P>,
+	options: &CarveOptions,
+) -> Result<ImageBuffer<P, Vec<S>>, String>
+where
+	I: GenericImageView<Pixel = P>,
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	if style.columns == 0 {
+		return Err("a contact sheet needs at least one column".to_string());
+	}
+	let rows = (images.len() as u32 + style.columns - 1) / style.columns;
+	let stride = |count: u32, cell: u32| count * (cell + style.gutter) + style.gutter;
+	let mut sheet = ImageBuffer::from_pixel(
+		stride(style.columns, style.cell_width),
+		stride(rows, style.cell_height),
+		style.background,
+	);
+	for (index, image) in images.iter().enumerate() {
+		let (column, row) = (index as u32 % style.columns, index as u32 / style.columns);
+		let cell = fit_to_cell(image, style.cell_width, style.cell_height, options)
+			.map_err(|e| format!("image {}: {}", index, e))?;
+		let (x, y) = (
+			stride(column, style.cell_width),
+			stride(row, style.cell_height),
+		);
+		if !sheet.copy_from(&cell, x, y) {
+			return Err(format!("image {} doesn't fit its cell", index));
+		}
+	}
+	Ok(sheet)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use image::{Rgb, RgbImage};
+
+	#[test]
+	fn every_image_fills_its_cell() {
+		let wide: RgbImage =
+			ImageBuffer::from_fn(24, 8, |x, y| Rgb([(x * 10) as u8, (y * 30) as u8, 90]));
+		let tall: RgbImage =
+			ImageBuffer::from_fn(6, 20, |x, y| Rgb([(y * 12) as u8, 40, (x * 40) as u8]));
+		let style = SheetStyle {
+			columns: 2,
+			cell_width: 8,
+			cell_height: 6,
+			gutter: 2,
+			background: Rgb([255, 0, 255]),
+		};
+		let images = [wide.clone(), tall, wide];
+		let sheet = contact_sheet(&images, style, &CarveOptions::default()).unwrap();
+		assert_eq!(sheet.dimensions(), (2 + 2 * 10, 2 + 2 * 8));
+
+		// The gutters, and the empty fourth cell, are background; the
+		// three filled cells have none of it.
+		let magenta = Rgb([255, 0, 255]);
+		assert_eq!(sheet[(0, 0)], magenta);
+		assert_eq!(sheet[(15, 12)], magenta);
+		for (left, top) in [(2, 2), (12, 2), (2, 10)].iter() {
+			for y in *top..*top + 6 {
+				for x in *left..*left + 8 {
+					assert_ne!(sheet[(x, y)], magenta, "({}, {})", x, y);
+				}
+			}
+		}
+	}
+}
@@ -0,0 +1,150 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! JPEG block artifacts
+//!
+//! A heavily compressed JPEG is a grid of 8×8 blocks, each smooth on
+//! the inside, that don't quite agree with their neighbors where they
+//! meet.  Gradient energy sees every one of those small steps, and so
+//! a sky or a wall that a seam could pass through invisibly looks to
+//! the finder like a field of faint lines to be avoided.
+//!
+//! This finds the block boundaries that look like compression rather
+//! than content: a small step between two pixels, with little going on
+//! to either side of it, repeated along most of the boundary between
+//! two blocks.  Real edges are either too strong, or don't fall on the
+//! grid along its whole length.  The result is a map of multipliers
+//! that the energy options use to damp the energy along those
+//! boundaries.
+
+use crate::cq;
+use crate::pixelpairs::channel_max;
+use image::{GenericImageView, ImageBuffer, Luma, Pixel, Primitive};
+use num_traits::NumCast;
+
+// The JPEG block size.
+const BLOCK: u32 = 8;
+
+// The largest step, on an 8-bit luma scale, that's taken to be a
+// compression artifact rather than an edge in the picture.
+const MAX_STEP: f32 = 24.0;
+
+// How many of the eight pixel pairs along a block boundary have to
+// look like an artifact before the boundary is damped.
+const QUORUM: usize = 6;
+
+/// Find the block boundaries in an image that look like JPEG
+/// compression artifacts, and return a map of energy multipliers the
+/// size of the image: `1.0 - strength` on either side of those
+/// boundaries, and `1.0` everywhere else.  `strength` is clamped to
+/// the range 0 to 1.  The block grid is assumed to start at the
+/// image's top left corner, as it does in any image that hasn't been
+/// cropped since it was decoded.
+pub fn block_artifacts<I, P, S>(image: &I, strength: f32) -> ImageBuffer<Luma<f32>, Vec<f32>>
+where
+	I: GenericImageView<Pixel = P>,
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	let (width, height) = image.dimensions();
+	let scale = channel_max::<S>() as f32;
+	let luma = |x: u32, y: u32| -> f32 {
+		let l: f32 = NumCast::from(image.get_pixel(x, y).to_luma()[0]).unwrap();
+		l * 255.0 / scale
+	};
+	let damped = 1.0 - strength.clamp(0.0, 1.0);
+	let mut multipliers = ImageBuffer::from_pixel(width, height, Luma([1.0f32]));
+
+	// Does the step between the pixels at `at(0)` and `at(1)` stand
+	// out from the steps on either side of it?  Those are `at(-1)` and
+	// `at(2)`, where they exist.
+	let artifact = |at: &dyn Fn(i64) -> Option<f32>| {
+		let (before, after) = (at(0).unwrap(), at(1).unwrap());
+		let step = (after - before).abs();
+		let inside = |a: Option<f32>, b: f32| a.map_or(0.0, |a| (a - b).abs());
+		let activity = inside(at(-1), before).max(inside(at(2), after));
+		step > 0.0 && step <= MAX_STEP && step > 2.0 * activity
+	};
+
+	// Vertical boundaries, between columns x - 1 and x.
+	for x in (BLOCK..width).step_by(BLOCK as usize) {
+		for top in (0..height).step_by(BLOCK as usize) {
+			let rows: Vec<u32> = (top..std::cmp::min(top + BLOCK, height)).collect();
+			let hits = rows
+				.iter()
+				.filter(|&&y| {
+					artifact(&|d: i64| {
+						let column = x as i64 - 1 + d;
+						cq!(
+							column >= 0 && column < width as i64,
+							Some(luma(column as u32, y)),
+							None
+						)
+					})
+				})
+				.count();
+			if hits >= std::cmp::min(QUORUM, rows.len()) {
+				for y in rows {
+					multipliers[(x - 1, y)] = Luma([damped]);
+					multipliers[(x, y)] = Luma([damped]);
+				}
+			}
+		}
+	}
+
+	// Horizontal boundaries, between rows y - 1 and y.
+	for y in (BLOCK..height).step_by(BLOCK as usize) {
+		for left in (0..width).step_by(BLOCK as usize) {
+			let columns: Vec<u32> = (left..std::cmp::min(left + BLOCK, width)).collect();
+			let hits = columns
+				.iter()
+				.filter(|&&x| {
+					artifact(&|d: i64| {
+						let row = y as i64 - 1 + d;
+						cq!(
+							row >= 0 && row < height as i64,
+							Some(luma(x, row as u32)),
+							None
+						)
+					})
+				})
+				.count();
+			if hits >= std::cmp::min(QUORUM, columns.len()) {
+				for x in columns {
+					multipliers[(x, y - 1)] = Luma([damped]);
+					multipliers[(x, y)] = Luma([damped]);
+				}
+			}
+		}
+	}
+
+	multipliers
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use image::GrayImage;
+
+	#[test]
+	fn block_steps_are_damped_and_edges_are_not() {
+		// Two flat blocks a few levels apart, then a hard edge between
+		// the second and third.
+		let image: GrayImage = ImageBuffer::from_fn(24, 8, |x, _| {
+			Luma([match x / 8 {
+				0 => 100,
+				1 => 106,
+				_ => 250,
+			}])
+		});
+		let damping = block_artifacts(&image, 0.75);
+		for y in 0..8 {
+			assert_eq!(damping[(7, y)][0], 0.25);
+			assert_eq!(damping[(8, y)][0], 0.25);
+			assert_eq!(damping[(15, y)][0], 1.0);
+			assert_eq!(damping[(16, y)][0], 1.0);
+			assert_eq!(damping[(3, y)][0], 1.0);
+		}
+	}
+}
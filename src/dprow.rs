@@ -0,0 +1,256 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! The seam DP row kernel
+//!
+//! Every pixel of the seam digraph picks the cheapest of the (up to)
+//! three pixels above it as its parent.  Done with `min_by_key` over
+//! a little range, that's a branchy, bounds-checked mess per pixel.
+//! This kernel does a whole row at once over plain slices: the
+//! interior is a window of three, and the choice is made with
+//! selects rather than branches, which is the shape LLVM's
+//! auto-vectorizer wants.  (`std::simd` would say the same thing more
+//! explicitly, but it isn't available on stable Rust.)
+//!
+//! Ties go to the leftmost candidate, as `min_by_key` does, unless a
+//! `TieBreak` says otherwise.  On a flat region every candidate ties,
+//! and always going left leans every seam found there the same way.
+
+use crate::cq;
+
+/// How the seam DP chooses among equally cheap parents, and among
+/// equally cheap columns to end a seam at.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum TieBreak {
+	/// The leftmost (or topmost) candidate.
+	#[default]
+	Leftmost,
+	/// The rightmost (or bottommost) candidate.
+	Rightmost,
+	/// The parent straight above, and the column nearest the middle.
+	Center,
+	/// Leftmost on even rows and rightmost on odd ones; a seam ends at
+	/// the leftmost column of an image of even width and the
+	/// rightmost of one of odd width, so that successive seams of a
+	/// carve, each a column narrower, alternate.
+	Alternating,
+}
+
+impl TieBreak {
+	// The rule for the parents of row y: alternating is the one place
+	// the row matters.
+	fn for_row(self, y: u32) -> TieBreak {
+		match self {
+			TieBreak::Alternating => {
+				cq!(y % 2 == 0, TieBreak::Leftmost, TieBreak::Rightmost)
+			}
+			rule => rule,
+		}
+	}
+
+	// The cheapest of the left, center, and right candidates for the
+	// pixel in column x of row y, of a row whose last column is
+	// `last`.  When a candidate doesn't exist, the center is passed in
+	// its place.
+	#[inline(always)]
+	pub(crate) fn pick<T: PartialOrd + Copy>(
+		self,
+		y: u32,
+		(left, center, right): (T, T, T),
+		x: u32,
+		last: u32,
+	) -> (T, u32) {
+		match self.for_row(y) {
+			TieBreak::Rightmost => pick_right(left, center, right, x, last),
+			TieBreak::Center => pick_center(left, center, right, x, last),
+			_ => pick(left, center, right, x),
+		}
+	}
+
+	/// The column to end a seam at, given the cost of the cheapest
+	/// seam ending at each column of the bottom row.
+	pub fn end_column<T: PartialOrd + Copy>(self, bottom: &[T]) -> u32 {
+		// Energies are only partially ordered, so this is a fold rather
+		// than min_by_key.
+		let width = bottom.len() as u32;
+		let cheapest = (1..width).fold(0, |best, x| {
+			cq!(bottom[x as usize] < bottom[best as usize], x, best)
+		});
+		let ties = || (0..width).filter(|x| bottom[*x as usize] <= bottom[cheapest as usize]);
+		let rightmost = || ties().next_back().unwrap_or(cheapest);
+		match self {
+			TieBreak::Leftmost => cheapest,
+			TieBreak::Rightmost => rightmost(),
+			TieBreak::Center => ties()
+				.min_by_key(|x| (2 * i64::from(*x) - i64::from(width) + 1).abs())
+				.unwrap_or(cheapest),
+			TieBreak::Alternating => cq!(width % 2 == 0, cheapest, rightmost()),
+		}
+	}
+}
+
+/// Given the running costs of the row above, write the cost of the
+/// cheapest parent of each pixel into `costs`, and that parent's
+/// column into `parents`.  All three slices must be the same length.
+pub fn min_parents<T: PartialOrd + Copy>(above: &[T], costs: &mut [T], parents: &mut [u32]) {
+	kernel(above, costs, parents, pick);
+}
+
+/// As `min_parents`, for row `y`, with ties broken as given.
+pub fn min_parents_by<T: PartialOrd + Copy>(
+	above: &[T],
+	costs: &mut [T],
+	parents: &mut [u32],
+	ties: TieBreak,
+	y: u32,
+) {
+	let last = above.len().saturating_sub(1) as u32;
+	match ties.for_row(y) {
+		TieBreak::Rightmost => kernel(above, costs, parents, |l, c, r, x| {
+			pick_right(l, c, r, x, last)
+		}),
+		TieBreak::Center => kernel(above, costs, parents, |l, c, r, x| {
+			pick_center(l, c, r, x, last)
+		}),
+		_ => kernel(above, costs, parents, pick),
+	}
+}
+
+// The row, with the choice among each pixel's candidates made by
+// `pick`.  Each rule gets its own copy of the loop to vectorize.
+#[inline(always)]
+fn kernel<T, F>(above: &[T], costs: &mut [T], parents: &mut [u32], pick: F)
+where
+	T: PartialOrd + Copy,
+	F: Fn(T, T, T, u32) -> (T, u32),
+{
+	let width = above.len();
+	assert!(costs.len() == width && parents.len() == width);
+	if width == 0 {
+		return;
+	}
+	if width == 1 {
+		costs[0] = above[0];
+		parents[0] = 0;
+		return;
+	}
+
+	// The edges only have two candidates.
+	let (cost, parent) = pick(above[0], above[0], above[1], 0);
+	costs[0] = cost;
+	parents[0] = parent;
+	let last = width - 1;
+	let (cost, parent) = pick(above[last - 1], above[last], above[last], last as u32);
+	costs[last] = cost;
+	parents[last] = parent;
+
+	let interior = above
+		.windows(3)
+		.zip(costs[1..last].iter_mut())
+		.zip(parents[1..last].iter_mut());
+	for (x, ((window, cost), parent)) in interior.enumerate() {
+		let (c, p) = pick(window[0], window[1], window[2], x as u32 + 1);
+		*cost = c;
+		*parent = p;
+	}
+}
+
+// Choose among left, center, and right for the pixel in column x.
+// When a candidate doesn't exist, the center is passed in its place,
+// which picks the same column either way.
+#[inline(always)]
+fn pick<T: PartialOrd + Copy>(left: T, center: T, right: T, x: u32) -> (T, u32) {
+	let right_wins = right < center;
+	let (m, i) = (
+		if right_wins { right } else { center },
+		if right_wins { x + 1 } else { x },
+	);
+	let left_wins = left <= m;
+	let left_x = if x == 0 { 0 } else { x - 1 };
+	(
+		if left_wins { left } else { m },
+		if left_wins { left_x } else { i },
+	)
+}
+
+// As `pick`, with ties going right.
+#[inline(always)]
+fn pick_right<T: PartialOrd + Copy>(left: T, center: T, right: T, x: u32, last: u32) -> (T, u32) {
+	let left_wins = left < center;
+	let (m, i) = (
+		if left_wins { left } else { center },
+		if left_wins { x - 1 } else { x },
+	);
+	let right_wins = right <= m;
+	let right_x = if x == last { x } else { x + 1 };
+	(
+		if right_wins { right } else { m },
+		if right_wins { right_x } else { i },
+	)
+}
+
+// As `pick`, with ties going straight up, and then left.
+#[inline(always)]
+fn pick_center<T: PartialOrd + Copy>(left: T, center: T, right: T, x: u32, last: u32) -> (T, u32) {
+	if center <= left && center <= right {
+		(center, x)
+	} else if left <= right {
+		(left, x.saturating_sub(1))
+	} else {
+		(right, std::cmp::min(x + 1, last))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn agrees_with_min_by_key() {
+		let mut seed = 0x2545_f491u32;
+		let mut next = || {
+			seed ^= seed << 13;
+			seed ^= seed >> 17;
+			seed ^= seed << 5;
+			seed % 7
+		};
+		for width in 1..12usize {
+			let above: Vec<u32> = (0..width).map(|_| next()).collect();
+			let mut costs = vec![0; width];
+			let mut parents = vec![0; width];
+			min_parents(&above, &mut costs, &mut parents);
+			for x in 0..width {
+				let range = x.saturating_sub(1)..=std::cmp::min(x + 1, width - 1);
+				let best = range.min_by_key(|x| above[*x]).unwrap();
+				assert_eq!(
+					(costs[x], parents[x]),
+					(above[best], best as u32),
+					"{:?}",
+					above
+				);
+			}
+		}
+	}
+
+	#[test]
+	fn ties_go_where_they_are_told() {
+		let above = [3u32, 3, 3, 5];
+		let run = |ties, y| {
+			let (mut costs, mut parents) = (vec![0; 4], vec![0; 4]);
+			min_parents_by(&above, &mut costs, &mut parents, ties, y);
+			parents
+		};
+		assert_eq!(run(TieBreak::Leftmost, 0), vec![0, 0, 1, 2]);
+		assert_eq!(run(TieBreak::Rightmost, 0), vec![1, 2, 2, 2]);
+		assert_eq!(run(TieBreak::Center, 0), vec![0, 1, 2, 2]);
+		assert_eq!(run(TieBreak::Alternating, 1), run(TieBreak::Rightmost, 0));
+
+		let bottom = [4u32, 2, 9, 2, 2, 7];
+		assert_eq!(TieBreak::Leftmost.end_column(&bottom), 1);
+		assert_eq!(TieBreak::Rightmost.end_column(&bottom), 4);
+		assert_eq!(TieBreak::Center.end_column(&bottom), 3);
+		assert_eq!(TieBreak::Alternating.end_column(&bottom), 1);
+		assert_eq!(TieBreak::Alternating.end_column(&bottom[..5]), 4);
+	}
+}
@@ -0,0 +1,135 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Edge detection
+//!
+//! Plain gradient energy sees a long, thin edge as a line of
+//! moderately energetic pixels, and a seam will happily nick it
+//! wherever it's a little weaker, leaving a visible kink in a
+//! horizon or a lamp post.  A Canny edge detector finds those edges
+//! as continuous one-pixel lines, which the energy options can then
+//! turn into walls.
+//!
+//! This is the textbook detector: Sobel gradients over luma,
+//! non-maximum suppression along the gradient direction, and
+//! hysteresis between a low and a high threshold.  The thresholds are
+//! gradient magnitudes on an 8-bit luma scale, so the largest
+//! possible magnitude is a little over 1,400.
+
+use crate::pixelpairs::channel_max;
+use image::{GenericImageView, GrayImage, ImageBuffer, Luma, Pixel, Primitive};
+use num_traits::NumCast;
+
+/// Find the edges in an image.  Pixels on an edge are white in the
+/// returned mask, and everything else is black.  Edges must reach
+/// `high` somewhere along their length, and are followed for as long
+/// as they stay above `low`.
+pub fn detect_edges<I, P, S>(image: &I, low: f32, high: f32) -> GrayImage
+where
+	I: GenericImageView<Pixel = P>,
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	let (width, height) = image.dimensions();
+	let scale = channel_max::<S>() as f32;
+	let (w, h) = (width as i64, height as i64);
+	let index = |x: i64, y: i64| (y.max(0).min(h - 1) * w + x.max(0).min(w - 1)) as usize;
+
+	let mut luma = Vec::with_capacity((width * height) as usize);
+	for y in 0..height {
+		for x in 0..width {
+			let l: f32 = NumCast::from(image.get_pixel(x, y).to_luma()[0]).unwrap();
+			luma.push(l * 255.0 / scale);
+		}
+	}
+
+	// Sobel gradients, with the direction quantized to one of four
+	// neighbor axes: 0°, 45°, 90°, and 135°.
+	let mut magnitude = vec![0.0f32; luma.len()];
+	let mut direction = vec![0u8; luma.len()];
+	for y in 0..h {
+		for x in 0..w {
+			let l = |dx, dy| luma[index(x + dx, y + dy)];
+			let gx = (l(1, -1) + 2.0 * l(1, 0) + l(1, 1)) - (l(-1, -1) + 2.0 * l(-1, 0) + l(-1, 1));
+			let gy = (l(-1, 1) + 2.0 * l(0, 1) + l(1, 1)) - (l(-1, -1) + 2.0 * l(0, -1) + l(1, -1));
+			let i = index(x, y);
+			magnitude[i] = (gx * gx + gy * gy).sqrt();
+			let angle = gy.atan2(gx).to_degrees().rem_euclid(180.0);
+			direction[i] = (((angle + 22.5) / 45.0) as u8) % 4;
+		}
+	}
+
+	// Keep only the pixels that are the strongest along their gradient.
+	let mut thin = vec![0.0f32; luma.len()];
+	for y in 0..h {
+		for x in 0..w {
+			let i = index(x, y);
+			let (dx, dy) = match direction[i] {
+				0 => (1, 0),
+				1 => (1, 1),
+				2 => (0, 1),
+				_ => (-1, 1),
+			};
+			let inside = |x, y| x >= 0 && x < w && y >= 0 && y < h;
+			let before = if inside(x - dx, y - dy) {
+				magnitude[index(x - dx, y - dy)]
+			} else {
+				0.0
+			};
+			let after = if inside(x + dx, y + dy) {
+				magnitude[index(x + dx, y + dy)]
+			} else {
+				0.0
+			};
+			if magnitude[i] >= before && magnitude[i] > after {
+				thin[i] = magnitude[i];
+			}
+		}
+	}
+
+	// Hysteresis: grow each strong pixel along its weak neighbors.
+	let mut edges: GrayImage = ImageBuffer::new(width, height);
+	let mut stack = Vec::new();
+	for y in 0..h {
+		for x in 0..w {
+			if thin[index(x, y)] < high || edges[(x as u32, y as u32)][0] != 0 {
+				continue;
+			}
+			edges.put_pixel(x as u32, y as u32, Luma([255]));
+			stack.push((x, y));
+			while let Some((px, py)) = stack.pop() {
+				for ny in (py - 1).max(0)..=(py + 1).min(h - 1) {
+					for nx in (px - 1).max(0)..=(px + 1).min(w - 1) {
+						if thin[index(nx, ny)] >= low && edges[(nx as u32, ny as u32)][0] == 0 {
+							edges.put_pixel(nx as u32, ny as u32, Luma([255]));
+							stack.push((nx, ny));
+						}
+					}
+				}
+			}
+		}
+	}
+	edges
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn a_step_becomes_a_single_line() {
+		let image: GrayImage =
+			ImageBuffer::from_fn(12, 8, |x, _| Luma([if x < 6 { 20 } else { 220 }]));
+		let edges = detect_edges(&image, 100.0, 300.0);
+		for y in 0..8 {
+			let columns: Vec<u32> = (0..12).filter(|x| edges[(*x, y)][0] == 255).collect();
+			assert_eq!(columns.len(), 1, "row {}: {:?}", y, columns);
+			assert!(columns[0] == 5 || columns[0] == 6);
+		}
+		let flat: GrayImage = ImageBuffer::from_pixel(6, 6, Luma([90]));
+		assert!(detect_edges(&flat, 100.0, 300.0)
+			.pixels()
+			.all(|p| p[0] == 0));
+	}
+}
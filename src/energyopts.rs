@@ -0,0 +1,497 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Energy options
+//!
+//! The knobs shared by the seam finders that change what energy they
+//! see: smoothing the image before the energy is calculated, and
+//! adjusting the per-pixel energy afterward.  The finders hold one of
+//! these and consult it at the two points where it matters, so a new
+//! adjustment only has to be taught to this module.
+
+use crate::alpha::{has_alpha, premultiplied, AlphaEnergy};
+use crate::border::BorderPolicy;
+use crate::cq;
+use crate::deblock::block_artifacts;
+use crate::dprow::TieBreak;
+use crate::edges::detect_edges;
+use crate::masks::{ProtectionMask, RemovalMask};
+use crate::normalize::Normalization;
+use crate::pixelpairs::channel_max;
+use crate::scalar::EnergyScalar;
+use crate::twodmap::TwoDimensionalMap;
+use image::{imageops, FilterType, GenericImageView, ImageBuffer, Luma, Pixel, Primitive};
+use num_traits::NumCast;
+
+// Importance is kept as a floating point image of multipliers, so
+// that resampling it doesn't lose what little precision it has.
+pub(crate) type ImportanceMap = ImageBuffer<Luma<f32>, Vec<f32>>;
+
+// Protection is kept as a plain grayscale mask.
+pub(crate) type ProtectionMap = ImageBuffer<Luma<u8>, Vec<u8>>;
+
+/// The collection of energy adjustments a seam finder applies.  The
+/// default is no adjustment at all.
+#[derive(Debug, Default, Clone)]
+pub struct EnergyOptions {
+	blur: Option<f32>,
+	alpha: AlphaEnergy,
+	pub(crate) border: BorderPolicy,
+	pub(crate) normalization: Normalization,
+	pub(crate) slope: Option<u32>,
+	pub(crate) pyramid: Option<(u32, u32)>,
+	pub(crate) ties: TieBreak,
+	importance: Option<ImportanceMap>,
+	protection: Option<ProtectionMap>,
+	removal: Option<ProtectionMap>,
+	damping: Option<ImportanceMap>,
+	edge_walls: Option<(f32, f32)>,
+	penalty: Option<TwoDimensionalMap<u32>>,
+}
+
+// The per-pixel maps in the options, fitted to an image of a
+// particular size.  The carver carries these along with the image,
+// removing each seam from them as it's removed from the image, so
+// that they stay registered with the content rather than being
+// re-stretched over the shrinking image.
+#[derive(Clone)]
+pub(crate) struct FittedMaps {
+	pub importance: Option<ImportanceMap>,
+	pub protection: Option<ProtectionMap>,
+	pub removal: Option<ProtectionMap>,
+	pub damping: Option<ImportanceMap>,
+}
+
+impl EnergyOptions {
+	/// No adjustments.
+	pub fn new() -> Self {
+		EnergyOptions::default()
+	}
+
+	/// Smooth the image with a Gaussian of the given sigma before the
+	/// energy is calculated.
+	pub fn blur(mut self, sigma: f32) -> Self {
+		self.blur = Some(sigma);
+		self
+	}
+
+	/// Choose how the energy takes the image's alpha into account.
+	/// `Premultiply` keeps hidden color in transparent regions from
+	/// looking busy.
+	pub fn alpha(mut self, mode: AlphaEnergy) -> Self {
+		self.alpha = mode;
+		self
+	}
+
+	/// Choose what stands in for the neighbors of edge pixels that
+	/// fall outside the image.  The default, `Clamp`, tends to draw
+	/// seams to the borders; `Mirror` doesn't.
+	pub fn border(mut self, policy: BorderPolicy) -> Self {
+		self.border = policy;
+		self
+	}
+
+	/// Normalize the energy map before it's adjusted and handed to
+	/// the seam DP.  This only matters to finders that build a whole
+	/// energy map first; the forward-energy finder works out its
+	/// costs as it goes, and ignores it.
+	pub fn normalize(mut self, mode: Normalization) -> Self {
+		self.normalization = mode;
+		self
+	}
+
+	/// Keep seams close to straight: a seam may step sideways at most
+	/// once in any `rows` consecutive rows (or columns, for a
+	/// horizontal seam).  Seams that wander diagonally shear the
+	/// straight lines of buildings; a few rows is usually enough to
+	/// stop that.  Finding a seam costs `rows` times as much.
+	pub fn max_slope(mut self, rows: u32) -> Self {
+		self.slope = cq!(rows > 1, Some(rows), None);
+		self
+	}
+
+	/// Find seams coarse to fine: first in a copy of the image shrunk
+	/// by `factor`, then at full size only within `corridor` pixels
+	/// either side of where the shrunken seam ran.  On a large image
+	/// this is much faster, and the seam is nearly always the one the
+	/// full search would have found.  A `factor` below 2 turns it off.
+	/// The full-size search can't normalize the energy, which needs
+	/// the whole energy map, and doesn't constrain the slope.
+	pub fn pyramid(mut self, factor: u32, corridor: u32) -> Self {
+		self.pyramid = cq!(factor > 1, Some((factor, corridor)), None);
+		self
+	}
+
+	/// Break ties in the seam DP as given, rather than always going
+	/// left, which on a flat region leans every seam the same way.
+	/// Seams found with a constrained slope or in a pyramid's corridor
+	/// still go left.
+	pub fn tie_break(mut self, ties: TieBreak) -> Self {
+		self.ties = ties;
+		self
+	}
+
+	/// Multiply a grayscale importance map into the energy before the
+	/// seam is found.  White leaves the energy alone and black zeroes
+	/// it, so darker regions are where the seams will prefer to go.
+	/// The map may be any size; it's bilinearly resampled to match the
+	/// image the seam is found in.
+	pub fn importance<I, P, S>(mut self, map: &I) -> Self
+	where
+		I: GenericImageView<Pixel = P>,
+		P: Pixel<Subpixel = S> + 'static,
+		S: Primitive + 'static,
+	{
+		let scale = channel_max::<S>() as f32;
+		self.importance = Some(ImageBuffer::from_fn(map.width(), map.height(), |x, y| {
+			let luma: f32 = NumCast::from(map.get_pixel(x, y).to_luma()[0]).unwrap();
+			Luma([luma / scale])
+		}));
+		self
+	}
+
+	/// Multiply a map of per-pixel weights into the energy before the
+	/// seam is found, as with `importance` but with the weights taken
+	/// as they are rather than scaled from a grayscale image.  This is
+	/// the shape saliency maps usually come in; see the `mapio`
+	/// feature for reading them from `.npy` and PFM files.
+	pub fn weights(mut self, map: &TwoDimensionalMap<f32>) -> Self {
+		self.importance = Some(ImageBuffer::from_fn(map.width, map.height, |x, y| {
+			Luma([map[(x, y)]])
+		}));
+		self
+	}
+
+	/// Protect the pixels under the light half of a grayscale mask by
+	/// walling them off, so that no seam will pass through them unless
+	/// there is no other way through.  Like the importance map, the
+	/// mask may be any size.
+	pub fn protect<I, P, S>(mut self, mask: &I) -> Self
+	where
+		I: GenericImageView<Pixel = P>,
+		P: Pixel<Subpixel = S> + 'static,
+		S: Primitive + 'static,
+	{
+		let scale = channel_max::<S>() as f32;
+		self.protection = Some(ImageBuffer::from_fn(mask.width(), mask.height(), |x, y| {
+			let luma: f32 = NumCast::from(mask.get_pixel(x, y).to_luma()[0]).unwrap();
+			Luma([(luma * 255.0 / scale) as u8])
+		}));
+		self
+	}
+
+	/// Mark the pixels under the light half of a grayscale mask for
+	/// removal.  While any marked pixel is left, every other pixel is
+	/// walled off, which is as good as giving the marked ones a huge
+	/// negative energy: a seam passes through the object wherever it
+	/// can.  A pixel both marked and protected stays protected.  The
+	/// carver takes seams out until the object is gone, and then
+	/// carves on to the size it was asked for; ask for the original
+	/// size to have the seams put back.  Like the protection mask,
+	/// the mask may be any size.
+	pub fn remove<I, P, S>(mut self, mask: &I) -> Self
+	where
+		I: GenericImageView<Pixel = P>,
+		P: Pixel<Subpixel = S> + 'static,
+		S: Primitive + 'static,
+	{
+		let scale = channel_max::<S>() as f32;
+		self.removal = Some(ImageBuffer::from_fn(mask.width(), mask.height(), |x, y| {
+			let luma: f32 = NumCast::from(mask.get_pixel(x, y).to_luma()[0]).unwrap();
+			Luma([(luma * 255.0 / scale) as u8])
+		}));
+		self
+	}
+
+	/// Protect the pixels a mask marks, as with `protect`.
+	pub fn protection_mask(self, mask: &ProtectionMask) -> Self {
+		self.protect(&mask.to_luma())
+	}
+
+	/// Carve out the object a mask marks, as with `remove`.
+	pub fn removal_mask(self, mask: &RemovalMask) -> Self {
+		self.remove(&mask.to_luma())
+	}
+
+	/// Damp the energy along the 8×8 block boundaries of a heavily
+	/// compressed JPEG, so that seams aren't kept out of smooth regions
+	/// by compression artifacts no one would miss.  The boundaries are
+	/// found in the given image, which should be the one the seams will
+	/// be found in, uncropped; `strength` is the fraction of their
+	/// energy to take away.  See `deblock::block_artifacts`.
+	pub fn suppress_jpeg_blocks<I, P, S>(mut self, image: &I, strength: f32) -> Self
+	where
+		I: GenericImageView<Pixel = P>,
+		P: Pixel<Subpixel = S> + 'static,
+		S: Primitive + 'static,
+	{
+		self.damping = Some(block_artifacts(image, strength));
+		self
+	}
+
+	/// Run an edge detector over the image before each seam is found,
+	/// and wall off the edges it finds, so that seams go around strong
+	/// structural edges rather than nicking them.  The thresholds are
+	/// those of `edges::detect_edges`; (100.0, 300.0) is a reasonable
+	/// start for photographs.
+	pub fn edge_walls(mut self, low: f32, high: f32) -> Self {
+		self.edge_walls = Some((low, high));
+		self
+	}
+
+	/// Find the faces in the image and protect them, as with `protect`.
+	/// Any existing protection mask is kept.
+	#[cfg(feature = "faces")]
+	pub fn protect_faces<I, P, S>(self, image: &I) -> Self
+	where
+		I: GenericImageView<Pixel = P>,
+		P: Pixel<Subpixel = S> + 'static,
+		S: Primitive + 'static,
+	{
+		let faces = crate::faces::face_mask(image);
+		let mask = match self.fitted(faces.width(), faces.height()).protection {
+			Some(existing) => ImageBuffer::from_fn(faces.width(), faces.height(), |x, y| {
+				Luma([std::cmp::max(faces[(x, y)][0], existing[(x, y)][0])])
+			}),
+			None => faces,
+		};
+		self.protect(&mask)
+	}
+
+	// The per-pixel maps, resampled to the given dimensions.  Masks
+	// are resampled by nearest neighbor so their edges stay hard.
+	pub(crate) fn fitted(&self, width: u32, height: u32) -> FittedMaps {
+		fn fit<P: Pixel + 'static>(
+			map: &ImageBuffer<P, Vec<P::Subpixel>>,
+			width: u32,
+			height: u32,
+			filter: FilterType,
+		) -> ImageBuffer<P, Vec<P::Subpixel>>
+		where
+			P::Subpixel: 'static,
+		{
+			if map.dimensions() == (width, height) {
+				map.clone()
+			} else {
+				imageops::resize(map, width, height, filter)
+			}
+		}
+
+		FittedMaps {
+			importance: self
+				.importance
+				.as_ref()
+				.map(|map| fit(map, width, height, FilterType::Triangle)),
+			protection: self
+				.protection
+				.as_ref()
+				.map(|map| fit(map, width, height, FilterType::Nearest)),
+			removal: self
+				.removal
+				.as_ref()
+				.map(|map| fit(map, width, height, FilterType::Nearest)),
+			damping: self
+				.damping
+				.as_ref()
+				.map(|map| fit(map, width, height, FilterType::Nearest)),
+		}
+	}
+
+	// Replace the per-pixel maps with ones already fitted to the image.
+	pub(crate) fn with_fitted(mut self, maps: FittedMaps) -> Self {
+		self.importance = maps.importance;
+		self.protection = maps.protection;
+		self.removal = maps.removal;
+		self.damping = maps.damping;
+		self
+	}
+
+	// Penalties are added to the energy of each pixel.  The carver
+	// uses these to push seams out of regions that have already given
+	// up their share, or are out of bounds; the map must match the
+	// image dimensions.  Penalties from several sources add up.
+	pub(crate) fn penalty(mut self, penalty: TwoDimensionalMap<u32>) -> Self {
+		self.penalty = Some(match self.penalty.take() {
+			Some(mut existing) => {
+				for (cell, more) in existing.energy.iter_mut().zip(penalty.energy.iter()) {
+					*cell = cell.saturating_add(*more);
+				}
+				existing
+			}
+			None => penalty,
+		});
+		self
+	}
+
+	// Whether there's an object marked for removal.
+	pub(crate) fn removes(&self) -> bool {
+		self.removal.is_some()
+	}
+
+	// The options with the removal mask dropped, for once the object
+	// it marked is gone.
+	pub(crate) fn without_removal(&self) -> Self {
+		let mut options = self.clone();
+		options.removal = None;
+		options
+	}
+
+	// The options to find the seam through a shrunken copy of the
+	// image with.  The penalties are the carver's, registered with the
+	// full-size image, and have no meaning on the copy.
+	pub(crate) fn coarse(&self) -> Self {
+		let mut options = self.clone();
+		options.pyramid = None;
+		options.penalty = None;
+		options
+	}
+
+	// Whether the options leave the forward costs as a compute device
+	// finds them: none of them is carried over to the device.
+	pub(crate) fn is_plain(&self) -> bool {
+		self.blur.is_none()
+			&& self.alpha == AlphaEnergy::default()
+			&& self.border == BorderPolicy::Clamp
+			&& self.slope.is_none()
+			&& self.pyramid.is_none()
+			&& self.ties == TieBreak::Leftmost
+			&& self.importance.is_none()
+			&& self.protection.is_none()
+			&& self.removal.is_none()
+			&& self.damping.is_none()
+			&& self.edge_walls.is_none()
+			&& self.penalty.is_none()
+	}
+
+	// Whether every adjustment is local to the pixel adjusted, and
+	// carved along with it, so that removing a seam changes nothing
+	// but the costs around it.
+	pub(crate) fn is_local(&self) -> bool {
+		self.blur.is_none()
+			&& self.slope.is_none()
+			&& self.pyramid.is_none()
+			&& self.edge_walls.is_none()
+			&& self.penalty.is_none()
+	}
+
+	// If premultiplying or smoothing was requested, return the copy
+	// of the image that the energy should be calculated from.
+	pub(crate) fn smooth<I, P, S>(&self, image: &I) -> Option<ImageBuffer<P, Vec<S>>>
+	where
+		I: GenericImageView<Pixel = P>,
+		P: Pixel<Subpixel = S> + 'static,
+		S: Primitive + 'static,
+	{
+		let premultiply = self.alpha == AlphaEnergy::Premultiply && has_alpha::<P>();
+		match (premultiply, self.blur) {
+			(true, Some(sigma)) => Some(imageops::blur(&premultiplied(image), sigma)),
+			(true, None) => Some(premultiplied(image)),
+			(false, Some(sigma)) => Some(imageops::blur(image, sigma)),
+			(false, None) => None,
+		}
+	}
+
+	// Fit the adjustments to the image a seam is about to be found in.
+	pub(crate) fn fit<I, P, S>(&self, image: &I) -> Adjustments<'_>
+	where
+		I: GenericImageView<Pixel = P>,
+		P: Pixel<Subpixel = S> + 'static,
+		S: Primitive + 'static,
+	{
+		let (width, height) = image.dimensions();
+		let maps = self.fitted(width, height);
+		Adjustments {
+			removing: maps.removal.as_ref().and_then(marked).is_some(),
+			maps,
+			edges: self
+				.edge_walls
+				.map(|(low, high)| detect_edges(image, low, high)),
+			penalty: self.penalty.as_ref(),
+			dimensions: (width, height),
+		}
+	}
+}
+
+// The energy options, made ready for a particular image.
+pub(crate) struct Adjustments<'a> {
+	maps: FittedMaps,
+	edges: Option<ProtectionMap>,
+	penalty: Option<&'a TwoDimensionalMap<u32>>,
+	dimensions: (u32, u32),
+	removing: bool,
+}
+
+// The bounds of the pixels still marked in a mask, as the left, top,
+// right, and bottom edges, inclusive; or nothing, if none are.
+pub(crate) fn marked(mask: &ProtectionMap) -> Option<(u32, u32, u32, u32)> {
+	mask.enumerate_pixels()
+		.filter(|(_, _, p)| p[0] > 127)
+		.fold(None, |bounds, (x, y, _)| match bounds {
+			Some((left, top, right, bottom)) => Some((
+				std::cmp::min(left, x),
+				std::cmp::min(top, y),
+				std::cmp::max(right, x),
+				std::cmp::max(bottom, y),
+			)),
+			None => Some((x, y, x, y)),
+		})
+}
+
+impl<'a> Adjustments<'a> {
+	pub(crate) fn is_empty(&self) -> bool {
+		self.maps.importance.is_none()
+			&& self.maps.protection.is_none()
+			&& !self.removing
+			&& self.maps.damping.is_none()
+			&& self.edges.is_none()
+			&& self.penalty.is_none()
+	}
+
+	// Adjust the energy of the pixel at (x, y).  Walls and penalties
+	// are added after the importance and block damping are multiplied
+	// in, so that an unimportant region can't shrug them off.
+	// Penalties are kept as integer fractions of the integer wall, and
+	// are carried over to other energy domains in proportion to that
+	// domain's wall.
+	pub fn adjust<E: EnergyScalar>(&self, x: u32, y: u32, energy: E) -> E {
+		let (width, height) = self.dimensions;
+		let mut energy = match &self.maps.importance {
+			Some(importance) => energy.scale(importance.get_pixel(x, y)[0] as f64),
+			None => energy,
+		};
+		if let Some(damping) = &self.maps.damping {
+			energy = energy.scale(damping.get_pixel(x, y)[0] as f64);
+		}
+		let walled = |mask: &Option<ProtectionMap>| match mask {
+			Some(mask) => mask.get_pixel(x, y)[0] > 127,
+			None => false,
+		};
+		let protected = walled(&self.maps.protection) || walled(&self.edges);
+		if protected {
+			energy = energy.saturating_add(E::wall(width, height));
+		}
+		if self.removing && (protected || !walled(&self.maps.removal)) {
+			energy = energy.saturating_add(E::wall(width, height));
+		}
+		match self.penalty {
+			Some(penalty) => {
+				let fraction = penalty[(x, y)] as f64 / u32::wall(width, height) as f64;
+				energy.saturating_add(E::wall(width, height).scale(fraction))
+			}
+			None => energy,
+		}
+	}
+
+	// Adjust every pixel in an energy map.
+	pub fn apply<E: EnergyScalar>(&self, energy: &mut TwoDimensionalMap<E>) {
+		if self.is_empty() {
+			return;
+		}
+		for y in 0..energy.height {
+			for x in 0..energy.width {
+				energy[(x, y)] = self.adjust(x, y, energy[(x, y)]);
+			}
+		}
+	}
+}
@@ -0,0 +1,268 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Carve errors
+//!
+//! Everything that can stop a carve before it starts, as something a
+//! caller can match on.  An application embedding the carver may want
+//! to clamp a bad target size and try again, or tell its user their
+//! region of interest is too narrow, which is hard to do with only a
+//! message to go on.  Each error still prints as a sentence, and
+//! converts to a `String` for code that only wants the message.
+
+use crate::seamcarver::RegionOfInterest;
+use crate::seamfinder::Orientation;
+use std::fmt;
+
+/// Why a carve couldn't be done.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CarveError {
+	/// The image can't be carved to a target with no pixels.
+	InvalidTargetSize {
+		/// The target width.
+		width: u32,
+		/// The target height.
+		height: u32,
+	},
+	/// An enlargement was asked to make the image smaller.
+	NotAnEnlargement {
+		/// The image's dimensions.
+		dimensions: (u32, u32),
+		/// The dimensions it was to be enlarged to.
+		target: (u32, u32),
+	},
+	/// The carve would shrink the image below the smallest size the
+	/// carve limits allow.
+	BelowMinimumSize {
+		/// The dimensions the image was to be carved to.
+		target: (u32, u32),
+		/// The smallest width and height allowed.
+		minimum: (u32, u32),
+	},
+	/// The carve would remove more of the image than the carve limits
+	/// allow.
+	RemovesTooMuch {
+		/// The image's dimensions.
+		dimensions: (u32, u32),
+		/// The dimensions it was to be carved to.
+		target: (u32, u32),
+		/// The largest percentage of the image that may be removed.
+		limit_percent: u32,
+	},
+	/// The image has no pixels to carve.
+	ImageTooSmall {
+		/// The image's width.
+		width: u32,
+		/// The image's height.
+		height: u32,
+	},
+	/// The region of interest isn't inside the image.
+	RegionOutsideImage {
+		/// The region.
+		region: RegionOfInterest,
+		/// The image's dimensions.
+		dimensions: (u32, u32),
+	},
+	/// The region of interest is too small to take the seams the carve
+	/// needs out of it.
+	RegionTooSmall {
+		/// The region.
+		region: RegionOfInterest,
+		/// The image's dimensions.
+		dimensions: (u32, u32),
+		/// The dimensions the image was to be carved to.
+		target: (u32, u32),
+	},
+	/// Two masks that were to be combined aren't the same size.
+	MaskDimensionMismatch {
+		/// The dimensions called for.
+		expected: (u32, u32),
+		/// The dimensions given.
+		found: (u32, u32),
+	},
+	/// An image the seams of a carve were to be replayed on isn't the
+	/// size of the image they were found in.
+	CompanionDimensionMismatch {
+		/// The dimensions of the carved image.
+		expected: (u32, u32),
+		/// The dimensions of the companion.
+		found: (u32, u32),
+	},
+	/// A seam to be removed wasn't made for the image, or doesn't run
+	/// the way asked for.
+	SeamMismatch {
+		/// Which way the seam runs.
+		orientation: Orientation,
+		/// The dimensions of the image the seam was made for.
+		seam: (u32, u32),
+		/// The dimensions of the image.
+		dimensions: (u32, u32),
+	},
+	/// A seam doesn't fit the image it was to be carved from: it's the
+	/// wrong length, leaves the image, or jumps sideways.
+	InvalidSeam(String),
+	/// A cost table has no seam ending where one was to be traced from.
+	NoSeamEnd {
+		/// The end asked for.
+		end: u32,
+		/// How many ends the table has.
+		ends: u32,
+	},
+	/// A buffer of marks doesn't have one for every pixel of the mask
+	/// it was to be made into.
+	MaskLengthMismatch {
+		/// The number of marks called for.
+		expected: usize,
+		/// The number given.
+		found: usize,
+	},
+	/// A buffer of values doesn't have one for every cell of the map it
+	/// was to be made into.
+	MapLengthMismatch {
+		/// The dimensions of the map.
+		dimensions: (u32, u32),
+		/// The number of values given.
+		found: usize,
+	},
+	/// Two maps that were to be combined aren't the same size.
+	MapDimensionMismatch {
+		/// The dimensions of the first map.
+		expected: (u32, u32),
+		/// The dimensions of the map it was combined with.
+		found: (u32, u32),
+	},
+	/// A blend was asked for with no maps to blend.
+	NothingToBlend,
+	/// An array is too wide or too tall to be made into a map.
+	MapTooLarge {
+		/// The array's width.
+		width: usize,
+		/// The array's height.
+		height: usize,
+	},
+	/// The image has more pixels than the carve was allowed to take
+	/// on, and the oversize policy was to refuse it.
+	TooManyPixels {
+		/// The image's dimensions.
+		dimensions: (u32, u32),
+		/// The most pixels allowed.
+		limit: u64,
+	},
+	/// The stream carve events were being written to failed.
+	EventStream(String),
+	/// The carve's cancel token was raised before it finished.
+	Cancelled,
+	/// One of the carve's threads panicked, with the message given.
+	Worker(String),
+}
+
+impl fmt::Display for CarveError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			CarveError::InvalidTargetSize { width, height } => {
+				write!(f, "can't carve an image down to {}x{}", width, height)
+			}
+			CarveError::NotAnEnlargement { dimensions, target } => write!(
+				f,
+				"can't enlarge a {}x{} image to {}x{}",
+				dimensions.0, dimensions.1, target.0, target.1
+			),
+			CarveError::BelowMinimumSize { target, minimum } => write!(
+				f,
+				"carving to {}x{} goes below the smallest size allowed, {}x{}",
+				target.0, target.1, minimum.0, minimum.1
+			),
+			CarveError::RemovesTooMuch {
+				dimensions,
+				target,
+				limit_percent,
+			} => write!(
+				f,
+				"carving a {}x{} image to {}x{} removes more than {}% of it",
+				dimensions.0, dimensions.1, target.0, target.1, limit_percent
+			),
+			CarveError::ImageTooSmall { width, height } => {
+				write!(f, "a {}x{} image has nothing to carve", width, height)
+			}
+			CarveError::RegionOutsideImage { region, dimensions } => write!(
+				f,
+				"a {}x{} region at ({}, {}) isn't inside a {}x{} image",
+				region.width, region.height, region.x, region.y, dimensions.0, dimensions.1
+			),
+			CarveError::RegionTooSmall {
+				region,
+				dimensions,
+				target,
+			} => write!(
+				f,
+				"a {}x{} region has no room to carve a {}x{} image down to {}x{}",
+				region.width, region.height, dimensions.0, dimensions.1, target.0, target.1
+			),
+			CarveError::MaskDimensionMismatch { expected, found } => write!(
+				f,
+				"cannot combine a {}x{} mask with a {}x{} one",
+				expected.0, expected.1, found.0, found.1
+			),
+			CarveError::CompanionDimensionMismatch { expected, found } => write!(
+				f,
+				"the seams of a {}x{} image can't be carved out of a {}x{} one",
+				expected.0, expected.1, found.0, found.1
+			),
+			CarveError::SeamMismatch {
+				orientation,
+				seam,
+				dimensions,
+			} => write!(
+				f,
+				"a {:?} seam through a {}x{} image can't be removed from a {}x{} one that way",
+				orientation, seam.0, seam.1, dimensions.0, dimensions.1
+			),
+			CarveError::InvalidSeam(message) => write!(f, "invalid seam: {}", message),
+			CarveError::NoSeamEnd { end, ends } => {
+				write!(f, "no seam ends at {} of {}", end, ends)
+			}
+			CarveError::MaskLengthMismatch { expected, found } => {
+				write!(f, "the mask needs {} marks, not {}", expected, found)
+			}
+			CarveError::MapLengthMismatch { dimensions, found } => write!(
+				f,
+				"a {}x{} map needs {} values, not {}",
+				dimensions.0,
+				dimensions.1,
+				dimensions.0 as usize * dimensions.1 as usize,
+				found
+			),
+			CarveError::MapDimensionMismatch { expected, found } => write!(
+				f,
+				"cannot combine a {}x{} map with a {}x{} one",
+				expected.0, expected.1, found.0, found.1
+			),
+			CarveError::NothingToBlend => write!(f, "a blend needs at least one map"),
+			CarveError::MapTooLarge { width, height } => {
+				write!(f, "a {}x{} array is too large for a map", width, height)
+			}
+			CarveError::TooManyPixels { dimensions, limit } => write!(
+				f,
+				"a {}x{} image is {} pixels, over the limit of {}",
+				dimensions.0,
+				dimensions.1,
+				u64::from(dimensions.0) * u64::from(dimensions.1),
+				limit
+			),
+			CarveError::EventStream(message) => {
+				write!(f, "couldn't send the carve events: {}", message)
+			}
+			CarveError::Cancelled => write!(f, "the carve was cancelled"),
+			CarveError::Worker(message) => write!(f, "a carve thread panicked: {}", message),
+		}
+	}
+}
+
+impl std::error::Error for CarveError {}
+
+impl From<CarveError> for String {
+	fn from(error: CarveError) -> String {
+		error.to_string()
+	}
+}
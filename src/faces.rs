@@ -0,0 +1,163 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Face protection
+//!
+//! Faces are the most common casualty of seam carving: a cheek is a
+//! smooth, low-energy region, and the seams go straight through it.
+//! This is a deliberately lightweight detector, in the skin-color
+//! tradition: pixels are classified as skin by their chroma in YCbCr
+//! (after Chai & Ngan, 1999), the skin pixels are gathered into
+//! connected blobs, and blobs that are big enough, roughly face-shaped,
+//! and reasonably solid are reported.  It will find hands and bare
+//! shoulders too, and it will miss faces in strange light, but it
+//! needs no model files and the cost of a false positive is only a
+//! little protected skin.
+
+use crate::pixelpairs::channel_max;
+use image::{GenericImageView, GrayImage, ImageBuffer, Luma, Pixel, Primitive};
+use num_traits::NumCast;
+
+/// A detected face, as a rectangle in image coordinates.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FaceRect {
+	/// The left edge of the face.
+	pub x: u32,
+	/// The top edge of the face.
+	pub y: u32,
+	/// The width of the face.
+	pub width: u32,
+	/// The height of the face.
+	pub height: u32,
+}
+
+fn is_skin<P, S>(pixel: &P) -> bool
+where
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	let scale = channel_max::<S>() as f32;
+	let rgb = pixel.to_rgb();
+	let c = |i: usize| -> f32 { NumCast::from(rgb[i]).map_or(0.0, |v: f32| v * 255.0 / scale) };
+	let (r, g, b) = (c(0), c(1), c(2));
+	let cb = 128.0 - 0.168_736 * r - 0.331_264 * g + 0.5 * b;
+	let cr = 128.0 + 0.5 * r - 0.418_688 * g - 0.081_312 * b;
+	(77.0..=127.0).contains(&cb) && (133.0..=173.0).contains(&cr)
+}
+
+/// Find the face-like skin regions in an image.
+pub fn detect_faces<I, P, S>(image: &I) -> Vec<FaceRect>
+where
+	I: GenericImageView<Pixel = P>,
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	let (width, height) = image.dimensions();
+	let index = |x: u32, y: u32| (y as usize) * (width as usize) + (x as usize);
+	let mut skin: Vec<bool> = Vec::with_capacity((width * height) as usize);
+	for y in 0..height {
+		for x in 0..width {
+			skin.push(is_skin(&image.get_pixel(x, y)));
+		}
+	}
+
+	// Ignore anything smaller than a thousandth of the image, or a
+	// four by four block, whichever is larger.
+	let min_area = std::cmp::max(16, (width as usize * height as usize) / 1000);
+	let mut faces = Vec::new();
+	let mut seen = vec![false; skin.len()];
+	let mut stack = Vec::new();
+	for y in 0..height {
+		for x in 0..width {
+			if !skin[index(x, y)] || seen[index(x, y)] {
+				continue;
+			}
+
+			// Flood fill the blob, tracking its area and bounds.
+			let (mut area, mut left, mut top, mut right, mut bottom) = (0, x, y, x, y);
+			seen[index(x, y)] = true;
+			stack.push((x, y));
+			while let Some((px, py)) = stack.pop() {
+				area += 1;
+				left = left.min(px);
+				right = right.max(px);
+				top = top.min(py);
+				bottom = bottom.max(py);
+				let neighbors = [
+					(px.wrapping_sub(1), py),
+					(px + 1, py),
+					(px, py.wrapping_sub(1)),
+					(px, py + 1),
+				];
+				for &(nx, ny) in &neighbors {
+					if nx < width && ny < height && skin[index(nx, ny)] && !seen[index(nx, ny)] {
+						seen[index(nx, ny)] = true;
+						stack.push((nx, ny));
+					}
+				}
+			}
+
+			// Faces are a little taller than wide, and mostly solid.
+			let (w, h) = (right - left + 1, bottom - top + 1);
+			let aspect = h as f32 / w as f32;
+			let fill = area as f32 / (w * h) as f32;
+			if area >= min_area && (0.8..=2.0).contains(&aspect) && fill >= 0.4 {
+				faces.push(FaceRect {
+					x: left,
+					y: top,
+					width: w,
+					height: h,
+				});
+			}
+		}
+	}
+	faces
+}
+
+/// A protection mask covering every face found in the image, each
+/// grown by a tenth on every side to take in hairlines and jaws.
+pub fn face_mask<I, P, S>(image: &I) -> GrayImage
+where
+	I: GenericImageView<Pixel = P>,
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	let (width, height) = image.dimensions();
+	let mut mask: GrayImage = ImageBuffer::new(width, height);
+	for face in detect_faces(image) {
+		let (mx, my) = (face.width / 10, face.height / 10);
+		let right = (face.x + face.width + mx).min(width);
+		let bottom = (face.y + face.height + my).min(height);
+		for y in face.y.saturating_sub(my)..bottom {
+			for x in face.x.saturating_sub(mx)..right {
+				mask.put_pixel(x, y, Luma([255]));
+			}
+		}
+	}
+	mask
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use image::Rgb;
+
+	#[test]
+	fn finds_a_skin_colored_oval() {
+		let image: ImageBuffer<Rgb<u8>, Vec<u8>> = ImageBuffer::from_fn(40, 30, |x, y| {
+			let (dx, dy) = (x as f32 - 20.0, y as f32 - 15.0);
+			if (dx / 6.0).powi(2) + (dy / 8.0).powi(2) <= 1.0 {
+				Rgb([224, 172, 140])
+			} else {
+				Rgb([30, 60, 200])
+			}
+		});
+		let faces = detect_faces(&image);
+		assert_eq!(faces.len(), 1);
+		let face = faces[0];
+		assert!(face.x >= 13 && face.x <= 15 && face.width >= 11 && face.width <= 13);
+		assert_eq!(face_mask(&image).get_pixel(20, 15)[0], 255);
+		assert_eq!(face_mask(&image).get_pixel(2, 2)[0], 0);
+	}
+}
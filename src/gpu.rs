@@ -0,0 +1,341 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Seam finding on the GPU
+//!
+//! A 4K image takes seconds per seam on the CPU, nearly all of it the
+//! energy map and the DP over it, and both are the same few sums at
+//! every pixel.  This runs them as wgpu compute shaders: the backward
+//! energy map a pixel to an invocation, and the forward-energy DP a
+//! row at a time, a dispatch to each row and a column of the row to
+//! each invocation.  Only the seam's traceback, one step a row, is
+//! left to the CPU.
+//!
+//! The GPU works in `f32`, as every `ComputeBackend` does; see
+//! `backend` for what that leaves out.  There may be no adapter at
+//! all, on a headless server or in a container, and `Gpu::new` says
+//! so rather than failing.  A plane larger than the device will bind
+//! as a storage buffer is worked on the CPU.
+
+use crate::backend::{ComputeBackend, DeviceSeamFinder};
+use crate::twodmap::TwoDimensionalMap;
+use wgpu::util::DeviceExt;
+
+const SHADER: &str = r#"
+struct Plane {
+	width: u32,
+	height: u32,
+	max: u32,
+	row: u32,
+}
+
+@group(0) @binding(0) var<uniform> plane: Plane;
+@group(0) @binding(1) var<storage, read> lumas: array<u32>;
+@group(0) @binding(2) var<storage, read_write> energy: array<f32>;
+@group(0) @binding(3) var<storage, read_write> costs: array<f32>;
+@group(0) @binding(4) var<storage, read_write> parents: array<u32>;
+
+fn at(x: u32, y: u32) -> u32 {
+	return y * plane.width + x;
+}
+
+fn of_lumas(l1: u32, l2: u32) -> f32 {
+	let css = (f32(l1) - f32(l2)) / f32(plane.max);
+	return css * css;
+}
+
+fn clamped(x: i32, y: i32) -> u32 {
+	let cx = u32(clamp(x, 0, i32(plane.width) - 1));
+	let cy = u32(clamp(y, 0, i32(plane.height) - 1));
+	return lumas[at(cx, cy)];
+}
+
+@compute @workgroup_size(16, 16)
+fn energy_map(@builtin(global_invocation_id) id: vec3<u32>) {
+	if (id.x >= plane.width || id.y >= plane.height) {
+		return;
+	}
+	let x = i32(id.x);
+	let y = i32(id.y);
+	energy[at(id.x, id.y)] = of_lumas(clamped(x - 1, y), clamped(x + 1, y))
+		+ of_lumas(clamped(x, y - 1), clamped(x, y + 1));
+}
+
+fn epp(x1: u32, y1: u32, x2: u32, y2: u32) -> f32 {
+	return of_lumas(lumas[at(x1, y1)], lumas[at(x2, y2)]);
+}
+
+fn transition(x: u32, y: u32, x_above: u32) -> f32 {
+	let last = plane.width - 1u;
+	let y_above = y - 1u;
+	var up: f32;
+	if (x == 0u) {
+		up = epp(x, y_above, min(x + 1u, last), y_above);
+	} else if (x == last) {
+		up = epp(x - 1u, y_above, x, y_above);
+	} else {
+		up = epp(x - 1u, y_above, x + 1u, y_above);
+	}
+	if (x_above == x) {
+		return up;
+	}
+	return up + epp(x, y_above, x_above, y);
+}
+
+// The DP is dispatched once for each row, in order, with the row in
+// `plane.row`; each dispatch sees the costs the one before it wrote.
+@compute @workgroup_size(256)
+fn forward_costs(@builtin(global_invocation_id) id: vec3<u32>) {
+	let x = id.x;
+	if (x >= plane.width) {
+		return;
+	}
+	let last = plane.width - 1u;
+	let y = plane.row;
+	if (y == 0u) {
+		let xl = select(x - 1u, 0u, x == 0u);
+		let xr = select(x + 1u, last, x == last);
+		costs[at(x, 0u)] = of_lumas(lumas[at(xl, 0u)], lumas[at(xr, 0u)]);
+		parents[at(x, 0u)] = x;
+		return;
+	}
+	let center = costs[at(x, y - 1u)] + transition(x, y, x);
+	var left = center;
+	if (x != 0u) {
+		left = costs[at(x - 1u, y - 1u)] + transition(x, y, x - 1u);
+	}
+	var right = center;
+	if (x != last) {
+		right = costs[at(x + 1u, y - 1u)] + transition(x, y, x + 1u);
+	}
+	// Ties go left, as `TieBreak::Leftmost` has them.
+	let right_wins = right < center;
+	let m = select(center, right, right_wins);
+	let i = select(x, x + 1u, right_wins);
+	let left_wins = left <= m;
+	costs[at(x, y)] = select(m, left, left_wins);
+	parents[at(x, y)] = select(i, select(x - 1u, 0u, x == 0u), left_wins);
+}
+"#;
+
+/// A GPU, and the compute pipelines for the energy map and the
+/// forward-energy DP.  Making one compiles the shaders, so make it
+/// once and hand it to every finder.
+pub struct Gpu {
+	device: wgpu::Device,
+	queue: wgpu::Queue,
+	energy: wgpu::ComputePipeline,
+	costs: wgpu::ComputePipeline,
+}
+
+impl Gpu {
+	/// The default adapter, or None if there isn't one to be had.
+	pub fn new() -> Option<Self> {
+		let instance = wgpu::Instance::default();
+		let adapter =
+			pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default()))?;
+		// As large a plane as the adapter can bind, rather than wgpu's
+		// default 128MiB.
+		let descriptor = wgpu::DeviceDescriptor {
+			required_limits: adapter.limits(),
+			..Default::default()
+		};
+		let (device, queue) = pollster::block_on(adapter.request_device(&descriptor, None)).ok()?;
+		let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+			label: Some("pnmseam"),
+			source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+		});
+		let pipeline = |entry_point| {
+			device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+				label: Some(entry_point),
+				layout: None,
+				module: &module,
+				entry_point,
+			})
+		};
+		let (energy, costs) = (pipeline("energy_map"), pipeline("forward_costs"));
+		Some(Gpu {
+			device,
+			queue,
+			energy,
+			costs,
+		})
+	}
+
+	// Run a pipeline over the plane, dispatched once for each of the
+	// first `rows` rows, and read back the plane-sized storage buffers
+	// at the given bindings.  None if the plane is too large for the
+	// device to bind, or the workgroups too many to dispatch.
+	fn run(
+		&self,
+		pipeline: &wgpu::ComputePipeline,
+		(lumas, max): (&TwoDimensionalMap<u32>, u32),
+		outputs: &[u32],
+		(x, y): (u32, u32),
+		rows: u32,
+	) -> Option<Vec<Vec<u8>>> {
+		let device = &self.device;
+		let limits = device.limits();
+		let size = (lumas.energy.len() * 4) as wgpu::BufferAddress;
+		if size > u64::from(limits.max_storage_buffer_binding_size)
+			|| size > limits.max_buffer_size
+			|| x > limits.max_compute_workgroups_per_dimension
+			|| y > limits.max_compute_workgroups_per_dimension
+		{
+			return None;
+		}
+
+		let plane = [lumas.width, lumas.height, max, 0];
+		let plane = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+			label: Some("plane"),
+			contents: bytemuck::cast_slice(&plane),
+			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+		});
+		// Each dispatch's row is copied into the plane from here.
+		let numbers: Vec<u32> = (0..rows).collect();
+		let numbers = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+			label: Some("rows"),
+			contents: bytemuck::cast_slice(&numbers),
+			usage: wgpu::BufferUsages::COPY_SRC,
+		});
+		let input = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+			label: Some("lumas"),
+			contents: bytemuck::cast_slice(&lumas.energy),
+			usage: wgpu::BufferUsages::STORAGE,
+		});
+		let buffer = |label, usage| {
+			device.create_buffer(&wgpu::BufferDescriptor {
+				label: Some(label),
+				size,
+				usage,
+				mapped_at_creation: false,
+			})
+		};
+		let (stored, read): (Vec<_>, Vec<_>) = outputs
+			.iter()
+			.map(|_| {
+				(
+					buffer(
+						"output",
+						wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+					),
+					buffer(
+						"readback",
+						wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+					),
+				)
+			})
+			.unzip();
+
+		let mut entries = vec![
+			wgpu::BindGroupEntry {
+				binding: 0,
+				resource: plane.as_entire_binding(),
+			},
+			wgpu::BindGroupEntry {
+				binding: 1,
+				resource: input.as_entire_binding(),
+			},
+		];
+		for (binding, output) in outputs.iter().zip(stored.iter()) {
+			entries.push(wgpu::BindGroupEntry {
+				binding: *binding,
+				resource: output.as_entire_binding(),
+			});
+		}
+		let group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: None,
+			layout: &pipeline.get_bind_group_layout(0),
+			entries: &entries,
+		});
+
+		let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+		for row in 0..rows {
+			encoder.copy_buffer_to_buffer(&numbers, u64::from(row) * 4, &plane, 12, 4);
+			let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
+			pass.set_pipeline(pipeline);
+			pass.set_bind_group(0, &group, &[]);
+			pass.dispatch_workgroups(x, y, 1);
+		}
+		for (output, readback) in stored.iter().zip(read.iter()) {
+			encoder.copy_buffer_to_buffer(output, 0, readback, 0, size);
+		}
+		self.queue.submit(Some(encoder.finish()));
+
+		for readback in &read {
+			readback.slice(..).map_async(wgpu::MapMode::Read, |_| {});
+		}
+		device.poll(wgpu::Maintain::Wait);
+		let read = read
+			.iter()
+			.map(|readback| {
+				let bytes = readback.slice(..).get_mapped_range().to_vec();
+				readback.unmap();
+				bytes
+			})
+			.collect();
+		Some(read)
+	}
+}
+
+impl ComputeBackend for Gpu {
+	fn try_energy_map_of(
+		&self,
+		lumas: &TwoDimensionalMap<u32>,
+		max: u32,
+	) -> Option<TwoDimensionalMap<f32>> {
+		let (width, height) = (lumas.width, lumas.height);
+		let mut energy = TwoDimensionalMap::new(width, height);
+		if width == 0 || height == 0 {
+			return Some(energy);
+		}
+		let groups = ((width + 15) / 16, (height + 15) / 16);
+		let mut out = self.run(&self.energy, (lumas, max), &[2], groups, 1)?;
+		energy.energy = bytemuck::cast_slice(&out.remove(0)).to_vec();
+		Some(energy)
+	}
+
+	fn try_forward_costs(
+		&self,
+		lumas: &TwoDimensionalMap<u32>,
+		max: u32,
+	) -> Option<(TwoDimensionalMap<f32>, TwoDimensionalMap<u32>)> {
+		let (width, height) = (lumas.width, lumas.height);
+		let mut costs = TwoDimensionalMap::new(width, height);
+		let mut parents = TwoDimensionalMap::new(width, height);
+		if width == 0 || height == 0 {
+			return Some((costs, parents));
+		}
+		let groups = ((width + 255) / 256, 1);
+		let out = self.run(&self.costs, (lumas, max), &[3, 4], groups, height)?;
+		costs.energy = bytemuck::cast_slice(&out[0]).to_vec();
+		parents.energy = bytemuck::cast_slice(&out[1]).to_vec();
+		Some((costs, parents))
+	}
+}
+
+/// A seam finder running the forward-energy DP on the GPU.
+pub type GpuSeamFinder<'a, I, P, S> = DeviceSeamFinder<'a, I, P, S, Gpu>;
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn the_shaders_are_valid() {
+		use wgpu::naga::valid::{Capabilities, ValidationFlags, Validator};
+		let module = wgpu::naga::front::wgsl::parse_str(SHADER).unwrap();
+		Validator::new(ValidationFlags::all(), Capabilities::empty())
+			.validate(&module)
+			.unwrap();
+	}
+
+	#[test]
+	fn the_gpu_finds_what_the_cpu_does() {
+		// Without an adapter there's nothing to compare.
+		if let Some(gpu) = Gpu::new() {
+			crate::backend::agrees_with_the_cpu(&gpu);
+		}
+	}
+}
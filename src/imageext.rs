@@ -0,0 +1,110 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Carving as a method of the image types
+//!
+//! `img.seam_carve_to(800, 600)?` reads better at the call site than
+//! a free function taking the image, and needs nothing imported but
+//! the trait.  Image buffers are carved to buffers of the same pixel
+//! type, and dynamic images to dynamic images of the same variant.
+
+use crate::error::CarveError;
+use crate::seamcarver::{seamcarve_with_options, CarveOptions};
+use image::{DynamicImage, ImageBuffer, Pixel, Primitive};
+
+/// Seam carving for an image type.
+pub trait SeamRetarget {
+	/// What the image carves to.
+	type Output;
+
+	/// Carve the image to the new size, with the default options.
+	fn seam_carve_to(&self, newwidth: u32, newheight: u32) -> Result<Self::Output, CarveError> {
+		self.seam_carve_with(newwidth, newheight, &CarveOptions::default())
+	}
+
+	/// Carve the image to the new size, governed by the given options.
+	fn seam_carve_with(
+		&self,
+		newwidth: u32,
+		newheight: u32,
+		options: &CarveOptions,
+	) -> Result<Self::Output, CarveError>;
+}
+
+impl<P, S> SeamRetarget for ImageBuffer<P, Vec<S>>
+where
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	type Output = ImageBuffer<P, Vec<S>>;
+
+	fn seam_carve_with(
+		&self,
+		newwidth: u32,
+		newheight: u32,
+		options: &CarveOptions,
+	) -> Result<Self::Output, CarveError> {
+		seamcarve_with_options(self, newwidth, newheight, options)
+	}
+}
+
+impl SeamRetarget for DynamicImage {
+	type Output = DynamicImage;
+
+	fn seam_carve_with(
+		&self,
+		newwidth: u32,
+		newheight: u32,
+		options: &CarveOptions,
+	) -> Result<Self::Output, CarveError> {
+		let (w, h) = (newwidth, newheight);
+		Ok(match self {
+			DynamicImage::ImageLuma8(image) => {
+				DynamicImage::ImageLuma8(image.seam_carve_with(w, h, options)?)
+			}
+			DynamicImage::ImageLumaA8(image) => {
+				DynamicImage::ImageLumaA8(image.seam_carve_with(w, h, options)?)
+			}
+			DynamicImage::ImageRgb8(image) => {
+				DynamicImage::ImageRgb8(image.seam_carve_with(w, h, options)?)
+			}
+			DynamicImage::ImageRgba8(image) => {
+				DynamicImage::ImageRgba8(image.seam_carve_with(w, h, options)?)
+			}
+			DynamicImage::ImageBgr8(image) => {
+				DynamicImage::ImageBgr8(image.seam_carve_with(w, h, options)?)
+			}
+			DynamicImage::ImageBgra8(image) => {
+				DynamicImage::ImageBgra8(image.seam_carve_with(w, h, options)?)
+			}
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::seamcarver::seamcarve;
+	use image::{GenericImageView, Rgb, RgbImage};
+
+	#[test]
+	fn images_carve_to_their_own_kind() {
+		let buf: RgbImage = ImageBuffer::from_fn(9, 6, |x, y| {
+			Rgb([(x * 27) as u8, (y * 41) as u8, ((x * y * 13) % 256) as u8])
+		});
+		let carved = buf.seam_carve_to(7, 5).unwrap();
+		assert_eq!(
+			carved.clone().into_raw(),
+			seamcarve(&buf, 7, 5).unwrap().into_raw()
+		);
+
+		let dynamic = DynamicImage::ImageRgb8(buf);
+		match dynamic.seam_carve_to(7, 5).unwrap() {
+			DynamicImage::ImageRgb8(image) => assert_eq!(image.into_raw(), carved.into_raw()),
+			_ => panic!("the carve changed the image's pixel type"),
+		}
+		assert_eq!(dynamic.seam_carve_to(10, 6).unwrap().dimensions(), (10, 6));
+		assert!(dynamic.seam_carve_to(0, 6).is_err());
+	}
+}
@@ -0,0 +1,169 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Carve events over a stream
+//!
+//! A frontend written in another language, or just running in another
+//! process, can't pass a closure to `seamcarve_with_events`.  It can
+//! read a pipe, though.  Each event is written as one frame: a JSON
+//! object on a line of its own, flushed as soon as it's written, so
+//! the frontend sees every seam as the carve finds it.  The frontend
+//! loads the original image itself and applies the events to it.
+//!
+//! `EventReader` is the other end, for frontends that are written in
+//! Rust but would rather not link the carver.
+
+use crate::error::CarveError;
+use crate::report::{CarveEvent, CarveReport};
+use crate::seamcarver::{seamcarve_with_events, CarveOptions};
+use image::{GenericImageView, ImageBuffer, Pixel, Primitive};
+use std::io::{self, BufRead, Write};
+
+/// Writes carve events to a stream, one frame at a time.
+pub struct EventWriter<W: Write> {
+	out: W,
+}
+
+impl<W: Write> EventWriter<W> {
+	/// Takes the stream to write to.
+	pub fn new(out: W) -> Self {
+		EventWriter { out }
+	}
+
+	/// Write one event as a frame, and flush it.
+	pub fn send(&mut self, event: &CarveEvent) -> io::Result<()> {
+		serde_json::to_writer(&mut self.out, event)?;
+		self.out.write_all(b"\n")?;
+		self.out.flush()
+	}
+
+	/// Give back the stream.
+	pub fn into_inner(self) -> W {
+		self.out
+	}
+}
+
+/// Reads carve events from a stream of frames, as an iterator.  Blank
+/// lines between frames are skipped; a frame that doesn't decode is
+/// an `InvalidData` error.
+pub struct EventReader<R: BufRead> {
+	input: R,
+	line: String,
+}
+
+impl<R: BufRead> EventReader<R> {
+	/// Takes the stream to read from.
+	pub fn new(input: R) -> Self {
+		EventReader {
+			input,
+			line: String::new(),
+		}
+	}
+}
+
+impl<R: BufRead> Iterator for EventReader<R> {
+	type Item = io::Result<CarveEvent>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		loop {
+			self.line.clear();
+			match self.input.read_line(&mut self.line) {
+				Ok(0) => return None,
+				Ok(_) if self.line.trim().is_empty() => continue,
+				Ok(_) => {
+					return Some(
+						serde_json::from_str(&self.line)
+							.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+					)
+				}
+				Err(e) => return Some(Err(e)),
+			}
+		}
+	}
+}
+
+/// As `seamcarve_with_report`, writing every event of the carve to
+/// `out` as it happens.  If the stream fails, nothing more is written
+/// to it, the carve is finished anyway, and the failure is the
+/// result.
+pub fn seamcarve_streaming<I, P, S, W>(
+	image: &I,
+	newwidth: u32,
+	newheight: u32,
+	options: &CarveOptions,
+	out: W,
+) -> Result<(ImageBuffer<P, Vec<S>>, CarveReport), CarveError>
+where
+	I: GenericImageView<Pixel = P>,
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+	W: Write,
+{
+	let mut writer = EventWriter::new(out);
+	let mut failure = None;
+	let carved = seamcarve_with_events(image, newwidth, newheight, options, &mut |event| {
+		if failure.is_none() {
+			failure = writer.send(event).err();
+		}
+	})?;
+	match failure {
+		Some(e) => Err(CarveError::EventStream(e.to_string())),
+		None => Ok(carved),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::seamfinder::Orientation;
+	use image::{ImageBuffer, Luma};
+
+	#[test]
+	fn streamed_events_read_back_as_sent() {
+		let buf: ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_fn(10, 8, |x, y| {
+			Luma([((x * 31 + y * 17 + x * y) % 256) as u8])
+		});
+		let mut stream = Vec::new();
+		let (_, report) =
+			seamcarve_streaming(&buf, 8, 10, &CarveOptions::default(), &mut stream).unwrap();
+		assert_eq!(stream.iter().filter(|&&b| b == b'\n').count(), 5);
+
+		let events: Vec<CarveEvent> = EventReader::new(&stream[..])
+			.collect::<io::Result<_>>()
+			.unwrap();
+		let removed: Vec<_> = events
+			.iter()
+			.filter_map(|event| match event {
+				CarveEvent::Removed { path, .. } => Some(&path[..]),
+				_ => None,
+			})
+			.collect();
+		let reported: Vec<_> = report.seams.iter().map(|(_, path)| path).collect();
+		assert_eq!(removed, reported);
+		match &events[3] {
+			CarveEvent::Inserted {
+				orientation,
+				positions,
+			} => {
+				assert_eq!(*orientation, Orientation::Horizontal);
+				assert_eq!(positions.len(), 8);
+				assert!(positions.iter().all(|line| line.len() == 2));
+			}
+			event => panic!("expected the insertions, got {:?}", event),
+		}
+		assert_eq!(
+			events.last(),
+			Some(&CarveEvent::Finished {
+				width: 8,
+				height: 10
+			})
+		);
+
+		let garbled = EventReader::new(&b"\n{\"Finished\":{\"width\":1}}\n"[..]).next();
+		assert_eq!(
+			garbled.unwrap().unwrap_err().kind(),
+			io::ErrorKind::InvalidData
+		);
+	}
+}
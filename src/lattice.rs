@@ -0,0 +1,260 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Seam lattice
+//!
+//! Splicing a seam out of an image copies every pixel after it, which
+//! over a carve of many seams is a copy of the image for each one.
+//! The lattice leaves the image as it is and keeps, for every line
+//! across the seams, the position in the image of each pixel still in
+//! it: removing a seam shifts those indices instead of the pixels, and
+//! the carved image is materialized once, when the carve is done.  It
+//! can be looked at as the carved image in the meantime.
+//!
+//! The indices of horizontal seams run down the image's columns, and
+//! materializing the image from them reads a column at a time across
+//! rows far apart.  A large image is better transposed once, its
+//! columns carved as rows, and transposed back.
+
+use crate::cq;
+use crate::seamcarver::Carve;
+use image::{GenericImageView, ImageBuffer, Pixel, Primitive};
+
+/// An image with seams in one direction taken out of it, by index.
+pub(crate) struct SeamLattice<P, S>
+where
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	image: ImageBuffer<P, Vec<S>>,
+	direction: Carve,
+	// Whether the image is kept transposed, its lines as rows.
+	transposed: bool,
+	// The lines across the seams, rows for vertical seams and columns
+	// for horizontal ones, each `stride` long, and the first `length`
+	// of each still in the image.
+	origins: Vec<u32>,
+	stride: usize,
+	length: u32,
+	lines: u32,
+}
+
+impl<P, S> SeamLattice<P, S>
+where
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	pub(crate) fn new(image: ImageBuffer<P, Vec<S>>, direction: Carve) -> Self {
+		SeamLattice::over(image, direction, false)
+	}
+
+	// A lattice for horizontal seams over a transposed copy of the
+	// image.
+	pub(crate) fn transposed(image: ImageBuffer<P, Vec<S>>) -> Self {
+		SeamLattice::over(transpose(&image), Carve::Height, true)
+	}
+
+	fn over(image: ImageBuffer<P, Vec<S>>, direction: Carve, transposed: bool) -> Self {
+		let (width, height) = image.dimensions();
+		let (length, lines) = match (direction, transposed) {
+			(Carve::Width, _) | (Carve::Height, true) => (width, height),
+			(Carve::Height, false) => (height, width),
+		};
+		let origins = (0..lines).flat_map(|_| 0..length).collect();
+		SeamLattice {
+			image,
+			direction,
+			transposed,
+			origins,
+			stride: length as usize,
+			length,
+			lines,
+		}
+	}
+
+	pub(crate) fn direction(&self) -> Carve {
+		self.direction
+	}
+
+	// Take a seam out, as `remove_seam_in_place` would.
+	pub(crate) fn remove(&mut self, seam: &[u32]) {
+		let length = self.length as usize;
+		for (line, &at) in seam.iter().enumerate().take(self.lines as usize) {
+			let start = line * self.stride;
+			let line = &mut self.origins[start..start + length];
+			line.copy_within(at as usize + 1.., at as usize);
+		}
+		self.length -= 1;
+	}
+
+	// Take several seams out at once, as taking them out one after
+	// another from the right would.  The seams mustn't share a pixel.
+	pub(crate) fn remove_all(&mut self, seams: &[&[u32]]) {
+		let (length, count) = (self.length as usize, seams.len());
+		let mut at: Vec<usize> = Vec::with_capacity(count);
+		for line in 0..self.lines as usize {
+			at.clear();
+			at.extend(seams.iter().map(|seam| seam[line] as usize));
+			at.sort_unstable();
+			let start = line * self.stride;
+			let line = &mut self.origins[start..start + length];
+			// The run after each seam moves left past it and those before.
+			for (passed, &from) in at.iter().enumerate() {
+				let end = at.get(passed + 1).copied().unwrap_or(length);
+				line.copy_within(from + 1..end, from - passed);
+			}
+		}
+		self.length -= count as u32;
+	}
+
+	// Where in the kept image the pixel now at (x, y) came from.
+	fn origin(&self, x: u32, y: u32) -> (u32, u32) {
+		let at = |line: u32, along: u32| self.origins[line as usize * self.stride + along as usize];
+		match (self.direction, self.transposed) {
+			(Carve::Width, _) => (at(y, x), y),
+			(Carve::Height, false) => (x, at(x, y)),
+			(Carve::Height, true) => (at(x, y), x),
+		}
+	}
+
+	// The carved image, copied straight out of the kept image's buffer
+	// a pixel's channels at a time, in the order the carved image is
+	// laid out.
+	pub(crate) fn materialize(self) -> ImageBuffer<P, Vec<S>> {
+		let channels = P::CHANNEL_COUNT as usize;
+		let kept = self.image.width() as usize;
+		let raw: &[S] = &self.image;
+		let (length, lines) = (self.length as usize, self.lines as usize);
+		let mut carved = Vec::with_capacity(length * lines * channels);
+		let mut copy =
+			|at: usize| carved.extend_from_slice(&raw[at * channels..(at + 1) * channels]);
+		let rows = match (self.direction, self.transposed) {
+			// The lines are rows of the kept image, and of the carved one.
+			(Carve::Width, _) | (Carve::Height, true) => {
+				for line in 0..lines {
+					let origins = &self.origins[line * self.stride..][..length];
+					for &origin in origins {
+						copy(line * kept + origin as usize);
+					}
+				}
+				(self.length, self.lines)
+			}
+			// The lines are columns, and the carved image is read across
+			// them a row at a time.
+			(Carve::Height, false) => {
+				for y in 0..length {
+					for x in 0..lines {
+						copy(self.origins[x * self.stride + y] as usize * kept + x);
+					}
+				}
+				(self.lines, self.length)
+			}
+		};
+		let carved = ImageBuffer::from_raw(rows.0, rows.1, carved).unwrap();
+		// Carved a row at a time, as it's kept, and then turned back.
+		cq!(self.transposed, transpose(&carved), carved)
+	}
+}
+
+// The image flipped about its diagonal.
+fn transpose<P, S>(image: &ImageBuffer<P, Vec<S>>) -> ImageBuffer<P, Vec<S>>
+where
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	let (width, height) = image.dimensions();
+	ImageBuffer::from_fn(height, width, |x, y| *image.get_pixel(y, x))
+}
+
+impl<P, S> GenericImageView for SeamLattice<P, S>
+where
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	type Pixel = P;
+	type InnerImageView = ImageBuffer<P, Vec<S>>;
+
+	fn dimensions(&self) -> (u32, u32) {
+		match self.direction {
+			Carve::Width => (self.length, self.lines),
+			Carve::Height => (self.lines, self.length),
+		}
+	}
+
+	fn bounds(&self) -> (u32, u32, u32, u32) {
+		let (width, height) = self.dimensions();
+		(0, 0, width, height)
+	}
+
+	fn get_pixel(&self, x: u32, y: u32) -> P {
+		let (x, y) = self.origin(x, y);
+		*self.image.get_pixel(x, y)
+	}
+
+	fn inner(&self) -> &Self::InnerImageView {
+		&self.image
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::seamcarver::remove_seam_in_place;
+	use image::Luma;
+
+	#[test]
+	fn the_lattice_carves_what_splicing_does() {
+		let pixel = |x: u32, y: u32| Luma([((x * 53 + y * 29 + x * y * 7) % 256) as u8]);
+		let buf: ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_fn(7, 5, pixel);
+		let vertical = vec![
+			vec![3, 2, 2, 3, 4],
+			vec![0, 1, 0, 0, 1],
+			vec![4, 4, 3, 2, 1],
+		];
+		let horizontal = vec![
+			vec![2, 2, 3, 3, 4, 4, 4],
+			vec![0, 1, 1, 0, 0, 1, 2],
+			vec![2, 1, 0, 0, 1, 2, 2],
+		];
+		let lattices = vec![
+			(SeamLattice::new(buf.clone(), Carve::Width), &vertical),
+			(SeamLattice::new(buf.clone(), Carve::Height), &horizontal),
+			(SeamLattice::transposed(buf.clone()), &horizontal),
+		];
+		for (mut lattice, seams) in lattices.into_iter() {
+			let direction = lattice.direction();
+			let mut spliced = buf.clone();
+			for seam in seams.iter() {
+				lattice.remove(seam);
+				spliced = remove_seam_in_place(spliced, seam, direction);
+				assert_eq!(lattice.dimensions(), spliced.dimensions());
+				assert_eq!(lattice.get_pixel(3, 1), spliced[(3, 1)]);
+			}
+			assert_eq!(lattice.materialize().into_raw(), spliced.into_raw());
+		}
+	}
+
+	#[test]
+	fn seams_come_out_of_the_lattice_at_once() {
+		let pixel = |x: u32, y: u32| Luma([((x * 53 + y * 29 + x * y * 7) % 256) as u8]);
+		let buf: ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_fn(7, 5, pixel);
+		let vertical: [&[u32]; 2] = [&[4, 4, 3, 2, 1], &[0, 1, 0, 0, 0]];
+		let horizontal: [&[u32]; 2] = [&[3, 4, 4, 3, 2, 2, 3], &[0, 0, 1, 1, 0, 0, 0]];
+		let lattices = vec![
+			(SeamLattice::new(buf.clone(), Carve::Width), &vertical),
+			(SeamLattice::new(buf.clone(), Carve::Height), &horizontal),
+			(SeamLattice::transposed(buf.clone()), &horizontal),
+		];
+		for (mut lattice, seams) in lattices.into_iter() {
+			let direction = lattice.direction();
+			lattice.remove_all(seams);
+			// Right to left, or bottom to top, the seams don't move.
+			let mut spliced = buf.clone();
+			for seam in seams.iter() {
+				spliced = remove_seam_in_place(spliced, seam, direction);
+			}
+			assert_eq!(lattice.materialize().into_raw(), spliced.into_raw());
+		}
+	}
+}
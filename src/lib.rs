@@ -15,19 +15,68 @@
 
 extern crate image;
 
-// A proxy for a generic image that rotates processing by 90 so that
-// intermediate models can be safely sliced up for multi-threaded
-// processing.
-mod flipper;
-
 // Trait defining how an image becomes a seam.
-mod seamfinder;
+pub mod seamfinder;
+pub use seamfinder::{CachingSeamFinder, CostTable, Orientation, Seam, SeamFinder};
 
 // Some simple macros
 mod ternary;
 
 // A generic two-dimensional map, used to hold intermediate data.
 mod twodmap;
+pub use twodmap::{blend, TwoDimensionalMap};
+
+// What stands in for the neighbors of pixels at the image's edge.
+pub mod border;
+pub use border::BorderPolicy;
+
+// How the energy sees transparency, and how inserted pixels blend it.
+pub mod alpha;
+pub use alpha::AlphaEnergy;
+
+// Canny edge detection, for walling off strong structural edges.
+pub mod edges;
+
+// Finding JPEG block boundaries, to damp their energy.
+pub mod deblock;
+
+// The pool of threads a carve's parallel work runs on.
+mod pool;
+
+// Abandoning a carve part way.
+pub mod cancel;
+pub use cancel::CancelToken;
+
+// How a carve can fail.
+pub mod error;
+pub use error::CarveError;
+
+// Typed masks of pixels to protect and objects to remove.
+pub mod masks;
+pub use masks::{ProtectionMask, RemovalMask};
+
+// The adjustments a seam finder makes to the energy it sees.
+pub mod energyopts;
+pub use energyopts::EnergyOptions;
+
+// Limits on how many seams may pass through each region of an image.
+pub mod budget;
+pub use budget::{InsertionCap, RegionBudget};
+
+// The scalar types energies may be kept in.
+pub mod scalar;
+pub use scalar::{EnergyScalar, Fixed, FloatEnergy};
+
+// Bringing energy maps from different sources to a common range.
+pub mod normalize;
+pub use normalize::Normalization;
+
+// The row-at-a-time kernel at the heart of the seam DP.
+pub mod dprow;
+pub use dprow::TieBreak;
+
+// The seam DP, with a limit on how often a seam may step sideways.
+mod slope;
 
 // Functions to calculate the energy distance between
 // two pixel pairs, using a variety of methods.
@@ -41,7 +90,117 @@ pub use avisha1::AviShaOne;
 pub mod avisha2;
 pub use avisha2::AviShaTwo;
 
+// An approximate finder that searches only near the previous seam.
+pub mod banded;
+pub use banded::BandedSeamFinder;
+
+// Seams through images too large to hold, found a band of rows at a time.
+pub mod streamed;
+pub use streamed::{streamed_vertical_seam, ImageRows, RowSource};
+
+// Finding seams in a shrunken copy first, then at full size nearby.
+mod pyramid;
+
+// A lightweight face detector, for protecting faces from the carve.
+#[cfg(feature = "faces")]
+pub mod faces;
+
+// Carve the frames of a sprite sheet down to a new cell size.
+pub mod sprites;
+
+// Lay a shoot out on a contact sheet, carving each image to its cell.
+pub mod contactsheet;
+
+// Carving many images to the same spec, a few at a time.
+pub mod batch;
+pub use batch::{carve_batch, BatchSpec, BatchTarget};
+
+// Writers for exporting energy maps to external tools.
+#[cfg(feature = "mapio")]
+pub mod mapio;
+
 // Takes an Image and an ImageSeam and produces a new image with a seam
 // carved out.
 pub mod seamcarver;
-pub use seamcarver::seamcarve;
+
+// Carving seams out of an image by index, copying it only once.
+mod lattice;
+pub use seamcarver::{
+	insert_seam, remove_horizontal_seam, remove_seam_into, remove_vertical_seam, seamcarve,
+	seamcarve_with_events, seamcarve_with_options, seamcarve_with_report, Algorithm, CarveLimits,
+	CarveOptions, OversizePolicy, RegionOfInterest, TRANSPOSE_ABOVE,
+};
+
+// How the pixels of inserted seams are made.
+pub mod synthesis;
+pub use synthesis::Synthesis;
+
+// Carving as a method of the image types.
+pub mod imageext;
+pub use imageext::SeamRetarget;
+
+// A builder for a carve, with every setting in one place.
+pub mod carver;
+pub use carver::SeamCarver;
+
+// Carving the same seams out of an image's masks and maps.
+pub mod companions;
+pub use companions::{replay_seams, seamcarve_with_companions};
+
+// Recording a carve's seams, to do the carve again without the search.
+pub mod seamlog;
+pub use seamlog::SeamLog;
+
+// The frames of a carve, each seam shown before it goes.
+pub mod animation;
+pub use animation::{CarveAnimation, CarveSteps};
+
+// Drawing seams at random from among the nearly cheapest.
+pub mod sampling;
+pub use sampling::NearOptimal;
+
+// Variants of an image for training, each carved from its own seed.
+pub mod augment;
+pub use augment::{augment, AugmentParams, Variant};
+
+// The order to take seams out in, when carving both ways.
+pub mod retarget;
+pub use retarget::{RetargetStrategy, TransportMap, TRANSPORT_MAP_LIMIT};
+
+// What a carve did, beyond the image it produced.
+pub mod report;
+pub use report::{CarveEvent, CarveReport, ColorHistogram, SeamSequence, StageTimings};
+
+// The devices the energy map and the forward-energy DP can be sent to.
+pub mod backend;
+pub use backend::{any_backend, Backend, ComputeBackend, DeviceSeamFinder};
+
+// The energy map and the forward-energy DP as GPU compute shaders.
+#[cfg(feature = "gpu")]
+pub mod gpu;
+#[cfg(feature = "gpu")]
+pub use gpu::{Gpu, GpuSeamFinder};
+
+// The same, as OpenCL kernels.
+#[cfg(feature = "opencl")]
+pub mod opencl;
+#[cfg(feature = "opencl")]
+pub use opencl::{OpenCl, OpenClSeamFinder};
+
+// Carve events as frames on a stream, for frontends in other processes.
+#[cfg(feature = "ipc")]
+pub mod ipc;
+
+// What this build of the library was compiled with.
+pub mod capabilities;
+pub use capabilities::Capabilities;
+
+// Colorblind-safe palettes and legends for everything drawn.
+pub mod palette;
+pub use palette::{energy_to_image, MapScaling, Palette};
+
+// Seams as SVG overlays.
+pub mod svg;
+
+// Carrying image-space annotations through a carve.
+pub mod annotations;
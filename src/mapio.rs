@@ -0,0 +1,400 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Energy map interchange
+//!
+//! Writers for getting the intermediate maps out of the library and
+//! into external analysis tools without having to hand-roll a
+//! serializer for `TwoDimensionalMap`: 16-bit grayscale PNG for
+//! looking at, and NumPy's `.npy` for computing with.
+//!
+//! Symmetrically, readers for the per-pixel weight maps (saliency
+//! and the like) that research tools commonly produce as `.npy` or
+//! PFM files, for use with `EnergyOptions::weights`.
+
+use crate::twodmap::TwoDimensionalMap;
+use image::png::PNGEncoder;
+use image::ColorType;
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::path::Path;
+
+fn invalid(message: String) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// An element type that can be written into a `.npy` file.
+pub trait NpyElement: Default + Copy {
+	/// The NumPy type descriptor for this type, little-endian.
+	const DESCR: &'static str;
+
+	/// Write this element out, little-endian.
+	fn write_le<W: Write>(&self, out: &mut W) -> io::Result<()>;
+}
+
+macro_rules! npy_element {
+	($t: ty, $descr: expr) => {
+		impl NpyElement for $t {
+			const DESCR: &'static str = $descr;
+
+			fn write_le<W: Write>(&self, out: &mut W) -> io::Result<()> {
+				out.write_all(&self.to_le_bytes())
+			}
+		}
+	};
+}
+
+npy_element!(u16, "<u2");
+npy_element!(u32, "<u4");
+npy_element!(u64, "<u8");
+npy_element!(i32, "<i4");
+npy_element!(f32, "<f4");
+npy_element!(f64, "<f8");
+
+/// Write a map as a version 1.0 `.npy` array of shape (height,
+/// width), row-major, values unscaled.
+pub fn write_npy<P, W>(map: &TwoDimensionalMap<P>, out: &mut W) -> io::Result<()>
+where
+	P: NpyElement,
+	W: Write,
+{
+	let mut header = format!(
+		"{{'descr': '{}', 'fortran_order': False, 'shape': ({}, {}), }}",
+		P::DESCR,
+		map.height,
+		map.width
+	);
+	// The magic, version, and length take ten bytes; the header is
+	// padded with spaces so the data starts on a 64-byte boundary, and
+	// terminated with a newline.
+	let unpadded = 10 + header.len() + 1;
+	header.push_str(&" ".repeat((64 - unpadded % 64) % 64));
+	header.push('\n');
+
+	out.write_all(b"\x93NUMPY\x01\x00")?;
+	out.write_all(&(header.len() as u16).to_le_bytes())?;
+	out.write_all(header.as_bytes())?;
+	for value in &map.energy {
+		value.write_le(out)?;
+	}
+	Ok(())
+}
+
+/// Write an energy map as a 16-bit grayscale PNG.  Energies are scaled
+/// linearly so that the map's maximum becomes full white.
+pub fn write_png16<W: Write>(map: &TwoDimensionalMap<u32>, out: W) -> io::Result<()> {
+	let max = u64::from(*map.energy.iter().max().unwrap_or(&0)).max(1);
+	// PNG wants its 16-bit samples big-endian.
+	let data: Vec<u8> = map
+		.energy
+		.iter()
+		.flat_map(|e| {
+			((u64::from(*e) * 65535 / max) as u16)
+				.to_be_bytes()
+				.to_vec()
+		})
+		.collect();
+	PNGEncoder::new(out).encode(&data, map.width, map.height, ColorType::Gray(16))
+}
+
+// Pull the quoted value of a key out of an npy header dictionary.
+fn header_value<'a>(header: &'a str, key: &str) -> Option<&'a str> {
+	let start = header.find(&format!("'{}':", key))? + key.len() + 3;
+	let rest = header[start..].trim_start();
+	match rest.chars().next()? {
+		'\'' => rest[1..].split('\'').next(),
+		'(' => rest[1..].split(')').next(),
+		_ => rest.split(&[',', '}'][..]).next(),
+	}
+}
+
+/// Read a two-dimensional `.npy` array as a map of f32 weights.
+/// Floating point, unsigned, and signed integer arrays of either
+/// endianness are accepted, in C or Fortran order.  A header that
+/// doesn't describe such an array, or data that stops short of it, is
+/// an `InvalidData` error.
+pub fn read_npy<R: Read>(input: &mut R) -> io::Result<TwoDimensionalMap<f32>> {
+	let mut preamble = [0u8; 8];
+	input.read_exact(&mut preamble)?;
+	if &preamble[..6] != b"\x93NUMPY" {
+		return Err(invalid("not an npy file".to_string()));
+	}
+	let header_len = if preamble[6] == 1 {
+		let mut len = [0u8; 2];
+		input.read_exact(&mut len)?;
+		u16::from_le_bytes(len) as usize
+	} else {
+		let mut len = [0u8; 4];
+		input.read_exact(&mut len)?;
+		u32::from_le_bytes(len) as usize
+	};
+	let mut header = vec![0u8; header_len];
+	input.read_exact(&mut header)?;
+	let header = String::from_utf8_lossy(&header);
+
+	let descr = header_value(&header, "descr")
+		.ok_or_else(|| invalid("npy header has no descr".to_string()))?;
+	let fortran = header_value(&header, "fortran_order").map(str::trim) == Some("True");
+	let shape: Vec<u32> = header_value(&header, "shape")
+		.ok_or_else(|| invalid("npy header has no shape".to_string()))?
+		.split(',')
+		.map(str::trim)
+		.filter(|d| !d.is_empty())
+		.map(|d| {
+			d.parse()
+				.map_err(|_| invalid(format!("bad npy dimension {}", d)))
+		})
+		.collect::<io::Result<_>>()?;
+	if shape.len() != 2 {
+		return Err(invalid(format!(
+			"expected a 2-d array, not {}-d",
+			shape.len()
+		)));
+	}
+	let (height, width) = (shape[0], shape[1]);
+
+	// A type is a kind letter and a size in bytes, of at most eight.
+	let unsupported = || invalid(format!("unsupported npy type {}", descr));
+	let (big, kind) = (
+		descr.starts_with('>'),
+		descr.trim_start_matches(|c| "<>|=".contains(c)).as_bytes(),
+	);
+	let (&letter, size) = kind.split_first().ok_or_else(unsupported)?;
+	let size: usize = std::str::from_utf8(size)
+		.ok()
+		.and_then(|size| size.parse().ok())
+		.filter(|size| (1..=8).contains(size))
+		.ok_or_else(unsupported)?;
+
+	// The values are gathered as they're read, rather than into a map
+	// made first, so that a header claiming a vast array fails when
+	// the data runs out instead of when the map is allocated.
+	let cells = usize::try_from(u64::from(width) * u64::from(height))
+		.map_err(|_| invalid(format!("a {}x{} npy array is too large", width, height)))?;
+	let mut raw = vec![0u8; size];
+	let mut values = Vec::new();
+	for _ in 0..cells {
+		input
+			.read_exact(&mut raw)
+			.map_err(|error| match error.kind() {
+				io::ErrorKind::UnexpectedEof => {
+					invalid("npy data is shorter than its shape".to_string())
+				}
+				_ => error,
+			})?;
+		if !big {
+			raw.reverse();
+		}
+		let mut bytes = [0u8; 8];
+		bytes[8 - size..].copy_from_slice(&raw);
+		let bits = u64::from_be_bytes(bytes);
+		let value = match (letter, size) {
+			(b'f', 4) => f32::from_bits(bits as u32),
+			(b'f', 8) => f64::from_bits(bits) as f32,
+			(b'u', _) | (b'b', 1) => bits as f32,
+			(b'i', _) => ((bits << (64 - 8 * size)) as i64 >> (64 - 8 * size)) as f32,
+			_ => return Err(unsupported()),
+		};
+		values.push(value);
+	}
+
+	// Fortran order runs down the columns, which are the rows of the
+	// transposed map.
+	if fortran {
+		let map = TwoDimensionalMap::from_vec(height, width, values);
+		map.map(|map| map.transposed())
+			.map_err(|error| invalid(error.to_string()))
+	} else {
+		TwoDimensionalMap::from_vec(width, height, values)
+			.map_err(|error| invalid(error.to_string()))
+	}
+}
+
+/// Read a Portable Float Map as a map of f32 weights.  Color maps are
+/// averaged down to a single channel.
+pub fn read_pfm<R: Read>(input: &mut R) -> io::Result<TwoDimensionalMap<f32>> {
+	// The header is three whitespace-separated fields after the magic,
+	// followed by a single whitespace character.
+	let mut fields = Vec::new();
+	let mut field = Vec::new();
+	let mut byte = [0u8; 1];
+	while fields.len() < 4 {
+		input.read_exact(&mut byte)?;
+		if byte[0].is_ascii_whitespace() {
+			if !field.is_empty() {
+				fields.push(String::from_utf8_lossy(&field).into_owned());
+				field.clear();
+			}
+		} else {
+			field.push(byte[0]);
+		}
+	}
+	let channels = match fields[0].as_str() {
+		"Pf" => 1,
+		"PF" => 3,
+		magic => return Err(invalid(format!("not a pfm file: {}", magic))),
+	};
+	let number = |f: &String| {
+		f.parse::<f32>()
+			.map_err(|_| invalid(format!("bad pfm header field {}", f)))
+	};
+	let (width, height, scale) = (
+		number(&fields[1])? as u32,
+		number(&fields[2])? as u32,
+		number(&fields[3])?,
+	);
+
+	// Rows are stored bottom to top.
+	let mut map = TwoDimensionalMap::new(width, height);
+	let mut sample = [0u8; 4];
+	for y in (0..height).rev() {
+		for x in 0..width {
+			let mut sum = 0.0;
+			for _ in 0..channels {
+				input.read_exact(&mut sample)?;
+				sum += if scale < 0.0 {
+					f32::from_le_bytes(sample)
+				} else {
+					f32::from_be_bytes(sample)
+				};
+			}
+			map[(x, y)] = sum / channels as f32;
+		}
+	}
+	Ok(map)
+}
+
+/// Read a weight map from a `.npy` or `.pfm` file, chosen by the
+/// file's extension.
+pub fn load_weights<Q: AsRef<Path>>(path: Q) -> io::Result<TwoDimensionalMap<f32>> {
+	let path = path.as_ref();
+	let mut input = BufReader::new(File::open(path)?);
+	match path.extension().and_then(|e| e.to_str()) {
+		Some("npy") => read_npy(&mut input),
+		Some("pfm") => read_pfm(&mut input),
+		_ => Err(io::Error::new(
+			io::ErrorKind::InvalidInput,
+			format!("{} is neither an .npy nor a .pfm file", path.display()),
+		)),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn npy_header_is_aligned() {
+		let mut map = TwoDimensionalMap::<u32>::new(3, 2);
+		map[(2, 1)] = 7;
+		let mut out = Vec::new();
+		write_npy(&map, &mut out).unwrap();
+		assert_eq!(&out[..8], b"\x93NUMPY\x01\x00");
+		let header_len = u16::from_le_bytes([out[8], out[9]]) as usize;
+		assert_eq!((10 + header_len) % 64, 0);
+		let header = std::str::from_utf8(&out[10..10 + header_len]).unwrap();
+		assert!(header.contains("'shape': (2, 3)"));
+		assert_eq!(out.len(), 10 + header_len + 6 * 4);
+		assert_eq!(&out[out.len() - 4..], &7u32.to_le_bytes());
+	}
+
+	#[test]
+	fn png16_round_trips() {
+		let mut map = TwoDimensionalMap::<u32>::new(4, 2);
+		map[(1, 0)] = 100_000;
+		map[(3, 1)] = 50_000;
+		let mut out = Vec::new();
+		write_png16(&map, &mut out).unwrap();
+		// The IHDR chunk follows the eight byte signature, its length
+		// and tag, and the width and height.
+		assert_eq!(&out[12..16], b"IHDR");
+		assert_eq!((out[24], out[25]), (16, 0));
+		// This version of image reads sixteen bit PNGs down to eight.
+		let image = image::load_from_memory(&out).unwrap().to_luma();
+		assert_eq!(image.get_pixel(1, 0)[0], 255);
+		assert_eq!(image.get_pixel(3, 1)[0], 127);
+		assert_eq!(image.get_pixel(0, 0)[0], 0);
+	}
+
+	#[test]
+	fn npy_round_trips_as_weights() {
+		let mut map = TwoDimensionalMap::<u32>::new(3, 2);
+		map[(2, 1)] = 7;
+		map[(0, 1)] = 3;
+		let mut out = Vec::new();
+		write_npy(&map, &mut out).unwrap();
+		let weights = read_npy(&mut &out[..]).unwrap();
+		assert_eq!((weights.width, weights.height), (3, 2));
+		assert_eq!(weights[(2, 1)], 7.0);
+		assert_eq!(weights[(0, 1)], 3.0);
+		assert_eq!(weights[(1, 0)], 0.0);
+	}
+
+	// An npy file with the given header dictionary and data.
+	fn npy(header: &str, data: &[u8]) -> Vec<u8> {
+		let mut out = b"\x93NUMPY\x01\x00".to_vec();
+		out.extend_from_slice(&(header.len() as u16).to_le_bytes());
+		out.extend_from_slice(header.as_bytes());
+		out.extend_from_slice(data);
+		out
+	}
+
+	#[test]
+	fn npy_in_fortran_order_runs_down_the_columns() {
+		let header = "{'descr': '|u1', 'fortran_order': True, 'shape': (2, 3), }";
+		let weights = read_npy(&mut &npy(header, &[1, 2, 3, 4, 5, 6])[..]).unwrap();
+		assert_eq!((weights.width, weights.height), (3, 2));
+		assert_eq!(weights.energy, [1.0, 3.0, 5.0, 2.0, 4.0, 6.0]);
+	}
+
+	#[test]
+	fn malformed_npy_is_invalid_data() {
+		let data = [0u8; 64];
+		let headers = [
+			// No type, or only a byte order.
+			"{'descr': '', 'fortran_order': False, 'shape': (2, 2), }",
+			"{'descr': '<', 'fortran_order': False, 'shape': (2, 2), }",
+			// Sizes no value can be read in.
+			"{'descr': '<f16', 'fortran_order': False, 'shape': (2, 2), }",
+			"{'descr': '<i0', 'fortran_order': False, 'shape': (2, 2), }",
+			"{'descr': '<u99999999999999999999', 'fortran_order': False, 'shape': (2, 2), }",
+			"{'descr': '<\u{e9}4', 'fortran_order': False, 'shape': (2, 2), }",
+			"{'descr': '<c8', 'fortran_order': False, 'shape': (2, 2), }",
+			// Shapes far larger than the data, or than a u32.
+			"{'descr': '<u4', 'fortran_order': False, 'shape': (4294967295, 4294967295), }",
+			"{'descr': '<u4', 'fortran_order': False, 'shape': (4294967296, 2), }",
+			"{'descr': '<u4', 'fortran_order': False, 'shape': (5, 5), }",
+			"{'descr': '<u4', 'fortran_order': False, 'shape': (2, 2, 2), }",
+			"{'fortran_order': False, 'shape': (2, 2), }",
+		];
+		for header in headers.iter() {
+			let error = read_npy(&mut &npy(header, &data)[..]).unwrap_err();
+			assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{}", header);
+		}
+
+		// Every truncation of a good file fails, and none panics.
+		let good = npy(
+			"{'descr': '>i2', 'fortran_order': False, 'shape': (2, 3), }",
+			&data[..12],
+		);
+		assert!(read_npy(&mut &good[..]).is_ok());
+		for end in 0..good.len() {
+			assert!(read_npy(&mut &good[..end]).is_err());
+		}
+	}
+
+	#[test]
+	fn pfm_rows_run_bottom_to_top() {
+		let mut pfm = b"Pf\n2 2\n-1.0\n".to_vec();
+		for v in &[0.25f32, 0.5, 0.75, 1.0] {
+			pfm.extend_from_slice(&v.to_le_bytes());
+		}
+		let weights = read_pfm(&mut &pfm[..]).unwrap();
+		assert_eq!(weights[(0, 1)], 0.25);
+		assert_eq!(weights[(1, 1)], 0.5);
+		assert_eq!(weights[(0, 0)], 0.75);
+		assert_eq!(weights[(1, 0)], 1.0);
+	}
+}
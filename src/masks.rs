@@ -0,0 +1,198 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Masks
+//!
+//! The carver takes two kinds of mask: one of pixels to keep seams
+//! out of, and one of an object to carve out of the image.  They're
+//! the same shape, a yes or no for every pixel, but mixing one up for
+//! the other does exactly the wrong thing, so each is its own type.
+//! Either can be made from a grayscale image, light where it marks,
+//! as paint programs produce them, or from a buffer of booleans, as
+//! a segmentation model might.  Where the two overlap, protection
+//! wins: carving out an object never costs something marked to keep.
+
+use crate::error::CarveError;
+use crate::pixelpairs::channel_max;
+use crate::twodmap::TwoDimensionalMap;
+use image::{GenericImageView, ImageBuffer, Luma, Pixel, Primitive};
+use num_traits::NumCast;
+use std::marker::PhantomData;
+
+/// The kind of a mask of pixels to keep seams out of.
+#[derive(Debug, Copy, Clone)]
+pub enum Protect {}
+
+/// The kind of a mask of an object to carve out of the image.
+#[derive(Debug, Copy, Clone)]
+pub enum Remove {}
+
+/// A mark for every pixel of an image, of one kind.
+#[derive(Debug, Clone)]
+pub struct Mask<K> {
+	map: TwoDimensionalMap<bool>,
+	kind: PhantomData<K>,
+}
+
+/// Pixels to keep seams out of; see `EnergyOptions::protection_mask`.
+pub type ProtectionMask = Mask<Protect>;
+
+/// An object to carve out of the image; see
+/// `EnergyOptions::removal_mask`.
+pub type RemovalMask = Mask<Remove>;
+
+impl<K> Mask<K> {
+	/// A mask marking nothing.
+	pub fn new(width: u32, height: u32) -> Self {
+		Mask {
+			map: TwoDimensionalMap::new(width, height),
+			kind: PhantomData,
+		}
+	}
+
+	/// A mask marking the light half of a grayscale image.
+	pub fn from_luma<I, P, S>(image: &I) -> Self
+	where
+		I: GenericImageView<Pixel = P>,
+		P: Pixel<Subpixel = S> + 'static,
+		S: Primitive + 'static,
+	{
+		let max = channel_max::<S>();
+		let mut mask = Mask::new(image.width(), image.height());
+		for (x, y, pixel) in image.pixels() {
+			let luma: f64 = NumCast::from(pixel.to_luma()[0]).unwrap();
+			mask.map[(x, y)] = luma > max / 2.0;
+		}
+		mask
+	}
+
+	/// A mask from a buffer of marks, row by row.  The buffer must
+	/// have one for every pixel.
+	pub fn from_bools(width: u32, height: u32, marks: Vec<bool>) -> Result<Self, CarveError> {
+		if marks.len() != width as usize * height as usize {
+			return Err(CarveError::MaskLengthMismatch {
+				expected: width as usize * height as usize,
+				found: marks.len(),
+			});
+		}
+		Ok(Mask {
+			map: TwoDimensionalMap {
+				width,
+				height,
+				energy: marks,
+			},
+			kind: PhantomData,
+		})
+	}
+
+	/// The width of the mask, in pixels.
+	pub fn width(&self) -> u32 {
+		self.map.width
+	}
+
+	/// The height of the mask, in pixels.
+	pub fn height(&self) -> u32 {
+		self.map.height
+	}
+
+	/// Whether the pixel at `(x, y)` is marked.
+	pub fn is_marked(&self, x: u32, y: u32) -> bool {
+		self.map[(x, y)]
+	}
+
+	/// Mark, or unmark, the pixel at `(x, y)`.
+	pub fn mark(&mut self, x: u32, y: u32, marked: bool) {
+		self.map[(x, y)] = marked;
+	}
+
+	/// How many pixels are marked.
+	pub fn count(&self) -> usize {
+		self.map.energy.iter().filter(|&&marked| marked).count()
+	}
+
+	/// The pixels marked in either mask, which must be the same size.
+	pub fn union(&self, other: &Self) -> Result<Self, CarveError> {
+		self.zip_with(other, |a, b| a || b)
+	}
+
+	/// The mask as a grayscale image, white where it marks.
+	pub fn to_luma(&self) -> ImageBuffer<Luma<u8>, Vec<u8>> {
+		ImageBuffer::from_fn(self.width(), self.height(), |x, y| {
+			Luma([if self.is_marked(x, y) { 255 } else { 0 }])
+		})
+	}
+
+	fn zip_with<L>(
+		&self,
+		other: &Mask<L>,
+		op: impl Fn(bool, bool) -> bool,
+	) -> Result<Self, CarveError> {
+		if (self.width(), self.height()) != (other.width(), other.height()) {
+			return Err(CarveError::MaskDimensionMismatch {
+				expected: (self.width(), self.height()),
+				found: (other.width(), other.height()),
+			});
+		}
+		let marks = self
+			.map
+			.energy
+			.iter()
+			.zip(other.map.energy.iter())
+			.map(|(a, b)| op(*a, *b))
+			.collect();
+		Mask::from_bools(self.width(), self.height(), marks)
+	}
+}
+
+impl RemovalMask {
+	/// The object, less whatever of it is protected.
+	pub fn without(&self, protection: &ProtectionMask) -> Result<Self, CarveError> {
+		self.zip_with(protection, |removed, protected| removed && !protected)
+	}
+}
+
+/// The two masks as one map: 1 where a pixel is protected, -1 where
+/// it's to be removed, and 0 elsewhere.  Protection wins where they
+/// overlap.  The masks must be the same size.
+pub fn combine(
+	protection: &ProtectionMask,
+	removal: &RemovalMask,
+) -> Result<TwoDimensionalMap<i32>, CarveError> {
+	let removal = removal.without(protection)?;
+	let mut combined = TwoDimensionalMap::new(protection.width(), protection.height());
+	for y in 0..combined.height {
+		for x in 0..combined.width {
+			combined[(x, y)] = match (protection.is_marked(x, y), removal.is_marked(x, y)) {
+				(true, _) => 1,
+				(false, true) => -1,
+				(false, false) => 0,
+			};
+		}
+	}
+	Ok(combined)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn masks_combine_with_protection_winning() {
+		let gray: ImageBuffer<Luma<u16>, Vec<u16>> =
+			ImageBuffer::from_fn(3, 2, |x, y| Luma([(x * 30000 + y * 100) as u16]));
+		let protection = ProtectionMask::from_luma(&gray);
+		assert_eq!(protection.count(), 2);
+		assert!(protection.is_marked(2, 1) && !protection.is_marked(1, 1));
+
+		let removal =
+			RemovalMask::from_bools(3, 2, vec![false, true, true, false, true, true]).unwrap();
+		assert!(RemovalMask::from_bools(3, 2, vec![true; 5]).is_err());
+		let combined = combine(&protection, &removal).unwrap();
+		assert_eq!(combined.energy, vec![0, -1, 1, 0, -1, 1]);
+		assert_eq!(removal.without(&protection).unwrap().count(), 2);
+		assert_eq!(removal.union(&RemovalMask::new(3, 2)).unwrap().count(), 4);
+		assert_eq!(removal.to_luma()[(1, 0)], Luma([255]));
+		assert!(combine(&protection, &RemovalMask::new(2, 3)).is_err());
+	}
+}
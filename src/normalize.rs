@@ -0,0 +1,102 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Energy normalization
+//!
+//! Energies from different sources don't share a scale: a gradient
+//! map of a 16-bit image runs to billions, a saliency map to one.
+//! Before they can be blended, or written out as an image someone
+//! can look at, they need to be brought to a common range.  Each mode
+//! here maps a map's energies onto (0..full scale) of its scalar
+//! type, keeping their order.
+
+use crate::scalar::EnergyScalar;
+use crate::twodmap::TwoDimensionalMap;
+use std::cmp::Ordering;
+
+/// How to bring an energy map to a common range.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum Normalization {
+	/// Leave the energies as they are.
+	#[default]
+	None,
+	/// Scale linearly, so that the largest energy becomes full scale.
+	MaxScale,
+	/// Replace each energy with the fraction of the map at or below
+	/// it, which spreads the energies evenly over the range.  A few
+	/// very strong edges no longer flatten everything else to zero.
+	Equalize,
+	/// Scale the logarithm of one plus the energy, so that the
+	/// weak energies keep their differences and the strong ones
+	/// are compressed.
+	Log,
+}
+
+impl<E: EnergyScalar> TwoDimensionalMap<E> {
+	/// Normalize the map in place.
+	pub fn normalize(&mut self, mode: Normalization) {
+		let full = E::full_scale().to_f64();
+		let max = self.energy.iter().map(|e| e.to_f64()).fold(0.0, f64::max);
+		match mode {
+			Normalization::None => {}
+			Normalization::MaxScale if max > 0.0 => {
+				for e in self.energy.iter_mut() {
+					*e = E::from_f64(e.to_f64() / max * full);
+				}
+			}
+			Normalization::Log if max > 0.0 => {
+				let top = max.ln_1p();
+				for e in self.energy.iter_mut() {
+					*e = E::from_f64(e.to_f64().ln_1p() / top * full);
+				}
+			}
+			Normalization::Equalize => {
+				let mut sorted: Vec<f64> = self.energy.iter().map(|e| e.to_f64()).collect();
+				sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+				let count = sorted.len() as f64;
+				for e in self.energy.iter_mut() {
+					let value = e.to_f64();
+					let at_or_below = sorted.partition_point(|s| *s <= value) as f64;
+					*e = E::from_f64(at_or_below / count * full);
+				}
+			}
+			// An all-zero map is already as normal as it gets.
+			_ => {}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn map_of(energy: &[u32]) -> TwoDimensionalMap<u32> {
+		TwoDimensionalMap {
+			width: energy.len() as u32,
+			height: 1,
+			energy: energy.to_vec(),
+		}
+	}
+
+	#[test]
+	fn modes_map_onto_the_full_scale() {
+		let mut map = map_of(&[0, 100, 200, 400]);
+		map.normalize(Normalization::MaxScale);
+		assert_eq!(map.energy, [0, 16384, 32768, 65535]);
+
+		let mut map = map_of(&[0, 1, 2, 1_000_000]);
+		map.normalize(Normalization::Equalize);
+		assert_eq!(map.energy, [16384, 32768, 49151, 65535]);
+
+		let mut map = map_of(&[0, 1, 1_000_000]);
+		map.normalize(Normalization::Log);
+		assert_eq!(map.energy[0], 0);
+		assert!(map.energy[1] > 3000);
+		assert_eq!(map.energy[2], 65535);
+
+		let mut map = map_of(&[0, 0]);
+		map.normalize(Normalization::MaxScale);
+		assert_eq!(map.energy, [0, 0]);
+	}
+}
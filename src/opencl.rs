@@ -0,0 +1,287 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Seam finding with OpenCL
+//!
+//! The same kernels as `gpu`, for machines wgpu can't reach: older
+//! servers without Vulkan, and headless boxes with only a vendor's
+//! OpenCL driver, or a CPU one.  The energy map is a work item to a
+//! pixel, and the forward-energy DP a single work group, working a
+//! row at a time with a barrier between rows.
+//!
+//! The kernels work in `f32`, as every `ComputeBackend` does, with
+//! contraction off and division rounded correctly, so that they sum
+//! exactly as the CPU does.  Without an OpenCL platform `OpenCl::new`
+//! returns None, and a plane the device can't allocate buffers for,
+//! or fails to work, is worked on the CPU.
+
+use crate::backend::{ComputeBackend, DeviceSeamFinder};
+use crate::twodmap::TwoDimensionalMap;
+use opencl3::command_queue::CommandQueue;
+use opencl3::context::Context;
+use opencl3::device::{Device, CL_DEVICE_TYPE_ALL};
+use opencl3::kernel::Kernel;
+use opencl3::memory::{Buffer, ClMem, CL_MEM_READ_WRITE};
+use opencl3::platform::get_platforms;
+use opencl3::program::Program;
+use opencl3::types::{cl_uint, CL_BLOCKING};
+use std::ptr;
+
+const KERNELS: &str = r#"
+#pragma OPENCL FP_CONTRACT OFF
+
+uint at(uint width, uint x, uint y) {
+	return y * width + x;
+}
+
+float of_lumas(uint l1, uint l2, uint max) {
+	float css = ((float)l1 - (float)l2) / (float)max;
+	return css * css;
+}
+
+uint clamped(__global const uint *lumas, uint width, uint height, int x, int y) {
+	uint cx = (uint)clamp(x, 0, (int)width - 1);
+	uint cy = (uint)clamp(y, 0, (int)height - 1);
+	return lumas[at(width, cx, cy)];
+}
+
+__kernel void energy_map(
+	uint width,
+	uint height,
+	uint max,
+	__global const uint *lumas,
+	__global float *energy)
+{
+	uint gx = get_global_id(0), gy = get_global_id(1);
+	if (gx >= width || gy >= height) {
+		return;
+	}
+	int x = (int)gx, y = (int)gy;
+	float across = of_lumas(clamped(lumas, width, height, x - 1, y),
+		clamped(lumas, width, height, x + 1, y), max);
+	float down = of_lumas(clamped(lumas, width, height, x, y - 1),
+		clamped(lumas, width, height, x, y + 1), max);
+	energy[at(width, gx, gy)] = across + down;
+}
+
+float epp(__global const uint *lumas, uint width, uint max, uint x1, uint y1, uint x2, uint y2) {
+	return of_lumas(lumas[at(width, x1, y1)], lumas[at(width, x2, y2)], max);
+}
+
+float transition(__global const uint *lumas, uint width, uint max, uint x, uint y, uint x_above) {
+	uint last = width - 1;
+	uint y_above = y - 1;
+	float up;
+	if (x == 0) {
+		up = epp(lumas, width, max, x, y_above, min(x + 1, last), y_above);
+	} else if (x == last) {
+		up = epp(lumas, width, max, x - 1, y_above, x, y_above);
+	} else {
+		up = epp(lumas, width, max, x - 1, y_above, x + 1, y_above);
+	}
+	if (x_above == x) {
+		return up;
+	}
+	return up + epp(lumas, width, max, x, y_above, x_above, y);
+}
+
+__kernel void forward_costs(
+	uint width,
+	uint height,
+	uint max,
+	__global const uint *lumas,
+	__global float *costs,
+	__global uint *parents)
+{
+	uint id = get_local_id(0), step = get_local_size(0);
+	uint last = width - 1;
+	for (uint x = id; x < width; x += step) {
+		uint xl = x == 0 ? 0 : x - 1;
+		uint xr = x == last ? last : x + 1;
+		costs[at(width, x, 0)] = of_lumas(lumas[at(width, xl, 0)], lumas[at(width, xr, 0)], max);
+		parents[at(width, x, 0)] = 0;
+	}
+	barrier(CLK_GLOBAL_MEM_FENCE);
+	for (uint y = 1; y < height; y++) {
+		for (uint x = id; x < width; x += step) {
+			float center = costs[at(width, x, y - 1)] + transition(lumas, width, max, x, y, x);
+			float left = center;
+			if (x != 0) {
+				left = costs[at(width, x - 1, y - 1)] + transition(lumas, width, max, x, y, x - 1);
+			}
+			float right = center;
+			if (x != last) {
+				right = costs[at(width, x + 1, y - 1)] + transition(lumas, width, max, x, y, x + 1);
+			}
+			// Ties go left, as `TieBreak::Leftmost` has them.
+			bool right_wins = right < center;
+			float m = right_wins ? right : center;
+			uint i = right_wins ? x + 1 : x;
+			bool left_wins = left <= m;
+			costs[at(width, x, y)] = left_wins ? left : m;
+			parents[at(width, x, y)] = left_wins ? (x == 0 ? 0 : x - 1) : i;
+		}
+		barrier(CLK_GLOBAL_MEM_FENCE);
+	}
+}
+"#;
+
+// The most work items the DP's work group is given.
+const GROUP: usize = 256;
+
+/// An OpenCL device, and the kernels for the energy map and the
+/// forward-energy DP built for it.  Making one builds the kernels, so
+/// make it once and hand it to every finder.
+pub struct OpenCl {
+	context: Context,
+	queue: CommandQueue,
+	// The kernels keep the program alive, but it's kept here too for
+	// anything that wants its build log.
+	_program: Program,
+	energy: Kernel,
+	costs: Kernel,
+	group: usize,
+}
+
+impl OpenCl {
+	/// The first device of the first platform that has one, or None if
+	/// there's no OpenCL to be had.
+	pub fn new() -> Option<Self> {
+		let device = get_platforms().ok()?.iter().find_map(|platform| {
+			platform
+				.get_devices(CL_DEVICE_TYPE_ALL)
+				.ok()?
+				.first()
+				.copied()
+		})?;
+		let device = Device::new(device);
+		let context = Context::from_device(&device).ok()?;
+		let queue = CommandQueue::create(&context, device.id(), 0).ok()?;
+		let program = Program::create_and_build_from_source(
+			&context,
+			KERNELS,
+			"-cl-fp32-correctly-rounded-divide-sqrt",
+		)
+		.ok()?;
+		let energy = Kernel::create(&program, "energy_map").ok()?;
+		let costs = Kernel::create(&program, "forward_costs").ok()?;
+		let group = std::cmp::min(GROUP, device.max_work_group_size().ok()?);
+		Some(OpenCl {
+			context,
+			queue,
+			_program: program,
+			energy,
+			costs,
+			group,
+		})
+	}
+
+	// Run a kernel over the plane, and read back the plane-sized
+	// outputs it writes after the plane's lumas.  None if the device
+	// fails along the way.
+	fn run<T: Copy + Default>(
+		&self,
+		kernel: &Kernel,
+		(lumas, max): (&TwoDimensionalMap<u32>, u32),
+		outputs: usize,
+		(global, local): (&[usize], Option<&[usize]>),
+	) -> Option<Vec<Vec<T>>> {
+		let count = lumas.energy.len();
+		let mut input =
+			Buffer::<cl_uint>::create(&self.context, CL_MEM_READ_WRITE, count, ptr::null_mut())
+				.ok()?;
+		self.queue
+			.enqueue_write_buffer(&mut input, CL_BLOCKING, 0, &lumas.energy, &[])
+			.ok()?;
+		let stored = (0..outputs)
+			.map(|_| {
+				Buffer::<T>::create(&self.context, CL_MEM_READ_WRITE, count, ptr::null_mut()).ok()
+			})
+			.collect::<Option<Vec<_>>>()?;
+
+		let (width, height): (cl_uint, cl_uint) = (lumas.width, lumas.height);
+		kernel.set_arg(0, &width).ok()?;
+		kernel.set_arg(1, &height).ok()?;
+		kernel.set_arg(2, &max).ok()?;
+		kernel.set_arg(3, &input.get()).ok()?;
+		for (at, output) in stored.iter().enumerate() {
+			kernel.set_arg(4 + at as cl_uint, &output.get()).ok()?;
+		}
+		let local = local.map_or(ptr::null(), |sizes| sizes.as_ptr());
+		self.queue
+			.enqueue_nd_range_kernel(
+				kernel.get(),
+				global.len() as cl_uint,
+				ptr::null(),
+				global.as_ptr(),
+				local,
+				&[],
+			)
+			.ok()?;
+
+		let mut read = Vec::with_capacity(outputs);
+		for output in &stored {
+			let mut values = vec![T::default(); count];
+			self.queue
+				.enqueue_read_buffer(output, CL_BLOCKING, 0, &mut values, &[])
+				.ok()?;
+			read.push(values);
+		}
+		Some(read)
+	}
+}
+
+impl ComputeBackend for OpenCl {
+	fn try_energy_map_of(
+		&self,
+		lumas: &TwoDimensionalMap<u32>,
+		max: u32,
+	) -> Option<TwoDimensionalMap<f32>> {
+		let (width, height) = (lumas.width, lumas.height);
+		let mut energy = TwoDimensionalMap::new(width, height);
+		if width == 0 || height == 0 {
+			return Some(energy);
+		}
+		let global = [width as usize, height as usize];
+		let mut out = self.run::<f32>(&self.energy, (lumas, max), 1, (&global, None))?;
+		energy.energy = out.remove(0);
+		Some(energy)
+	}
+
+	fn try_forward_costs(
+		&self,
+		lumas: &TwoDimensionalMap<u32>,
+		max: u32,
+	) -> Option<(TwoDimensionalMap<f32>, TwoDimensionalMap<u32>)> {
+		let (width, height) = (lumas.width, lumas.height);
+		let mut costs = TwoDimensionalMap::new(width, height);
+		let mut parents = TwoDimensionalMap::new(width, height);
+		if width == 0 || height == 0 {
+			return Some((costs, parents));
+		}
+		// The costs come back as bits, to share a buffer type with the
+		// parents.
+		let group = [self.group];
+		let mut out = self.run::<u32>(&self.costs, (lumas, max), 2, (&group, Some(&group)))?;
+		parents.energy = out.pop().unwrap();
+		costs.energy = out[0].iter().map(|bits| f32::from_bits(*bits)).collect();
+		Some((costs, parents))
+	}
+}
+
+/// A seam finder running the forward-energy DP with OpenCL.
+pub type OpenClSeamFinder<'a, I, P, S> = DeviceSeamFinder<'a, I, P, S, OpenCl>;
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn opencl_finds_what_the_cpu_does() {
+		// Without a platform there's nothing to compare.
+		if let Some(opencl) = OpenCl::new() {
+			crate::backend::agrees_with_the_cpu(&opencl);
+		}
+	}
+}
@@ -0,0 +1,321 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Palettes
+//!
+//! The pictures the library draws (energy heatmaps, seam overlays,
+//! the order seams were inserted in) all map a magnitude to a color.
+//! Red-on-gray and rainbow scales are unreadable to a good fraction
+//! of the people who'll look at them, so the built-in palettes are
+//! the perceptually uniform, colorblind-safe viridis and cividis, and
+//! plain gray; anything else can be given as a list of color stops.
+//! Each drawing comes with a legend, so a reader can tell what the
+//! colors mean without the code that made them.
+//!
+//! An energy map can also be drawn as a plain grayscale image, with a
+//! choice of how its energies are scaled to the 256 levels of gray.
+
+use crate::scalar::EnergyScalar;
+use crate::twodmap::TwoDimensionalMap;
+use image::{GrayImage, ImageBuffer, Luma, Rgb, RgbImage};
+use std::cmp::Ordering;
+use std::fmt::Write;
+use std::str::FromStr;
+
+const VIRIDIS: [[u8; 3]; 9] = [
+	[68, 1, 84],
+	[71, 45, 123],
+	[59, 82, 139],
+	[44, 114, 142],
+	[33, 145, 140],
+	[40, 174, 128],
+	[94, 201, 98],
+	[173, 220, 48],
+	[253, 231, 37],
+];
+
+const CIVIDIS: [[u8; 3]; 5] = [
+	[0, 32, 77],
+	[65, 77, 107],
+	[124, 123, 120],
+	[188, 175, 111],
+	[255, 234, 70],
+];
+
+/// A mapping from magnitudes in the unit range to colors, low to
+/// high.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub enum Palette {
+	/// Black to white.
+	Gray,
+	/// Dark purple through teal to yellow; colorblind-safe, and still
+	/// ordered when printed in grayscale.
+	#[default]
+	Viridis,
+	/// Dark blue through gray to yellow; designed to read the same
+	/// with and without the commonest color vision deficiencies.
+	Cividis,
+	/// Evenly spaced color stops, low to high, interpolated between.
+	Stops(Vec<Rgb<u8>>),
+}
+
+impl Palette {
+	/// The color of a magnitude in the unit range.  Magnitudes outside
+	/// it are clamped, and a palette with no stops is black.
+	pub fn color(&self, t: f64) -> Rgb<u8> {
+		let stops: Vec<[u8; 3]> = match self {
+			Palette::Gray => vec![[0, 0, 0], [255, 255, 255]],
+			Palette::Viridis => VIRIDIS.to_vec(),
+			Palette::Cividis => CIVIDIS.to_vec(),
+			Palette::Stops(stops) => stops.iter().map(|stop| stop.0).collect(),
+		};
+		match stops.len() {
+			0 => Rgb([0, 0, 0]),
+			1 => Rgb(stops[0]),
+			n => {
+				let at = if t.is_nan() { 0.0 } else { t.clamp(0.0, 1.0) } * (n - 1) as f64;
+				let low = std::cmp::min(at.floor() as usize, n - 2);
+				let frac = at - low as f64;
+				let mix = |a: u8, b: u8| {
+					(f64::from(a) + (f64::from(b) - f64::from(a)) * frac).round() as u8
+				};
+				let (a, b) = (stops[low], stops[low + 1]);
+				Rgb([mix(a[0], b[0]), mix(a[1], b[1]), mix(a[2], b[2])])
+			}
+		}
+	}
+
+	/// The color for the `index`th of `count` things drawn in order,
+	/// such as seams, first lowest.
+	pub fn ordinal(&self, index: usize, count: usize) -> Rgb<u8> {
+		self.color(index as f64 / std::cmp::max(count, 2).saturating_sub(1) as f64)
+	}
+
+	/// The color as an SVG/CSS hex string.
+	pub fn hex(color: Rgb<u8>) -> String {
+		format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2])
+	}
+
+	/// Draw a map as a heatmap, its lowest value at the bottom of the
+	/// palette and its highest at the top.  Walls and other huge
+	/// values stretch the range, and push everything else to the
+	/// bottom; clear them from the map first to see the rest.  Returns
+	/// the picture and the range it spans, for its legend.
+	pub fn heatmap<E: EnergyScalar>(&self, map: &TwoDimensionalMap<E>) -> (RgbImage, (f64, f64)) {
+		let values: Vec<f64> = map.energy.iter().map(|e| e.to_f64()).collect();
+		let low = values.iter().cloned().fold(f64::INFINITY, f64::min);
+		let high = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+		let span = high - low;
+		let image = ImageBuffer::from_fn(map.width, map.height, |x, y| {
+			let value = values[(y * map.width + x) as usize];
+			self.color(if span > 0.0 {
+				(value - low) / span
+			} else {
+				0.0
+			})
+		});
+		(image, (low, high))
+	}
+
+	/// A legend for a picture drawn in the palette: a bar `width` by
+	/// `height` pixels running from the bottom of the palette on the
+	/// left to the top on the right.
+	pub fn legend(&self, width: u32, height: u32) -> RgbImage {
+		ImageBuffer::from_fn(width, height, |x, _| {
+			self.color(f64::from(x) / f64::from(std::cmp::max(width, 2) - 1))
+		})
+	}
+
+	/// As `legend`, as an SVG fragment to place in a drawing at
+	/// `(x, y)`: the bar, with `ticks` evenly spaced labels below it
+	/// running from `low` to `high`, and a caption above.
+	pub fn legend_svg(
+		&self,
+		(x, y): (u32, u32),
+		(width, height): (u32, u32),
+		(low, high): (f64, f64),
+		ticks: u32,
+		caption: &str,
+	) -> String {
+		let steps = 16;
+		let mut svg = format!(
+			"  <g class=\"legend\" transform=\"translate({},{})\" font-family=\"sans-serif\" font-size=\"10\">\n",
+			x, y
+		);
+		writeln!(svg, "    <text x=\"0\" y=\"-3\">{}</text>", escape(caption)).unwrap();
+		for step in 0..steps {
+			let (left, right) = (width * step / steps, width * (step + 1) / steps);
+			writeln!(
+				svg,
+				"    <rect x=\"{}\" y=\"0\" width=\"{}\" height=\"{}\" fill=\"{}\"/>",
+				left,
+				right - left,
+				height,
+				Palette::hex(self.color((f64::from(step) + 0.5) / f64::from(steps)))
+			)
+			.unwrap();
+		}
+		let ticks = std::cmp::max(ticks, 2);
+		for tick in 0..ticks {
+			let t = f64::from(tick) / f64::from(ticks - 1);
+			writeln!(
+				svg,
+				"    <text x=\"{}\" y=\"{}\" text-anchor=\"middle\">{}</text>",
+				(t * f64::from(width)).round(),
+				height + 11,
+				magnitude(low + t * (high - low))
+			)
+			.unwrap();
+		}
+		svg.push_str("  </g>\n");
+		svg
+	}
+}
+
+/// How the energies of a map are scaled to levels of gray.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MapScaling {
+	/// Linearly, from black at the lowest energy to white at the
+	/// highest.
+	Linear,
+	/// By the logarithm of one plus each energy's height above the
+	/// lowest, so the weak energies keep their differences and the
+	/// strong ones are compressed.
+	Log,
+	/// Linearly between the energies at the given low and high
+	/// percentiles, with everything outside them clipped to black or
+	/// white, so a few walls or strong edges don't wash out the rest.
+	PercentileClip(f64, f64),
+}
+
+/// Draw an energy map as a grayscale image, scaled as asked.  A map
+/// whose energies are all the same is black.
+pub fn energy_to_image<E: EnergyScalar>(
+	map: &TwoDimensionalMap<E>,
+	scaling: MapScaling,
+) -> GrayImage {
+	let values: Vec<f64> = map.energy.iter().map(|e| e.to_f64()).collect();
+	let (low, high) = match scaling {
+		MapScaling::PercentileClip(bottom, top) => {
+			let mut sorted = values.clone();
+			sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+			let at = |percent: f64| {
+				let last = sorted.len().saturating_sub(1) as f64;
+				let rank = (percent.clamp(0.0, 100.0) / 100.0 * last).round() as usize;
+				sorted.get(rank).cloned().unwrap_or(0.0)
+			};
+			(at(bottom), at(top))
+		}
+		_ => (
+			values.iter().cloned().fold(f64::INFINITY, f64::min),
+			values.iter().cloned().fold(f64::NEG_INFINITY, f64::max),
+		),
+	};
+	let level = |value: f64| match scaling {
+		_ if high <= low => 0.0,
+		MapScaling::Log => (value - low).max(0.0).ln_1p() / (high - low).ln_1p(),
+		_ => (value - low) / (high - low),
+	};
+	ImageBuffer::from_fn(map.width, map.height, |x, y| {
+		let t = level(values[(y * map.width + x) as usize]);
+		Luma([(t.clamp(0.0, 1.0) * 255.0).round() as u8])
+	})
+}
+
+impl FromStr for Palette {
+	type Err = String;
+
+	/// A built-in palette by name: `gray`, `viridis`, or `cividis`.
+	fn from_str(name: &str) -> Result<Self, Self::Err> {
+		match name.to_lowercase().as_str() {
+			"gray" | "grey" => Ok(Palette::Gray),
+			"viridis" => Ok(Palette::Viridis),
+			"cividis" => Ok(Palette::Cividis),
+			_ => Err(format!(
+				"'{}' isn't a palette; try gray, viridis, or cividis",
+				name
+			)),
+		}
+	}
+}
+
+// A magnitude short enough for a legend label.
+fn magnitude(value: f64) -> String {
+	if value != 0.0 && (value.abs() >= 1.0e5 || value.abs() < 1.0e-2) {
+		format!("{:.1e}", value)
+	} else if value.fract() == 0.0 {
+		format!("{}", value)
+	} else {
+		format!("{:.2}", value)
+	}
+}
+
+// Text safe to put in an SVG element.
+fn escape(text: &str) -> String {
+	text.replace('&', "&amp;")
+		.replace('<', "&lt;")
+		.replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn palettes_run_low_to_high() {
+		assert_eq!(Palette::Gray.color(0.0), Rgb([0, 0, 0]));
+		assert_eq!(Palette::Gray.color(0.5), Rgb([128, 128, 128]));
+		assert_eq!(Palette::Viridis.color(-1.0), Rgb([68, 1, 84]));
+		assert_eq!(Palette::Viridis.color(2.0), Rgb([253, 231, 37]));
+		assert_eq!(Palette::Cividis.color(0.5), Rgb([124, 123, 120]));
+		let custom = Palette::Stops(vec![Rgb([0, 0, 255]), Rgb([255, 0, 0])]);
+		assert_eq!(custom.ordinal(1, 3), Rgb([128, 0, 128]));
+		assert_eq!(Palette::hex(custom.color(1.0)), "#ff0000");
+		assert_eq!("Cividis".parse(), Ok(Palette::Cividis));
+		assert!("rainbow".parse::<Palette>().is_err());
+
+		// Brightness rises steadily through the built-in palettes, so
+		// they still read in order without color.
+		for palette in [Palette::Viridis, Palette::Cividis].iter() {
+			let luma = |t| {
+				let Rgb([r, g, b]) = palette.color(t);
+				299 * u32::from(r) + 587 * u32::from(g) + 114 * u32::from(b)
+			};
+			assert!((0..10).all(|i| luma(f64::from(i) / 10.0) < luma(f64::from(i + 1) / 10.0)));
+		}
+	}
+
+	#[test]
+	fn energy_images_scale_as_asked() {
+		let map = TwoDimensionalMap::from_vec(5, 1, vec![0u32, 10, 20, 30, 1000]).unwrap();
+		let levels = |scaling| energy_to_image(&map, scaling).into_raw();
+		assert_eq!(levels(MapScaling::Linear), [0, 3, 5, 8, 255]);
+		assert_eq!(levels(MapScaling::Log), [0, 89, 112, 127, 255]);
+		assert_eq!(
+			levels(MapScaling::PercentileClip(25.0, 75.0)),
+			[0, 0, 128, 255, 255]
+		);
+		let flat = TwoDimensionalMap::from_vec(2, 1, vec![7u32, 7]).unwrap();
+		assert_eq!(energy_to_image(&flat, MapScaling::Log).into_raw(), [0, 0]);
+	}
+
+	#[test]
+	fn heatmaps_span_the_map_and_say_so() {
+		let map = TwoDimensionalMap {
+			width: 3,
+			height: 1,
+			energy: vec![10u32, 20, 30],
+		};
+		let (image, range) = Palette::Gray.heatmap(&map);
+		assert_eq!(range, (10.0, 30.0));
+		assert_eq!(image[(0, 0)], Rgb([0, 0, 0]));
+		assert_eq!(image[(2, 0)], Rgb([255, 255, 255]));
+		assert_eq!(Palette::Gray.legend(5, 2)[(4, 1)], Rgb([255, 255, 255]));
+
+		let legend = Palette::Viridis.legend_svg((4, 20), (100, 8), range, 3, "energy <raw>");
+		assert!(legend.contains(">energy &lt;raw&gt;</text>"));
+		assert!(legend.contains(">10</text>") && legend.contains(">20</text>"));
+		assert_eq!(legend.matches("<rect").count(), 16);
+	}
+}
@@ -10,8 +10,10 @@
 //! from the classic d(R^2) + d(G^2) + d(B^2) to a
 //! simple convert-to-grayscale and d(L^2).
 
-use image::{Pixel, Primitive};
-use num_traits::NumCast;
+use crate::cq;
+use crate::twodmap::TwoDimensionalMap;
+use image::{GenericImageView, Pixel, Primitive};
+use num_traits::{Float, NumCast};
 
 /// The type signature of our energy pair function.
 pub type PixelPair<P> = dyn Fn(&P, &P) -> u32;
@@ -25,17 +27,167 @@ pub fn energy_of_pair_luma<P, S>(p1: &P, p2: &P) -> u32
 where
 	P: Pixel<Subpixel = S> + 'static,
 	S: Primitive + 'static,
+{
+	energy_of_lumas(luma_of(p1), luma_of(p2))
+}
+
+/// (Luma, Luma) -> Energy
+///
+/// The energy between two luma values, as `energy_of_pair_luma`
+/// calculates it.
+#[inline]
+pub fn energy_of_lumas(l1: u32, l2: u32) -> u32 {
+	let css = cq!(l1 > l2, l1 - l2, l2 - l1);
+	css.saturating_mul(css)
+}
+
+// The span of the unit range in the lumas of a floating point image:
+// the same steps as a 16-bit one.
+const FLOAT_LUMA_SCALE: f64 = 65535.0;
+
+#[inline]
+pub(crate) fn luma_of<P, S>(p: &P) -> u32
+where
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	let luma = p.to_luma()[0];
+	if !is_float::<S>() {
+		return NumCast::from(luma).unwrap_or(0);
+	}
+	// HDR values above 1.0 keep their steps, up to the top of a u32;
+	// negative and NaN values are black.
+	let luma = luma.to_f64().unwrap_or(0.0) * FLOAT_LUMA_SCALE;
+	cq!(
+		luma.is_nan(),
+		0,
+		luma.round().clamp(0.0, u32::MAX as f64) as u32
+	)
+}
+
+/// Whether the subpixel type is floating point.  A float image's
+/// channels run from 0.0 to 1.0, and further for HDR, rather than up
+/// to the largest value of the type.
+pub fn is_float<S: Primitive>() -> bool {
+	<S as NumCast>::from(0.5).and_then(|half| half.to_f64()) == Some(0.5)
+}
+
+/// The value of a full-intensity channel of the given subpixel type:
+/// the largest value of an integer type, and 1.0 for a float.
+pub fn channel_max<S: Primitive>() -> f64 {
+	if is_float::<S>() {
+		1.0
+	} else {
+		S::max_value().to_f64().unwrap_or(1.0)
+	}
+}
+
+/// Convert every pixel of an image to luma once, up front.  The
+/// energy calculators look at each pixel from every neighbor, and
+/// converting it again each time is most of their work.  The plane
+/// also serves as the cache of rows the forward-energy DP looks back
+/// into: each pixel is fetched from the view exactly once, in row
+/// order, so a view with an expensive `get_pixel`, decoded on demand
+/// or flipped, costs no more than one pass over it.
+pub fn luma_plane<I, P, S>(image: &I) -> TwoDimensionalMap<u32>
+where
+	I: GenericImageView<Pixel = P>,
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	let (width, height) = image.dimensions();
+	let mut plane = TwoDimensionalMap::new(width, height);
+	for (x, y, pixel) in image.pixels() {
+		plane[(x, y)] = luma_of(&pixel);
+	}
+	plane
+}
+
+/// The luma value of a full-intensity pixel of the given subpixel
+/// type, for scaling the values of a luma plane to the unit range.
+/// A float image's lumas are kept at 16-bit precision, and the HDR
+/// values of one may go above this.
+pub fn luma_max<S: Primitive>() -> u32 {
+	cq!(
+		is_float::<S>(),
+		FLOAT_LUMA_SCALE as u32,
+		NumCast::from(S::max_value()).unwrap_or(u32::MAX)
+	)
+}
+
+/// (Pixel, Pixel) -> Energy
+///
+/// As `energy_of_pair_luma`, but with the luma scaled to the unit
+/// range before the difference is taken, so that the energy lies in
+/// (0.0..1.0) whatever the bit depth of the image.  The luma is left
+/// gamma-encoded, which is already close to perceptually uniform; a
+/// step in a dark region counts as much as the same visible step in a
+/// light one.
+#[inline]
+pub fn energy_of_pair_luma_f32<P, S>(p1: &P, p2: &P) -> f32
+where
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	energy_of_pair_unit_luma(p1, p2)
+}
+
+/// (Pixel, Pixel) -> Energy
+///
+/// As `energy_of_pair_luma_f32`, in double precision.
+#[inline]
+pub fn energy_of_pair_luma_f64<P, S>(p1: &P, p2: &P) -> f64
+where
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	energy_of_pair_unit_luma(p1, p2)
+}
+
+#[inline]
+fn energy_of_pair_unit_luma<P, S, F>(p1: &P, p2: &P) -> F
+where
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+	F: Float,
 {
 	#[inline]
-	fn lumachannel<S, P>(p: &P) -> u32
+	fn lumachannel<S, P, F>(p: &P) -> F
 	where
 		P: Pixel<Subpixel = S> + 'static,
 		S: Primitive + 'static,
+		F: Float,
 	{
-		let c = p.to_luma().channels().to_owned();
-		NumCast::from(c[0]).unwrap()
+		let (luma, scale): (F, F) = (
+			NumCast::from(p.to_luma()[0]).unwrap_or_else(F::zero),
+			NumCast::from(channel_max::<S>()).unwrap(),
+		);
+		luma / scale
 	}
 
-	let css = lumachannel(p1) - lumachannel(p2);
+	let css = lumachannel::<S, P, F>(p1) - lumachannel::<S, P, F>(p2);
 	css * css
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use image::Luma;
+
+	#[test]
+	fn luma_energy_is_the_same_in_both_orderings() {
+		let (dark, light) = (Luma([10u8]), Luma([250u8]));
+		assert_eq!(energy_of_pair_luma(&dark, &light), 240 * 240);
+		assert_eq!(energy_of_pair_luma(&light, &dark), 240 * 240);
+
+		// The widest difference there is still fits, either way round.
+		let (black, white) = (Luma([0u16]), Luma([u16::MAX]));
+		assert_eq!(energy_of_pair_luma(&black, &white), 65535 * 65535);
+		assert_eq!(energy_of_pair_luma(&white, &black), 65535 * 65535);
+
+		assert_eq!(
+			energy_of_pair_luma_f32(&dark, &light),
+			energy_of_pair_luma_f32(&light, &dark)
+		);
+	}
+}
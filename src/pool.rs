@@ -0,0 +1,177 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! The carve's threads
+//!
+//! With the `threaded` feature on, the DP shares its rows out among
+//! rayon's threads, and rayon's global pool has a thread for every
+//! core.  A carve limited to fewer threads gets a pool of that many,
+//! which is made the pool for the thread the carve runs on until the
+//! carve is done, and the DP's parallel work is installed in it.  The
+//! images being carved needn't be sent anywhere: only the DP's lumas
+//! and costs go to the pool's threads.
+//!
+//! Like the global pool, each pool is started the first time a carve
+//! asks for its number of threads, and kept for every carve after.
+//!
+//! A carve already running on a pool's thread, as each image of a
+//! batch does, stays in that pool.
+//!
+//! Rayon hands a worker's panic back to the thread that installed the
+//! work.  The carve catches it there and returns it as a
+//! `CarveError::Worker`, so a caller embedding the carver gets an
+//! error instead of an unwinding thread.
+
+use crate::error::CarveError;
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+
+#[cfg(feature = "threaded")]
+use rayon::ThreadPool;
+#[cfg(feature = "threaded")]
+use std::cell::RefCell;
+#[cfg(feature = "threaded")]
+use std::sync::{Arc, Mutex};
+
+// The pools started so far, by their number of threads.
+#[cfg(feature = "threaded")]
+static POOLS: Mutex<Vec<(usize, Arc<ThreadPool>)>> = Mutex::new(Vec::new());
+
+#[cfg(feature = "threaded")]
+thread_local! {
+	static POOL: RefCell<Option<Arc<ThreadPool>>> = const { RefCell::new(None) };
+}
+
+// The pool of the given number of threads, started if it hasn't been.
+#[cfg(feature = "threaded")]
+fn pool_of(threads: usize) -> Option<Arc<ThreadPool>> {
+	let mut pools = POOLS
+		.lock()
+		.unwrap_or_else(|poisoned| poisoned.into_inner());
+	if let Some((_, pool)) = pools.iter().find(|(size, _)| *size == threads) {
+		return Some(pool.clone());
+	}
+	let pool = rayon::ThreadPoolBuilder::new()
+		.num_threads(threads)
+		.build()
+		.ok()?;
+	let pool = Arc::new(pool);
+	pools.push((threads, pool.clone()));
+	Some(pool)
+}
+
+// Run the carve with its parallel work on a pool of at most `threads`
+// threads, or on the pool it's already in if not given.  A panic in
+// the carve or any of its workers comes back as an error.
+pub(crate) fn with_threads<R>(
+	threads: Option<usize>,
+	carve: impl FnOnce() -> Result<R, CarveError>,
+) -> Result<R, CarveError> {
+	let carve = AssertUnwindSafe(carve);
+	#[cfg(feature = "threaded")]
+	{
+		let pooled = POOL.with(|pool| pool.borrow().is_some());
+		if !pooled && rayon::current_thread_index().is_none() {
+			if let Some(pool) = threads.and_then(pool_of) {
+				POOL.with(|current| current.replace(Some(pool)));
+				let _done = Uninstall;
+				return panic::catch_unwind(carve).unwrap_or_else(|panic| Err(worker(panic)));
+			}
+		}
+	}
+	#[cfg(not(feature = "threaded"))]
+	let _ = threads;
+	panic::catch_unwind(carve).unwrap_or_else(|panic| Err(worker(panic)))
+}
+
+// The error for a panic, with its message if it had one.
+fn worker(panic: Box<dyn Any + Send>) -> CarveError {
+	let message = match panic.downcast::<String>() {
+		Ok(message) => *message,
+		Err(panic) => panic
+			.downcast_ref::<&str>()
+			.map_or("no message", |message| message)
+			.to_string(),
+	};
+	CarveError::Worker(message)
+}
+
+// Put the thread back on the global pool when the carve is done, or
+// panics.
+#[cfg(feature = "threaded")]
+struct Uninstall;
+
+#[cfg(feature = "threaded")]
+impl Drop for Uninstall {
+	fn drop(&mut self) {
+		POOL.with(|pool| pool.replace(None));
+	}
+}
+
+// Run parallel work on the carve's pool, if it has one, and otherwise
+// on the pool the thread is in.
+pub(crate) fn install<R: Send>(work: impl FnOnce() -> R + Send) -> R {
+	#[cfg(feature = "threaded")]
+	{
+		if let Some(pool) = POOL.with(|pool| pool.borrow().clone()) {
+			return pool.install(work);
+		}
+	}
+	work()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[cfg(feature = "threaded")]
+	#[test]
+	fn the_carve_works_in_its_own_pool() {
+		let global = rayon::current_num_threads();
+		assert_eq!(
+			with_threads(Some(1), || Ok(install(rayon::current_num_threads))),
+			Ok(1)
+		);
+		assert_eq!(
+			with_threads(Some(3), || Ok(install(rayon::current_num_threads))),
+			Ok(3)
+		);
+		assert_eq!(
+			with_threads(None, || Ok(install(rayon::current_num_threads))),
+			Ok(global)
+		);
+		assert_eq!(install(rayon::current_num_threads), global);
+	}
+
+	#[test]
+	fn the_carve_runs_whatever_the_threads() {
+		assert_eq!(with_threads(Some(2), || Ok(install(|| 6 * 7))), Ok(42));
+	}
+
+	#[test]
+	fn a_panicking_worker_is_an_error() {
+		let carve = || {
+			install(|| {
+				#[cfg(feature = "threaded")]
+				{
+					use rayon::prelude::*;
+					(0..64).into_par_iter().for_each(|row| {
+						if row == 47 {
+							panic!("row {} is out of bounds", row);
+						}
+					});
+				}
+				#[cfg(not(feature = "threaded"))]
+				panic!("row {} is out of bounds", 47);
+			});
+			Ok(())
+		};
+		assert_eq!(
+			with_threads(Some(2), carve),
+			Err(CarveError::Worker("row 47 is out of bounds".to_string()))
+		);
+		// The thread is back on its own pool after the panic.
+		assert_eq!(with_threads(None, || Ok(install(|| 6 * 7))), Ok(42));
+	}
+}
@@ -0,0 +1,112 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Coarse-to-fine seam finding
+//!
+//! On a large image most of the time goes into the DP, and most of
+//! the DP goes into columns the seam never comes near.  Shrinking the
+//! image by some factor and finding the seam there is cheap; the seam
+//! found is close to where the full-size seam would run.  The finders
+//! then look for the full-size seam only within a corridor around the
+//! coarse one, scaled back up.
+//!
+//! The seam found is the best one in the corridor, which isn't
+//! necessarily the best one in the image: a narrow valley the
+//! shrinking averaged away can't be found again.  A wider corridor
+//! costs more and misses less.
+
+use crate::banded::bands_around;
+use crate::cq;
+use crate::energyopts::EnergyOptions;
+use image::{imageops, FilterType, GenericImageView, ImageBuffer, Pixel, Primitive};
+
+// Below this size, in either direction, the shrunken image says too
+// little about where the seam should go to be worth finding.
+const SMALLEST: u32 = 3;
+
+// Finds the seam through the shrunken image, with the options given.
+type CoarseFinder<'a, P, S> = &'a dyn Fn(&ImageBuffer<P, Vec<S>>, EnergyOptions) -> Vec<u32>;
+
+// Map a vertical seam through a plane `coarse_width` wide onto a
+// plane `width` by `height`, and return the bands `margin` columns
+// either side of it.  Each full-size row takes its column from the
+// coarse row it falls in, scaled from the middle of the coarse pixel.
+fn corridor(
+	coarse: &[u32],
+	coarse_width: u32,
+	width: u32,
+	height: u32,
+	margin: u32,
+) -> Vec<(u32, u32)> {
+	let (rows, scale) = (
+		coarse.len() as u64,
+		f64::from(width) / f64::from(coarse_width),
+	);
+	let centers: Vec<u32> = (0..u64::from(height))
+		.map(|y| {
+			let row = std::cmp::min(y * rows / u64::from(height), rows - 1) as usize;
+			((f64::from(coarse[row]) + 0.5) * scale) as u32
+		})
+		.collect();
+	bands_around(&centers, width, margin)
+}
+
+/// The corridor a full-size seam through `image` should be looked
+/// for in, found by shrinking the image by the options' pyramid
+/// factor and asking `find` for the seam through the shrunken copy.
+/// `find` is given the shrunken image and the options to find its
+/// seam with, and should find it the same way, vertically or
+/// horizontally, as the full-size seam is wanted.  The corridor is
+/// given as bands of the luma plane the full-size seam is found in,
+/// transposed for a horizontal seam.  Returns `None` when the options
+/// don't ask for a pyramid, or the image is too small to shrink.
+pub(crate) fn coarse_corridor<I, P, S>(
+	image: &I,
+	options: &EnergyOptions,
+	horizontal: bool,
+	find: CoarseFinder<P, S>,
+) -> Option<Vec<(u32, u32)>>
+where
+	I: GenericImageView<Pixel = P>,
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	let (factor, margin) = options.pyramid?;
+	let (width, height) = image.dimensions();
+	let (coarse_width, coarse_height) = (width / factor, height / factor);
+	if coarse_width < SMALLEST || coarse_height < SMALLEST {
+		return None;
+	}
+	let shrunk = imageops::resize(image, coarse_width, coarse_height, FilterType::Triangle);
+	let seam = find(&shrunk, options.coarse());
+	let (across, along, coarse_across) = cq!(
+		horizontal,
+		(height, width, coarse_height),
+		(width, height, coarse_width)
+	);
+	Some(corridor(&seam, coarse_across, across, along, margin))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn the_corridor_follows_the_coarse_seam_at_full_size() {
+		let bands = corridor(&[0, 1, 2, 2], 4, 16, 16, 2);
+		assert_eq!(bands.len(), 16);
+		// The first coarse pixel covers columns 0 through 3, centered on 2.
+		assert_eq!(bands[0], (0, 4));
+		// Centers step at most a column a row, however far the scaled
+		// coarse seam jumps.
+		let centers: Vec<u32> = bands
+			.iter()
+			.map(|(left, right)| (left + right) / 2)
+			.collect();
+		assert!(centers
+			.windows(2)
+			.all(|w| w[1] <= w[0] + 1 && w[0] <= w[1] + 1));
+		assert_eq!(bands[15], (8, 12));
+	}
+}
@@ -0,0 +1,416 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Carve reports
+//!
+//! What a carve did, beyond the image it produced.  A pipeline
+//! running unattended can't look at every result, but it can look at
+//! the report and notice, say, that half the seams went through the
+//! sky and most of the sky is gone.
+
+use crate::backend::Backend;
+use crate::palette::Palette;
+use crate::pixelpairs::channel_max;
+use crate::seamfinder::Orientation;
+use image::{GenericImageView, GrayImage, ImageBuffer, Luma, Pixel, Primitive, Rgb, RgbImage};
+use num_traits::NumCast;
+use std::time::Duration;
+
+/// A coarse color histogram, with each RGB channel divided into
+/// `BINS_PER_CHANNEL` equal bins whatever the image's bit depth.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColorHistogram {
+	counts: Vec<u64>,
+}
+
+impl Default for ColorHistogram {
+	fn default() -> Self {
+		ColorHistogram::new()
+	}
+}
+
+impl ColorHistogram {
+	/// The number of bins each channel is divided into.
+	pub const BINS_PER_CHANNEL: usize = 8;
+
+	/// An empty histogram.
+	pub fn new() -> Self {
+		let bins = Self::BINS_PER_CHANNEL;
+		ColorHistogram {
+			counts: vec![0; bins * bins * bins],
+		}
+	}
+
+	/// The histogram of every pixel in an image.
+	pub fn of_image<I, P, S>(image: &I) -> Self
+	where
+		I: GenericImageView<Pixel = P>,
+		P: Pixel<Subpixel = S> + 'static,
+		S: Primitive + 'static,
+	{
+		let mut histogram = ColorHistogram::new();
+		for (_, _, pixel) in image.pixels() {
+			histogram.add(&pixel);
+		}
+		histogram
+	}
+
+	/// The (red, green, blue) bin a pixel falls in.
+	pub fn bin_of<P, S>(pixel: &P) -> (usize, usize, usize)
+	where
+		P: Pixel<Subpixel = S> + 'static,
+		S: Primitive + 'static,
+	{
+		let max = channel_max::<S>();
+		let bins = Self::BINS_PER_CHANNEL;
+		let rgb = pixel.to_rgb();
+		let bin = |c: S| {
+			let c: f64 = NumCast::from(c).unwrap();
+			std::cmp::min((c / max * bins as f64) as usize, bins - 1)
+		};
+		(bin(rgb[0]), bin(rgb[1]), bin(rgb[2]))
+	}
+
+	/// Count one pixel.
+	pub fn add<P, S>(&mut self, pixel: &P)
+	where
+		P: Pixel<Subpixel = S> + 'static,
+		S: Primitive + 'static,
+	{
+		let index = Self::index(Self::bin_of(pixel));
+		self.counts[index] += 1;
+	}
+
+	/// The number of pixels counted in a bin.
+	pub fn count(&self, bin: (usize, usize, usize)) -> u64 {
+		self.counts[Self::index(bin)]
+	}
+
+	/// The number of pixels counted in all.
+	pub fn total(&self) -> u64 {
+		self.counts.iter().sum()
+	}
+
+	/// The bin holding the most pixels, or None if nothing has been
+	/// counted.  Ties go to the darkest.
+	pub fn dominant(&self) -> Option<(usize, usize, usize)> {
+		let bins = Self::BINS_PER_CHANNEL;
+		let (index, count) =
+			self.counts.iter().enumerate().fold(
+				(0, 0),
+				|best, (i, c)| if *c > best.1 { (i, *c) } else { best },
+			);
+		if count == 0 {
+			None
+		} else {
+			Some((index / (bins * bins), index / bins % bins, index % bins))
+		}
+	}
+
+	/// The fraction of the pixels in each bin of `original` that this
+	/// histogram accounts for, by bin.  Run on the removed pixels
+	/// against the original image, a bin near 1.0 is a color the
+	/// carve has all but erased.
+	pub fn fraction_of(&self, original: &ColorHistogram) -> Vec<((usize, usize, usize), f64)> {
+		let bins = Self::BINS_PER_CHANNEL;
+		self.counts
+			.iter()
+			.zip(original.counts.iter())
+			.enumerate()
+			.filter(|(_, (_, total))| **total > 0)
+			.map(|(i, (count, total))| {
+				(
+					(i / (bins * bins), i / bins % bins, i % bins),
+					*count as f64 / *total as f64,
+				)
+			})
+			.collect()
+	}
+
+	fn index((r, g, b): (usize, usize, usize)) -> usize {
+		let bins = Self::BINS_PER_CHANNEL;
+		(r * bins + g) * bins + b
+	}
+}
+
+/// The seams a carve removed, in the order it removed them.  Each
+/// seam is in the coordinates of the image as it was when the seam
+/// was removed, so together they describe how any point of the
+/// original moved.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SeamSequence {
+	seams: Vec<(Orientation, Vec<u32>)>,
+}
+
+impl SeamSequence {
+	/// An empty sequence.
+	pub fn new() -> Self {
+		SeamSequence::default()
+	}
+
+	/// Add a seam to the end of the sequence.
+	pub fn push(&mut self, orientation: Orientation, seam: Vec<u32>) {
+		self.seams.push((orientation, seam));
+	}
+
+	/// The number of seams in the sequence.
+	pub fn len(&self) -> usize {
+		self.seams.len()
+	}
+
+	/// Whether the sequence is empty.
+	pub fn is_empty(&self) -> bool {
+		self.seams.is_empty()
+	}
+
+	/// The seams, in the order they were removed.
+	pub fn iter(&self) -> impl Iterator<Item = (Orientation, &[u32])> {
+		self.seams.iter().map(|(o, seam)| (*o, &seam[..]))
+	}
+}
+
+/// Where the time in a carve went, summed over every seam.  The
+/// forward-energy finder works out its energies as it runs its DP, so
+/// `energy` is the time spent getting the image ready for it (any
+/// smoothing, the luma plane, and the fitted adjustments) and `dp` the
+/// DP proper.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct StageTimings {
+	/// Copying the source image into the carver's working buffer.
+	pub wrap: Duration,
+
+	/// Preparing the energy for each seam search.
+	pub energy: Duration,
+
+	/// Running the seam DP.
+	pub dp: Duration,
+
+	/// Tracing each seam back through the DP.
+	pub traceback: Duration,
+
+	/// Splicing each seam out of (or into) the image.
+	pub splice: Duration,
+
+	/// Encoding the result.  The library never encodes anything, so
+	/// this is left for the caller to fill in.
+	pub encode: Duration,
+}
+
+impl StageTimings {
+	/// Add another set of timings to these.
+	pub fn add(&mut self, other: &StageTimings) {
+		self.wrap += other.wrap;
+		self.energy += other.energy;
+		self.dp += other.dp;
+		self.traceback += other.traceback;
+		self.splice += other.splice;
+		self.encode += other.encode;
+	}
+
+	/// The time spent in all the stages together.
+	pub fn total(&self) -> Duration {
+		self.wrap + self.energy + self.dp + self.traceback + self.splice + self.encode
+	}
+
+	// The time spent in each stage since the earlier timings were
+	// taken.
+	pub(crate) fn since(&self, earlier: &StageTimings) -> StageTimings {
+		StageTimings {
+			wrap: self.wrap - earlier.wrap,
+			energy: self.energy - earlier.energy,
+			dp: self.dp - earlier.dp,
+			traceback: self.traceback - earlier.traceback,
+			splice: self.splice - earlier.splice,
+			encode: self.encode - earlier.encode,
+		}
+	}
+}
+
+/// An account of a finished carve.
+#[derive(Debug, Clone, Default)]
+pub struct CarveReport {
+	/// The number of vertical seams removed, narrowing the image.
+	pub width_seams: u32,
+
+	/// The number of horizontal seams removed, shortening it.
+	pub height_seams: u32,
+
+	/// The number of vertical seams inserted, widening the image.
+	pub width_seams_inserted: u32,
+
+	/// The number of horizontal seams inserted, heightening it.
+	pub height_seams_inserted: u32,
+
+	/// Every seam removed, in order.  Inserted seams aren't included.
+	pub seams: SeamSequence,
+
+	/// The colors of the original image, if color tracking was
+	/// requested in the carve options.
+	pub original_colors: Option<ColorHistogram>,
+
+	/// The colors of every pixel the carve removed, if color tracking
+	/// was requested.
+	pub removed_colors: Option<ColorHistogram>,
+
+	/// How long each stage of the carve took.
+	pub timings: StageTimings,
+
+	/// How long each stage took for each seam removed, in the order
+	/// they were removed, if asked for in the carve options.  A patched
+	/// carve sets up its tables on the first seam of a run, which takes
+	/// longer than the rest, and materializes the carved image after
+	/// the last, which isn't counted against any seam.
+	pub seam_timings: Option<Vec<StageTimings>>,
+
+	/// Anything the carve did other than what was asked, and why.
+	pub warnings: Vec<String>,
+
+	/// For every pixel of the carved image, the number of the inserted
+	/// seam it belongs to, counting from 1 in the order the seams were
+	/// found, or 0 if it isn't an inserted pixel.  Only kept if asked
+	/// for in the carve options, and only if the carve enlarged.
+	pub insertion_order: Option<ImageBuffer<Luma<u32>, Vec<u32>>>,
+
+	/// Where the seams were found: on a compute device only if the
+	/// carve options sent the image to one, and it never failed.
+	pub backend: Backend,
+}
+
+impl CarveReport {
+	/// The insertion order as a grayscale picture: the pixels of the
+	/// first seam inserted white, those of later seams darker, down to
+	/// a dark gray for the last, and everything else black.
+	pub fn insertion_order_image(&self) -> Option<GrayImage> {
+		let order = self.insertion_order.as_ref()?;
+		let last = order.pixels().map(|p| p[0]).max().unwrap_or(0);
+		let shade = |n: u32| match n {
+			0 => 0,
+			n => (255 - (n - 1) as u64 * 191 / (std::cmp::max(last, 2) - 1) as u64) as u8,
+		};
+		Some(ImageBuffer::from_fn(
+			order.width(),
+			order.height(),
+			|x, y| Luma([shade(order[(x, y)][0])]),
+		))
+	}
+
+	/// As `insertion_order_image`, with the seams shaded through the
+	/// palette, the first seam inserted lowest, and the pixels of the
+	/// original image in `background`.
+	pub fn insertion_order_image_with(
+		&self,
+		palette: &Palette,
+		background: Rgb<u8>,
+	) -> Option<RgbImage> {
+		let order = self.insertion_order.as_ref()?;
+		let last = order.pixels().map(|p| p[0]).max().unwrap_or(0) as usize;
+		Some(ImageBuffer::from_fn(
+			order.width(),
+			order.height(),
+			|x, y| match order[(x, y)][0] {
+				0 => background,
+				n => palette.ordinal(n as usize - 1, last),
+			},
+		))
+	}
+}
+
+/// Something that happened during a carve, as it happened.  A
+/// frontend that has the original image can follow the carve by
+/// applying each event to its own copy: removing a seam's pixels, or
+/// duplicating those at the inserted positions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub enum CarveEvent {
+	/// The carve has begun on an image of the given size.
+	Started {
+		/// The width of the original image.
+		width: u32,
+		/// The height of the original image.
+		height: u32,
+		/// The width being carved to.
+		target_width: u32,
+		/// The height being carved to.
+		target_height: u32,
+	},
+
+	/// A seam was removed from the image as it then was.
+	Removed {
+		/// Which way the seam ran.
+		orientation: Orientation,
+		/// One coordinate per row (or column) of the seam.
+		path: Vec<u32>,
+	},
+
+	/// A round of seams was inserted.  For every row (or column) of
+	/// the image as it was before the round, the sorted positions of
+	/// the pixels that were duplicated, each once for every seam that
+	/// passed through it.
+	Inserted {
+		/// Which way the inserted seams ran.
+		orientation: Orientation,
+		/// The positions duplicated in each row (or column).
+		positions: Vec<Vec<u32>>,
+	},
+
+	/// The carve is done, and the image is the given size.
+	Finished {
+		/// The width of the carved image.
+		width: u32,
+		/// The height of the carved image.
+		height: u32,
+	},
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::cq;
+	use crate::seamcarver::{seamcarve_with_report, CarveOptions};
+	use image::{ImageBuffer, Luma, Rgb};
+
+	#[test]
+	fn histograms_bin_by_channel() {
+		let mut histogram = ColorHistogram::new();
+		assert_eq!(histogram.dominant(), None);
+		histogram.add(&Rgb([255u8, 0, 0]));
+		histogram.add(&Rgb([250u8, 10, 0]));
+		histogram.add(&Luma([128u8]));
+		assert_eq!(histogram.count((7, 0, 0)), 2);
+		assert_eq!(histogram.count((4, 4, 4)), 1);
+		assert_eq!(histogram.dominant(), Some((7, 0, 0)));
+		assert_eq!(ColorHistogram::bin_of(&Luma([u16::MAX])), (7, 7, 7));
+
+		let mut original = histogram.clone();
+		original.add(&Luma([128u8]));
+		let fractions = histogram.fraction_of(&original);
+		assert_eq!(fractions, [((4, 4, 4), 0.5), ((7, 0, 0), 1.0)]);
+	}
+
+	#[test]
+	fn carves_report_the_colors_they_remove() {
+		// Flat black on the left, busy red on the right: the seams all
+		// come out of the black.
+		let buf: ImageBuffer<Rgb<u8>, Vec<u8>> = ImageBuffer::from_fn(12, 6, |x, y| {
+			cq!(
+				x < 6,
+				Rgb([0, 0, 0]),
+				Rgb([255, ((x * 97 + y * 41) % 64) as u8, 0])
+			)
+		});
+		let options = CarveOptions {
+			track_removed_colors: true,
+			..CarveOptions::default()
+		};
+		let (carved, report) = seamcarve_with_report(&buf, 9, 6, &options).unwrap();
+		assert_eq!(carved.dimensions(), (9, 6));
+		assert_eq!((report.width_seams, report.height_seams), (3, 0));
+		let removed = report.removed_colors.unwrap();
+		assert_eq!(removed.total(), 18);
+		assert_eq!(removed.dominant(), Some((0, 0, 0)));
+		let original = report.original_colors.unwrap();
+		assert_eq!(original.total(), 72);
+		assert!(removed.fraction_of(&original).contains(&((0, 0, 0), 0.5)));
+	}
+}
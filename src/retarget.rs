@@ -0,0 +1,388 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Optimal seam order
+//!
+//! When an image loses both width and height, it matters which seams
+//! go first: a horizontal seam found after a few vertical seams have
+//! been taken out isn't the one that would have been found before.
+//! Avidan & Shamir's transport map settles the order with one more
+//! dynamic program.  Cell (c, r) of the map holds the least total
+//! cost of any order that takes c vertical and r horizontal seams out
+//! of the image, and is reached either from (c - 1, r) by removing a
+//! vertical seam or from (c, r - 1) by removing a horizontal one.
+//!
+//! This is expensive: it finds two seams for every cell of the map, so
+//! taking 100 seams out in each direction means finding some 20,000
+//! seams.  A row of intermediate images is kept, each replaced as the
+//! row below is worked.  The greedy strategy is the cheap alternative:
+//! at every step, find the best seam each way and take out whichever
+//! costs less.  By default the transport map is built when the work it
+//! takes is under `TRANSPORT_MAP_LIMIT`, and the greedy strategy is
+//! used beyond it.
+
+use crate::cq;
+use crate::energyopts::EnergyOptions;
+use crate::report::StageTimings;
+use crate::seamcarver::{cheapest_seam, remove_seam, Algorithm, Carve};
+use crate::seamfinder::Orientation;
+use crate::twodmap::TwoDimensionalMap;
+use image::{GenericImageView, ImageBuffer, Pixel, Primitive};
+
+/// The most work the default strategy will put into a transport map:
+/// the number of cells in the map, times the pixels in the image each
+/// cell's seams are found in.  Taking eight seams out each way of a
+/// 640x480 image is under it; ten each way is over.
+pub const TRANSPORT_MAP_LIMIT: u64 = 1 << 25;
+
+/// How the carver orders its seams when an image is to lose both
+/// width and height.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum RetargetStrategy {
+	/// The transport map if building it is no more work than
+	/// `TRANSPORT_MAP_LIMIT`, and greedy if it would be.
+	#[default]
+	Auto,
+
+	/// At each step, remove whichever of the best vertical and best
+	/// horizontal seams is cheaper.  Two seams are found per seam
+	/// removed.
+	Greedy,
+
+	/// Follow the transport map: the cheapest order overall, at the
+	/// cost of finding two seams for every combination of seam counts.
+	/// The time taken grows with the product of the two seam counts.
+	TransportMap,
+}
+
+impl RetargetStrategy {
+	// The strategy for taking `columns` vertical and `rows` horizontal
+	// seams out of an image of the given dimensions, with `Auto`
+	// settled one way or the other.
+	pub(crate) fn settled(self, (width, height): (u32, u32), (columns, rows): (u32, u32)) -> Self {
+		if self != RetargetStrategy::Auto {
+			return self;
+		}
+		let cells = u64::from(columns + 1) * u64::from(rows + 1);
+		let work = cells.saturating_mul(u64::from(width) * u64::from(height));
+		cq!(
+			work <= TRANSPORT_MAP_LIMIT,
+			RetargetStrategy::TransportMap,
+			RetargetStrategy::Greedy
+		)
+	}
+}
+
+/// The transport map for some number of vertical and horizontal
+/// seams, and the choice made to reach each of its cells.
+pub struct TransportMap {
+	/// The least total seam cost of removing `x` vertical and `y`
+	/// horizontal seams, in any order.
+	pub costs: TwoDimensionalMap<f64>,
+
+	// True where the cheapest way to a cell was to remove a vertical
+	// seam last.
+	vertical: TwoDimensionalMap<bool>,
+}
+
+impl TransportMap {
+	/// The order in which to remove the seams that reaches the far
+	/// corner of the map most cheaply.
+	pub fn order(&self) -> Vec<Orientation> {
+		let (mut x, mut y) = (self.costs.width - 1, self.costs.height - 1);
+		let mut order = Vec::with_capacity((x + y) as usize);
+		while x > 0 || y > 0 {
+			if self.vertical[(x, y)] {
+				order.push(Orientation::Vertical);
+				x -= 1;
+			} else {
+				order.push(Orientation::Horizontal);
+				y -= 1;
+			}
+		}
+		order.reverse();
+		order
+	}
+
+	// The cells on the chosen path through the map, from the far
+	// corner back to the origin.
+	fn path(&self) -> Vec<(u32, u32)> {
+		let (mut x, mut y) = (self.costs.width - 1, self.costs.height - 1);
+		let mut path = vec![(x, y)];
+		while x > 0 || y > 0 {
+			if self.vertical[(x, y)] {
+				x -= 1;
+			} else {
+				y -= 1;
+			}
+			path.push((x, y));
+		}
+		path
+	}
+
+	/// Render the map as a Graphviz DOT digraph.  Each cell is a node
+	/// labelled with its seam counts and cost, and has an edge from
+	/// each cell it could have been reached from.  The edge the map
+	/// chose into each cell is solid, the other dashed, and the chosen
+	/// order from the origin to the far corner is drawn in red.
+	pub fn to_dot(&self) -> String {
+		let path = self.path();
+		let on_path = |x, y| path.contains(&(x, y));
+		let mut dot = String::from("digraph transport {\n  rankdir=LR;\n  node [shape=box];\n");
+		for y in 0..self.costs.height {
+			for x in 0..self.costs.width {
+				dot.push_str(&format!(
+					"  c{}_{} [label=\"{}V {}H\\n{:.3}\"{}];\n",
+					x,
+					y,
+					x,
+					y,
+					self.costs[(x, y)],
+					cq!(on_path(x, y), ", color=red", "")
+				));
+			}
+		}
+		for y in 0..self.costs.height {
+			for x in 0..self.costs.width {
+				let from = [
+					(x > 0, (x.wrapping_sub(1), y), true),
+					(y > 0, (x, y.wrapping_sub(1)), false),
+				];
+				for (exists, (fx, fy), vertical) in from.iter() {
+					if !exists {
+						continue;
+					}
+					let chosen = self.vertical[(x, y)] == *vertical;
+					let style = match (chosen && on_path(x, y) && on_path(*fx, *fy), chosen) {
+						(true, _) => "color=red, penwidth=2",
+						(false, true) => "style=solid",
+						(false, false) => "style=dashed",
+					};
+					dot.push_str(&format!(
+						"  c{}_{} -> c{}_{} [label=\"{}\", {}];\n",
+						fx,
+						fy,
+						x,
+						y,
+						cq!(*vertical, "V", "H"),
+						style
+					));
+				}
+			}
+		}
+		dot.push_str("}\n");
+		dot
+	}
+
+	/// Render the map as JSON: the `costs` as an array of rows, one
+	/// per count of horizontal seams; `choices` in the same shape,
+	/// holding `"V"` or `"H"` for the seam removed last to reach each
+	/// cell (`null` at the origin); and the chosen `order`.
+	pub fn to_json(&self) -> String {
+		let rows = |cell: &dyn Fn(u32, u32) -> String| {
+			(0..self.costs.height)
+				.map(|y| {
+					let row: Vec<String> = (0..self.costs.width).map(|x| cell(x, y)).collect();
+					format!("    [{}]", row.join(", "))
+				})
+				.collect::<Vec<String>>()
+				.join(",\n")
+		};
+		let costs = rows(&|x, y| format!("{}", self.costs[(x, y)]));
+		let choices = rows(&|x, y| match (x, y) {
+			(0, 0) => "null".to_string(),
+			_ => cq!(self.vertical[(x, y)], "\"V\"", "\"H\"").to_string(),
+		});
+		let order: Vec<&str> = self
+			.order()
+			.iter()
+			.map(|o| cq!(*o == Orientation::Vertical, "\"V\"", "\"H\""))
+			.collect();
+		format!(
+			"{{\n  \"costs\": [\n{}\n  ],\n  \"choices\": [\n{}\n  ],\n  \"order\": [{}]\n}}\n",
+			costs,
+			choices,
+			order.join(", ")
+		)
+	}
+}
+
+// The image with its cheapest seam in one direction removed, and the
+// seam's total cost.
+fn carve_cheapest<I, P, S>(
+	image: &I,
+	direction: Carve,
+	(options, algorithm): (&EnergyOptions, Algorithm),
+) -> (ImageBuffer<P, Vec<S>>, f64)
+where
+	I: GenericImageView<Pixel = P>,
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	let mut timings = StageTimings::default();
+	let seam = cheapest_seam(image, direction, algorithm, options.clone(), &mut timings);
+	(remove_seam(image, &seam, direction), seam.cost())
+}
+
+/// Build the transport map for removing `columns` vertical and `rows`
+/// horizontal seams from an image, with the seams found by the given
+/// algorithm under the given options, in the energy domain a carve
+/// would use for the image.  Any per-pixel maps in the options are
+/// refitted to each intermediate image rather than carved along with
+/// it.
+pub fn transport_map<I, P, S>(
+	image: &I,
+	columns: u32,
+	rows: u32,
+	options: &EnergyOptions,
+	algorithm: Algorithm,
+) -> TransportMap
+where
+	I: GenericImageView<Pixel = P>,
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	let finder = (options, algorithm);
+	let mut costs: TwoDimensionalMap<f64> = TwoDimensionalMap::new(columns + 1, rows + 1);
+	let mut vertical: TwoDimensionalMap<bool> = TwoDimensionalMap::new(columns + 1, rows + 1);
+
+	// The image at each cell of one row.  Working along the next row,
+	// the images left of the cell are already that row's, and the
+	// image at the cell is the one above it until it's replaced.
+	let mut first = ImageBuffer::new(image.width(), image.height());
+	for (x, y, pixel) in image.pixels() {
+		first[(x, y)] = pixel;
+	}
+	let mut images: Vec<ImageBuffer<P, Vec<S>>> = Vec::with_capacity(columns as usize + 1);
+	images.push(first);
+	for x in 1..=columns {
+		let (next, cost) = carve_cheapest(&images[x as usize - 1], Carve::Width, finder);
+		costs[(x, 0)] = costs[(x - 1, 0)] + cost;
+		vertical[(x, 0)] = true;
+		images.push(next);
+	}
+
+	for y in 1..=rows {
+		for x in 0..=columns {
+			let (down, down_cost) = carve_cheapest(&images[x as usize], Carve::Height, finder);
+			let down_cost = costs[(x, y - 1)] + down_cost;
+			let across = match x {
+				0 => None,
+				_ => Some(carve_cheapest(
+					&images[x as usize - 1],
+					Carve::Width,
+					finder,
+				)),
+			};
+			images[x as usize] = match across {
+				Some((next, cost)) if costs[(x - 1, y)] + cost < down_cost => {
+					costs[(x, y)] = costs[(x - 1, y)] + cost;
+					vertical[(x, y)] = true;
+					next
+				}
+				_ => {
+					costs[(x, y)] = down_cost;
+					down
+				}
+			};
+		}
+	}
+
+	TransportMap { costs, vertical }
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::cq;
+	use image::{ImageBuffer, Luma};
+
+	#[test]
+	fn the_order_is_no_worse_than_either_extreme() {
+		let image: ImageBuffer<Luma<u8>, Vec<u8>> =
+			ImageBuffer::from_fn(9, 7, |x, y| Luma([((x * x * 13 + y * 29) % 256) as u8]));
+		let map = transport_map(&image, 3, 2, &EnergyOptions::default(), Algorithm::Forward);
+		let order = map.order();
+		assert_eq!(order.len(), 5);
+		assert_eq!(
+			order
+				.iter()
+				.filter(|o| **o == Orientation::Vertical)
+				.count(),
+			3
+		);
+
+		// All the vertical seams first, and all the horizontal first,
+		// are both paths through the map.
+		let best = map.costs[(3, 2)];
+		let options = EnergyOptions::default();
+		let finder = (&options, Algorithm::Forward);
+		let (mut verticals, mut horizontals) = (image.clone(), image.clone());
+		let (mut vcost, mut hcost) = (0.0, 0.0);
+		for direction in [
+			Carve::Width,
+			Carve::Width,
+			Carve::Width,
+			Carve::Height,
+			Carve::Height,
+		]
+		.iter()
+		{
+			let (next, cost) = carve_cheapest(&verticals, *direction, finder);
+			verticals = next;
+			vcost += cost;
+		}
+		for direction in [
+			Carve::Height,
+			Carve::Height,
+			Carve::Width,
+			Carve::Width,
+			Carve::Width,
+		]
+		.iter()
+		{
+			let (next, cost) = carve_cheapest(&horizontals, *direction, finder);
+			horizontals = next;
+			hcost += cost;
+		}
+		assert!(best <= vcost && best <= hcost);
+	}
+
+	#[test]
+	fn the_default_strategy_is_the_map_while_it_is_cheap() {
+		let auto = RetargetStrategy::Auto;
+		assert_eq!(
+			auto.settled((640, 480), (8, 8)),
+			RetargetStrategy::TransportMap
+		);
+		assert_eq!(auto.settled((640, 480), (10, 10)), RetargetStrategy::Greedy);
+		let greedy = RetargetStrategy::Greedy;
+		assert_eq!(greedy.settled((9, 7), (1, 1)), greedy);
+		let map = RetargetStrategy::TransportMap;
+		assert_eq!(map.settled((4000, 3000), (500, 500)), map);
+	}
+
+	#[test]
+	fn the_map_exports_its_decisions() {
+		let image: ImageBuffer<Luma<u8>, Vec<u8>> =
+			ImageBuffer::from_fn(6, 5, |x, y| Luma([((x * x * 13 + y * 29) % 256) as u8]));
+		let map = transport_map(&image, 2, 1, &EnergyOptions::default(), Algorithm::Forward);
+
+		let dot = map.to_dot();
+		assert!(dot.starts_with("digraph transport {"));
+		// Six cells, and an edge into each from each neighbor: two
+		// along each row, three down each column.
+		assert_eq!(dot.matches(" [label=\"").count(), 6 + 4 + 3);
+		assert_eq!(dot.matches("penwidth=2").count(), 3);
+
+		let json = map.to_json();
+		assert!(json.contains("\"choices\": [\n    [null, \"V\", \"V\"],"));
+		let order: Vec<&str> = map
+			.order()
+			.iter()
+			.map(|o| cq!(*o == Orientation::Vertical, "\"V\"", "\"H\""))
+			.collect();
+		assert!(json.contains(&format!("\"order\": [{}]", order.join(", "))));
+	}
+}
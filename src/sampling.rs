@@ -0,0 +1,104 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Near-optimal seam sampling
+//!
+//! Always taking the single cheapest seam carves the same low-energy
+//! region over and over, and on a texture (grass, gravel, foliage)
+//! that wears a visible channel through it.  Taking instead a seam at
+//! random from among those nearly as cheap spreads the damage out,
+//! where it's much harder to see.  "Nearly as cheap" is measured at
+//! the end of the seam: each pixel of the last row whose cost is
+//! within the tolerance of the cheapest is a candidate, and the seam
+//! is traced back from the one drawn.
+//!
+//! The draws come from a small seeded generator, so that a carve is
+//! the same every time it's run with the same seed.
+
+/// Pick every seam at random from among those within `tolerance` of
+/// the cheapest, as a fraction of its cost: 0.05 takes any seam no
+/// more than 5% dearer.  A tolerance of 0 takes the cheapest, as an
+/// ordinary carve does, but chooses at random among any that tie.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct NearOptimal {
+	/// How much dearer than the cheapest a seam may be, as a fraction.
+	pub tolerance: f64,
+	/// The seed of the draws.
+	pub seed: u64,
+}
+
+impl NearOptimal {
+	/// Choose within the given tolerance, drawing from the given seed.
+	pub fn new(tolerance: f64, seed: u64) -> Self {
+		NearOptimal {
+			tolerance: tolerance.max(0.0),
+			seed,
+		}
+	}
+}
+
+// The carve's source of draws: splitmix64, which is tiny, fast, and
+// good enough to pick among a few columns.
+pub(crate) struct Sampler {
+	tolerance: f64,
+	state: u64,
+}
+
+impl Sampler {
+	pub(crate) fn new(choice: NearOptimal) -> Self {
+		Sampler {
+			tolerance: choice.tolerance,
+			state: choice.seed,
+		}
+	}
+
+	// The tolerance, and a fresh draw, for the next seam.
+	pub(crate) fn draw(&mut self) -> (f64, u64) {
+		self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+		let mut z = self.state;
+		z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+		z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+		(self.tolerance, z ^ (z >> 31))
+	}
+}
+
+// The end of the seam to trace back from: the `draw`th, wrapping
+// round, of the columns whose cost is within `tolerance` of the
+// cheapest.
+pub(crate) fn near_optimal(costs: &[f64], (tolerance, draw): (f64, u64)) -> u32 {
+	let cheapest = costs.iter().cloned().fold(f64::INFINITY, f64::min);
+	let limit = cheapest + cheapest.abs() * tolerance;
+	let candidates: Vec<u32> = (0..costs.len() as u32)
+		.filter(|&x| costs[x as usize] <= limit)
+		.collect();
+	match candidates.len() {
+		0 => 0,
+		n => candidates[(draw % n as u64) as usize],
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn only_near_optimal_columns_are_drawn() {
+		let costs = [110.0, 100.0, 300.0, 104.0, 100.0];
+		assert_eq!(near_optimal(&costs, (0.0, 0)), 1);
+		assert_eq!(near_optimal(&costs, (0.0, 1)), 4);
+		let mut sampler = Sampler::new(NearOptimal::new(0.05, 7));
+		let drawn: Vec<u32> = (0..40)
+			.map(|_| near_optimal(&costs, sampler.draw()))
+			.collect();
+		assert!(drawn.iter().all(|x| [1, 3, 4].contains(x)));
+		assert!([1, 3, 4].iter().all(|x| drawn.contains(x)));
+
+		// The same seed draws the same columns.
+		let mut again = Sampler::new(NearOptimal::new(0.05, 7));
+		let redrawn: Vec<u32> = (0..40)
+			.map(|_| near_optimal(&costs, again.draw()))
+			.collect();
+		assert_eq!(drawn, redrawn);
+	}
+}
@@ -0,0 +1,323 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Energy scalars
+//!
+//! The energy of a pixel has always been a u32, the squared
+//! difference of two luma values.  That's fine for gradients, but
+//! learned saliency comes as floating point scores, and multiplying
+//! them into an integer map throws away everything below the
+//! decimal point.  The energy calculator and the seam DP are generic
+//! over this trait instead, so a float map can go all the way from
+//! the image to the seam without being quantized along the way.
+//! Implementations are provided for `u32`, `u64`, `f32`, and `f64`.
+//!
+//! Floats have a price: the same carve can come out differently on
+//! another platform or under another compiler, as operations are
+//! reordered or fused.  `Fixed` keeps fractional energies as scaled
+//! integers instead, so that sums come out the same everywhere.  With
+//! the `deterministic` feature on, `FloatEnergy`, the domain to ask for
+//! when fractional energies are wanted, is `Fixed` rather than `f64`.
+
+use crate::pixelpairs::energy_of_lumas;
+use std::fmt::Debug;
+use std::ops::Add;
+
+/// A type the per-pixel energy and running seam costs can be kept in.
+/// Energies are shared between threads when the carve is threaded.
+pub trait EnergyScalar:
+	Default + Copy + PartialOrd + Add<Output = Self> + Debug + Send + Sync
+{
+	/// The type a seam's running cost is summed in.  A tall image of
+	/// strong edges can overflow a u32 sum, which wraps around and
+	/// makes the worst seam look like the best; integer energies are
+	/// therefore summed in u64.
+	type Sum: EnergyScalar;
+
+	/// The energy, in the type it's summed in.
+	fn widen(self) -> Self::Sum;
+
+	/// The energy between a pair of pixels, given their lumas and the
+	/// largest luma the image's pixels can have, in this domain.
+	fn of_lumas(l1: u32, l2: u32, max: u32) -> Self;
+
+	/// Multiply the energy by a factor, as an importance map does.
+	fn scale(self, factor: f64) -> Self;
+
+	/// An energy so large that no seam will pass through a pixel
+	/// carrying it if there's any other way, for an image of the given
+	/// dimensions.  A seam made of nothing but walls still mustn't
+	/// overflow its running sum.
+	fn wall(width: u32, height: u32) -> Self;
+
+	/// The top of the range normalized energies are scaled to.
+	fn full_scale() -> Self;
+
+	/// The energy as a float, for arithmetic that needs one.
+	fn to_f64(self) -> f64;
+
+	/// An energy from a float, rounded if the domain needs it.
+	fn from_f64(value: f64) -> Self;
+
+	/// The sum of two energies, held at the top of the domain rather
+	/// than overflowing.  The squared luma difference of a 16-bit
+	/// image fills most of a u32, and two of them, or one and a wall,
+	/// don't fit.
+	fn saturating_add(self, other: Self) -> Self {
+		self + other
+	}
+}
+
+impl EnergyScalar for u32 {
+	type Sum = u64;
+
+	fn widen(self) -> u64 {
+		u64::from(self)
+	}
+
+	fn of_lumas(l1: u32, l2: u32, _max: u32) -> Self {
+		energy_of_lumas(l1, l2)
+	}
+
+	fn scale(self, factor: f64) -> Self {
+		(f64::from(self) * factor).round() as u32
+	}
+
+	fn wall(width: u32, height: u32) -> Self {
+		(u32::MAX / 4) / std::cmp::max(width, height)
+	}
+
+	// The same range as the luma energy of an 8-bit image, near
+	// enough, which leaves room for a seam's running sum.
+	fn full_scale() -> Self {
+		u32::from(u16::MAX)
+	}
+
+	fn to_f64(self) -> f64 {
+		self as f64
+	}
+
+	fn from_f64(value: f64) -> Self {
+		value.round() as u32
+	}
+
+	fn saturating_add(self, other: Self) -> Self {
+		u32::saturating_add(self, other)
+	}
+}
+
+impl EnergyScalar for f32 {
+	type Sum = f32;
+
+	fn widen(self) -> f32 {
+		self
+	}
+
+	fn of_lumas(l1: u32, l2: u32, max: u32) -> Self {
+		let css = (l1 as f32 - l2 as f32) / max as f32;
+		css * css
+	}
+
+	fn scale(self, factor: f64) -> Self {
+		(f64::from(self) * factor) as f32
+	}
+
+	fn wall(width: u32, height: u32) -> Self {
+		(f32::MAX / 4.0) / std::cmp::max(width, height) as f32
+	}
+
+	fn full_scale() -> Self {
+		1.0
+	}
+
+	fn to_f64(self) -> f64 {
+		self as f64
+	}
+
+	fn from_f64(value: f64) -> Self {
+		value as f32
+	}
+}
+
+impl EnergyScalar for f64 {
+	type Sum = f64;
+
+	fn widen(self) -> f64 {
+		self
+	}
+
+	fn of_lumas(l1: u32, l2: u32, max: u32) -> Self {
+		let css = (f64::from(l1) - f64::from(l2)) / f64::from(max);
+		css * css
+	}
+
+	fn scale(self, factor: f64) -> Self {
+		self * factor
+	}
+
+	fn wall(width: u32, height: u32) -> Self {
+		(f64::MAX / 4.0) / f64::from(std::cmp::max(width, height))
+	}
+
+	fn full_scale() -> Self {
+		1.0
+	}
+
+	fn to_f64(self) -> f64 {
+		self
+	}
+
+	fn from_f64(value: f64) -> Self {
+		value
+	}
+}
+
+impl EnergyScalar for u64 {
+	type Sum = u64;
+
+	fn widen(self) -> u64 {
+		self
+	}
+
+	fn of_lumas(l1: u32, l2: u32, _max: u32) -> Self {
+		u64::from(energy_of_lumas(l1, l2))
+	}
+
+	fn scale(self, factor: f64) -> Self {
+		(self as f64 * factor).round() as u64
+	}
+
+	fn wall(width: u32, height: u32) -> Self {
+		(u64::MAX / 4) / u64::from(std::cmp::max(width, height))
+	}
+
+	fn full_scale() -> Self {
+		u64::from(u16::MAX)
+	}
+
+	fn to_f64(self) -> f64 {
+		self as f64
+	}
+
+	fn from_f64(value: f64) -> Self {
+		value.round() as u64
+	}
+
+	fn saturating_add(self, other: Self) -> Self {
+		u64::saturating_add(self, other)
+	}
+}
+
+/// A fixed-point energy: a signed 64-bit integer counting units of
+/// 2^-32.  Energies are kept in the unit range, as with the floats,
+/// and sums are exact integer additions.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Fixed(pub i64);
+
+// The number of fractional bits in a `Fixed`.
+const FIXED_FRACTION: u32 = 32;
+
+impl Add for Fixed {
+	type Output = Fixed;
+
+	fn add(self, other: Fixed) -> Fixed {
+		Fixed(self.0 + other.0)
+	}
+}
+
+impl EnergyScalar for Fixed {
+	type Sum = Fixed;
+
+	fn widen(self) -> Fixed {
+		self
+	}
+
+	// Worked through in integers, so that the energy is exact to the
+	// last bit of the fraction.
+	fn of_lumas(l1: u32, l2: u32, max: u32) -> Self {
+		let css = i128::from(l1) - i128::from(l2);
+		let max = i128::from(max);
+		Fixed((((css * css) << FIXED_FRACTION) / (max * max)) as i64)
+	}
+
+	// A single IEEE multiplication and rounding is the same on every
+	// platform; it's only chains of them that drift.
+	fn scale(self, factor: f64) -> Self {
+		Fixed((self.0 as f64 * factor).round() as i64)
+	}
+
+	fn wall(width: u32, height: u32) -> Self {
+		Fixed((i64::MAX / 4) / i64::from(std::cmp::max(width, height)))
+	}
+
+	fn full_scale() -> Self {
+		Fixed(1 << FIXED_FRACTION)
+	}
+
+	fn to_f64(self) -> f64 {
+		self.0 as f64 / (1u64 << FIXED_FRACTION) as f64
+	}
+
+	fn from_f64(value: f64) -> Self {
+		Fixed((value * (1u64 << FIXED_FRACTION) as f64).round() as i64)
+	}
+
+	fn saturating_add(self, other: Self) -> Self {
+		Fixed(self.0.saturating_add(other.0))
+	}
+}
+
+/// The domain to keep fractional energies in: `Fixed` when the
+/// `deterministic` feature is on, and `f64` otherwise.
+#[cfg(feature = "deterministic")]
+pub type FloatEnergy = Fixed;
+
+/// The domain to keep fractional energies in: `Fixed` when the
+/// `deterministic` feature is on, and `f64` otherwise.
+#[cfg(not(feature = "deterministic"))]
+pub type FloatEnergy = f64;
+
+#[cfg(test)]
+mod tests {
+	use super::Fixed;
+	use crate::avisha2::AviShaTwo;
+	use crate::seamfinder::SeamFinder;
+	use image::{ImageBuffer, Luma};
+
+	#[test]
+	fn domains_agree_on_forward_energy_seams() {
+		let buf: ImageBuffer<Luma<u8>, Vec<u8>> =
+			ImageBuffer::from_fn(9, 7, |x, y| Luma([((x * 53 + y * 29) % 256) as u8]));
+		let seam = AviShaTwo::new(&buf).find_vertical_seam().into_path();
+		assert_eq!(
+			AviShaTwo::new(&buf)
+				.in_domain::<f32>()
+				.find_vertical_seam()
+				.into_path(),
+			seam
+		);
+		assert_eq!(
+			AviShaTwo::new(&buf)
+				.in_domain::<f64>()
+				.find_vertical_seam()
+				.into_path(),
+			seam
+		);
+		assert_eq!(
+			AviShaTwo::new(&buf)
+				.in_domain::<Fixed>()
+				.find_vertical_seam()
+				.into_path(),
+			seam
+		);
+		let seam = AviShaTwo::new(&buf).find_horizontal_seam().into_path();
+		assert_eq!(
+			AviShaTwo::new(&buf)
+				.in_domain::<f64>()
+				.find_horizontal_seam()
+				.into_path(),
+			seam
+		);
+	}
+}
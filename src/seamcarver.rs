@@ -13,14 +13,32 @@
 // the horizontal seams will give us nightmares when we start trying
 // to multithread this beast.
 
-use crate::avisha2::AviShaTwo;
-use crate::seamfinder::SeamFinder;
-use image::{GenericImageView, ImageBuffer, Pixel, Primitive};
+use crate::alpha::blend;
+use crate::avisha1::{AviShaOne, CarveScratch, EnergyTable};
+use crate::avisha2::{Adjust, AviShaTwo, ForwardTable};
+use crate::backend::{any_backend, plan, Backend, ComputeBackend, DeviceSeamFinder, Devices};
+use crate::budget::{InsertionCap, InsertionTracker, Origins, RegionBudget, RegionTracker};
+use crate::cancel::CancelToken;
+use crate::cq;
+use crate::energyopts::{marked, EnergyOptions, FittedMaps};
+use crate::error::CarveError;
+use crate::lattice::SeamLattice;
+use crate::normalize::Normalization;
+use crate::pixelpairs::{is_float, luma_max, luma_plane};
+use crate::pool;
+use crate::report::{CarveEvent, CarveReport, ColorHistogram, StageTimings};
+use crate::retarget::{transport_map, RetargetStrategy};
+use crate::sampling::{NearOptimal, Sampler};
+use crate::scalar::{EnergyScalar, FloatEnergy};
+use crate::seamfinder::{Orientation, Seam, SeamFinder};
+use crate::synthesis::Synthesis;
+use crate::twodmap::TwoDimensionalMap;
+use image::{imageops, FilterType, GenericImageView, ImageBuffer, Luma, Pixel, Primitive};
+use std::time::Instant;
 
-// The one tiny inefficiency here is that the seam is copied, into the
-// new image, and then the path of pixels immediately to the right of
-// the seam are copied over it.
-fn remove_vertical_seam<I, P, S>(image: &I, seam: &Vec<u32>) -> ImageBuffer<P, Vec<S>>
+// Every pixel but the seam's is copied across, those right of the
+// seam one column to the left.
+fn copy_without_vertical_seam<I, P, S>(image: &I, seam: &[u32]) -> ImageBuffer<P, Vec<S>>
 where
 	I: GenericImageView<Pixel = P>,
 	P: Pixel<Subpixel = S> + 'static,
@@ -29,26 +47,17 @@ where
 	let (width, height) = image.dimensions();
 	let mut imgbuf = image::ImageBuffer::new(width - 1, height);
 	for y in 0..height {
-		for x in 0..width {
-			let pixel = image.get_pixel(x, y);
-			imgbuf.put_pixel(
-				if x < seam[y as usize] || x == 0 {
-					x
-				} else {
-					x - 1
-				},
-				y,
-				pixel,
-			);
+		let at = seam[y as usize];
+		for x in (0..width).filter(|&x| x != at) {
+			imgbuf.put_pixel(cq!(x < at, x, x - 1), y, image.get_pixel(x, y));
 		}
 	}
 	imgbuf
 }
 
-// The one tiny inefficiency here is that the seam is copied, into the
-// new image, and then the path of pixels immediately below the seam
-// are copied over it.
-fn remove_horizontal_seam<I, P, S>(image: &I, seam: &Vec<u32>) -> ImageBuffer<P, Vec<S>>
+// Every pixel but the seam's is copied across, those below the seam
+// one row up.
+fn copy_without_horizontal_seam<I, P, S>(image: &I, seam: &[u32]) -> ImageBuffer<P, Vec<S>>
 where
 	I: GenericImageView<Pixel = P>,
 	P: Pixel<Subpixel = S> + 'static,
@@ -57,95 +66,2456 @@ where
 	let (width, height) = image.dimensions();
 	let mut imgbuf = image::ImageBuffer::new(width, height - 1);
 	for y in 0..height {
-		for x in 0..width {
-			let pixel = image.get_pixel(x, y);
-			imgbuf.put_pixel(
-				x,
-				if y < seam[x as usize] || y == 0 {
-					y
-				} else {
-					y - 1
-				},
-				pixel,
-			);
+		for x in (0..width).filter(|&x| y != seam[x as usize]) {
+			let at = seam[x as usize];
+			imgbuf.put_pixel(x, cq!(y < at, y, y - 1), image.get_pixel(x, y));
 		}
 	}
 	imgbuf
 }
 
-#[derive(PartialEq, Copy, Clone)]
-enum Carve {
+// The seam is taken out of the image's own buffer: every pixel moves
+// to the same or an earlier place in it, so each row can be compacted
+// where it is, and the buffer truncated to the smaller image without
+// being reallocated.  Removing a vertical seam moves the pixels right
+// of it, and a horizontal seam only those below it.
+pub(crate) fn remove_seam_in_place<P, S>(
+	image: ImageBuffer<P, Vec<S>>,
+	seam: &[u32],
+	direction: Carve,
+) -> ImageBuffer<P, Vec<S>>
+where
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	let (width, height) = image.dimensions();
+	let channels = P::CHANNEL_COUNT as usize;
+	let (w, h) = (width as usize, height as usize);
+	let mut raw = image.into_raw();
+	match direction {
+		Carve::Width => {
+			for (y, &at) in seam.iter().enumerate().take(h) {
+				let at = at as usize;
+				let (from, to) = (y * w * channels, y * (w - 1) * channels);
+				raw.copy_within(from..from + at * channels, to);
+				raw.copy_within(
+					from + (at + 1) * channels..from + w * channels,
+					to + at * channels,
+				);
+			}
+			raw.truncate((w - 1) * h * channels);
+			ImageBuffer::from_raw(width - 1, height, raw).unwrap()
+		}
+		Carve::Height => {
+			for y in 0..h - 1 {
+				for x in (0..w).filter(|&x| seam[x] as usize <= y) {
+					let to = (y * w + x) * channels;
+					raw.copy_within(to + w * channels..to + (w + 1) * channels, to);
+				}
+			}
+			raw.truncate(w * (h - 1) * channels);
+			ImageBuffer::from_raw(width, height - 1, raw).unwrap()
+		}
+	}
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub(crate) enum Carve {
 	Width,
 	Height,
 }
 
-fn carveonce<I, P, S>(image: &I, direction: Carve) -> ImageBuffer<P, Vec<S>>
+impl Carve {
+	// Carving width removes vertical seams, and height horizontal ones.
+	pub(crate) fn orientation(self) -> Orientation {
+		match self {
+			Carve::Width => Orientation::Vertical,
+			Carve::Height => Orientation::Horizontal,
+		}
+	}
+}
+
+pub(crate) fn remove_seam<I, P, S>(
+	image: &I,
+	seam: &[u32],
+	direction: Carve,
+) -> ImageBuffer<P, Vec<S>>
 where
 	I: GenericImageView<Pixel = P>,
 	P: Pixel<Subpixel = S> + 'static,
 	S: Primitive + 'static,
 {
-	if direction == Carve::Height {
-		let carver = AviShaTwo::new(image);
-		let seam = carver.find_horizontal_seam();
-		remove_horizontal_seam(image, &seam)
+	match direction {
+		Carve::Width => copy_without_vertical_seam(image, seam),
+		Carve::Height => copy_without_horizontal_seam(image, seam),
+	}
+}
+
+/// The image with a vertical seam removed, one pixel narrower.  The
+/// seam must have been found in, or made for, an image of the same
+/// dimensions.  For seams found elsewhere, or made by hand.
+pub fn remove_vertical_seam<I, P, S>(
+	image: &I,
+	seam: &Seam,
+) -> Result<ImageBuffer<P, Vec<S>>, CarveError>
+where
+	I: GenericImageView<Pixel = P>,
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	seam.check(Orientation::Vertical, image.dimensions())?;
+	Ok(copy_without_vertical_seam(image, seam))
+}
+
+/// The image with a horizontal seam removed, one pixel shorter.  As
+/// `remove_vertical_seam`.
+pub fn remove_horizontal_seam<I, P, S>(
+	image: &I,
+	seam: &Seam,
+) -> Result<ImageBuffer<P, Vec<S>>, CarveError>
+where
+	I: GenericImageView<Pixel = P>,
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	seam.check(Orientation::Horizontal, image.dimensions())?;
+	Ok(copy_without_horizontal_seam(image, seam))
+}
+
+/// Remove a seam of either orientation from the image in its own
+/// buffer, which is compacted and truncated rather than copied.  The
+/// seam must fit the image as for `remove_vertical_seam`.
+pub fn remove_seam_into<P, S>(
+	image: ImageBuffer<P, Vec<S>>,
+	seam: &Seam,
+) -> Result<ImageBuffer<P, Vec<S>>, CarveError>
+where
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	seam.check(seam.orientation(), image.dimensions())?;
+	let direction = match seam.orientation() {
+		Orientation::Vertical => Carve::Width,
+		Orientation::Horizontal => Carve::Height,
+	};
+	Ok(remove_seam_in_place(image, seam, direction))
+}
+
+/// The image with a seam inserted beside the given one, one pixel
+/// wider for a vertical seam or taller for a horizontal one: each
+/// pixel of the seam is followed by a new pixel made from it and its
+/// neighbor on the far side, as the synthesis has it.  The seam must
+/// fit the image as for `remove_vertical_seam`.
+pub fn insert_seam<I, P, S>(
+	image: &I,
+	seam: &Seam,
+	synthesis: Synthesis,
+) -> Result<ImageBuffer<P, Vec<S>>, CarveError>
+where
+	I: GenericImageView<Pixel = P>,
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	let (width, height) = image.dimensions();
+	seam.check(seam.orientation(), (width, height))?;
+	let direction = match seam.orientation() {
+		Orientation::Vertical => Carve::Width,
+		Orientation::Horizontal => Carve::Height,
+	};
+	let image = ImageBuffer::from_fn(width, height, |x, y| image.get_pixel(x, y));
+	let positions: Vec<Vec<(u32, u32)>> = seam.iter().map(|&at| vec![(at, 0)]).collect();
+	Ok(insert_seams_with(
+		&image,
+		&positions,
+		direction,
+		|pixel, neighbor| synthesis.synthesize(pixel, neighbor),
+	))
+}
+
+/// The seam finder a carve uses.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Algorithm {
+	/// Avidan and Shamir's original, `AviShaOne`: the seam through the
+	/// least energy of the image as it is.
+	Backward,
+	/// Avidan and Shamir's forward energy, `AviShaTwo`: the seam whose
+	/// removal puts the least new energy into the image.
+	#[default]
+	Forward,
+}
+
+/// How far a carve may shrink an image before it's refused as a
+/// mistake.  A carve to a sliver, or one that takes out nearly
+/// everything, leaves nothing worth having, and is far more often a
+/// swapped width and height or a misplaced digit than what was meant.
+/// Only shrinking counts: an image already narrower than the minimum
+/// may still be carved taller or shorter.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CarveLimits {
+	/// The narrowest an image may be carved to.
+	pub min_width: u32,
+	/// The shortest an image may be carved to.
+	pub min_height: u32,
+	/// The most of the original's pixels the carve may remove, as a
+	/// percentage.
+	pub max_removed_percent: u32,
+}
+
+impl Default for CarveLimits {
+	fn default() -> Self {
+		CarveLimits {
+			min_width: 2,
+			min_height: 2,
+			max_removed_percent: 90,
+		}
+	}
+}
+
+impl CarveLimits {
+	// Refuse a carve from `dimensions` to `target` past the limits.
+	fn check(&self, (width, height): (u32, u32), target: (u32, u32)) -> Result<(), CarveError> {
+		let (newwidth, newheight) = target;
+		let minimum = (self.min_width, self.min_height);
+		if (newwidth < self.min_width && newwidth < width)
+			|| (newheight < self.min_height && newheight < height)
+		{
+			return Err(CarveError::BelowMinimumSize { target, minimum });
+		}
+		let kept = u64::from(newwidth.min(width)) * u64::from(newheight.min(height));
+		let removed = 100 - kept * 100 / (u64::from(width) * u64::from(height));
+		if removed > u64::from(self.max_removed_percent) {
+			return Err(CarveError::RemovesTooMuch {
+				dimensions: (width, height),
+				target,
+				limit_percent: self.max_removed_percent,
+			});
+		}
+		Ok(())
+	}
+}
+
+/// What to do with an image bigger than the carve options allow.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum OversizePolicy {
+	/// Refuse to carve it.
+	#[default]
+	Refuse,
+	/// Carve it at full size, but find the seams coarse to fine, in a
+	/// copy shrunk to within the limit first.
+	Pyramid,
+	/// Shrink it to within the limit, carve that, and scale the result
+	/// up to the size asked for.
+	Downscale,
+}
+
+/// A rectangle of the image to confine the carve to.  Vertical seams
+/// are kept to its columns and horizontal seams to its rows, so that
+/// carving width leaves every column left of it exactly as it was,
+/// and every column right of it the same but for being moved over.
+/// The rectangle shrinks and grows with the carve.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RegionOfInterest {
+	/// The left edge of the region.
+	pub x: u32,
+	/// The top edge of the region.
+	pub y: u32,
+	/// The width of the region, in pixels.
+	pub width: u32,
+	/// The height of the region, in pixels.
+	pub height: u32,
+}
+
+impl RegionOfInterest {
+	/// The region `width` by `height` pixels with its top left corner
+	/// at `(x, y)`.
+	pub fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+		RegionOfInterest {
+			x,
+			y,
+			width,
+			height,
+		}
+	}
+
+	// A wall over every pixel of an image outside the region's columns
+	// (or rows), where seams in the given direction may not go.
+	fn walls(&self, width: u32, height: u32, direction: Carve) -> TwoDimensionalMap<u32> {
+		let wall = u32::wall(width, height);
+		let mut walls = TwoDimensionalMap::new(width, height);
+		for y in 0..height {
+			for x in 0..width {
+				let (at, start, extent) = match direction {
+					Carve::Width => (x, self.x, self.width),
+					Carve::Height => (y, self.y, self.height),
+				};
+				if at < start || at >= start + extent {
+					walls[(x, y)] = wall;
+				}
+			}
+		}
+		walls
+	}
+
+	// Make sure the region is inside the image, and has room for the
+	// seams to be taken out of it.
+	fn check(
+		&self,
+		(width, height): (u32, u32),
+		(newwidth, newheight): (u32, u32),
+	) -> Result<(), CarveError> {
+		if self.width == 0
+			|| self.height == 0
+			|| self.x + self.width > width
+			|| self.y + self.height > height
+		{
+			return Err(CarveError::RegionOutsideImage {
+				region: *self,
+				dimensions: (width, height),
+			});
+		}
+		if width.saturating_sub(newwidth) >= self.width
+			|| height.saturating_sub(newheight) >= self.height
+		{
+			return Err(CarveError::RegionTooSmall {
+				region: *self,
+				dimensions: (width, height),
+				target: (newwidth, newheight),
+			});
+		}
+		Ok(())
+	}
+}
+
+/// The knobs that govern a whole carve, as opposed to the finding of
+/// any one seam.  The default is an unconstrained carve.
+#[derive(Debug, Default, Clone)]
+pub struct CarveOptions {
+	/// The energy adjustments to make when finding every seam.  Any
+	/// importance, weight, protection, or block damping map is fitted
+	/// to the image once, and then carved along with it.
+	pub energy: EnergyOptions,
+
+	/// Limit how many seams may pass through each region of the
+	/// image, to keep the carve from collapsing one side of the
+	/// composition.
+	pub region_budget: Option<RegionBudget>,
+
+	/// Keep a histogram of the colors of the removed pixels, and of
+	/// the original image, in the carve report.
+	pub track_removed_colors: bool,
+
+	/// How to order the seams when the image loses both width and
+	/// height.  By default the transport map's order, unless building
+	/// the map would take more than `TRANSPORT_MAP_LIMIT`, when it's
+	/// greedy.  Either way the seams are found by the carve's
+	/// algorithm.
+	pub strategy: RetargetStrategy,
+
+	/// Limit how many seams an enlargement may insert close together,
+	/// so that a small smooth patch isn't smeared into a band.
+	pub insertion_cap: Option<InsertionCap>,
+
+	/// Keep a map of the order the seams were inserted in, when
+	/// enlarging, in the carve report.
+	pub track_insertion_order: bool,
+
+	/// Keep the time each stage took for every seam removed in the
+	/// carve report, as well as the totals, to see where the time goes
+	/// as the image shrinks.
+	pub track_seam_timings: bool,
+
+	/// The most seams an enlargement may insert in one round, as a
+	/// fraction of the image's width or height at the time; half, if
+	/// not given.  Inserting more than half at once forces the seams
+	/// through everything, including what matters, so larger
+	/// fractions are taken as a half.  The rest are inserted in
+	/// further rounds.
+	pub insertion_ratio: Option<f64>,
+
+	/// How the pixels of inserted seams are made: the average of the
+	/// seam's pixel and its neighbor, unless this asks otherwise.
+	pub synthesis: Synthesis,
+
+	/// Carve only this fraction of the change in size, and scale the
+	/// carved image the rest of the way.  Carving alone wears an image
+	/// badly over a large change, and scaling alone squashes what
+	/// matters in it; half and half is a good start for thumbnails.
+	/// The report and events describe the carve, not the scaling.
+	pub carve_fraction: Option<f64>,
+
+	/// How far the carve may shrink the image; see `CarveLimits`.
+	pub limits: CarveLimits,
+
+	/// Carve past the limits anyway.
+	pub allow_extreme: bool,
+
+	/// The most pixels an image may have for the carve to take it as
+	/// it is.  A server carving whatever it's sent should set this:
+	/// a small file can decode to an enormous image.
+	pub max_pixels: Option<u64>,
+
+	/// What to do with an image over `max_pixels`.  Anything but
+	/// refusing it is noted in the report's warnings.
+	pub oversize: OversizePolicy,
+
+	/// Take each seam removed at random from among the nearly
+	/// cheapest, rather than always the cheapest, to spread the damage
+	/// over a textured region instead of wearing a channel through
+	/// it.  Seams found in a corridor, with a constrained slope, by
+	/// the greedy strategy, or by backward energy are always the
+	/// cheapest.
+	pub near_optimal: Option<NearOptimal>,
+
+	/// Confine the carve to a rectangle of the image, leaving the
+	/// rest alone.
+	pub region_of_interest: Option<RegionOfInterest>,
+
+	/// The seam finder to use.  Backward energy seams are found from
+	/// scratch every time, and always the cheapest.
+	pub algorithm: Algorithm,
+
+	/// The most threads the carve may use, or as many as there are
+	/// cores.  With the `threaded` feature on, the carve's DP runs on
+	/// a pool of this many threads rather than rayon's global pool;
+	/// without it, the carve runs on one thread whatever this says.
+	pub threads: Option<usize>,
+
+	/// Abandon the carve, with `CarveError::Cancelled`, once this
+	/// token is raised.
+	pub cancel: Option<CancelToken>,
+
+	/// Carve the height of images of at least this many pixels in a
+	/// transposed copy, made once and transposed back at the end,
+	/// rather than following each horizontal seam down the columns of
+	/// the image as it is; `TRANSPOSE_ABOVE`, if not given.  Only carves
+	/// whose seams are patched from one to the next are transposed.
+	pub transpose_above: Option<u64>,
+
+	/// Work out the forward costs of images of at least this many
+	/// pixels on the compute device `any_backend` finds, if there is
+	/// one, tracing and splicing out the seams on the CPU.  Only a
+	/// forward-energy carve with none of the energy options, a region
+	/// budget, a region of interest, or near-optimal seams is sent to
+	/// a device, and its seams are those `AviShaTwo` finds in `f32`.
+	/// If the device fails, the rest of the carve is done on the CPU.
+	/// The report says where the seams were found.  The seams an
+	/// enlargement inserts are always found on the CPU, and every
+	/// carve is, if this isn't given.
+	pub device_above: Option<u64>,
+
+	/// Take up to this many seams out at once, in the runs of seams a
+	/// carve patches from one to the next: the cheapest seam, and the
+	/// cheapest of the rest that keep well clear of it and of each
+	/// other, spliced out together with the costs around each patched
+	/// in parallel.  Each seam after the first is the cheapest before
+	/// any of them is removed, not after, so this trades a little of
+	/// the carve for speed on wide images.  Only forward-energy seams
+	/// not drawn from among the nearly cheapest are taken more than
+	/// one at a time.  With seam timings kept, the time of each pass
+	/// is noted against its first seam.  One at a time, if not given.
+	pub seams_per_pass: Option<u32>,
+}
+
+/// The fewest pixels an image must have for its height to be carved
+/// in a transposed copy, unless the carve's options say otherwise.
+/// Below 512 by 512 the copies cost more than they save; see
+/// `benches/transpose.rs`.
+pub const TRANSPOSE_ABOVE: u64 = 1 << 18;
+
+// Per-carve state that outlives any one seam.
+struct CarveState<'a> {
+	energy: &'a EnergyOptions,
+	maps: FittedMaps,
+	regions: Option<RegionTracker>,
+	report: CarveReport,
+	incremental: bool,
+	cap: Option<InsertionCap>,
+	track_insertion_order: bool,
+	insertion_ratio: f64,
+	synthesis: Synthesis,
+	penalty: Option<TwoDimensionalMap<u32>>,
+	events: Option<&'a mut dyn FnMut(&CarveEvent)>,
+	sampler: Option<Sampler>,
+	roi: Option<RegionOfInterest>,
+	algorithm: Algorithm,
+	cancel: Option<CancelToken>,
+	transpose_above: u64,
+	device: Option<Box<dyn ComputeBackend>>,
+	per_pass: u32,
+}
+
+impl<'a> CarveState<'a> {
+	fn new(
+		options: &'a CarveOptions,
+		width: u32,
+		height: u32,
+		events: &'a mut dyn FnMut(&CarveEvent),
+	) -> Self {
+		CarveState {
+			energy: &options.energy,
+			maps: options.energy.fitted(width, height),
+			regions: options.region_budget.map(RegionTracker::new),
+			report: CarveReport {
+				removed_colors: cq!(
+					options.track_removed_colors,
+					Some(ColorHistogram::new()),
+					None
+				),
+				seam_timings: cq!(options.track_seam_timings, Some(Vec::new()), None),
+				..CarveReport::default()
+			},
+			incremental: (options.algorithm == Algorithm::Forward
+				|| options.energy.normalization == Normalization::None)
+				&& options.region_budget.is_none()
+				&& options.energy.is_local(),
+			cap: options.insertion_cap,
+			track_insertion_order: options.track_insertion_order,
+			insertion_ratio: options.insertion_ratio.map_or(0.5, |r| r.clamp(0.0, 0.5)),
+			synthesis: options.synthesis,
+			penalty: None,
+			events: Some(events),
+			sampler: options.near_optimal.map(Sampler::new),
+			roi: options.region_of_interest,
+			algorithm: options.algorithm,
+			cancel: options.cancel.clone(),
+			transpose_above: options.transpose_above.unwrap_or(TRANSPOSE_ABOVE),
+			device: None,
+			per_pass: options.seams_per_pass.map_or(1, |n| std::cmp::max(n, 1)),
+		}
+	}
+
+	// Carve on the CPU from here on, the device having failed.
+	fn fall_back(&mut self) {
+		self.device = None;
+		self.report.backend = Backend::Cpu;
+		self.report
+			.warnings
+			.push("the compute device failed, so the carve went on on the CPU".to_string());
+	}
+
+	// Stop the carve if it's been cancelled.
+	fn check(&self) -> Result<(), CarveError> {
+		match &self.cancel {
+			Some(cancel) if cancel.is_cancelled() => Err(CarveError::Cancelled),
+			_ => Ok(()),
+		}
+	}
+
+	fn emit(&mut self, event: CarveEvent) {
+		if let Some(events) = &mut self.events {
+			events(&event);
+		}
+	}
+
+	fn energy_options(&self, width: u32, height: u32, direction: Carve) -> EnergyOptions {
+		let options = self.energy.clone().with_fitted(self.maps.clone());
+		let options = match &self.regions {
+			Some(regions) => options.penalty(regions.penalties(width, height, direction)),
+			None => options,
+		};
+		let options = match &self.roi {
+			Some(roi) => options.penalty(roi.walls(width, height, direction)),
+			None => options,
+		};
+		match &self.penalty {
+			Some(penalty) => options.penalty(penalty.clone()),
+			None => options,
+		}
+	}
+
+	// Note the time each stage took for a seam, from the timings taken
+	// before it was found, if the carve is keeping them.
+	fn timed(&mut self, before: StageTimings) {
+		let timings = self.report.timings.since(&before);
+		if let Some(seams) = &mut self.report.seam_timings {
+			seams.push(timings);
+		}
+	}
+
+	fn record<I, P, S>(&mut self, image: &I, seam: &[u32], direction: Carve)
+	where
+		I: GenericImageView<Pixel = P>,
+		P: Pixel<Subpixel = S> + 'static,
+		S: Primitive + 'static,
+	{
+		let (width, height) = image.dimensions();
+		match direction {
+			Carve::Width => self.report.width_seams += 1,
+			Carve::Height => self.report.height_seams += 1,
+		}
+		self.report
+			.seams
+			.push(direction.orientation(), seam.to_vec());
+		self.emit(CarveEvent::Removed {
+			orientation: direction.orientation(),
+			path: seam.to_vec(),
+		});
+		if let Some(removed) = &mut self.report.removed_colors {
+			for (i, at) in seam.iter().enumerate() {
+				let (x, y) = cq!(direction == Carve::Width, (*at, i as u32), (i as u32, *at));
+				removed.add(&image.get_pixel(x, y));
+			}
+		}
+		if let Some(regions) = &mut self.regions {
+			regions.record(seam, width, height, direction);
+		}
+		self.carve_maps(seam, direction);
+	}
+
+	// Remove a seam from the fitted maps, and the region of interest,
+	// keeping them registered with the image it was removed from.
+	fn carve_maps(&mut self, seam: &[u32], direction: Carve) {
+		if let Some(roi) = &mut self.roi {
+			match direction {
+				Carve::Width => roi.width -= 1,
+				Carve::Height => roi.height -= 1,
+			}
+		}
+		let maps = &mut self.maps;
+		maps.importance = maps
+			.importance
+			.take()
+			.map(|m| remove_seam_in_place(m, seam, direction));
+		maps.protection = maps
+			.protection
+			.take()
+			.map(|m| remove_seam_in_place(m, seam, direction));
+		maps.removal = maps
+			.removal
+			.take()
+			.map(|m| remove_seam_in_place(m, seam, direction));
+		maps.damping = maps
+			.damping
+			.take()
+			.map(|m| remove_seam_in_place(m, seam, direction));
+	}
+}
+
+fn find_seam<I, P, S>(image: &I, direction: Carve, state: &mut CarveState) -> Seam
+where
+	I: GenericImageView<Pixel = P>,
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	// A float image's seams are found over float energies.  Backward
+	// energies are otherwise kept in u64, where those of a 16-bit
+	// image, and the walls above them, have room.
+	if is_float::<S>() {
+		find_seam_in::<I, P, S, FloatEnergy, FloatEnergy>(image, direction, state)
 	} else {
-		let carver = AviShaTwo::new(image);
-		let seam = carver.find_vertical_seam();
-		println!("{:?}", seam);
-		remove_vertical_seam(image, &seam)
+		find_seam_in::<I, P, S, u64, u32>(image, direction, state)
 	}
 }
 
+// Find the seam with the backward energy in `B`, or the forward energy
+// in `F`, as the carve's algorithm has it.
+fn find_seam_in<I, P, S, B, F>(image: &I, direction: Carve, state: &mut CarveState) -> Seam
+where
+	I: GenericImageView<Pixel = P>,
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+	B: EnergyScalar,
+	F: EnergyScalar,
+{
+	if let Some(device) = state.device.as_deref() {
+		if state.penalty.is_none() {
+			let start = Instant::now();
+			let finder = DeviceSeamFinder::new(image, device);
+			let seam = finder.try_find_seam(direction.orientation());
+			state.report.timings.dp += start.elapsed();
+			match seam {
+				Some(seam) => return seam,
+				None => state.fall_back(),
+			}
+		}
+	}
+	let (width, height) = image.dimensions();
+	let options = state.energy_options(width, height, direction);
+	if state.algorithm == Algorithm::Backward {
+		return backward_seam::<I, P, S, B>(image, direction, options, &mut state.report.timings);
+	}
+	let carver = AviShaTwo::new(image)
+		.with_options(options)
+		.sampled(state.sampler.as_mut().map(Sampler::draw))
+		.cancelled_by(state.cancel.clone())
+		.in_domain::<F>();
+	let seam = match direction {
+		Carve::Width => carver.find_vertical_seam(),
+		Carve::Height => carver.find_horizontal_seam(),
+	};
+	state.report.timings.add(&carver.timings());
+	seam
+}
+
+// The seam the backward-energy finder finds in `B`, its time spent
+// added to the timings.
+fn backward_seam<I, P, S, B>(
+	image: &I,
+	direction: Carve,
+	options: EnergyOptions,
+	timings: &mut StageTimings,
+) -> Seam
+where
+	I: GenericImageView<Pixel = P>,
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+	B: EnergyScalar,
+{
+	let start = Instant::now();
+	let carver = AviShaOne::new(image).with_options(options).in_domain::<B>();
+	let seam = match direction {
+		Carve::Width => carver.find_vertical_seam(),
+		Carve::Height => carver.find_horizontal_seam(),
+	};
+	timings.dp += start.elapsed();
+	seam
+}
+
+// The cheapest seam in one direction by the given algorithm, in the
+// domain `find_seam` would find it in, but with none of a carve's
+// sampling or cancelling.  The time spent is added to the timings.
+pub(crate) fn cheapest_seam<I, P, S>(
+	image: &I,
+	direction: Carve,
+	algorithm: Algorithm,
+	options: EnergyOptions,
+	timings: &mut StageTimings,
+) -> Seam
+where
+	I: GenericImageView<Pixel = P>,
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	if is_float::<S>() {
+		cheapest_seam_in::<I, P, S, FloatEnergy, FloatEnergy>(
+			image, direction, algorithm, options, timings,
+		)
+	} else {
+		cheapest_seam_in::<I, P, S, u64, u32>(image, direction, algorithm, options, timings)
+	}
+}
+
+fn cheapest_seam_in<I, P, S, B, F>(
+	image: &I,
+	direction: Carve,
+	algorithm: Algorithm,
+	options: EnergyOptions,
+	timings: &mut StageTimings,
+) -> Seam
+where
+	I: GenericImageView<Pixel = P>,
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+	B: EnergyScalar,
+	F: EnergyScalar,
+{
+	if algorithm == Algorithm::Backward {
+		return backward_seam::<I, P, S, B>(image, direction, options, timings);
+	}
+	let carver = AviShaTwo::new(image).with_options(options).in_domain::<F>();
+	let seam = match direction {
+		Carve::Width => carver.find_vertical_seam(),
+		Carve::Height => carver.find_horizontal_seam(),
+	};
+	timings.add(&carver.timings());
+	seam
+}
+
+// Splice a seam out of the image, in place, timing it.
+fn splice<P, S>(
+	image: ImageBuffer<P, Vec<S>>,
+	seam: &[u32],
+	direction: Carve,
+	state: &mut CarveState,
+) -> ImageBuffer<P, Vec<S>>
+where
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	let start = Instant::now();
+	let carved = remove_seam_in_place(image, seam, direction);
+	state.report.timings.splice += start.elapsed();
+	carved
+}
+
+fn carveonce<P, S>(
+	image: ImageBuffer<P, Vec<S>>,
+	direction: Carve,
+	state: &mut CarveState,
+) -> ImageBuffer<P, Vec<S>>
+where
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	let before = state.report.timings;
+	let seam = find_seam(&image, direction, state);
+	state.record(&image, &seam, direction);
+	let carved = splice(image, &seam, direction, state);
+	state.timed(before);
+	carved
+}
+
+// The forward-energy costs are kept in u32's summing type.
+type Cost = <u32 as EnergyScalar>::Sum;
+
+// Hand the adjustments for the image to `f`, asked about pixels in
+// the coordinates of the luma plane seams are found in: transposed,
+// for horizontal seams.
+fn with_adjust<I, P, S, R>(
+	image: &I,
+	direction: Carve,
+	state: &CarveState,
+	f: impl FnOnce(&Adjust<Cost>) -> R,
+) -> R
+where
+	I: GenericImageView<Pixel = P>,
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	let (width, height) = image.dimensions();
+	let options = state.energy_options(width, height, direction);
+	let adjustments = options.fit(image);
+	match direction {
+		Carve::Width => f(&|x, y, e| adjustments.adjust(x, y, e)),
+		Carve::Height => f(&|x, y, e| adjustments.adjust(y, x, e)),
+	}
+}
+
+// A table kept from one seam to the next: the forward costs, or the
+// backward energies, as the carve's algorithm has it.
+enum Patched {
+	Forward(ForwardTable<u32>),
+	Backward(EnergyTable<Cost>),
+}
+
+// Carve seams in one direction for as long as `left`, given the
+// image's width and height, says there are seams left to carve.  When
+// the carve allows it the seams are found in a table kept from the
+// last seam and patched around it, as many at once as the carve's
+// options and the seams left allow, and are taken out of a lattice of
+// indices into the image rather than the image itself, which is
+// materialized once when the run is over.  Otherwise each seam is
+// found afresh and spliced out.
+fn carve_run<P, S>(
+	image: ImageBuffer<P, Vec<S>>,
+	direction: Carve,
+	state: &mut CarveState,
+	left: impl Fn(&CarveState, u32, u32) -> u32,
+) -> Result<ImageBuffer<P, Vec<S>>, CarveError>
+where
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	// The patched table is kept in u32, which would quantize a float
+	// image's energies.  A device works out the costs afresh for each
+	// seam.
+	if !state.incremental || is_float::<S>() || state.device.is_some() {
+		let mut image = image;
+		while left(state, image.width(), image.height()) > 0 {
+			state.check()?;
+			image = carveonce(image, direction, state);
+		}
+		return Ok(image);
+	}
+	let (width, height) = image.dimensions();
+	let mut lattice = match direction {
+		Carve::Height if u64::from(width) * u64::from(height) >= state.transpose_above => {
+			let start = Instant::now();
+			let lattice = SeamLattice::transposed(image);
+			state.report.timings.splice += start.elapsed();
+			lattice
+		}
+		_ => SeamLattice::new(image, direction),
+	};
+	let (mut table, mut buffers) = (None, CarveScratch::new());
+	loop {
+		let count = left(state, lattice.width(), lattice.height());
+		if count == 0 {
+			break;
+		}
+		state.check()?;
+		let count = std::cmp::min(count, state.per_pass);
+		carve_patched(&mut lattice, state, &mut table, &mut buffers, count);
+	}
+	let start = Instant::now();
+	let carved = lattice.materialize();
+	state.report.timings.splice += start.elapsed();
+	Ok(carved)
+}
+
+// Find up to `count` seams in the patched table, starting it on the
+// first seam of a run, and take them out of the lattice and the table.
+// The buffers are kept by the run, to find each seam in.
+fn carve_patched<P, S>(
+	lattice: &mut SeamLattice<P, S>,
+	state: &mut CarveState,
+	table: &mut Option<Patched>,
+	buffers: &mut CarveScratch<Cost>,
+	count: u32,
+) where
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	let (direction, before) = (lattice.direction(), state.report.timings);
+	let table = match table {
+		Some(table) => table,
+		None => {
+			let start = Instant::now();
+			let lumas = match state.energy.smooth(lattice) {
+				Some(smoothed) => luma_plane(&smoothed),
+				None => luma_plane(lattice),
+			};
+			let lumas = cq!(direction == Carve::Width, lumas, lumas.transposed());
+			let max = luma_max::<S>();
+			if state.algorithm == Algorithm::Backward {
+				let fresh = EnergyTable::new(lumas, max, state.energy.border);
+				state.report.timings.energy += start.elapsed();
+				table.get_or_insert(Patched::Backward(fresh))
+			} else {
+				state.report.timings.energy += start.elapsed();
+				let start = Instant::now();
+				let fresh = with_adjust(lattice, direction, state, |adjust| {
+					let cancel = state.cancel.as_ref();
+					ForwardTable::new(lumas, max, state.energy.ties, adjust, cancel)
+				});
+				state.report.timings.dp += start.elapsed();
+				table.get_or_insert(Patched::Forward(fresh))
+			}
+		}
+	};
+
+	let start = Instant::now();
+	let seams = match table {
+		Patched::Forward(table) => {
+			let pick = state.sampler.as_mut().map(Sampler::draw);
+			let seams = match pick {
+				None if count > 1 => table.seams(count as usize),
+				_ => vec![table.seam(pick)],
+			};
+			state.report.timings.traceback += start.elapsed();
+			seams
+		}
+		Patched::Backward(table) => {
+			let ties = state.energy.ties;
+			let seam = with_adjust(lattice, direction, state, |adjust| {
+				table.seam(ties, adjust, buffers)
+			});
+			state.report.timings.dp += start.elapsed();
+			vec![seam]
+		}
+	};
+	// Taken out right to left, each seam is still in the columns it
+	// was found in.
+	for seam in seams.iter().rev() {
+		state.record(lattice, seam, direction);
+	}
+	let seams: Vec<&[u32]> = seams.iter().map(|seam| &seam[..]).collect();
+	let start = Instant::now();
+	if seams.len() == 1 {
+		lattice.remove(seams[0]);
+	} else {
+		lattice.remove_all(&seams);
+	}
+	state.report.timings.splice += start.elapsed();
+	let start = Instant::now();
+	match table {
+		Patched::Forward(table) => {
+			with_adjust(lattice, direction, state, |adjust| {
+				if seams.len() == 1 {
+					table.remove(seams[0], adjust);
+				} else {
+					table.remove_all(&seams, adjust);
+				}
+			});
+			state.report.timings.dp += start.elapsed();
+		}
+		Patched::Backward(table) => {
+			table.remove(seams[0]);
+			state.report.timings.energy += start.elapsed();
+		}
+	}
+	// The pass's time is noted against its first seam.
+	state.timed(before);
+	let after = state.report.timings;
+	for _ in 1..seams.len() {
+		state.timed(after);
+	}
+}
+
+// Seam insertion, after Avidan & Shamir: find the `count` cheapest
+// seams by carving them out of a copy of the image one at a time,
+// keeping track of where in the original each remaining pixel came
+// from.  Inserting the seams found one at a time instead would find
+// the same seam over and over, since the duplicate is as cheap as the
+// original.  Under an insertion cap, the positions in full windows
+// are walled off from later seams.  Returns, for every row (or
+// column) of the image, the positions the seams passed through,
+// sorted, each with the number of the seam that passed through it.
+fn seams_to_insert<P, S>(
+	image: &ImageBuffer<P, Vec<S>>,
+	count: u32,
+	direction: Carve,
+	state: &mut CarveState,
+) -> Result<Vec<Vec<(u32, u32)>>, CarveError>
+where
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	let (width, height) = image.dimensions();
+	let mut search = CarveState {
+		energy: state.energy,
+		maps: state.maps.clone(),
+		regions: None,
+		report: CarveReport::default(),
+		incremental: false,
+		cap: None,
+		track_insertion_order: false,
+		insertion_ratio: state.insertion_ratio,
+		synthesis: state.synthesis,
+		penalty: None,
+		events: None,
+		sampler: None,
+		roi: state.roi,
+		algorithm: state.algorithm,
+		cancel: state.cancel.clone(),
+		transpose_above: state.transpose_above,
+		device: None,
+		per_pass: 1,
+	};
+	let mut scratch = image.clone();
+	let mut origin: Origins = ImageBuffer::from_fn(width, height, |x, y| {
+		Luma([cq!(direction == Carve::Width, x, y)])
+	});
+	let (lines, length) = cq!(direction == Carve::Width, (height, width), (width, height));
+	let mut positions = vec![Vec::with_capacity(count as usize); lines as usize];
+	let mut tracker = state
+		.cap
+		.map(|cap| InsertionTracker::new(cap, lines, length));
+	for number in 0..count {
+		search.check()?;
+		if let Some(tracker) = &tracker {
+			search.penalty = Some(tracker.penalties(&origin, direction));
+		}
+		let seam = find_seam(&scratch, direction, &mut search);
+		for (line, at) in seam.iter().enumerate() {
+			let (x, y) = cq!(
+				direction == Carve::Width,
+				(*at, line as u32),
+				(line as u32, *at)
+			);
+			let position = origin[(x, y)][0];
+			positions[line].push((position, number));
+			if let Some(tracker) = &mut tracker {
+				tracker.record(line, position);
+			}
+		}
+		search.carve_maps(&seam, direction);
+		scratch = splice(scratch, &seam, direction, &mut search);
+		origin = remove_seam_in_place(origin, &seam, direction);
+	}
+	for line in positions.iter_mut() {
+		line.sort();
+	}
+	state.report.timings.add(&search.report.timings);
+	Ok(positions)
+}
+
+// Duplicate the pixel at each of the given positions, making the
+// duplicate the average of the pixel and its neighbor on the far side
+// of the seam.
+fn insert_seams<P, S>(
+	image: &ImageBuffer<P, Vec<S>>,
+	positions: &[Vec<(u32, u32)>],
+	direction: Carve,
+) -> ImageBuffer<P, Vec<S>>
+where
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	insert_seams_with(image, positions, direction, blend)
+}
+
+// As `insert_seams`, making each duplicate with `mix` from the pixel
+// and its neighbor.
+pub(crate) fn insert_seams_with<P, S>(
+	image: &ImageBuffer<P, Vec<S>>,
+	positions: &[Vec<(u32, u32)>],
+	direction: Carve,
+	mix: impl Fn(&P, &P) -> P,
+) -> ImageBuffer<P, Vec<S>>
+where
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	let (width, height) = image.dimensions();
+	let count = positions.first().map_or(0, |line| line.len() as u32);
+	let (newwidth, newheight, length) = match direction {
+		Carve::Width => (width + count, height, width),
+		Carve::Height => (width, height + count, height),
+	};
+	let mut imgbuf = ImageBuffer::new(newwidth, newheight);
+	for (line, inserts) in positions.iter().enumerate() {
+		let at = |i: u32| {
+			cq!(
+				direction == Carve::Width,
+				(i, line as u32),
+				(line as u32, i)
+			)
+		};
+		let mut inserts = inserts.iter().peekable();
+		let mut out = 0;
+		for i in 0..length {
+			let pixel = image[at(i)];
+			imgbuf[at(out)] = pixel;
+			out += 1;
+			if inserts.peek().map(|(at, _)| *at) == Some(i) {
+				inserts.next();
+				let neighbor = image[at(std::cmp::min(i + 1, length - 1))];
+				imgbuf[at(out)] = mix(&pixel, &neighbor);
+				out += 1;
+			}
+		}
+	}
+	imgbuf
+}
+
+// Grow the map of the order seams were inserted in along with the
+// image, numbering the pixels of the new seams after the `numbered`
+// seams already inserted.
+fn number_insertions(
+	order: &Origins,
+	positions: &[Vec<(u32, u32)>],
+	direction: Carve,
+	numbered: u32,
+) -> Origins {
+	let mut grown = insert_seams(order, positions, direction);
+	for (line, inserts) in positions.iter().enumerate() {
+		for (shift, (at, number)) in inserts.iter().enumerate() {
+			let inserted = at + shift as u32 + 1;
+			let point = cq!(
+				direction == Carve::Width,
+				(inserted, line as u32),
+				(line as u32, inserted)
+			);
+			grown[point] = Luma([numbered + number + 1]);
+		}
+	}
+	grown
+}
+
+// Grow the image to the target size in one direction.  No more than
+// the insertion ratio (half, unless the options ask for less) of the
+// image's current extent is inserted in a round, so that the seams
+// found for insertion aren't forced through everything, including
+// what matters.
+fn enlarge<P, S>(
+	mut image: ImageBuffer<P, Vec<S>>,
+	target: u32,
+	direction: Carve,
+	state: &mut CarveState,
+) -> Result<ImageBuffer<P, Vec<S>>, CarveError>
+where
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	let extent = |image: &ImageBuffer<P, Vec<S>>| {
+		cq!(direction == Carve::Width, image.width(), image.height())
+	};
+	while extent(&image) < target {
+		let round = (extent(&image) as f64 * state.insertion_ratio) as u32;
+		let count = std::cmp::min(target - extent(&image), std::cmp::max(1, round));
+		let positions = seams_to_insert(&image, count, direction, state)?;
+		if state.track_insertion_order {
+			let report = &mut state.report;
+			let order = report
+				.insertion_order
+				.take()
+				.unwrap_or_else(|| ImageBuffer::new(image.width(), image.height()));
+			let numbered = report.width_seams_inserted + report.height_seams_inserted;
+			report.insertion_order =
+				Some(number_insertions(&order, &positions, direction, numbered));
+		}
+		state.emit(CarveEvent::Inserted {
+			orientation: direction.orientation(),
+			positions: positions
+				.iter()
+				.map(|line| line.iter().map(|(at, _)| *at).collect())
+				.collect(),
+		});
+		let start = Instant::now();
+		let synthesis = state.synthesis;
+		image = insert_seams_with(&image, &positions, direction, |pixel, neighbor| {
+			synthesis.synthesize(pixel, neighbor)
+		});
+		state.report.timings.splice += start.elapsed();
+		match direction {
+			Carve::Width => state.report.width_seams_inserted += count,
+			Carve::Height => state.report.height_seams_inserted += count,
+		}
+		if let Some(roi) = &mut state.roi {
+			match direction {
+				Carve::Width => roi.width += count,
+				Carve::Height => roi.height += count,
+			}
+		}
+		// The inserted seams have no counterpart in the fitted maps, so
+		// they are stretched over the enlarged image instead.
+		state.maps = state.energy.fitted(image.width(), image.height());
+	}
+	Ok(image)
+}
+
 /// Given an image and a desired new width and height, repeatedly carve
-/// seams out of the image.  This is absurdly inefficient, as the
-/// entire energy map and energy seam digraph is recalculated every
-/// time.  It should be possible to find the span of columns or rows
-/// affected by the carve and recalculate only the new ones.
+/// seams out of the image, or insert them into it to enlarge it.
+/// Between seams in the same direction only the costs around the
+/// last seam removed are recalculated, unless the energy options or a
+/// region budget look at the image as a whole, in which case the
+/// entire cost map is recalculated every time.
+///
+/// When both dimensions shrink, the seams come out in the order the
+/// transport map finds cheapest, unless the map would take more than
+/// `TRANSPORT_MAP_LIMIT` work, in which case each step greedily takes
+/// the cheaper of the next horizontal and vertical seam.
+///
+/// Asking for the image's own dimensions is always safe and cheap:
+/// the result is an exact copy, and no energy is calculated.  Asking
+/// for a width or height of zero, or carving an empty image, is a
+/// `CarveError`.
 pub fn seamcarve<I, P, S>(
 	image: &I,
 	newwidth: u32,
 	newheight: u32,
-) -> Result<ImageBuffer<P, Vec<S>>, String>
+) -> Result<ImageBuffer<P, Vec<S>>, CarveError>
+where
+	I: GenericImageView<Pixel = P>,
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	seamcarve_with_options(image, newwidth, newheight, &CarveOptions::default())
+}
+
+/// As `seamcarve`, with the carve governed by the given options.
+pub fn seamcarve_with_options<I, P, S>(
+	image: &I,
+	newwidth: u32,
+	newheight: u32,
+	options: &CarveOptions,
+) -> Result<ImageBuffer<P, Vec<S>>, CarveError>
+where
+	I: GenericImageView<Pixel = P>,
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	seamcarve_with_report(image, newwidth, newheight, options).map(|(image, _)| image)
+}
+
+/// As `seamcarve_with_options`, returning a report of the carve along
+/// with the carved image.
+pub fn seamcarve_with_report<I, P, S>(
+	image: &I,
+	newwidth: u32,
+	newheight: u32,
+	options: &CarveOptions,
+) -> Result<(ImageBuffer<P, Vec<S>>, CarveReport), CarveError>
+where
+	I: GenericImageView<Pixel = P>,
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	seamcarve_with_events(image, newwidth, newheight, options, &mut |_| {})
+}
+
+/// As `seamcarve_with_report`, telling `events` about every seam as
+/// it's removed or inserted, so that a frontend can show the carve
+/// while it's under way.  A panic in any of the carve's threads is
+/// returned as `CarveError::Worker`.
+pub fn seamcarve_with_events<I, P, S>(
+	image: &I,
+	newwidth: u32,
+	newheight: u32,
+	options: &CarveOptions,
+	events: &mut dyn FnMut(&CarveEvent),
+) -> Result<(ImageBuffer<P, Vec<S>>, CarveReport), CarveError>
+where
+	I: GenericImageView<Pixel = P>,
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	pool::with_threads(options.threads, || {
+		carve_with_events(image, newwidth, newheight, options, events, any_backend)
+	})
+}
+
+fn carve_with_events<I, P, S>(
+	image: &I,
+	newwidth: u32,
+	newheight: u32,
+	options: &CarveOptions,
+	events: &mut dyn FnMut(&CarveEvent),
+	devices: Devices,
+) -> Result<(ImageBuffer<P, Vec<S>>, CarveReport), CarveError>
 where
 	I: GenericImageView<Pixel = P>,
 	P: Pixel<Subpixel = S> + 'static,
 	S: Primitive + 'static,
 {
 	let (mut width, mut height) = image.dimensions();
-	if width < newwidth || height < newheight {
-		return Err("seamcarve cannot upscale an image".to_string());
+	if newwidth == 0 || newheight == 0 {
+		return Err(CarveError::InvalidTargetSize {
+			width: newwidth,
+			height: newheight,
+		});
 	}
-
-	let mut direction = Carve::Width;
+	if width == 0 || height == 0 {
+		return Err(CarveError::ImageTooSmall { width, height });
+	}
+	if !options.allow_extreme {
+		options
+			.limits
+			.check((width, height), (newwidth, newheight))?;
+	}
+	if let Some(roi) = &options.region_of_interest {
+		roi.check((width, height), (newwidth, newheight))?;
+	}
+	if let Some(limit) = options.max_pixels {
+		if width as u64 * height as u64 > limit {
+			return carve_oversized(image, newwidth, newheight, options, events, limit);
+		}
+	}
+	if let Some(fraction) = options.carve_fraction {
+		let fraction = if fraction.is_nan() {
+			1.0
+		} else {
+			fraction.clamp(0.0, 1.0)
+		};
+		let part = |extent: u32, target: u32| {
+			let carved = f64::from(extent) + (f64::from(target) - f64::from(extent)) * fraction;
+			std::cmp::max(1, carved.round() as u32)
+		};
+		let (partwidth, partheight) = (part(width, newwidth), part(height, newheight));
+		let whole = CarveOptions {
+			carve_fraction: None,
+			..options.clone()
+		};
+		let (carved, mut report) =
+			seamcarve_with_events(image, partwidth, partheight, &whole, events)?;
+		if (partwidth, partheight) == (newwidth, newheight) {
+			return Ok((carved, report));
+		}
+		let start = Instant::now();
+		let scaled = imageops::resize(&carved, newwidth, newheight, FilterType::Triangle);
+		report.timings.splice += start.elapsed();
+		return Ok((scaled, report));
+	}
+	events(&CarveEvent::Started {
+		width,
+		height,
+		target_width: newwidth,
+		target_height: newheight,
+	});
+	let start = Instant::now();
 	let mut scratch = ImageBuffer::<P, Vec<S>>::new(width, height);
 	for p in image.pixels() {
-		scratch[(p.0, p.1)] = p.2.clone()
+		scratch[(p.0, p.1)] = p.2
 	}
+	let wrap = start.elapsed();
 
-	while width > newwidth && height > newheight {
-		scratch = carveonce(&scratch, direction);
-		direction = if direction == Carve::Height {
-			Carve::Width
-		} else {
-			Carve::Height
+	// Pipelines call the carver whether or not there's anything to
+	// carve; when there isn't, don't fit any maps or find any energy.
+	if (width, height) == (newwidth, newheight) && !options.energy.removes() {
+		let mut report = CarveReport::default();
+		report.timings.wrap = wrap;
+		if options.track_removed_colors {
+			report.original_colors = Some(ColorHistogram::of_image(image));
+			report.removed_colors = Some(ColorHistogram::new());
+		}
+		events(&CarveEvent::Finished { width, height });
+		return Ok((scratch, report));
+	}
+
+	let mut state = CarveState::new(options, width, height, events);
+	state.report.timings.wrap = wrap;
+	state.device = plan(options, (width, height), devices);
+	if state.device.is_some() {
+		state.report.backend = Backend::Device;
+	}
+	if options.track_removed_colors {
+		state.report.original_colors = Some(ColorHistogram::of_image(image));
+	}
+
+	// An object marked for removal goes first, by whichever seams take
+	// fewer to cross it, and then the carve goes on without the mask.
+	let unmarked = options.energy.without_removal();
+	if let Some(mask) = &state.maps.removal {
+		if let Some((left, top, right, bottom)) = marked(mask) {
+			let direction = cq!(right - left <= bottom - top, Carve::Width, Carve::Height);
+			scratch = carve_run(scratch, direction, &mut state, |state, width, height| {
+				let extent = cq!(direction == Carve::Width, width, height);
+				let marked = state.maps.removal.as_ref().and_then(marked);
+				cq!(marked.is_some() && extent > 1, 1, 0)
+			})?;
+			width = scratch.width();
+			height = scratch.height();
+			if state.maps.removal.as_ref().and_then(marked).is_some() {
+				state
+					.report
+					.warnings
+					.push("the object marked for removal couldn't all be removed".to_string());
+			}
+		}
+	}
+	state.energy = &unmarked;
+	state.maps.removal = None;
+
+	// When the image is to lose both width and height, the order the
+	// seams come out in is settled by the transport map, or greedily.
+	let (columns, rows) = (
+		width.saturating_sub(newwidth),
+		height.saturating_sub(newheight),
+	);
+	match options.strategy.settled((width, height), (columns, rows)) {
+		RetargetStrategy::TransportMap if columns > 0 && rows > 0 => {
+			let order =
+				transport_map(&scratch, columns, rows, state.energy, state.algorithm).order();
+			for orientation in order {
+				state.check()?;
+				let direction = cq!(
+					orientation == Orientation::Vertical,
+					Carve::Width,
+					Carve::Height
+				);
+				scratch = carveonce(scratch, direction, &mut state);
+			}
+		}
+		RetargetStrategy::Greedy => {
+			while width > newwidth && height > newheight {
+				state.check()?;
+				let before = state.report.timings;
+				let mut best = |direction| {
+					let options = state.energy_options(width, height, direction);
+					let timings = &mut state.report.timings;
+					cheapest_seam(&scratch, direction, state.algorithm, options, timings)
+				};
+				let (vertical, horizontal) = (best(Carve::Width), best(Carve::Height));
+				let (direction, seam) = cq!(
+					horizontal.cost() < vertical.cost(),
+					(Carve::Height, horizontal),
+					(Carve::Width, vertical)
+				);
+				state.record(&scratch, &seam, direction);
+				scratch = splice(scratch, &seam, direction, &mut state);
+				state.timed(before);
+				width = scratch.width();
+				height = scratch.height();
+			}
+		}
+		_ => {}
+	}
+
+	scratch = carve_run(scratch, Carve::Width, &mut state, |_, w, _| {
+		w.saturating_sub(newwidth)
+	})?;
+	width = scratch.width();
+
+	scratch = carve_run(scratch, Carve::Height, &mut state, |_, _, h| {
+		h.saturating_sub(newheight)
+	})?;
+	height = scratch.height();
+
+	// Any growing is done after all the shrinking, so that the seams
+	// inserted are found in the image as it will finally be.
+	if width < newwidth {
+		scratch = enlarge(scratch, newwidth, Carve::Width, &mut state)?;
+	}
+	if height < newheight {
+		scratch = enlarge(scratch, newheight, Carve::Height, &mut state)?;
+	}
+
+	state.emit(CarveEvent::Finished {
+		width: scratch.width(),
+		height: scratch.height(),
+	});
+	Ok((scratch, state.report))
+}
+
+// An image over the pixel limit, carved as the oversize policy says,
+// or not at all.  The events are those of the carve actually done, so
+// after downscaling they're in the coordinates of the shrunken image.
+fn carve_oversized<I, P, S>(
+	image: &I,
+	newwidth: u32,
+	newheight: u32,
+	options: &CarveOptions,
+	events: &mut dyn FnMut(&CarveEvent),
+	limit: u64,
+) -> Result<(ImageBuffer<P, Vec<S>>, CarveReport), CarveError>
+where
+	I: GenericImageView<Pixel = P>,
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	let (width, height) = image.dimensions();
+	let pixels = width as u64 * height as u64;
+	let over = format!(
+		"a {}x{} image is {} pixels, over the limit of {}",
+		width, height, pixels, limit
+	);
+	let shrink = (pixels as f64 / std::cmp::max(limit, 1) as f64).sqrt();
+	let mut within = CarveOptions {
+		max_pixels: None,
+		..options.clone()
+	};
+	match options.oversize {
+		OversizePolicy::Refuse => Err(CarveError::TooManyPixels {
+			dimensions: (width, height),
+			limit,
+		}),
+		OversizePolicy::Pyramid => {
+			let factor = std::cmp::max(2, shrink.ceil() as u32);
+			within.energy = within.energy.pyramid(factor, 2 * factor);
+			let (carved, mut report) =
+				seamcarve_with_events(image, newwidth, newheight, &within, events)?;
+			report.warnings.push(format!(
+				"{}; its seams were found at 1/{} scale first",
+				over, factor
+			));
+			Ok((carved, report))
+		}
+		OversizePolicy::Downscale => {
+			let scaled = |extent: u32| std::cmp::max(1, (extent as f64 / shrink) as u32);
+			let small =
+				imageops::resize(image, scaled(width), scaled(height), FilterType::Triangle);
+			let (carved, mut report) = seamcarve_with_events(
+				&small,
+				scaled(newwidth),
+				scaled(newheight),
+				&within,
+				events,
+			)?;
+			report.warnings.push(format!(
+				"{}; it was carved at {}x{} and scaled up to fit",
+				over,
+				small.width(),
+				small.height()
+			));
+			let carved = imageops::resize(&carved, newwidth, newheight, FilterType::Triangle);
+			Ok((carved, report))
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::alpha::AlphaEnergy;
+	use crate::border::BorderPolicy;
+	use crate::palette::Palette;
+	use image::{Rgb, Rgba};
+	use std::time::Duration;
+
+	#[test]
+	fn enlarging_inserts_averaged_seams() {
+		let buf: ImageBuffer<Luma<u8>, Vec<u8>> =
+			ImageBuffer::from_fn(6, 4, |x, y| Luma([((x * 40 + y * 7) % 250) as u8]));
+		let grown = seamcarve(&buf, 7, 4).unwrap();
+		assert_eq!(grown.dimensions(), (7, 4));
+		for y in 0..4 {
+			// Taking the inserted pixel back out leaves the original
+			// row, and it averages its neighbors in the original.
+			let row: Vec<u8> = (0..7).map(|x| grown[(x, y)][0]).collect();
+			let original: Vec<u8> = (0..6).map(|x| buf[(x, y)][0]).collect();
+			let found = (0..6).any(|k| {
+				let mut carved = row.clone();
+				let inserted = carved.remove(k + 1);
+				let next = original[std::cmp::min(k + 1, 5)];
+				carved == original && inserted == ((original[k] as u32 + next as u32) / 2) as u8
+			});
+			assert!(found, "row {}: {:?}", y, row);
+		}
+	}
+
+	#[test]
+	fn enlarging_past_double_takes_several_rounds() {
+		let buf: ImageBuffer<Luma<u8>, Vec<u8>> =
+			ImageBuffer::from_fn(4, 3, |x, y| Luma([(x * 50 + y) as u8]));
+		let (grown, report) = seamcarve_with_report(&buf, 11, 7, &CarveOptions::default()).unwrap();
+		assert_eq!(grown.dimensions(), (11, 7));
+		assert_eq!(
+			(report.width_seams_inserted, report.height_seams_inserted),
+			(7, 4)
+		);
+		assert_eq!(report.width_seams, 0);
+
+		// A smaller insertion ratio takes more, smaller rounds.
+		let wide: ImageBuffer<Luma<u8>, Vec<u8>> =
+			ImageBuffer::from_fn(10, 3, |x, y| Luma([(x * 25 + y) as u8]));
+		let rounds = |insertion_ratio| {
+			let options = CarveOptions {
+				insertion_ratio,
+				..CarveOptions::default()
+			};
+			let mut rounds = Vec::new();
+			seamcarve_with_events(&wide, 16, 3, &options, &mut |event| {
+				if let CarveEvent::Inserted { positions, .. } = event {
+					rounds.push(positions[0].len());
+				}
+			})
+			.unwrap();
+			rounds
 		};
-		width = scratch.width();
-		height = scratch.height();
-		println!("B: {}, {}", width, height);
+		assert_eq!(rounds(None), vec![5, 1]);
+		assert_eq!(rounds(Some(0.9)), vec![5, 1]);
+		assert_eq!(rounds(Some(0.2)), vec![2, 2, 2]);
 	}
 
-	while width > newwidth {
-		scratch = carveonce(&scratch, Carve::Width);
-		width = scratch.width();
-		println!("W: {}, {}", width, height);
+	#[test]
+	fn greedy_retargeting_meets_both_targets() {
+		let buf: ImageBuffer<Luma<u8>, Vec<u8>> =
+			ImageBuffer::from_fn(10, 8, |x, y| Luma([((x * x * 11 + y * 23) % 256) as u8]));
+		let options = CarveOptions {
+			strategy: RetargetStrategy::Greedy,
+			..CarveOptions::default()
+		};
+		let (carved, report) = seamcarve_with_report(&buf, 7, 6, &options).unwrap();
+		assert_eq!(carved.dimensions(), (7, 6));
+		assert_eq!((report.width_seams, report.height_seams), (3, 2));
+	}
+
+	#[test]
+	fn transport_map_retargeting_meets_both_targets() {
+		let buf: ImageBuffer<Luma<u8>, Vec<u8>> =
+			ImageBuffer::from_fn(10, 8, |x, y| Luma([((x * x * 11 + y * 23) % 256) as u8]));
+		let options = CarveOptions {
+			strategy: RetargetStrategy::TransportMap,
+			..CarveOptions::default()
+		};
+		let (carved, report) = seamcarve_with_report(&buf, 7, 6, &options).unwrap();
+		assert_eq!(carved.dimensions(), (7, 6));
+		assert_eq!((report.width_seams, report.height_seams), (3, 2));
 	}
 
-	while height > newheight {
-		scratch = carveonce(&scratch, Carve::Height);
-		height = scratch.height();
-		println!("H: {}, {}", width, height);
+	#[test]
+	fn retargeting_finds_seams_by_the_carve_algorithm() {
+		type Gray = ImageBuffer<Luma<u8>, Vec<u8>>;
+		let buf: Gray =
+			ImageBuffer::from_fn(10, 8, |x, y| Luma([((x * x * 11 + y * 23) % 256) as u8]));
+		let backward = |image: &Gray, direction| {
+			let finder = AviShaOne::new(image).in_domain::<u64>();
+			match direction {
+				Carve::Width => finder.find_vertical_seam(),
+				Carve::Height => finder.find_horizontal_seam(),
+			}
+		};
+		let carve = |mut image: Gray, direction| {
+			let seam = backward(&image, direction);
+			image = remove_seam(&image, &seam, direction);
+			image
+		};
+		let finish = |mut image: Gray| {
+			while image.width() > 7 {
+				image = carve(image, Carve::Width);
+			}
+			while image.height() > 6 {
+				image = carve(image, Carve::Height);
+			}
+			image
+		};
+
+		// Greedily, the cheaper of AviShaOne's seams each way.
+		let mut greedy = buf.clone();
+		while greedy.width() > 7 && greedy.height() > 6 {
+			let (vertical, horizontal) = (
+				backward(&greedy, Carve::Width),
+				backward(&greedy, Carve::Height),
+			);
+			let (direction, seam) = cq!(
+				horizontal.cost() < vertical.cost(),
+				(Carve::Height, horizontal),
+				(Carve::Width, vertical)
+			);
+			greedy = remove_seam(&greedy, &seam, direction);
+		}
+		let greedy = finish(greedy);
+
+		// And in the order of a transport map of AviShaOne's seams.
+		let map = transport_map(&buf, 3, 2, &EnergyOptions::default(), Algorithm::Backward);
+		let mut ordered = buf.clone();
+		for orientation in map.order() {
+			let direction = cq!(
+				orientation == Orientation::Vertical,
+				Carve::Width,
+				Carve::Height
+			);
+			ordered = carve(ordered, direction);
+		}
+		let ordered = finish(ordered);
+
+		for (strategy, expected) in [
+			(RetargetStrategy::Greedy, greedy),
+			(RetargetStrategy::TransportMap, ordered),
+		]
+		.iter()
+		{
+			let options = CarveOptions {
+				algorithm: Algorithm::Backward,
+				strategy: *strategy,
+				..CarveOptions::default()
+			};
+			let (carved, _) = seamcarve_with_report(&buf, 7, 6, &options).unwrap();
+			assert_eq!(
+				carved.into_raw(),
+				expected.clone().into_raw(),
+				"{:?}",
+				strategy
+			);
+		}
+	}
+
+	#[test]
+	fn carving_to_the_same_size_is_the_identity() {
+		let buf: ImageBuffer<Luma<u8>, Vec<u8>> =
+			ImageBuffer::from_fn(5, 4, |x, y| Luma([(x * 31 + y * 17) as u8]));
+		let options = CarveOptions {
+			track_removed_colors: true,
+			..CarveOptions::default()
+		};
+		let (carved, report) = seamcarve_with_report(&buf, 5, 4, &options).unwrap();
+		assert_eq!(carved.into_raw(), buf.into_raw());
+		assert!(report.seams.is_empty());
+		assert_eq!(report.removed_colors.unwrap().total(), 0);
+		assert_eq!(report.original_colors.unwrap().total(), 20);
+	}
+
+	#[test]
+	fn seams_come_out_in_place_as_they_do_by_copying() {
+		let buf: ImageBuffer<Rgb<u8>, Vec<u8>> = ImageBuffer::from_fn(7, 5, |x, y| {
+			Rgb([(x * 30 + y) as u8, (y * 40) as u8, (x * y) as u8])
+		});
+		for (seam, direction) in [
+			(vec![0, 1, 2, 6, 6], Carve::Width),
+			(vec![4, 3, 0, 0, 1, 2, 4], Carve::Height),
+		]
+		.iter()
+		{
+			let copied = remove_seam(&buf, seam, *direction);
+			let working = buf.clone();
+			let before = working.as_ptr();
+			let compacted = remove_seam_in_place(working, seam, *direction);
+			assert_eq!(compacted.as_ptr(), before, "{:?}", direction);
+			assert_eq!(compacted.dimensions(), copied.dimensions());
+			assert_eq!(compacted.into_raw(), copied.into_raw(), "{:?}", direction);
+		}
+	}
+
+	#[test]
+	fn seams_made_by_hand_can_be_removed_and_inserted() {
+		let buf: ImageBuffer<Luma<u8>, Vec<u8>> =
+			ImageBuffer::from_fn(4, 3, |x, y| Luma([(y * 10 + x) as u8]));
+		let vertical = Seam::new(Orientation::Vertical, vec![1, 2, 3], 0.0, (4, 3)).unwrap();
+		let narrower = remove_vertical_seam(&buf, &vertical).unwrap();
+		assert_eq!(narrower.into_raw(), vec![0, 2, 3, 10, 11, 13, 20, 21, 22]);
+		let horizontal = Seam::new(Orientation::Horizontal, vec![0, 0, 1, 2], 0.0, (4, 3)).unwrap();
+		let shorter = remove_horizontal_seam(&buf, &horizontal).unwrap();
+		assert_eq!(shorter.into_raw(), vec![10, 11, 2, 3, 20, 21, 22, 13]);
+		let reused = remove_seam_into(buf.clone(), &horizontal).unwrap();
+		assert_eq!(reused.into_raw(), vec![10, 11, 2, 3, 20, 21, 22, 13]);
+
+		assert_eq!(
+			remove_horizontal_seam(&buf, &vertical).unwrap_err(),
+			CarveError::SeamMismatch {
+				orientation: Orientation::Vertical,
+				seam: (4, 3),
+				dimensions: (4, 3)
+			}
+		);
+		let narrower = remove_seam(&buf, &[0, 0, 0], Carve::Width);
+		assert!(remove_seam_into(narrower, &vertical).is_err());
+
+		let wider = insert_seam(&buf, &vertical, Synthesis::Average).unwrap();
+		assert_eq!(
+			wider.into_raw(),
+			vec![0, 1, 1, 2, 3, 10, 11, 12, 12, 13, 20, 21, 22, 23, 23]
+		);
+		let taller = insert_seam(&buf, &horizontal, Synthesis::Duplicate).unwrap();
+		assert_eq!(taller.dimensions(), (4, 4));
+		// Taking out the copies gives back the original.
+		let copies = Seam::new(Orientation::Horizontal, vec![1, 1, 2, 3], 0.0, (4, 4)).unwrap();
+		let restored = remove_horizontal_seam(&taller, &copies).unwrap();
+		assert_eq!(restored.into_raw(), buf.clone().into_raw());
+		assert!(insert_seam(&taller, &vertical, Synthesis::Average).is_err());
 	}
 
-	Ok(scratch)
+	#[test]
+	fn the_report_times_every_stage_it_runs() {
+		let buf: ImageBuffer<Luma<u8>, Vec<u8>> =
+			ImageBuffer::from_fn(40, 30, |x, y| Luma([((x * x * 7 + y * 13) % 256) as u8]));
+		let (_, report) = seamcarve_with_report(&buf, 30, 30, &CarveOptions::default()).unwrap();
+		let timings = report.timings;
+		assert!(timings.energy > Duration::from_secs(0));
+		assert!(timings.dp > Duration::from_secs(0));
+		assert!(timings.splice > Duration::from_secs(0));
+		assert_eq!(timings.encode, Duration::from_secs(0));
+		assert!(timings.total() >= timings.dp + timings.energy);
+		assert!(report.seam_timings.is_none());
+
+		for algorithm in [Algorithm::Forward, Algorithm::Backward].iter() {
+			let options = CarveOptions {
+				track_seam_timings: true,
+				algorithm: *algorithm,
+				..CarveOptions::default()
+			};
+			let (_, report) = seamcarve_with_report(&buf, 30, 27, &options).unwrap();
+			let seams = report.seam_timings.unwrap();
+			assert_eq!(seams.len(), 13);
+			let mut summed = StageTimings::default();
+			for seam in seams.iter() {
+				summed.add(seam);
+			}
+			assert!(summed.dp <= report.timings.dp && summed.splice <= report.timings.splice);
+			assert_eq!(summed.wrap, Duration::from_secs(0));
+		}
+	}
+
+	#[test]
+	fn patched_costs_carve_what_recalculated_ones_do() {
+		let buf: ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_fn(16, 12, |x, y| {
+			Luma([((x * x * 7 + y * 13 + x * y) % 256) as u8])
+		});
+		let weights: ImageBuffer<Luma<u8>, Vec<u8>> =
+			ImageBuffer::from_fn(16, 12, |x, _| Luma([cq!(x < 8, 255, 60)]));
+		for algorithm in [Algorithm::Forward, Algorithm::Backward].iter() {
+			let options = CarveOptions {
+				energy: EnergyOptions::new().importance(&weights),
+				algorithm: *algorithm,
+				..CarveOptions::default()
+			};
+			for direction in [Carve::Width, Carve::Height].iter() {
+				let carve = |incremental| {
+					let mut quiet = |_: &CarveEvent| {};
+					let mut state = CarveState::new(&options, 16, 12, &mut quiet);
+					state.incremental = incremental;
+					// Six seams either way.
+					let left = |_: &CarveState, width, height| {
+						cq!(*direction == Carve::Width, width - 10, height - 6)
+					};
+					let image = carve_run(buf.clone(), *direction, &mut state, left).unwrap();
+					(image.into_raw(), state.report.seams)
+				};
+				let what = (algorithm, direction);
+				assert_eq!(carve(true), carve(false), "{:?}", what);
+			}
+		}
+	}
+
+	#[test]
+	fn transposed_carves_match_untransposed_ones() {
+		let buf: ImageBuffer<Rgb<u8>, Vec<u8>> = ImageBuffer::from_fn(14, 11, |x, y| {
+			Rgb([
+				(x * 53 % 256) as u8,
+				(y * 29 % 256) as u8,
+				((x * y * 7) % 256) as u8,
+			])
+		});
+		let carve = |transpose_above| {
+			let options = CarveOptions {
+				transpose_above: Some(transpose_above),
+				track_removed_colors: true,
+				..CarveOptions::default()
+			};
+			let (image, report) = seamcarve_with_report(&buf, 10, 6, &options).unwrap();
+			(image.into_raw(), report.seams, report.removed_colors)
+		};
+		assert_eq!(carve(0), carve(u64::MAX));
+	}
+
+	#[test]
+	fn removals_replayed_from_the_events_give_the_carved_image() {
+		let buf: ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_fn(12, 9, |x, y| {
+			Luma([((x * 53 + y * 29 + x * y * 7) % 256) as u8])
+		});
+		let mut events = Vec::new();
+		let (carved, _) = seamcarve_with_events(&buf, 9, 7, &CarveOptions::default(), &mut |e| {
+			events.push(e.clone())
+		})
+		.unwrap();
+		assert_eq!(
+			events.first(),
+			Some(&CarveEvent::Started {
+				width: 12,
+				height: 9,
+				target_width: 9,
+				target_height: 7,
+			})
+		);
+		assert_eq!(
+			events.last(),
+			Some(&CarveEvent::Finished {
+				width: 9,
+				height: 7
+			})
+		);
+		let mut replayed = buf.clone();
+		for event in &events {
+			if let CarveEvent::Removed { orientation, path } = event {
+				let direction = cq!(
+					*orientation == Orientation::Vertical,
+					Carve::Width,
+					Carve::Height
+				);
+				replayed = remove_seam(&replayed, path, direction);
+			}
+		}
+		assert_eq!(replayed.into_raw(), carved.into_raw());
+	}
+
+	#[test]
+	fn seams_taken_several_at_a_pass_replay_one_at_a_time() {
+		let buf: ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_fn(96, 10, |x, y| {
+			Luma([((x * x * 7 + y * 13 + x * y) % 256) as u8])
+		});
+		let carve = |seams_per_pass| {
+			let options = CarveOptions {
+				seams_per_pass,
+				track_seam_timings: true,
+				..CarveOptions::default()
+			};
+			let mut events = Vec::new();
+			let (carved, report) =
+				seamcarve_with_events(&buf, 70, 10, &options, &mut |e| events.push(e.clone()))
+					.unwrap();
+			(carved, report, events)
+		};
+		let (carved, report, events) = carve(Some(4));
+		assert_eq!(carved.dimensions(), (70, 10));
+		assert_eq!(report.width_seams, 26);
+		assert_eq!(report.seams.len(), 26);
+		assert_eq!(report.seam_timings.unwrap().len(), 26);
+		let mut replayed = buf.clone();
+		for event in &events {
+			if let CarveEvent::Removed { path, .. } = event {
+				replayed = remove_seam(&replayed, path, Carve::Width);
+			}
+		}
+		assert_eq!(replayed.into_raw(), carved.into_raw());
+
+		let (one, _, _) = carve(Some(1));
+		let (default, _, _) = carve(None);
+		assert_eq!(one.into_raw(), default.into_raw());
+	}
+
+	#[test]
+	fn oversized_images_are_refused_or_downgraded() {
+		let buf: ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_fn(16, 12, |x, y| {
+			Luma([((x * 53 + y * 29 + x * y * 7) % 256) as u8])
+		});
+		let limited = |oversize| CarveOptions {
+			max_pixels: Some(60),
+			oversize,
+			..CarveOptions::default()
+		};
+		let refused = seamcarve_with_report(&buf, 12, 12, &limited(OversizePolicy::Refuse));
+		let refused = refused.unwrap_err();
+		assert_eq!(
+			refused,
+			CarveError::TooManyPixels {
+				dimensions: (16, 12),
+				limit: 60
+			}
+		);
+		assert!(refused.to_string().contains("over the limit of 60"));
+		for policy in [OversizePolicy::Pyramid, OversizePolicy::Downscale].iter() {
+			let (carved, report) = seamcarve_with_report(&buf, 12, 12, &limited(*policy)).unwrap();
+			assert_eq!(carved.dimensions(), (12, 12));
+			assert_eq!(report.warnings.len(), 1, "{:?}", policy);
+		}
+		let (_, report) =
+			seamcarve_with_report(&buf, 12, 12, &limited(OversizePolicy::Downscale)).unwrap();
+		assert_eq!(report.width_seams, 2);
+
+		let roomy = CarveOptions {
+			max_pixels: Some(16 * 12),
+			..CarveOptions::default()
+		};
+		assert!(seamcarve_with_report(&buf, 12, 12, &roomy)
+			.unwrap()
+			.1
+			.warnings
+			.is_empty());
+	}
+
+	#[test]
+	fn near_optimal_carves_vary_with_the_seed() {
+		let buf: ImageBuffer<Luma<u8>, Vec<u8>> =
+			ImageBuffer::from_fn(24, 8, |x, y| Luma([((x * 37 + y * 11) % 7 * 30) as u8]));
+		let seams = |near_optimal| {
+			let options = CarveOptions {
+				near_optimal,
+				..CarveOptions::default()
+			};
+			seamcarve_with_report(&buf, 18, 8, &options)
+				.unwrap()
+				.1
+				.seams
+		};
+		let cheapest = seams(None);
+		assert_eq!(
+			seams(Some(NearOptimal::new(0.5, 1))),
+			seams(Some(NearOptimal::new(0.5, 1)))
+		);
+		assert_ne!(seams(Some(NearOptimal::new(0.5, 1))), cheapest);
+		assert_ne!(
+			seams(Some(NearOptimal::new(0.5, 1))),
+			seams(Some(NearOptimal::new(0.5, 2)))
+		);
+	}
+
+	#[test]
+	fn a_marked_object_is_carved_out_and_the_size_restored() {
+		// A checkered patch on a gentler background: left alone, the
+		// carve would go anywhere but through the patch.
+		let inside = |x, y| (5..8).contains(&x) && (3..9).contains(&y);
+		let buf: ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_fn(16, 12, |x, y| {
+			Luma([cq!(
+				inside(x, y),
+				cq!((x + y) % 2 == 0, 255, 0),
+				((x * 7 + y * 5) % 60) as u8
+			)])
+		});
+		let mask: ImageBuffer<Luma<u8>, Vec<u8>> =
+			ImageBuffer::from_fn(16, 12, |x, y| Luma([cq!(inside(x, y), 255, 0)]));
+		let options = CarveOptions {
+			energy: EnergyOptions::new().remove(&mask),
+			..CarveOptions::default()
+		};
+		let (carved, report) = seamcarve_with_report(&buf, 16, 12, &options).unwrap();
+		assert_eq!(carved.dimensions(), (16, 12));
+		assert_eq!((report.width_seams, report.width_seams_inserted), (3, 3));
+		assert_eq!(report.height_seams, 0);
+		assert!(report.warnings.is_empty());
+		for (_, seam) in report.seams.iter() {
+			assert!((3..9).all(|y| seam[y] >= 5 && seam[y] < 8), "{:?}", seam);
+		}
+
+		// Asking for less than what's left carves on as usual.
+		let (carved, report) = seamcarve_with_report(&buf, 10, 12, &options).unwrap();
+		assert_eq!(carved.dimensions(), (10, 12));
+		assert_eq!(report.width_seams, 6);
+	}
+
+	#[test]
+	fn carving_a_region_leaves_the_rest_alone() {
+		let buf: ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_fn(20, 10, |x, y| {
+			Luma([((x * x * 7 + y * 13 + x * y) % 256) as u8])
+		});
+		let options = CarveOptions {
+			region_of_interest: Some(RegionOfInterest::new(6, 2, 8, 5)),
+			..CarveOptions::default()
+		};
+		let (carved, report) = seamcarve_with_report(&buf, 16, 7, &options).unwrap();
+		assert_eq!(carved.dimensions(), (16, 7));
+		for (orientation, seam) in report.seams.iter() {
+			let (start, extent) = cq!(orientation == Orientation::Vertical, (6, 8), (2, 5));
+			assert!(
+				seam.iter().all(|&at| at >= start && at < start + extent),
+				"{:?}",
+				seam
+			);
+		}
+		// The corners outside both the region's columns and its rows
+		// are untouched, though those right of or below it have moved.
+		for (x, y) in [(0, 0), (5, 1), (0, 9), (19, 0), (19, 9)].iter() {
+			let moved = (cq!(*x > 13, x - 4, *x), cq!(*y > 6, y - 3, *y));
+			assert_eq!(carved[moved], buf[(*x, *y)]);
+		}
+
+		for direction in [Carve::Width, Carve::Height].iter() {
+			let carve = |incremental| {
+				let mut quiet = |_: &CarveEvent| {};
+				let mut state = CarveState::new(&options, 20, 10, &mut quiet);
+				state.incremental = incremental;
+				let extent = cq!(*direction == Carve::Width, 20, 10);
+				let left = |_: &CarveState, width, height| {
+					cq!(*direction == Carve::Width, width, height) + 4 - extent
+				};
+				carve_run(buf.clone(), *direction, &mut state, left).unwrap();
+				state.report.seams
+			};
+			assert_eq!(carve(true), carve(false), "{:?}", direction);
+		}
+
+		let cramped = CarveOptions {
+			region_of_interest: Some(RegionOfInterest::new(6, 2, 4, 5)),
+			..CarveOptions::default()
+		};
+		assert!(matches!(
+			seamcarve_with_report(&buf, 16, 10, &cramped),
+			Err(CarveError::RegionTooSmall {
+				target: (16, 10),
+				..
+			})
+		));
+	}
+
+	#[test]
+	fn alpha_comes_through_the_carve() {
+		// Every pixel is unique, and says in its alpha which column it
+		// came from.
+		let buf: ImageBuffer<Rgba<u8>, Vec<u8>> = ImageBuffer::from_fn(10, 6, |x, y| {
+			Rgba([
+				(x * 23 + y * 7) as u8,
+				(y * 40) as u8,
+				(x * y) as u8,
+				(x * 20 + 5) as u8,
+			])
+		});
+		let carved = seamcarve(&buf, 7, 6).unwrap();
+		for y in 0..6 {
+			let columns: Vec<u32> = (0..7)
+				.map(|x| (carved[(x, y)][3] as u32 - 5) / 20)
+				.collect();
+			assert!(columns.windows(2).all(|pair| pair[0] < pair[1]));
+			for (x, column) in columns.iter().enumerate() {
+				assert_eq!(carved[(x as u32, y)], buf[(*column, y)]);
+			}
+		}
+		let grown = seamcarve(&buf, 12, 6).unwrap();
+		assert!(grown.pixels().all(|p| p[3] >= 5 && p[3] <= 185));
+
+		// Hidden color in the transparent left half looks busy until
+		// it's premultiplied away.
+		let sprite: ImageBuffer<Rgba<u8>, Vec<u8>> = ImageBuffer::from_fn(12, 8, |x, y| {
+			if x < 6 {
+				Rgba([((x * 97 + y * 61) % 256) as u8, 0, 0, 0])
+			} else {
+				Rgba([(x * 12 + y * 9) as u8, 90, 90, 255])
+			}
+		});
+		let first_seam = |energy| {
+			let options = CarveOptions {
+				energy,
+				..CarveOptions::default()
+			};
+			let (_, report) = seamcarve_with_report(&sprite, 11, 8, &options).unwrap();
+			let seam = report.seams.iter().next().unwrap().1.to_vec();
+			seam
+		};
+		assert!(first_seam(EnergyOptions::new()).iter().all(|&x| x >= 6));
+		let premultiplied = EnergyOptions::new().alpha(AlphaEnergy::Premultiply);
+		assert!(first_seam(premultiplied).iter().all(|&x| x < 6));
+	}
+
+	#[test]
+	fn sixteen_bit_images_carve_without_overflow() {
+		// The strongest edges a 16-bit image can have, everywhere but
+		// one protected column.
+		let buf: ImageBuffer<Luma<u16>, Vec<u16>> = ImageBuffer::from_fn(12, 10, |x, y| match x {
+			5 => Luma([1234]),
+			_ => Luma([cq!((x / 2 + y / 2) % 2 == 0, 0, 65535)]),
+		});
+		let protection: ImageBuffer<Luma<u8>, Vec<u8>> =
+			ImageBuffer::from_fn(12, 10, |x, _| Luma([cq!(x == 5, 255, 0)]));
+		for algorithm in [Algorithm::Forward, Algorithm::Backward].iter() {
+			let options = CarveOptions {
+				energy: EnergyOptions::new().protect(&protection),
+				algorithm: *algorithm,
+				..CarveOptions::default()
+			};
+			let carved = seamcarve_with_options(&buf, 8, 10, &options).unwrap();
+			let kept = (0..8).filter(|&x| carved[(x, 0)] == Luma([1234])).count();
+			assert_eq!(kept, 1, "{:?}", algorithm);
+			assert!((0..10).all(|y| carved.get_pixel(0, y)[0] % 65535 == 0));
+		}
+
+		let rgb: ImageBuffer<Rgb<u16>, Vec<u16>> = ImageBuffer::from_fn(9, 7, |x, y| {
+			Rgb([
+				(x * 7000) as u16,
+				(y * 9000) as u16,
+				((x * y * 1500) % 65536) as u16,
+			])
+		});
+		// In u32, the sum of two of the largest squared differences
+		// holds at the top of the range.
+		let energy = crate::avisha1::calculate_energy(&buf);
+		assert_eq!(energy[(1, 1)], u32::MAX);
+		assert_eq!(seamcarve(&rgb, 6, 5).unwrap().dimensions(), (6, 5));
+	}
+
+	#[test]
+	fn a_hybrid_resize_carves_part_of_the_way_and_scales_the_rest() {
+		let buf: ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_fn(20, 10, |x, y| {
+			Luma([((x * 37 + y * 11 + x * y) % 256) as u8])
+		});
+		let hybrid = |fraction| CarveOptions {
+			carve_fraction: Some(fraction),
+			..CarveOptions::default()
+		};
+		let (half, report) = seamcarve_with_report(&buf, 10, 13, &hybrid(0.5)).unwrap();
+		assert_eq!(half.dimensions(), (10, 13));
+		assert_eq!(report.width_seams, 5);
+		assert_eq!(report.height_seams_inserted, 2);
+
+		let (whole, report) = seamcarve_with_report(&buf, 10, 13, &hybrid(1.0)).unwrap();
+		let carved = seamcarve(&buf, 10, 13).unwrap();
+		assert_eq!(whole.into_raw(), carved.into_raw());
+		assert_eq!(report.width_seams, 10);
+		let (_, report) = seamcarve_with_report(&buf, 10, 13, &hybrid(0.0)).unwrap();
+		assert!(report.seams.is_empty());
+	}
+
+	#[test]
+	fn float_images_carve_in_float() {
+		// Steps too faint for 8 bits, and an HDR stripe well above 1.0.
+		let buf: ImageBuffer<Rgb<f32>, Vec<f32>> = ImageBuffer::from_fn(12, 10, |x, y| match x {
+			5 | 6 => Rgb([4.0, 3.5, 3.0]),
+			_ => {
+				let v = ((x * 3 + y * 5) % 7) as f32 * 0.0007;
+				Rgb([v, v * 0.5, v * 0.25])
+			}
+		});
+		assert_eq!(luma_max::<f32>(), 65535);
+		for algorithm in [Algorithm::Forward, Algorithm::Backward].iter() {
+			let options = CarveOptions {
+				algorithm: *algorithm,
+				..CarveOptions::default()
+			};
+			let carved = seamcarve_with_options(&buf, 8, 10, &options).unwrap();
+			assert_eq!(carved.dimensions(), (8, 10));
+			for y in 0..10 {
+				let row: Vec<Rgb<f32>> = (0..12).map(|x| buf[(x, y)]).collect();
+				let kept = (0..8).filter(|&x| carved[(x, y)] == Rgb([4.0, 3.5, 3.0]));
+				assert_eq!(kept.count(), 2, "{:?}", algorithm);
+				assert!((0..8).all(|x| row.contains(&carved[(x, y)])));
+			}
+		}
+		let grown = seamcarve(&buf, 14, 10).unwrap();
+		assert_eq!(grown.dimensions(), (14, 10));
+		assert!(grown.pixels().all(|p| (0.0..=4.0).contains(&p[0])));
+	}
+
+	#[test]
+	fn a_cancelled_carve_stops_between_seams() {
+		let buf: ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_fn(12, 8, |x, y| {
+			Luma([((x * 43 + y * 11 + x * y) % 256) as u8])
+		});
+		let cancel = CancelToken::new();
+		let options = CarveOptions {
+			cancel: Some(cancel.clone()),
+			..CarveOptions::default()
+		};
+		let mut removed = 0;
+		let carved = seamcarve_with_events(&buf, 6, 8, &options, &mut |event| {
+			if let CarveEvent::Removed { .. } = event {
+				removed += 1;
+				if removed == 2 {
+					cancel.cancel();
+				}
+			}
+		});
+		assert_eq!(carved.unwrap_err(), CarveError::Cancelled);
+		assert_eq!(removed, 2);
+
+		// Enlargements look between the seams they search for, too.
+		assert_eq!(
+			seamcarve_with_options(&buf, 16, 8, &options).unwrap_err(),
+			CarveError::Cancelled
+		);
+	}
+
+	#[test]
+	fn impossible_carves_say_why() {
+		let buf: ImageBuffer<Luma<u8>, Vec<u8>> =
+			ImageBuffer::from_fn(6, 4, |x, _| Luma([x as u8]));
+		assert_eq!(
+			seamcarve(&buf, 0, 4).unwrap_err(),
+			CarveError::InvalidTargetSize {
+				width: 0,
+				height: 4
+			}
+		);
+		let empty: ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::new(0, 4);
+		assert_eq!(
+			seamcarve(&empty, 3, 4).unwrap_err(),
+			CarveError::ImageTooSmall {
+				width: 0,
+				height: 4
+			}
+		);
+		let outside = CarveOptions {
+			region_of_interest: Some(RegionOfInterest::new(4, 0, 3, 4)),
+			..CarveOptions::default()
+		};
+		let error = seamcarve_with_options(&buf, 5, 4, &outside).unwrap_err();
+		assert!(matches!(error, CarveError::RegionOutsideImage { .. }));
+		assert_eq!(
+			String::from(error),
+			"a 3x4 region at (4, 0) isn't inside a 6x4 image"
+		);
+
+		assert_eq!(
+			seamcarve(&buf, 1, 4).unwrap_err(),
+			CarveError::BelowMinimumSize {
+				target: (1, 4),
+				minimum: (2, 2)
+			}
+		);
+		let strict = CarveOptions {
+			limits: CarveLimits {
+				max_removed_percent: 40,
+				..CarveLimits::default()
+			},
+			..CarveOptions::default()
+		};
+		assert!(seamcarve_with_options(&buf, 4, 4, &strict).is_ok());
+		assert_eq!(
+			seamcarve_with_options(&buf, 3, 4, &strict).unwrap_err(),
+			CarveError::RemovesTooMuch {
+				dimensions: (6, 4),
+				target: (3, 4),
+				limit_percent: 40
+			}
+		);
+		let extreme = CarveOptions {
+			allow_extreme: true,
+			..strict
+		};
+		assert_eq!(
+			seamcarve_with_options(&buf, 1, 1, &extreme)
+				.unwrap()
+				.dimensions(),
+			(1, 1)
+		);
+		// A sliver may still be carved the other way.
+		let sliver: ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::new(1, 4);
+		assert_eq!(seamcarve(&sliver, 1, 6).unwrap().dimensions(), (1, 6));
+	}
+
+	#[test]
+	fn capped_insertions_spread_out_and_are_numbered() {
+		// A smooth patch on the left draws every uncapped seam.
+		let buf: ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_fn(24, 6, |x, y| {
+			Luma([cq!(x < 4, 100, ((x * x * 7 + y * 13) % 256) as u8)])
+		});
+		let options = CarveOptions {
+			insertion_cap: Some(InsertionCap::new(3, 1)),
+			track_insertion_order: true,
+			..CarveOptions::default()
+		};
+		let (grown, report) = seamcarve_with_report(&buf, 30, 6, &options).unwrap();
+		let order = report.insertion_order.as_ref().unwrap();
+		assert_eq!(order.dimensions(), grown.dimensions());
+		for y in 0..6 {
+			let inserted: Vec<u32> = (0..30).filter(|&x| order[(x, y)][0] > 0).collect();
+			let mut numbers: Vec<u32> = inserted.iter().map(|&x| order[(x, y)][0]).collect();
+			numbers.sort();
+			assert_eq!(numbers, [1, 2, 3, 4, 5, 6]);
+			// Where each inserted pixel's seam went through the original.
+			let origins: Vec<u32> = (inserted.iter().enumerate())
+				.map(|(shift, &x)| x - shift as u32 - 1)
+				.collect();
+			assert!(
+				origins.windows(2).all(|w| w[1] - w[0] >= 3),
+				"{:?}",
+				origins
+			);
+		}
+		let picture = report.insertion_order_image().unwrap();
+		let first = (0..30).find(|&x| order[(x, 0)][0] == 1).unwrap();
+		assert_eq!(picture[(first, 0)][0], 255);
+		let white = Rgb([255, 255, 255]);
+		let colored = report
+			.insertion_order_image_with(&Palette::Viridis, white)
+			.unwrap();
+		assert_eq!(colored[(first, 0)], Palette::Viridis.color(0.0));
+		let original = (0..30).find(|&x| order[(x, 0)][0] == 0).unwrap();
+		assert_eq!(colored[(original, 0)], white);
+	}
+
+	// A device that works out the costs on the CPU for its first few
+	// seams, and then fails.
+	struct Flaky(std::cell::Cell<u32>);
+
+	impl ComputeBackend for Flaky {
+		fn try_energy_map_of(
+			&self,
+			_: &TwoDimensionalMap<u32>,
+			_: u32,
+		) -> Option<TwoDimensionalMap<f32>> {
+			None
+		}
+
+		fn try_forward_costs(
+			&self,
+			lumas: &TwoDimensionalMap<u32>,
+			max: u32,
+		) -> Option<(TwoDimensionalMap<f32>, TwoDimensionalMap<u32>)> {
+			let works = self.0.get().checked_sub(1)?;
+			self.0.set(works);
+			Some(crate::backend::cpu_forward_costs(lumas, max))
+		}
+	}
+
+	#[test]
+	fn large_plain_carves_go_to_the_device() {
+		fn sound() -> Option<Box<dyn ComputeBackend>> {
+			Some(Box::new(Flaky(std::cell::Cell::new(u32::MAX))))
+		}
+		let buf: ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_fn(24, 12, |x, y| {
+			Luma([((x * x * 7 + y * 31 + x * y) % 256) as u8])
+		});
+		let carve = |options: &CarveOptions| {
+			carve_with_events(&buf, 18, 9, options, &mut |_| {}, sound).unwrap()
+		};
+		let (cpu, report) = carve(&CarveOptions::default());
+		assert_eq!(report.backend, Backend::Cpu);
+
+		let device = CarveOptions {
+			device_above: Some(24 * 12),
+			..CarveOptions::default()
+		};
+		let (carved, report) = carve(&device);
+		assert_eq!(report.backend, Backend::Device);
+		assert!(report.warnings.is_empty());
+		assert_eq!(carved.into_raw(), cpu.into_raw());
+
+		// Too small, or not a plain forward-energy carve.
+		for options in [
+			CarveOptions {
+				device_above: Some(24 * 12 + 1),
+				..CarveOptions::default()
+			},
+			CarveOptions {
+				algorithm: Algorithm::Backward,
+				..device.clone()
+			},
+			CarveOptions {
+				energy: EnergyOptions::new().border(BorderPolicy::Mirror),
+				..device.clone()
+			},
+		] {
+			assert_eq!(carve(&options).1.backend, Backend::Cpu);
+		}
+	}
+
+	#[test]
+	fn a_failing_device_leaves_the_carve_to_the_cpu() {
+		fn flaky() -> Option<Box<dyn ComputeBackend>> {
+			Some(Box::new(Flaky(std::cell::Cell::new(3))))
+		}
+		let buf: ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_fn(24, 12, |x, y| {
+			Luma([((x * x * 7 + y * 31 + x * y) % 256) as u8])
+		});
+		let options = CarveOptions {
+			device_above: Some(0),
+			..CarveOptions::default()
+		};
+		let (carved, report) =
+			carve_with_events(&buf, 18, 9, &options, &mut |_| {}, flaky).unwrap();
+		assert_eq!(report.backend, Backend::Cpu);
+		assert_eq!(report.warnings.len(), 1);
+		assert_eq!(report.width_seams + report.height_seams, 9);
+		assert_eq!(
+			carved.into_raw(),
+			seamcarve(&buf, 18, 9).unwrap().into_raw()
+		);
+	}
 }
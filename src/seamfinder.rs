@@ -1,11 +1,476 @@
+//! The seam finder interface, and a caching adapter for it.
+
+use crate::cq;
+use crate::error::CarveError;
+use crate::twodmap::TwoDimensionalMap;
+use std::cell::RefCell;
+use std::ops::Deref;
+
+/// Which way a seam runs through the image.  A vertical seam has one
+/// x coordinate per row, and removing it narrows the image; a
+/// horizontal seam has one y coordinate per column.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub enum Orientation {
+	/// Top to bottom.
+	Vertical,
+	/// Left to right.
+	Horizontal,
+}
+
+/// A seam found in an image: which way it runs, the path it takes,
+/// what it cost the finder, and the dimensions of the image it was
+/// found in.  The path has one x coordinate per row of a vertical
+/// seam, or one y coordinate per column of a horizontal one; the
+/// seam derefs to it, so it can be indexed and iterated as the bare
+/// path could.  The cost is in whatever terms the finder measures
+/// seams by, projected to `f64`, and is only comparable between seams
+/// found the same way.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Seam {
+	orientation: Orientation,
+	path: Vec<u32>,
+	cost: f64,
+	dimensions: (u32, u32),
+}
+
+impl Seam {
+	/// A seam through an image of the given dimensions, checked: the
+	/// path must cross the whole image, stay inside it, and step at
+	/// most one pixel sideways at a time.  A path that doesn't is
+	/// `CarveError::InvalidSeam`.
+	pub fn new(
+		orientation: Orientation,
+		path: Vec<u32>,
+		cost: f64,
+		dimensions: (u32, u32),
+	) -> Result<Self, CarveError> {
+		let (width, height) = dimensions;
+		let (along, across) = match orientation {
+			Orientation::Vertical => (height, width),
+			Orientation::Horizontal => (width, height),
+		};
+		if path.len() != along as usize {
+			return Err(CarveError::InvalidSeam(format!(
+				"a {:?} seam through a {}x{} image needs {} positions, not {}",
+				orientation,
+				width,
+				height,
+				along,
+				path.len()
+			)));
+		}
+		if let Some(at) = path.iter().find(|&&at| at >= across) {
+			return Err(CarveError::InvalidSeam(format!(
+				"a {:?} seam through a {}x{} image can't pass through {}",
+				orientation, width, height, at
+			)));
+		}
+		if path
+			.windows(2)
+			.any(|w| cq!(w[0] > w[1], w[0] - w[1], w[1] - w[0]) > 1)
+		{
+			return Err(CarveError::InvalidSeam(
+				"a seam may only step one pixel sideways at a time".to_string(),
+			));
+		}
+		Ok(Seam {
+			orientation,
+			path,
+			cost,
+			dimensions,
+		})
+	}
+
+	// A seam a finder has just traced, and so knows to be sound.
+	pub(crate) fn traced(
+		orientation: Orientation,
+		path: Vec<u32>,
+		cost: f64,
+		dimensions: (u32, u32),
+	) -> Self {
+		debug_assert!(Seam::new(orientation, path.clone(), cost, dimensions).is_ok());
+		Seam {
+			orientation,
+			path,
+			cost,
+			dimensions,
+		}
+	}
+
+	/// Which way the seam runs.
+	pub fn orientation(&self) -> Orientation {
+		self.orientation
+	}
+
+	/// The path of the seam.
+	pub fn path(&self) -> &[u32] {
+		&self.path
+	}
+
+	/// The path of the seam, taken out of it.
+	pub fn into_path(self) -> Vec<u32> {
+		self.path
+	}
+
+	/// What the seam cost the finder.
+	pub fn cost(&self) -> f64 {
+		self.cost
+	}
+
+	/// The width and height of the image the seam was found in.
+	pub fn dimensions(&self) -> (u32, u32) {
+		self.dimensions
+	}
+
+	/// Make sure the seam can be applied as intended: that it runs the
+	/// given way, through an image of the given dimensions.  A seam
+	/// found in one image and applied to another of a different size,
+	/// or applied the wrong way round, would tear it, and is refused
+	/// as `CarveError::SeamMismatch`.
+	pub fn check(
+		&self,
+		orientation: Orientation,
+		dimensions: (u32, u32),
+	) -> Result<(), CarveError> {
+		if orientation != self.orientation || dimensions != self.dimensions {
+			return Err(CarveError::SeamMismatch {
+				orientation: self.orientation,
+				seam: self.dimensions,
+				dimensions,
+			});
+		}
+		Ok(())
+	}
+
+	/// The (x, y) of every pixel the seam passes through.
+	pub fn pixels(&self) -> impl Iterator<Item = (u32, u32)> + '_ {
+		let vertical = self.orientation == Orientation::Vertical;
+		self.path
+			.iter()
+			.enumerate()
+			.map(move |(i, &at)| cq!(vertical, (at, i as u32), (i as u32, at)))
+	}
+
+	// Whether two seams through the same image share a pixel.
+	fn crosses(&self, other: &Seam) -> bool {
+		self.orientation == other.orientation
+			&& self.path.iter().zip(other.path.iter()).any(|(a, b)| a == b)
+	}
+}
+
+impl Deref for Seam {
+	type Target = [u32];
+
+	fn deref(&self) -> &[u32] {
+		&self.path
+	}
+}
+
+/// A seam, and the cumulative cost map it was traced back through if
+/// the finder keeps one.  Costs are projected to `f64` whatever
+/// scalar the finder works in, and are in the image's coordinates.
+pub type SeamAndCosts = (Seam, Option<TwoDimensionalMap<f64>>);
+
+/// The whole of a seam DP, for tools that want more than the one
+/// seam: the cumulative cost of reaching every pixel, and the parent
+/// each pixel's cheapest way there came through.  Both are in the
+/// image's coordinates.  For vertical seams a pixel's parent is the
+/// column it came from in the row above; for horizontal ones, the
+/// row it came from in the column to the left.  The first row (or
+/// column) has no parents, and its entries are zero.
+#[derive(Debug, Clone)]
+pub struct CostTable {
+	/// Which way the seams run.
+	pub orientation: Orientation,
+	/// The cost of the cheapest seam from the first row (or column)
+	/// to every pixel.
+	pub costs: TwoDimensionalMap<f64>,
+	/// The parent of every pixel.
+	pub parents: TwoDimensionalMap<u32>,
+}
+
+impl CostTable {
+	// The number of positions across the seams, and along them.
+	fn extents(&self) -> (u32, u32) {
+		let (width, height) = (self.costs.width, self.costs.height);
+		cq!(
+			self.orientation == Orientation::Vertical,
+			(width, height),
+			(height, width)
+		)
+	}
+
+	// The parent of the pixel at `at` across the seams and `along`
+	// them.
+	fn parent(&self, at: u32, along: u32) -> u32 {
+		cq!(
+			self.orientation == Orientation::Vertical,
+			self.parents[(at, along)],
+			self.parents[(along, at)]
+		)
+	}
+
+	/// The cheapest seam ending at `end`: a column of the bottom row,
+	/// or a row of the rightmost column.  Its cost is the cost of
+	/// reaching its end.
+	pub fn trace(&self, end: u32) -> Result<Seam, CarveError> {
+		let (across, along) = self.extents();
+		if end >= across || along == 0 {
+			return Err(CarveError::NoSeamEnd {
+				end,
+				ends: cq!(along == 0, 0, across),
+			});
+		}
+		let mut path = vec![0; along as usize];
+		let mut at = end;
+		for step in (0..along).rev() {
+			path[step as usize] = at;
+			at = self.parent(at, step);
+		}
+		let last = along - 1;
+		let cost = cq!(
+			self.orientation == Orientation::Vertical,
+			self.costs[(end, last)],
+			self.costs[(last, end)]
+		);
+		let dimensions = (self.costs.width, self.costs.height);
+		Ok(Seam::traced(self.orientation, path, cost, dimensions))
+	}
+
+	/// How many of the seams traced from every end pass through each
+	/// pixel.  Where cheap seams run together, the count climbs.
+	pub fn confluence(&self) -> TwoDimensionalMap<u32> {
+		let (across, along) = self.extents();
+		let mut counts = TwoDimensionalMap::new(self.costs.width, self.costs.height);
+		// Counting down the DAG, every pixel passes on its count to its
+		// parent, so the work is linear in the table.
+		let mut row: Vec<u32> = vec![1; across as usize];
+		for step in (0..along).rev() {
+			let mut above = vec![0; across as usize];
+			for at in 0..across {
+				let count = row[at as usize];
+				let pixel = cq!(
+					self.orientation == Orientation::Vertical,
+					(at, step),
+					(step, at)
+				);
+				counts[pixel] = count;
+				if step > 0 {
+					above[self.parent(at, step) as usize] += count;
+				}
+			}
+			row = above;
+		}
+		counts
+	}
+}
+
 /// This trait defines how we will return seams from an image.  It's a
 /// primitive interface, just enough to make room for multiple seam
 /// carvers as well as caching.
 pub trait SeamFinder {
 	/// Once a SeamFinder has an image (or whatever it needs to make a
 	/// rational decision), request a horizontal seam.
-	fn find_horizontal_seam(&self) -> Vec<u32>;
+	fn find_horizontal_seam(&self) -> Seam;
 
 	/// Request a vertical seam.
-	fn find_vertical_seam(&self) -> Vec<u32>;
+	fn find_vertical_seam(&self) -> Seam;
+
+	/// Request a horizontal seam along with its cost map.  Finders
+	/// that don't keep a whole cost map return the seam alone.
+	fn find_horizontal_seam_with_costs(&self) -> SeamAndCosts {
+		(self.find_horizontal_seam(), None)
+	}
+
+	/// Request a vertical seam along with its cost map.
+	fn find_vertical_seam_with_costs(&self) -> SeamAndCosts {
+		(self.find_vertical_seam(), None)
+	}
+
+	/// Request up to `count` seams running the given way that share no
+	/// pixels, cheapest first, all in the coordinates of the image as
+	/// it is; remove them all at once, not one after another.  Finders
+	/// that can route later seams around earlier ones from a single
+	/// energy map should say so here.  By default the finder is simply
+	/// asked again until it has `count` seams or repeats itself, which
+	/// for a finder that always gives the same answer is after one.
+	fn find_seams(&self, orientation: Orientation, count: usize) -> Vec<Seam> {
+		let mut seams: Vec<Seam> = Vec::with_capacity(count);
+		while seams.len() < count {
+			let seam = match orientation {
+				Orientation::Vertical => self.find_vertical_seam(),
+				Orientation::Horizontal => self.find_horizontal_seam(),
+			};
+			if seams.iter().any(|found| found.crosses(&seam)) {
+				break;
+			}
+			seams.push(seam);
+		}
+		seams
+	}
+}
+
+/// A seam finder that remembers the last seam, and cost map, it found
+/// in each direction, so that asking again costs nothing.  Anything
+/// that changes what the wrapped finder would find, such as a change
+/// to the image behind it, must be followed by `invalidate()`.
+pub struct CachingSeamFinder<F: SeamFinder> {
+	finder: F,
+	horizontal: RefCell<Option<SeamAndCosts>>,
+	vertical: RefCell<Option<SeamAndCosts>>,
+}
+
+impl<F: SeamFinder> CachingSeamFinder<F> {
+	/// Wrap a seam finder.
+	pub fn new(finder: F) -> Self {
+		CachingSeamFinder {
+			finder,
+			horizontal: RefCell::new(None),
+			vertical: RefCell::new(None),
+		}
+	}
+
+	/// Forget both cached seams.
+	pub fn invalidate(&mut self) {
+		self.horizontal.replace(None);
+		self.vertical.replace(None);
+	}
+
+	/// The wrapped finder.
+	pub fn inner(&self) -> &F {
+		&self.finder
+	}
+
+	/// The wrapped finder, for changing.  Since any change may alter
+	/// the seams, the cache is invalidated.
+	pub fn inner_mut(&mut self) -> &mut F {
+		self.invalidate();
+		&mut self.finder
+	}
+
+	/// Unwrap the finder, discarding the cache.
+	pub fn into_inner(self) -> F {
+		self.finder
+	}
+
+	/// The cost map of the horizontal seam, if the finder keeps one.
+	pub fn horizontal_costs(&self) -> Option<TwoDimensionalMap<f64>> {
+		self.find_horizontal_seam_with_costs().1
+	}
+
+	/// The cost map of the vertical seam, if the finder keeps one.
+	pub fn vertical_costs(&self) -> Option<TwoDimensionalMap<f64>> {
+		self.find_vertical_seam_with_costs().1
+	}
+
+	fn cached(
+		cell: &RefCell<Option<SeamAndCosts>>,
+		find: impl FnOnce() -> SeamAndCosts,
+	) -> SeamAndCosts {
+		cell.borrow_mut().get_or_insert_with(find).clone()
+	}
+}
+
+impl<F: SeamFinder> SeamFinder for CachingSeamFinder<F> {
+	fn find_horizontal_seam(&self) -> Seam {
+		self.find_horizontal_seam_with_costs().0
+	}
+
+	fn find_vertical_seam(&self) -> Seam {
+		self.find_vertical_seam_with_costs().0
+	}
+
+	fn find_horizontal_seam_with_costs(&self) -> SeamAndCosts {
+		Self::cached(&self.horizontal, || {
+			self.finder.find_horizontal_seam_with_costs()
+		})
+	}
+
+	fn find_vertical_seam_with_costs(&self) -> SeamAndCosts {
+		Self::cached(&self.vertical, || {
+			self.finder.find_vertical_seam_with_costs()
+		})
+	}
+
+	// Batches aren't cached, but the wrapped finder's batching is kept.
+	fn find_seams(&self, orientation: Orientation, count: usize) -> Vec<Seam> {
+		self.finder.find_seams(orientation, count)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::avisha2::AviShaTwo;
+	use image::{ImageBuffer, Luma};
+	use std::cell::Cell;
+
+	struct Counting<'a>(&'a Cell<u32>);
+
+	impl<'a> SeamFinder for Counting<'a> {
+		fn find_horizontal_seam(&self) -> Seam {
+			self.0.set(self.0.get() + 1);
+			Seam::traced(Orientation::Horizontal, vec![0], 0.0, (1, 2))
+		}
+
+		fn find_vertical_seam(&self) -> Seam {
+			self.0.set(self.0.get() + 1);
+			Seam::traced(Orientation::Vertical, vec![1], 0.0, (2, 1))
+		}
+	}
+
+	#[test]
+	fn seams_are_found_once_per_direction() {
+		let runs = Cell::new(0);
+		let mut finder = CachingSeamFinder::new(Counting(&runs));
+		for _ in 0..3 {
+			assert_eq!(finder.find_vertical_seam().path(), [1]);
+			assert_eq!(finder.find_horizontal_seam().path(), [0]);
+		}
+		assert_eq!(runs.get(), 2);
+		finder.invalidate();
+		finder.find_vertical_seam();
+		assert_eq!(runs.get(), 3);
+
+		// Asked for a batch, a finder that always gives the same answer
+		// gives it once.
+		assert_eq!(
+			Counting(&runs).find_seams(Orientation::Vertical, 4).len(),
+			1
+		);
+		assert_eq!(runs.get(), 5);
+	}
+
+	#[test]
+	fn forward_energy_costs_are_cached_in_image_coordinates() {
+		let buf: ImageBuffer<Luma<u8>, Vec<u8>> =
+			ImageBuffer::from_fn(6, 4, |x, y| Luma([((x * 53 + y * 29) % 256) as u8]));
+		let finder = CachingSeamFinder::new(AviShaTwo::new(&buf));
+		let costs = finder.horizontal_costs().unwrap();
+		assert_eq!((costs.width, costs.height), (6, 4));
+		assert_eq!(
+			finder.find_vertical_seam(),
+			AviShaTwo::new(&buf).find_vertical_seam()
+		);
+	}
+
+	#[test]
+	fn seams_are_checked_against_their_image() {
+		let seam = Seam::new(Orientation::Vertical, vec![1, 2, 2], 5.0, (4, 3)).unwrap();
+		assert_eq!(seam[1], 2);
+		assert_eq!(seam.pixels().last(), Some((2, 2)));
+		assert!(seam.check(Orientation::Vertical, (4, 3)).is_ok());
+		let mismatch = |result| matches!(result, Err(CarveError::SeamMismatch { .. }));
+		assert!(mismatch(seam.check(Orientation::Horizontal, (4, 3))));
+		assert!(mismatch(seam.check(Orientation::Vertical, (3, 3))));
+
+		let invalid = |orientation, path| {
+			let seam = Seam::new(orientation, path, 0.0, (4, 3));
+			matches!(seam, Err(CarveError::InvalidSeam(_)))
+		};
+		assert!(invalid(Orientation::Vertical, vec![1, 2]));
+		assert!(invalid(Orientation::Vertical, vec![1, 2, 4]));
+		assert!(invalid(Orientation::Horizontal, vec![0, 2, 1, 1]));
+	}
 }
@@ -0,0 +1,176 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Seam logs
+//!
+//! Finding seams is the slow part of a carve; taking them out is
+//! quick.  A seam log keeps every seam a carve removed or inserted,
+//! in order, so the carve can be done again without the search: on
+//! the same image, or on another of the same size, such as the
+//! full-quality original of a proxy the carve was previewed on.  The
+//! replay inserts seams as the carve did, making each new pixel with
+//! the carve's synthesis, so replaying a log on the image it was made
+//! from gives exactly the carved image.
+//!
+//! With the `ipc` feature on, a log can be saved in the frames of
+//! `ipc::EventWriter`, one event to a line, and read back, or
+//! serialized any other way through serde.
+
+use crate::companions::replay_events;
+use crate::error::CarveError;
+use crate::report::CarveEvent;
+use crate::seamcarver::{seamcarve_with_events, CarveOptions};
+use crate::synthesis::Synthesis;
+use image::{GenericImageView, ImageBuffer, Pixel, Primitive};
+#[cfg(feature = "ipc")]
+use std::io::{self, BufRead, Write};
+
+/// Every seam of a carve, in the order they were removed or inserted,
+/// and the size of the image they were found in.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub struct SeamLog {
+	events: Vec<CarveEvent>,
+	synthesis: Synthesis,
+}
+
+impl SeamLog {
+	/// An empty log, to `record` a carve's events into.
+	pub fn new() -> Self {
+		SeamLog::default()
+	}
+
+	/// Carve the image to the new size, as `seamcarve_with_options`
+	/// does, and log the carve.
+	pub fn carve<I, P, S>(
+		image: &I,
+		newwidth: u32,
+		newheight: u32,
+		options: &CarveOptions,
+	) -> Result<(ImageBuffer<P, Vec<S>>, SeamLog), CarveError>
+	where
+		I: GenericImageView<Pixel = P>,
+		P: Pixel<Subpixel = S> + 'static,
+		S: Primitive + 'static,
+	{
+		let mut log = SeamLog::new().synthesized(options.synthesis);
+		let (carved, _) =
+			seamcarve_with_events(image, newwidth, newheight, options, &mut |event| {
+				log.record(event)
+			})?;
+		Ok((carved, log))
+	}
+
+	/// Replay inserted seams with the given synthesis, as the carve
+	/// being recorded makes them; see `CarveOptions::synthesis`.
+	pub fn synthesized(mut self, synthesis: Synthesis) -> Self {
+		self.synthesis = synthesis;
+		self
+	}
+
+	/// Add an event of a carve to the log, for use as the callback of
+	/// `seamcarve_with_events`.
+	pub fn record(&mut self, event: &CarveEvent) {
+		self.events.push(event.clone());
+	}
+
+	/// The size of the image the log's carve began with, if the log
+	/// has its start.
+	pub fn dimensions(&self) -> Option<(u32, u32)> {
+		self.events.iter().find_map(|event| match event {
+			CarveEvent::Started { width, height, .. } => Some((*width, *height)),
+			_ => None,
+		})
+	}
+
+	/// The number of seams removed.
+	pub fn removed(&self) -> usize {
+		self.events
+			.iter()
+			.filter(|event| matches!(event, CarveEvent::Removed { .. }))
+			.count()
+	}
+
+	/// The logged events, in order.
+	pub fn events(&self) -> &[CarveEvent] {
+		&self.events
+	}
+
+	/// Carve the logged seams out of the image, and insert them into
+	/// it, in the order the carve did.  The image must be the size of
+	/// the one the log was made from.
+	pub fn replay<I, P, S>(&self, image: &I) -> Result<ImageBuffer<P, Vec<S>>, CarveError>
+	where
+		I: GenericImageView<Pixel = P>,
+		P: Pixel<Subpixel = S> + 'static,
+		S: Primitive + 'static,
+	{
+		let synthesis = self.synthesis;
+		replay_events(&self.events, image, |pixel, neighbor| {
+			synthesis.synthesize(pixel, neighbor)
+		})
+	}
+
+	/// Write the log's events as frames of `ipc::EventWriter`, one to
+	/// a line.  The synthesis isn't written; give it again to the log
+	/// read back.
+	#[cfg(feature = "ipc")]
+	pub fn write_to<W: Write>(&self, out: W) -> io::Result<()> {
+		let mut writer = crate::ipc::EventWriter::new(out);
+		self.events.iter().try_for_each(|event| writer.send(event))
+	}
+
+	/// Read a log written by `write_to`.
+	#[cfg(feature = "ipc")]
+	pub fn read_from<R: BufRead>(input: R) -> io::Result<Self> {
+		let events: Vec<CarveEvent> =
+			crate::ipc::EventReader::new(input).collect::<io::Result<_>>()?;
+		Ok(SeamLog::from(events))
+	}
+}
+
+impl From<Vec<CarveEvent>> for SeamLog {
+	fn from(events: Vec<CarveEvent>) -> Self {
+		SeamLog {
+			events,
+			synthesis: Synthesis::default(),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use image::{Luma, Rgb};
+
+	#[test]
+	fn a_replayed_log_carves_as_the_carve_did() {
+		let buf: ImageBuffer<Rgb<u8>, Vec<u8>> = ImageBuffer::from_fn(11, 9, |x, y| {
+			Rgb([
+				((x * 29 + y * 13) % 256) as u8,
+				((x * y * 7) % 256) as u8,
+				((x + y * 41) % 256) as u8,
+			])
+		});
+		let options = CarveOptions {
+			synthesis: Synthesis::Toward(0.3),
+			..CarveOptions::default()
+		};
+		let (carved, log) = SeamLog::carve(&buf, 8, 12, &options).unwrap();
+		assert_eq!(log.dimensions(), Some((11, 9)));
+		assert_eq!(log.removed(), 3);
+		assert_eq!(log.replay(&buf).unwrap().into_raw(), carved.into_raw());
+
+		let small: ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::new(10, 9);
+		assert!(log.replay(&small).is_err());
+
+		#[cfg(feature = "ipc")]
+		{
+			let mut saved = Vec::new();
+			log.write_to(&mut saved).unwrap();
+			let read = SeamLog::read_from(&saved[..]).unwrap();
+			assert_eq!(read.synthesized(Synthesis::Toward(0.3)), log);
+		}
+	}
+}
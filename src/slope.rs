@@ -0,0 +1,149 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Slope-constrained seams
+//!
+//! An ordinary seam may step sideways on every row, and a seam that
+//! wanders diagonally through a building shears everything it passes
+//! through.  Limiting a seam to one step sideways in every `rows`
+//! rows keeps it close to straight, and the window frames stay
+//! square.
+//!
+//! The constraint can't be checked one pixel at a time, so the DP
+//! carries more state: for every pixel, the cheapest way to reach it
+//! having last stepped sideways 0, 1, … `rows - 1` or more rows ago.
+//! A seam may only step sideways from that last state.  This costs
+//! `rows` times the time and memory of the plain DP.
+
+use crate::scalar::EnergyScalar;
+use crate::twodmap::TwoDimensionalMap;
+
+// Keep the candidate if it's cheaper than what's there; the first
+// candidate offered wins a tie.
+fn offer<C: EnergyScalar>(slot: &mut Option<C>, candidate: C) -> bool {
+	match slot {
+		Some(existing) if candidate >= *existing => false,
+		_ => {
+			*slot = Some(candidate);
+			true
+		}
+	}
+}
+
+/// Find the cheapest vertical seam through a `width` by `height`
+/// plane that steps sideways at most once in any `rows` consecutive
+/// rows.  `start(x)` is the cost of starting a seam at column `x` of
+/// the first row, and `extend(x, y, from, cost)` the cost of reaching
+/// `(x, y)` from column `from` of the row above, given the cost of
+/// reaching that.  Returns the seam, and the cheapest cost of
+/// reaching each pixel under the constraint.  A `rows` of 0 or 1 is
+/// no constraint at all.
+pub(crate) fn sloped_seam<C: EnergyScalar>(
+	width: u32,
+	height: u32,
+	rows: u32,
+	start: &dyn Fn(u32) -> C,
+	extend: &dyn Fn(u32, u32, u32, C) -> C,
+) -> (Vec<u32>, TwoDimensionalMap<C>) {
+	let (w, states) = (width as usize, std::cmp::max(rows, 1) as usize);
+	let free = states - 1;
+	let mut best: TwoDimensionalMap<C> = TwoDimensionalMap::new(width, height);
+
+	// Costs are indexed by state, then column; a seam is free to step
+	// sideways from the last state, which it starts in.
+	let mut above: Vec<Option<C>> = vec![None; states * w];
+	for x in 0..width {
+		let cost = start(x);
+		above[free * w + x as usize] = Some(cost);
+		best[(x, 0)] = cost;
+	}
+	let mut current: Vec<Option<C>> = vec![None; states * w];
+	let mut parents: Vec<(u32, u32)> = vec![(0, 0); states * w * height as usize];
+
+	for y in 1..height {
+		for slot in current.iter_mut() {
+			*slot = None;
+		}
+		for x in 0..width {
+			let candidates = [
+				Some(x),
+				x.checked_sub(1),
+				Some(x + 1).filter(|&p| p < width),
+			];
+			for from in candidates.iter().filter_map(|c| *c) {
+				for k in 0..states {
+					let cost = match above[k * w + from as usize] {
+						Some(cost) => cost,
+						None => continue,
+					};
+					let next = match (from == x, k == free) {
+						(true, _) => std::cmp::min(k + 1, free),
+						(false, true) => 0,
+						(false, false) => continue,
+					};
+					let at = next * w + x as usize;
+					if offer(&mut current[at], extend(x, y, from, cost)) {
+						parents[(y as usize * states + next) * w + x as usize] = (from, k as u32);
+					}
+				}
+			}
+			let mut cheapest = None;
+			for k in 0..states {
+				if let Some(cost) = current[k * w + x as usize] {
+					offer(&mut cheapest, cost);
+				}
+			}
+			best[(x, y)] = cheapest.unwrap();
+		}
+		std::mem::swap(&mut above, &mut current);
+	}
+
+	// The cheapest state at the bottom, leftmost on a tie.
+	let mut bottom = None;
+	let mut end = (0, free);
+	for x in 0..width {
+		for k in 0..states {
+			if let Some(cost) = above[k * w + x as usize] {
+				if offer(&mut bottom, cost) {
+					end = (x, k);
+				}
+			}
+		}
+	}
+
+	let (mut x, mut k) = end;
+	let mut seam = vec![0; height as usize];
+	for y in (0..height).rev() {
+		seam[y as usize] = x;
+		let (from, state) = parents[(y as usize * states + k) * w + x as usize];
+		x = from;
+		k = state as usize;
+	}
+	(seam, best)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::cq;
+
+	#[test]
+	fn sloped_seams_step_sideways_rarely() {
+		// A diagonal valley of zeros, which an unconstrained seam would
+		// follow all the way down.
+		let cost = |x: u32, y: u32| cq!(x == y, 0u64, 10);
+		let extend = |x, y, _from, above: u64| above + cost(x, y);
+		let (free, _) = sloped_seam(8, 8, 1, &|x| cost(x, 0), &extend);
+		assert_eq!(free, (0..8).collect::<Vec<u32>>());
+
+		let (seam, costs) = sloped_seam(8, 8, 3, &|x| cost(x, 0), &extend);
+		let steps: Vec<usize> = (1..8).filter(|&y| seam[y] != seam[y - 1]).collect();
+		assert!(steps.windows(2).all(|w| w[1] - w[0] >= 3), "{:?}", seam);
+		assert!(seam
+			.windows(2)
+			.all(|w| (w[0] as i64 - w[1] as i64).abs() <= 1));
+		let total: u64 = (0..8).map(|y| cost(seam[y], y as u32)).sum();
+		assert_eq!(costs[(seam[7], 7)], total);
+	}
+}
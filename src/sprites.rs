@@ -0,0 +1,191 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Sprite sheet repacking
+//!
+//! Game art often needs the same sprite sheet at a different cell
+//! size.  Squashing each frame distorts the characters in it; carving
+//! each frame down to the new cell instead keeps the parts that
+//! matter, especially if the artist supplies a protection mask for
+//! each frame.  The repacked sheet keeps the original's grid, and its
+//! frame rectangles are reported back for the game's metadata.
+
+use crate::energyopts::EnergyOptions;
+use crate::seamcarver::{seamcarve_with_options, CarveOptions};
+use image::{GenericImage, GenericImageView, GrayImage, ImageBuffer, Pixel, Primitive};
+
+/// The grid a sprite sheet is laid out on: `columns` by `rows` cells,
+/// each `cell_width` by `cell_height` pixels, starting at the top left
+/// corner of the sheet.  Frames are numbered left to right, top to
+/// bottom.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SheetLayout {
+	/// The number of cells across the sheet.
+	pub columns: u32,
+	/// The number of cells down the sheet.
+	pub rows: u32,
+	/// The width of each cell, in pixels.
+	pub cell_width: u32,
+	/// The height of each cell, in pixels.
+	pub cell_height: u32,
+}
+
+/// Where one frame landed in the repacked sheet.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Frame {
+	/// The frame's number, left to right, top to bottom.
+	pub index: u32,
+	/// The left edge of the frame.
+	pub x: u32,
+	/// The top edge of the frame.
+	pub y: u32,
+	/// The width of the frame.
+	pub width: u32,
+	/// The height of the frame.
+	pub height: u32,
+}
+
+/// A repacked sprite sheet and the rectangle of each frame within it.
+pub struct RepackedSheet<P: Pixel> {
+	/// The new sheet.
+	pub image: ImageBuffer<P, Vec<P::Subpixel>>,
+	/// Where each frame landed, in frame order.
+	pub frames: Vec<Frame>,
+}
+
+/// Carve every frame of a sprite sheet down to a new cell size and
+/// pack the results into a new sheet on the same grid.  `masks` holds
+/// an optional protection mask for each frame, in frame order; it may
+/// be shorter than the number of frames.
+pub fn repack_sprites<I, P, S>(
+	sheet: &I,
+	layout: SheetLayout,
+	cell_width: u32,
+	cell_height: u32,
+	masks: &[Option<GrayImage>],
+) -> Result<RepackedSheet<P>, String>
+where
+	I: GenericImageView<Pixel = P>,
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	let (width, height) = sheet.dimensions();
+	if layout.columns * layout.cell_width > width || layout.rows * layout.cell_height > height {
+		return Err(format!(
+			"a {}x{} grid of {}x{} cells doesn't fit in a {}x{} sheet",
+			layout.columns, layout.rows, layout.cell_width, layout.cell_height, width, height
+		));
+	}
+
+	let mut packed = ImageBuffer::new(layout.columns * cell_width, layout.rows * cell_height);
+	let mut frames = Vec::with_capacity((layout.columns * layout.rows) as usize);
+	for row in 0..layout.rows {
+		for column in 0..layout.columns {
+			let index = row * layout.columns + column;
+			let (left, top) = (column * layout.cell_width, row * layout.cell_height);
+			let sprite: ImageBuffer<P, Vec<S>> =
+				ImageBuffer::from_fn(layout.cell_width, layout.cell_height, |x, y| {
+					sheet.get_pixel(left + x, top + y)
+				});
+
+			let mut options = CarveOptions::default();
+			if let Some(Some(mask)) = masks.get(index as usize) {
+				options.energy = EnergyOptions::new().protect(mask);
+			}
+			let carved = seamcarve_with_options(&sprite, cell_width, cell_height, &options)
+				.map_err(|e| format!("frame {}: {}", index, e))?;
+
+			let frame = Frame {
+				index,
+				x: column * cell_width,
+				y: row * cell_height,
+				width: cell_width,
+				height: cell_height,
+			};
+			if !packed.copy_from(&carved, frame.x, frame.y) {
+				return Err(format!("frame {} doesn't fit its cell", index));
+			}
+			frames.push(frame);
+		}
+	}
+	Ok(RepackedSheet {
+		image: packed,
+		frames,
+	})
+}
+
+/// Render frame rectangles as the JSON metadata most sprite loaders
+/// expect: a `frames` array of `{index, x, y, w, h}` objects, plus the
+/// size of the whole sheet under `meta`.
+pub fn frames_to_json(frames: &[Frame], sheet_width: u32, sheet_height: u32) -> String {
+	let frames: Vec<String> = frames
+		.iter()
+		.map(|f| {
+			format!(
+				"    {{\"index\": {}, \"x\": {}, \"y\": {}, \"w\": {}, \"h\": {}}}",
+				f.index, f.x, f.y, f.width, f.height
+			)
+		})
+		.collect();
+	format!(
+		"{{\n  \"frames\": [\n{}\n  ],\n  \"meta\": {{\"size\": {{\"w\": {}, \"h\": {}}}}}\n}}\n",
+		frames.join(",\n"),
+		sheet_width,
+		sheet_height
+	)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use image::{Luma, Rgb};
+
+	#[test]
+	fn sprites_are_carved_into_the_new_grid() {
+		// Two 6x5 frames, each a dark field with a bright post that the
+		// mask protects.
+		let sheet: ImageBuffer<Rgb<u8>, Vec<u8>> = ImageBuffer::from_fn(12, 5, |x, y| {
+			let v = ((x * 29 + y * 53) % 40) as u8;
+			if x % 6 == 1 {
+				Rgb([250, 250, 250])
+			} else {
+				Rgb([v, v, v])
+			}
+		});
+		let mask: GrayImage =
+			ImageBuffer::from_fn(6, 5, |x, _| Luma([if x == 1 { 255 } else { 0 }]));
+		let layout = SheetLayout {
+			columns: 2,
+			rows: 1,
+			cell_width: 6,
+			cell_height: 5,
+		};
+
+		let RepackedSheet {
+			image: packed,
+			frames,
+		} = repack_sprites(&sheet, layout, 4, 4, &[Some(mask.clone()), Some(mask)]).unwrap();
+		assert_eq!(packed.dimensions(), (8, 4));
+		assert_eq!(
+			frames[1],
+			Frame {
+				index: 1,
+				x: 4,
+				y: 0,
+				width: 4,
+				height: 4
+			}
+		);
+		for frame in &frames {
+			let post = (0..4)
+				.filter(|x| packed.get_pixel(frame.x + x, 0)[0] == 250)
+				.count();
+			assert_eq!(post, 1);
+		}
+
+		let json = frames_to_json(&frames, 8, 4);
+		assert!(json.contains("{\"index\": 1, \"x\": 4, \"y\": 0, \"w\": 4, \"h\": 4}"));
+		assert!(json.contains("\"size\": {\"w\": 8, \"h\": 4}"));
+	}
+}
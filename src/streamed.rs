@@ -0,0 +1,198 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Streamed seams
+//!
+//! A scanned map or a gigapixel panorama won't fit in memory, let
+//! alone its cost table, which is several times the size of the image.
+//! The forward-energy cost of a row depends only on the costs of the
+//! row above and the lumas of the two rows, so the DP can be run down
+//! the image a band of rows at a time, each band read with the last
+//! row of the band above it to stitch the two together, and only one
+//! row of costs carried from band to band.
+//!
+//! Tracing the seam back needs the choices made on the way down, which
+//! are as big as the image.  Rather than keep them, the costs carried
+//! into each band are kept, one row per band, and on the way back up
+//! each band is read and worked again from those to recover its
+//! choices, bottom band first.  Every row is read twice, and the DP
+//! done twice, in exchange for holding no more than a band of lumas
+//! and choices at once.
+//!
+//! The seams are vertical, and the energy plain forward energy, as
+//! `AviShaTwo` finds with no adjustments.  A horizontal seam is found
+//! through a source that reads the image's columns as its rows.
+
+use crate::avisha2::strip_row_costs;
+use crate::dprow::TieBreak;
+use crate::pixelpairs::{luma_max, luma_of};
+use crate::scalar::EnergyScalar;
+use crate::seamfinder::{Orientation, Seam};
+use crate::twodmap::{EnergyAndBackPointer, TwoDimensionalMap};
+use image::{GenericImageView, Pixel, Primitive};
+use std::io;
+
+/// Where the lumas of an image too large to hold come from, a band of
+/// rows at a time.  Rows may be asked for more than once.
+pub trait RowSource {
+	/// The image's width and height.
+	fn dimensions(&self) -> (u32, u32);
+
+	/// The largest luma a pixel of the image can have.
+	fn max_luma(&self) -> u32;
+
+	/// Fill `lumas` with the lumas of the rows from `top` down, row
+	/// after row, as many rows as it has room for.
+	fn read_rows(&mut self, top: u32, lumas: &mut [u32]) -> io::Result<()>;
+}
+
+/// The rows of an image already in memory.
+pub struct ImageRows<'a, I>(pub &'a I);
+
+impl<'a, I, P, S> RowSource for ImageRows<'a, I>
+where
+	I: GenericImageView<Pixel = P>,
+	P: Pixel<Subpixel = S> + 'static,
+	S: Primitive + 'static,
+{
+	fn dimensions(&self) -> (u32, u32) {
+		self.0.dimensions()
+	}
+
+	fn max_luma(&self) -> u32 {
+		luma_max::<S>()
+	}
+
+	fn read_rows(&mut self, top: u32, lumas: &mut [u32]) -> io::Result<()> {
+		let width = std::cmp::max(self.0.width(), 1);
+		for (y, row) in (top..).zip(lumas.chunks_mut(width as usize)) {
+			for (x, luma) in (0..).zip(row.iter_mut()) {
+				*luma = luma_of(&self.0.get_pixel(x, y));
+			}
+		}
+		Ok(())
+	}
+}
+
+/// The cheapest vertical seam through the image the source reads,
+/// worked `band` rows at a time.  An image with no pixels has no seam.
+pub fn streamed_vertical_seam<R: RowSource>(
+	source: &mut R,
+	band: u32,
+	ties: TieBreak,
+) -> io::Result<Option<Seam>> {
+	let (width, height) = source.dimensions();
+	if width == 0 || height == 0 {
+		return Ok(None);
+	}
+	let max = source.max_luma();
+	let band = std::cmp::max(band, 1);
+	let bands: Vec<(u32, u32)> = (0..height)
+		.step_by(band as usize)
+		.map(|top| (top, std::cmp::min(top + band, height)))
+		.collect();
+
+	// Down the image, keeping the costs carried into each band.
+	let mut strip = TwoDimensionalMap::new(width, 0);
+	let mut row = vec![EnergyAndBackPointer::default(); width as usize];
+	let mut above: Vec<u64> = Vec::new();
+	let mut carried = Vec::with_capacity(bands.len());
+	for &rows in &bands {
+		carried.push(above.clone());
+		let first = read_strip(source, rows, &mut strip)?;
+		for y in rows.0..rows.1 {
+			strip_row_costs::<u32>((&strip, max), (y - first, y), &above, ties, &mut row);
+			above.clear();
+			above.extend(row.iter().map(|cell| cell.energy));
+		}
+	}
+
+	// And back up, each band's choices found again from its costs.
+	let mut column = ties.end_column(&above);
+	let cost = above[column as usize].to_f64();
+	let mut path = vec![0; height as usize];
+	let mut choices = TwoDimensionalMap::new(width, 0);
+	for (&rows, mut above) in bands.iter().zip(carried).rev() {
+		let first = read_strip(source, rows, &mut strip)?;
+		choices.reshape(width, rows.1 - rows.0);
+		for y in rows.0..rows.1 {
+			let row = choices.row_mut(y - rows.0);
+			strip_row_costs::<u32>((&strip, max), (y - first, y), &above, ties, row);
+			above.clear();
+			above.extend(row.iter().map(|cell| cell.energy));
+		}
+		for y in (rows.0..rows.1).rev() {
+			path[y as usize] = column;
+			column = choices[(column, y - rows.0)].parent(column);
+		}
+	}
+	Ok(Some(Seam::traced(
+		Orientation::Vertical,
+		path,
+		cost,
+		(width, height),
+	)))
+}
+
+// Read a band's rows into the strip, along with the row above the
+// band if there is one, and return the row the strip starts at.
+fn read_strip<R: RowSource>(
+	source: &mut R,
+	(top, bottom): (u32, u32),
+	strip: &mut TwoDimensionalMap<u32>,
+) -> io::Result<u32> {
+	let first = top.saturating_sub(1);
+	strip.reshape(strip.width, bottom - first);
+	source.read_rows(first, &mut strip.energy)?;
+	Ok(first)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::avisha2::AviShaTwo;
+	use crate::energyopts::EnergyOptions;
+	use crate::seamfinder::SeamFinder;
+	use image::{ImageBuffer, Luma};
+
+	// A source that remembers the most rows it was asked for at once.
+	struct Counted<'a, I>(ImageRows<'a, I>, u32);
+
+	impl<'a, I> RowSource for Counted<'a, I>
+	where
+		ImageRows<'a, I>: RowSource,
+	{
+		fn dimensions(&self) -> (u32, u32) {
+			self.0.dimensions()
+		}
+
+		fn max_luma(&self) -> u32 {
+			self.0.max_luma()
+		}
+
+		fn read_rows(&mut self, top: u32, lumas: &mut [u32]) -> io::Result<()> {
+			let rows = (lumas.len() / self.dimensions().0 as usize) as u32;
+			self.1 = std::cmp::max(self.1, rows);
+			self.0.read_rows(top, lumas)
+		}
+	}
+
+	#[test]
+	fn streamed_seams_match_the_whole_table() {
+		let pixel = |x: u32, y: u32| Luma([((x * 53 + y * 29 + x * y * 7) % 256) as u8]);
+		let buf: ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_fn(13, 11, pixel);
+		for &ties in &[TieBreak::Leftmost, TieBreak::Alternating] {
+			let options = EnergyOptions::default().tie_break(ties);
+			let whole = AviShaTwo::new(&buf)
+				.with_options(options)
+				.find_vertical_seam();
+			for &band in &[1, 3, 4, 11, 20] {
+				let mut source = Counted(ImageRows(&buf), 0);
+				let streamed = streamed_vertical_seam(&mut source, band, ties).unwrap();
+				assert_eq!(streamed, Some(whole.clone()), "band {}", band);
+				assert!(source.1 <= band + 1);
+			}
+		}
+	}
+}
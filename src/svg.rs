@@ -0,0 +1,153 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! SVG seam overlays
+//!
+//! A seam is a list of coordinates, one per row or column, which is a
+//! fine thing to carve with but a poor thing to show anyone.  This
+//! writes seams out as SVG polylines over an image of the given size,
+//! for design tools and web viewers to lay over the original.  Runs
+//! of pixels that go the same way are merged into a single segment,
+//! so a seam that goes straight down is two points, not a thousand.
+//! Seams can be drawn in one color, or shaded through a palette in
+//! the order they were found, with a legend.
+
+use crate::cq;
+use crate::palette::Palette;
+use crate::seamfinder::Orientation;
+use std::fmt::Write;
+
+/// Render seams as an SVG document the size of the image.  The seams
+/// must all be in the coordinates of that image; seams found one
+/// after another during a carve are each in the coordinates of a
+/// successively smaller image, and won't line up.
+pub fn seams_to_svg(width: u32, height: u32, seams: &[(Orientation, &[u32])]) -> String {
+	let mut svg = svg_header(width, height);
+	for (orientation, seam) in seams {
+		svg.push_str(&polyline(*orientation, seam, "red"));
+	}
+	svg.push_str("</svg>\n");
+	svg
+}
+
+/// As `seams_to_svg`, with the seams shaded through the palette in
+/// the order they're given, first lowest, and a legend below the
+/// image numbering them.  The document is tall enough to hold the
+/// legend.
+pub fn seams_to_svg_with_palette(
+	width: u32,
+	height: u32,
+	seams: &[(Orientation, &[u32])],
+	palette: &Palette,
+) -> String {
+	let mut svg = svg_header(width, height + LEGEND_HEIGHT);
+	for (i, (orientation, seam)) in seams.iter().enumerate() {
+		let color = Palette::hex(palette.ordinal(i, seams.len()));
+		svg.push_str(&polyline(*orientation, seam, &color));
+	}
+	let count = std::cmp::max(seams.len(), 1) as f64;
+	svg.push_str(&palette.legend_svg(
+		(4, height + 16),
+		(width.saturating_sub(8), 8),
+		(1.0, count),
+		cq!(seams.len() > 2, 3, 2),
+		"seam, in order found",
+	));
+	svg.push_str("</svg>\n");
+	svg
+}
+
+// The room below the image for a legend: its caption, bar, and
+// labels.
+const LEGEND_HEIGHT: u32 = 40;
+
+fn svg_header(width: u32, height: u32) -> String {
+	format!(
+		"<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">\n",
+		w = width,
+		h = height
+	)
+}
+
+fn polyline(orientation: Orientation, seam: &[u32], color: &str) -> String {
+	let points = simplify(&seam_points(orientation, seam))
+		.iter()
+		.map(|(x, y)| format!("{},{}", x, y))
+		.collect::<Vec<_>>()
+		.join(" ");
+	let mut line = String::new();
+	writeln!(
+		line,
+		"  <polyline points=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"1\"/>",
+		points, color
+	)
+	.unwrap();
+	line
+}
+
+// The center of every pixel in the seam.
+fn seam_points(orientation: Orientation, seam: &[u32]) -> Vec<(f32, f32)> {
+	seam.iter()
+		.enumerate()
+		.map(|(i, at)| {
+			let (along, across) = (i as f32 + 0.5, *at as f32 + 0.5);
+			match orientation {
+				Orientation::Vertical => (across, along),
+				Orientation::Horizontal => (along, across),
+			}
+		})
+		.collect()
+}
+
+// Drop every point that continues the line between its neighbors.
+// Seams only ever step by one pixel, so "the same line" is just "the
+// same step".
+fn simplify(points: &[(f32, f32)]) -> Vec<(f32, f32)> {
+	let step = |a: (f32, f32), b: (f32, f32)| (b.0 - a.0, b.1 - a.1);
+	let mut kept: Vec<(f32, f32)> = points.iter().take(1).cloned().collect();
+	for (i, point) in points.iter().enumerate().skip(1) {
+		let last = i == points.len() - 1;
+		if last || step(points[i - 1], *point) != step(*point, points[i + 1]) {
+			kept.push(*point);
+		}
+	}
+	kept
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn seams_become_simplified_polylines() {
+		let svg = seams_to_svg(
+			6,
+			6,
+			&[
+				(Orientation::Vertical, &[2, 2, 2, 3, 4, 4]),
+				(Orientation::Horizontal, &[0]),
+			],
+		);
+		assert!(
+			svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"6\" height=\"6\"")
+		);
+		assert!(svg.contains("points=\"2.5,0.5 2.5,2.5 4.5,4.5 4.5,5.5\""));
+		assert!(svg.contains("points=\"0.5,0.5\""));
+		assert!(svg.ends_with("</svg>\n"));
+	}
+
+	#[test]
+	fn paletted_seams_shade_in_order_above_a_legend() {
+		let seams: [(Orientation, &[u32]); 2] = [
+			(Orientation::Vertical, &[1, 1, 1]),
+			(Orientation::Vertical, &[2, 2, 2]),
+		];
+		let svg = seams_to_svg_with_palette(40, 3, &seams, &Palette::Viridis);
+		assert!(svg.contains("height=\"43\""));
+		let first = svg.find("stroke=\"#440154\"").unwrap();
+		let last = svg.find("stroke=\"#fde725\"").unwrap();
+		assert!(first < last);
+		assert!(svg.contains("seam, in order found"));
+	}
+}
@@ -0,0 +1,81 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Seam synthesis
+//!
+//! An enlargement inserts a new pixel beside each pixel of the seams
+//! it finds, between it and its neighbor on the far side.  What the
+//! new pixel should be depends on the picture.  A straight copy
+//! doubles the column, which shows as a stutter in fine texture; the
+//! average of the two neighbors hides it, but softens a hard edge the
+//! seam runs along; anything in between can be asked for.  Averages
+//! are weighted by alpha, as `alpha` describes.
+
+use crate::alpha::blend_toward;
+use image::{Pixel, Primitive};
+
+/// How the pixels of an inserted seam are made from the seam's pixel
+/// and its neighbor on the far side.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub enum Synthesis {
+	/// A copy of the seam's pixel.
+	Duplicate,
+	/// The average of the seam's pixel and its neighbor.
+	#[default]
+	Average,
+	/// A mix of the two, this fraction of it from the neighbor: 0.0
+	/// is a duplicate and 0.5 the average.  Fractions outside the unit
+	/// range are clamped to it.
+	Toward(f64),
+}
+
+impl Synthesis {
+	/// The pixel to insert between `pixel`, on the seam, and
+	/// `neighbor`.
+	pub fn synthesize<P, S>(self, pixel: &P, neighbor: &P) -> P
+	where
+		P: Pixel<Subpixel = S> + 'static,
+		S: Primitive + 'static,
+	{
+		match self {
+			Synthesis::Duplicate => *pixel,
+			Synthesis::Average => blend_toward(pixel, neighbor, 0.5),
+			Synthesis::Toward(t) => {
+				let t = if t.is_nan() { 0.5 } else { t.clamp(0.0, 1.0) };
+				blend_toward(pixel, neighbor, t)
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use image::{Rgb, Rgba};
+
+	#[test]
+	fn synthesis_mixes_as_much_of_the_neighbor_as_asked() {
+		let (pixel, neighbor) = (Rgb([0u8, 100, 200]), Rgb([200u8, 100, 0]));
+		assert_eq!(Synthesis::Duplicate.synthesize(&pixel, &neighbor), pixel);
+		assert_eq!(
+			Synthesis::Average.synthesize(&pixel, &neighbor),
+			Rgb([100, 100, 100])
+		);
+		assert_eq!(
+			Synthesis::Toward(0.25).synthesize(&pixel, &neighbor),
+			Rgb([50, 100, 150])
+		);
+		assert_eq!(
+			Synthesis::Toward(7.0).synthesize(&pixel, &neighbor),
+			neighbor
+		);
+		let clear = Rgba([255u8, 255, 255, 0]);
+		let solid = Rgba([0u8, 0, 0, 255]);
+		assert_eq!(
+			Synthesis::Toward(0.25).synthesize(&clear, &solid),
+			Rgba([0, 0, 0, 63])
+		);
+	}
+}
@@ -1,15 +1,27 @@
+use crate::cq;
+use crate::error::CarveError;
+use crate::scalar::EnergyScalar;
+use crate::seamfinder::{Orientation, Seam};
+#[cfg(feature = "threaded")]
+use rayon::prelude::*;
 use std::ops::{Index, IndexMut};
 
+// The side of the blocks a map is transposed in.  Sixty-four cells of
+// the largest values kept in a map span a few cache lines.
+const TRANSPOSE_BLOCK: usize = 64;
+
 /// Defines the basic energy map: An addressable two-dimensional field
 /// containing an object that represents one of several possible
 /// objects during processing: a basic u32 for the energy map, or an
 /// energy map + parent address, for the seam digraph, or the costs
 /// map for the forward energy calculation.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct TwoDimensionalMap<P: Default + Copy> {
+    /// The width of the map, in pixels.
     pub width: u32,
+    /// The height of the map, in pixels.
     pub height: u32,
-    energy: Vec<P>,
+    pub(crate) energy: Vec<P>,
 }
 
 impl<P: Default + Copy> TwoDimensionalMap<P> {
@@ -23,36 +35,579 @@ impl<P: Default + Copy> TwoDimensionalMap<P> {
         }
     }
 
+    /// A map of the given dimensions holding the given values, row
+    /// after row, top to bottom.  There must be exactly one for every
+    /// cell.
+    pub fn from_vec(width: u32, height: u32, energy: Vec<P>) -> Result<Self, CarveError> {
+        if energy.len() != width as usize * height as usize {
+            return Err(CarveError::MapLengthMismatch {
+                dimensions: (width, height),
+                found: energy.len(),
+            });
+        }
+        Ok(TwoDimensionalMap {
+            width,
+            height,
+            energy,
+        })
+    }
+
+    /// The map's values, row after row, top to bottom.
+    pub fn into_vec(self) -> Vec<P> {
+        self.energy
+    }
+
     // Absolutely, the number one name of this game is keep the index
     // math in a singular location and never, ever mess with it.  This
     // particular variant is the same one used in image.rs.
     fn get_index(&self, x: u32, y: u32) -> usize {
         (y as usize) * (self.width as usize) + (x as usize)
     }
+
+    /// The value at (x, y), or None if that's outside the map.
+    pub fn get(&self, x: u32, y: u32) -> Option<&P> {
+        cq!(
+            x < self.width && y < self.height,
+            Some(&self.energy[self.get_index(x, y)]),
+            None
+        )
+    }
+
+    /// As `get`, mutably.
+    pub fn get_mut(&mut self, x: u32, y: u32) -> Option<&mut P> {
+        if x < self.width && y < self.height {
+            let index = self.get_index(x, y);
+            Some(&mut self.energy[index])
+        } else {
+            None
+        }
+    }
+
+    // A whole row, as a slice.
+    pub(crate) fn row(&self, y: u32) -> &[P] {
+        let start = self.get_index(0, y);
+        &self.energy[start..start + self.width as usize]
+    }
+
+    pub(crate) fn row_mut(&mut self, y: u32) -> &mut [P] {
+        let start = self.get_index(0, y);
+        &mut self.energy[start..start + self.width as usize]
+    }
+
+    /// The rows of the map, top to bottom, each a mutable slice of its
+    /// own, so that different rows can be handed to different writers.
+    pub fn rows_mut(&mut self) -> std::slice::ChunksMut<'_, P> {
+        let stride = std::cmp::max(self.width as usize, 1);
+        self.energy.chunks_mut(stride)
+    }
+
+    /// As `rows_mut`, as a parallel iterator over rayon's threads.
+    #[cfg(feature = "threaded")]
+    pub fn par_rows_mut(&mut self) -> rayon::slice::ChunksMut<'_, P>
+    where
+        P: Send,
+    {
+        let stride = std::cmp::max(self.width as usize, 1);
+        self.energy.par_chunks_mut(stride)
+    }
+
+    /// The cells of the rows above row y, and of row y and those below
+    /// it, both mutable: a row of a DP can be written while the rows
+    /// above it are read.
+    pub fn split_at_row(&mut self, y: u32) -> (&mut [P], &mut [P]) {
+        let at = self.get_index(0, y);
+        self.energy.split_at_mut(at)
+    }
+
+    // Make the map the given size, keeping the allocation it has if
+    // that's big enough.  What's in it is left unspecified.
+    pub(crate) fn reshape(&mut self, width: u32, height: u32) {
+        self.width = width;
+        self.height = height;
+        self.energy
+            .resize(width as usize * height as usize, P::default());
+    }
+
+    /// Take a vertical seam out of the map, which becomes a column
+    /// narrower.  The seam must have been found in, or made for, an
+    /// image of the map's dimensions, as for
+    /// `seamcarver::remove_vertical_seam`.  The cells right of the seam
+    /// move left in the map's own buffer, which is shrunk rather than
+    /// reallocated, so a map can be carried through a carve alongside
+    /// the image.
+    pub fn remove_vertical_seam(&mut self, seam: &Seam) -> Result<(), CarveError> {
+        self.fits(seam, Orientation::Vertical)?;
+        self.remove_seam(seam);
+        Ok(())
+    }
+
+    /// As `remove_vertical_seam`, for a horizontal seam; the cells
+    /// below it move up, and the map becomes a row shorter.
+    pub fn remove_horizontal_seam(&mut self, seam: &Seam) -> Result<(), CarveError> {
+        self.fits(seam, Orientation::Horizontal)?;
+        let (width, height) = (self.width as usize, self.height as usize);
+        // Each cell moves up, if at all, so it's read before it can be
+        // overwritten.
+        for y in 0..height - 1 {
+            for (x, &at) in seam.iter().enumerate() {
+                if at as usize <= y {
+                    self.energy[y * width + x] = self.energy[(y + 1) * width + x];
+                }
+            }
+        }
+        self.energy.truncate(width * (height - 1));
+        self.height -= 1;
+        Ok(())
+    }
+
+    // Refuse a seam that wasn't made for a map this size, or that runs
+    // across a map with nothing across it.
+    fn fits(&self, seam: &Seam, orientation: Orientation) -> Result<(), CarveError> {
+        seam.check(orientation, (self.width, self.height))?;
+        if self.width == 0 || self.height == 0 {
+            return Err(CarveError::ImageTooSmall {
+                width: self.width,
+                height: self.height,
+            });
+        }
+        Ok(())
+    }
+
+    // Take a vertical seam out of the map, in place.
+    pub(crate) fn remove_seam(&mut self, seam: &[u32]) {
+        self.remove_seam_with(seam, |_, _, cell| cell);
+    }
+
+    // As `remove_seam`, with each cell that's kept passed through `f`,
+    // along with the row and the columns it moves to and from.
+    pub(crate) fn remove_seam_with(
+        &mut self,
+        seam: &[u32],
+        f: impl FnMut(u32, (u32, u32), P) -> P,
+    ) {
+        self.remove_seams_with(&[seam], f);
+    }
+
+    // Take several vertical seams out of the map at once, in place.
+    // The seams mustn't share a cell.
+    pub(crate) fn remove_seams(&mut self, seams: &[&[u32]]) {
+        self.remove_seams_with(seams, |_, _, cell| cell);
+    }
+
+    // As `remove_seams`, with each cell that's kept passed through `f`,
+    // along with the row and the columns it moves to and from.
+    pub(crate) fn remove_seams_with(
+        &mut self,
+        seams: &[&[u32]],
+        mut f: impl FnMut(u32, (u32, u32), P) -> P,
+    ) {
+        let (width, height, count) = (self.width as usize, self.height, seams.len());
+        let mut at: Vec<usize> = Vec::with_capacity(count);
+        for y in 0..height {
+            at.clear();
+            at.extend(seams.iter().map(|seam| seam[y as usize] as usize));
+            at.sort_unstable();
+            let (from, to) = (y as usize * width, y as usize * (width - count));
+            // Each cell moves left, if at all, so it's read before it
+            // can be overwritten.
+            let mut passed = 0;
+            for x in 0..width - count {
+                while passed < count && at[passed] <= x + passed {
+                    passed += 1;
+                }
+                let was = x + passed;
+                self.energy[to + x] = f(y, (x as u32, was as u32), self.energy[from + was]);
+            }
+        }
+        self.energy.truncate((width - count) * height as usize);
+        self.width -= count as u32;
+    }
+
+    /// The map flipped about its diagonal, so that its columns become
+    /// contiguous rows: a horizontal seam can be found as a vertical
+    /// one in the flipped map, and a map made for an image on its side
+    /// turned to fit it.  The copy is made a square block at a time,
+    /// so that the reads and the writes both stay within a few lines
+    /// of the cache, however large the map.
+    pub fn transposed(&self) -> Self {
+        let (width, height) = (self.width as usize, self.height as usize);
+        let mut flipped = TwoDimensionalMap::new(self.height, self.width);
+        for top in (0..height).step_by(TRANSPOSE_BLOCK) {
+            let bottom = std::cmp::min(top + TRANSPOSE_BLOCK, height);
+            for left in (0..width).step_by(TRANSPOSE_BLOCK) {
+                let right = std::cmp::min(left + TRANSPOSE_BLOCK, width);
+                for y in top..bottom {
+                    for x in left..right {
+                        flipped.energy[x * height + y] = self.energy[y * width + x];
+                    }
+                }
+            }
+        }
+        flipped
+    }
 }
 
 impl<P: Default + Copy> Index<(u32, u32)> for TwoDimensionalMap<P> {
     type Output = P;
 
-    /// A convenience addressing mode for getting values.
+    /// A convenience addressing mode for getting values.  Panics,
+    /// saying where and how big the map is, outside the map.
     fn index(&self, (x, y): (u32, u32)) -> &P {
-        let index = self.get_index(x, y);
-        &self.energy[index]
+        let (width, height) = (self.width, self.height);
+        self.get(x, y)
+            .unwrap_or_else(|| outside((x, y), (width, height)))
     }
 }
 
 impl<P: Default + Copy> IndexMut<(u32, u32)> for TwoDimensionalMap<P> {
     /// A convenience addressing mode for setting values.
     fn index_mut(&mut self, (x, y): (u32, u32)) -> &mut P {
-        let index = self.get_index(x, y);
-        &mut self.energy[index]
+        let (width, height) = (self.width, self.height);
+        self.get_mut(x, y)
+            .unwrap_or_else(|| outside((x, y), (width, height)))
     }
 }
 
+// Off the hot path: a column past the end of a row would otherwise
+// read the next row, or panic with only an index into the buffer.
+#[cold]
+#[inline(never)]
+fn outside((x, y): (u32, u32), (width, height): (u32, u32)) -> ! {
+    panic!("({}, {}) is outside a {}x{} map", x, y, width, height)
+}
+
+// Element-wise arithmetic, for combining energy maps and
+// post-processing them without index loops of one's own.
+impl<E: EnergyScalar> TwoDimensionalMap<E> {
+    /// The sum of two maps of the same dimensions, held at the top of
+    /// the energy's domain where it would overflow.
+    pub fn add(&self, other: &Self) -> Result<Self, CarveError> {
+        self.zip_with(other, E::saturating_add)
+    }
+
+    /// The larger of the two maps' energies at every pixel.
+    pub fn max(&self, other: &Self) -> Result<Self, CarveError> {
+        self.zip_with(other, |a, b| if b > a { b } else { a })
+    }
+
+    /// The smaller of the two maps' energies at every pixel.
+    pub fn min(&self, other: &Self) -> Result<Self, CarveError> {
+        self.zip_with(other, |a, b| if b < a { b } else { a })
+    }
 
+    /// The map with every energy multiplied by a factor.  An integer
+    /// energy is held at the top of its domain, or at zero, rather
+    /// than wrapping.
+    pub fn scale(&self, factor: f64) -> Self {
+        TwoDimensionalMap {
+            width: self.width,
+            height: self.height,
+            energy: self.energy.iter().map(|e| e.scale(factor)).collect(),
+        }
+    }
+
+    fn zip_with(&self, other: &Self, op: impl Fn(E, E) -> E) -> Result<Self, CarveError> {
+        if (self.width, self.height) != (other.width, other.height) {
+            return Err(CarveError::MapDimensionMismatch {
+                expected: (self.width, self.height),
+                found: (other.width, other.height),
+            });
+        }
+        Ok(TwoDimensionalMap {
+            width: self.width,
+            height: self.height,
+            energy: self
+                .energy
+                .iter()
+                .zip(other.energy.iter())
+                .map(|(a, b)| op(*a, *b))
+                .collect(),
+        })
+    }
+}
+
+/// Combine several energy maps of the same dimensions into one, each
+/// contributing in proportion to its weight: `[(&gradient, 0.7),
+/// (&saliency, 0.3)]` gives 0.7 × gradient + 0.3 × saliency at every
+/// pixel.  The weights are not normalized, and the result is rounded
+/// to the nearest integer energy.  There must be at least one map.
+pub fn blend(
+    sources: &[(&TwoDimensionalMap<u32>, f32)],
+) -> Result<TwoDimensionalMap<u32>, CarveError> {
+    let (first, _) = sources.first().ok_or(CarveError::NothingToBlend)?;
+    let (width, height) = (first.width, first.height);
+    if let Some((map, _)) = sources
+        .iter()
+        .find(|(map, _)| map.width != width || map.height != height)
+    {
+        return Err(CarveError::MapDimensionMismatch {
+            expected: (width, height),
+            found: (map.width, map.height),
+        });
+    }
+
+    let mut blended = TwoDimensionalMap::new(width, height);
+    for (i, energy) in blended.energy.iter_mut().enumerate() {
+        let sum: f32 = sources
+            .iter()
+            .map(|(map, weight)| map.energy[i] as f32 * weight)
+            .sum();
+        *energy = sum.round() as u32;
+    }
+    Ok(blended)
+}
+
+// An ndarray array is indexed row first, as `[[y, x]]`, and holds its
+// rows in the same order as the map does, so the map goes into one
+// without a copy.
+#[cfg(feature = "ndarray")]
+impl<P: Default + Copy> From<TwoDimensionalMap<P>> for ndarray::Array2<P> {
+    fn from(map: TwoDimensionalMap<P>) -> Self {
+        let shape = (map.height as usize, map.width as usize);
+        ndarray::Array2::from_shape_vec(shape, map.energy)
+            .expect("a map has a value for every cell")
+    }
+}
+
+// An array may be too large for a map, or laid out in another order,
+// and is read in its logical order whatever its layout.
+#[cfg(feature = "ndarray")]
+impl<P: Default + Copy> std::convert::TryFrom<ndarray::Array2<P>> for TwoDimensionalMap<P> {
+    type Error = CarveError;
+
+    fn try_from(array: ndarray::Array2<P>) -> Result<Self, CarveError> {
+        let (height, width) = array.dim();
+        match (u32::try_from(width), u32::try_from(height)) {
+            (Ok(width), Ok(height)) => {
+                TwoDimensionalMap::from_vec(width, height, array.iter().copied().collect())
+            }
+            _ => Err(CarveError::MapTooLarge { width, height }),
+        }
+    }
+}
+
+// A cost of the forward-energy DP and the column of the row above it
+// came from.  The parent is never more than a column away, so it's
+// kept as a step from the cell's own column.  The cell isn't packed:
+// a packed cell of a `u64` cost is nine bytes rather than sixteen, but
+// every cost read from it is unaligned, and the DP ran over half again
+// as long for it.
 #[derive(Default, Debug, Copy, Clone)]
 pub(crate) struct EnergyAndBackPointer<P: Default + Copy> {
     pub energy: P,
-    pub parent: u32,
+    pub step: i8,
 }
 
+impl<P: Default + Copy> EnergyAndBackPointer<P> {
+    // The cell at column `x` with the given cost and parent.
+    pub(crate) fn new(energy: P, x: u32, parent: u32) -> Self {
+        EnergyAndBackPointer {
+            energy,
+            step: (i64::from(parent) - i64::from(x)) as i8,
+        }
+    }
+
+    // The parent of the cell at column `x`.
+    pub(crate) fn parent(self, x: u32) -> u32 {
+        (i64::from(x) + i64::from(self.step)) as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map_of(width: u32, height: u32, energy: &[u32]) -> TwoDimensionalMap<u32> {
+        TwoDimensionalMap {
+            width,
+            height,
+            energy: energy.to_vec(),
+        }
+    }
+
+    #[test]
+    fn blend_weighs_each_source() {
+        let gradient = map_of(2, 2, &[10, 20, 30, 40]);
+        let saliency = map_of(2, 2, &[100, 0, 100, 0]);
+        let blended = blend(&[(&gradient, 0.7), (&saliency, 0.3)]).unwrap();
+        assert_eq!(blended.energy, [37, 14, 51, 28]);
+    }
+
+    #[test]
+    fn maps_combine_element_wise() {
+        let a = map_of(2, 2, &[1, 5, 3, 8]);
+        let b = map_of(2, 2, &[4, 2, 3, 0]);
+        assert_eq!(a.add(&b).unwrap().energy, [5, 7, 6, 8]);
+        assert_eq!(a.max(&b).unwrap().energy, [4, 5, 3, 8]);
+        assert_eq!(a.min(&b).unwrap().energy, [1, 2, 3, 0]);
+        assert_eq!(a.scale(2.5).energy, [3, 13, 8, 20]);
+        assert_eq!(
+            a.add(&map_of(4, 1, &[0; 4])).unwrap_err(),
+            CarveError::MapDimensionMismatch {
+                expected: (2, 2),
+                found: (4, 1)
+            }
+        );
+    }
+
+    #[test]
+    fn saturated_maps_stay_saturated() {
+        let full = map_of(2, 1, &[u32::MAX, u32::MAX - 1]);
+        assert_eq!(full.add(&full).unwrap().energy, [u32::MAX, u32::MAX]);
+        assert_eq!(full.scale(2.0).energy, [u32::MAX, u32::MAX]);
+        assert_eq!(full.scale(-1.0).energy, [0, 0]);
+    }
+
+    #[test]
+    fn blend_rejects_mismatched_maps() {
+        let a = map_of(2, 2, &[0; 4]);
+        let b = map_of(4, 1, &[0; 4]);
+        assert_eq!(
+            blend(&[(&a, 1.0), (&b, 1.0)]).unwrap_err(),
+            CarveError::MapDimensionMismatch {
+                expected: (a.width, a.height),
+                found: (b.width, b.height)
+            }
+        );
+        assert_eq!(blend(&[]).unwrap_err(), CarveError::NothingToBlend);
+    }
+
+    #[test]
+    fn maps_are_made_from_and_into_vecs() {
+        let map = TwoDimensionalMap::from_vec(3, 2, vec![1u32, 2, 3, 4, 5, 6]).unwrap();
+        assert_eq!((map[(2, 0)], map[(0, 1)]), (3, 4));
+        assert_eq!(map.into_vec(), [1, 2, 3, 4, 5, 6]);
+        assert_eq!(
+            TwoDimensionalMap::from_vec(3, 2, vec![0u32; 5]).unwrap_err(),
+            CarveError::MapLengthMismatch {
+                dimensions: (3, 2),
+                found: 5
+            }
+        );
+    }
+
+    #[cfg(feature = "ndarray")]
+    #[test]
+    fn maps_convert_to_and_from_arrays() {
+        use std::convert::TryFrom;
+        let map = map_of(3, 2, &[1, 2, 3, 4, 5, 6]);
+        let array = ndarray::Array2::from(map.clone());
+        assert_eq!((array.dim(), array[[1, 0]]), ((2, 3), 4));
+        let back = TwoDimensionalMap::try_from(array.reversed_axes()).unwrap();
+        assert_eq!((back.width, back.height), (2, 3));
+        assert_eq!(back.into_vec(), [1, 4, 2, 5, 3, 6]);
+        let round = TwoDimensionalMap::try_from(ndarray::Array2::from(map.clone())).unwrap();
+        assert_eq!(round.into_vec(), map.into_vec());
+    }
+
+    #[test]
+    fn seams_come_out_of_maps_either_way() {
+        let cells: Vec<u32> = (0..12).collect();
+        let seam = |orientation, path: &[u32], dimensions| {
+            Seam::new(orientation, path.to_vec(), 0.0, dimensions).unwrap()
+        };
+        let vertical = seam(Orientation::Vertical, &[1, 2, 3], (4, 3));
+        let mut map = map_of(4, 3, &cells);
+        map.remove_vertical_seam(&vertical).unwrap();
+        assert_eq!((map.width, map.height), (3, 3));
+        assert_eq!(map.energy, [0, 2, 3, 4, 5, 7, 8, 9, 10]);
+
+        let horizontal = seam(Orientation::Horizontal, &[0, 1, 2, 1], (4, 3));
+        let mut map = map_of(4, 3, &cells);
+        map.remove_horizontal_seam(&horizontal).unwrap();
+        assert_eq!((map.width, map.height), (4, 2));
+        assert_eq!(map.energy, [4, 1, 2, 3, 8, 9, 6, 11]);
+
+        // The map is now a row shorter than the seams were made for,
+        // and neither runs the other way.
+        let mismatch = |seam: &Seam| CarveError::SeamMismatch {
+            orientation: seam.orientation(),
+            seam: (4, 3),
+            dimensions: (4, 2),
+        };
+        assert_eq!(
+            map.remove_vertical_seam(&vertical),
+            Err(mismatch(&vertical))
+        );
+        assert_eq!(
+            map.remove_horizontal_seam(&horizontal),
+            Err(mismatch(&horizontal))
+        );
+        let mut other = map_of(4, 3, &cells);
+        assert!(other.remove_vertical_seam(&horizontal).is_err());
+        assert_eq!((map.width, map.height), (4, 2));
+    }
+
+    #[test]
+    fn several_seams_come_out_at_once() {
+        let cells: Vec<u32> = (0..18).collect();
+        let (left, right): (&[u32], &[u32]) = (&[0, 1, 1], &[4, 3, 5]);
+        let mut together = map_of(6, 3, &cells);
+        together.remove_seams(&[right, left]);
+        let mut apart = map_of(6, 3, &cells);
+        apart.remove_seam(right);
+        apart.remove_seam(left);
+        assert_eq!((together.width, together.height), (4, 3));
+        assert_eq!(together.energy, apart.energy);
+        assert_eq!(together.energy, [1, 2, 3, 5, 6, 8, 10, 11, 12, 14, 15, 16]);
+    }
+
+    #[test]
+    fn maps_transpose_across_block_edges() {
+        let (width, height) = (TRANSPOSE_BLOCK as u32 + 5, 3);
+        let cells: Vec<u32> = (0..width * height).collect();
+        let flipped = map_of(width, height, &cells).transposed();
+        assert_eq!((flipped.width, flipped.height), (height, width));
+        for y in 0..height {
+            for x in 0..width {
+                assert_eq!(flipped[(y, x)], y * width + x);
+            }
+        }
+        assert_eq!(flipped.transposed().energy, cells);
+    }
+
+    #[test]
+    fn checked_access_stays_inside_the_map() {
+        let mut map = map_of(3, 2, &[1, 2, 3, 4, 5, 6]);
+        assert_eq!(
+            (map.get(2, 1), map.get(3, 0), map.get(0, 2)),
+            (Some(&6), None, None)
+        );
+        *map.get_mut(0, 1).unwrap() = 9;
+        assert!(map.get_mut(1, 5).is_none());
+        assert_eq!(map[(0, 1)], 9);
+        let message = std::panic::catch_unwind(|| map[(3, 0)])
+            .unwrap_err()
+            .downcast::<String>()
+            .unwrap();
+        assert_eq!(*message, "(3, 0) is outside a 3x2 map");
+    }
+
+    #[test]
+    fn rows_split_into_disjoint_slices() {
+        let mut map = map_of(3, 3, &[0; 9]);
+        for (y, row) in map.rows_mut().enumerate() {
+            for cell in row.iter_mut() {
+                *cell = y as u32 + 1;
+            }
+        }
+        let (above, rest) = map.split_at_row(2);
+        rest[0] = above[3] * 10;
+        assert_eq!(map.energy, [1, 1, 1, 2, 2, 2, 20, 3, 3]);
+        #[cfg(feature = "threaded")]
+        {
+            map.par_rows_mut()
+                .enumerate()
+                .for_each(|(y, row)| row[y] = 0);
+            assert_eq!(map.energy, [0, 1, 1, 2, 0, 2, 20, 3, 0]);
+        }
+    }
+
+    #[test]
+    fn back_pointers_are_steps_in_aligned_cells() {
+        assert_eq!(std::mem::align_of::<EnergyAndBackPointer<u64>>(), 8);
+        let cell = EnergyAndBackPointer::new(7u64, 5, 4);
+        assert_eq!((cell.step, cell.parent(5)), (-1, 4));
+        assert_eq!(EnergyAndBackPointer::new(7u64, 0, 1).parent(0), 1);
+    }
+}
@@ -0,0 +1,162 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// Exhaustive checks of both seam finders.  For small random images,
+// every connected seam is enumerated and costed, and the seam each
+// finder returns must cost exactly the true minimum.  Any refactor of
+// the DPs that breaks optimality, or returns a seam that isn't
+// connected, fails here.
+
+use image::{ImageBuffer, Luma};
+use pnmseam::avisha1::{calculate_energy, energy_to_horizontal_seam, energy_to_vertical_seam};
+use pnmseam::pixelpairs::energy_of_pair_luma;
+use pnmseam::{AviShaTwo, SeamFinder, TwoDimensionalMap};
+
+type Gray = ImageBuffer<Luma<u8>, Vec<u8>>;
+
+fn random_image(width: u32, height: u32, seed: &mut u32) -> Gray {
+	ImageBuffer::from_fn(width, height, |_, _| {
+		*seed ^= *seed << 13;
+		*seed ^= *seed >> 17;
+		*seed ^= *seed << 5;
+		Luma([(*seed % 256) as u8])
+	})
+}
+
+// Every connected vertical seam through a map of the given size.
+fn all_seams(width: u32, height: u32) -> Vec<Vec<u32>> {
+	let mut seams: Vec<Vec<u32>> = (0..width).map(|x| vec![x]).collect();
+	for _ in 1..height {
+		seams = seams
+			.into_iter()
+			.flat_map(|seam| {
+				let last = *seam.last().unwrap();
+				let low = if last == 0 { 0 } else { last - 1 };
+				let high = std::cmp::min(last + 1, width - 1);
+				(low..=high).map(move |x| {
+					let mut next = seam.clone();
+					next.push(x);
+					next
+				})
+			})
+			.collect();
+	}
+	seams
+}
+
+fn is_connected(seam: &[u32], width: u32) -> bool {
+	seam.iter().all(|x| *x < width)
+		&& seam
+			.windows(2)
+			.all(|w| (w[0] as i64 - w[1] as i64).abs() <= 1)
+}
+
+// The backward energy of a seam: the sum of the energies it crosses.
+fn backward_cost(energy: &TwoDimensionalMap<u32>, seam: &[u32]) -> u64 {
+	seam.iter()
+		.enumerate()
+		.map(|(y, x)| u64::from(energy[(*x, y as u32)]))
+		.sum()
+}
+
+// The forward energy of a seam, as AviShaTwo defines it: the
+// difference between the pixels brought together in each row, plus
+// the differences exposed by each diagonal step.
+fn forward_cost(image: &Gray, seam: &[u32]) -> u64 {
+	let mw = image.width() - 1;
+	let d = |(x1, y1), (x2, y2)| u64::from(energy_of_pair_luma(&image[(x1, y1)], &image[(x2, y2)]));
+	let closing = |x: u32, y: u32| {
+		if x == 0 {
+			d((0, y), (1, y))
+		} else if x == mw {
+			d((x - 1, y), (x, y))
+		} else {
+			d((x - 1, y), (x + 1, y))
+		}
+	};
+	let mut cost = closing(seam[0], 0);
+	for y in 1..seam.len() as u32 {
+		let (x, above) = (seam[y as usize], seam[y as usize - 1]);
+		cost += closing(x, y - 1);
+		if above != x {
+			cost += d((x, y - 1), (above, y));
+		}
+	}
+	cost
+}
+
+#[test]
+fn backward_energy_seams_are_optimal() {
+	let mut seed = 0x9e37_79b9;
+	for width in 1..=8 {
+		for height in 1..=8 {
+			let image = random_image(width, height, &mut seed);
+			let energy = calculate_energy(&image);
+
+			let best = all_seams(width, height)
+				.iter()
+				.map(|seam| backward_cost(&energy, seam))
+				.min()
+				.unwrap();
+			let seam = energy_to_vertical_seam(&energy);
+			assert!(
+				is_connected(&seam, width),
+				"{}x{}: {:?}",
+				width,
+				height,
+				seam
+			);
+			assert_eq!(seam.len(), height as usize);
+			assert_eq!(backward_cost(&energy, &seam), best, "{}x{}", width, height);
+
+			// Horizontal seams are vertical seams of the transposed map.
+			let mut transposed = TwoDimensionalMap::new(height, width);
+			for y in 0..height {
+				for x in 0..width {
+					transposed[(y, x)] = energy[(x, y)];
+				}
+			}
+			let best = all_seams(height, width)
+				.iter()
+				.map(|seam| backward_cost(&transposed, seam))
+				.min()
+				.unwrap();
+			let seam = energy_to_horizontal_seam(&energy);
+			assert!(is_connected(&seam, height));
+			assert_eq!(
+				backward_cost(&transposed, &seam),
+				best,
+				"{}x{}",
+				width,
+				height
+			);
+		}
+	}
+}
+
+#[test]
+fn forward_energy_seams_are_optimal() {
+	let mut seed = 0x2545_f491;
+	// The forward energy calculator needs two columns to close a gap.
+	for width in 2..=8 {
+		for height in 1..=8 {
+			let image = random_image(width, height, &mut seed);
+			let best = all_seams(width, height)
+				.iter()
+				.map(|seam| forward_cost(&image, seam))
+				.min()
+				.unwrap();
+			let seam = AviShaTwo::new(&image).find_vertical_seam();
+			assert!(
+				is_connected(&seam, width),
+				"{}x{}: {:?}",
+				width,
+				height,
+				seam
+			);
+			assert_eq!(seam.len(), height as usize);
+			assert_eq!(forward_cost(&image, &seam), best, "{}x{}", width, height);
+		}
+	}
+}